@@ -35,24 +35,17 @@ fn main() -> std::io::Result<()> {
 
             let cx = Rc::new(spirt::Context::new());
 
-            let multi_version_printing = true;
-            let mut per_pass_module = vec![];
-            let mut after_pass = |pass, module: &spirt::Module| {
-                if multi_version_printing {
-                    per_pass_module.push((pass, module.clone()));
-                    Ok(())
-                } else {
-                    save_print_plan(
-                        &format!("after.{pass}"),
-                        spirt::print::Plan::for_module(module),
-                    )
-                }
-            };
-
             let mut module =
                 eprint_duration(|| spirt::Module::lower_from_spv_file(cx.clone(), in_file_path))?;
             eprintln!("Module::lower_from_spv_file({})", in_file_path.display());
 
+            // HACK(eddyb) this is roughly what Rust-GPU would need.
+            let layout_config = spirt::qptr::LayoutConfig {
+                abstract_bool_size_align: (1, 1),
+                logical_ptr_size_align: (4, 4),
+                ..spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT
+            };
+
             let original_export_count = module.exports.len();
             eprint_duration(|| {
                 spirt::passes::link::minimize_exports(&mut module, |export_key| {
@@ -64,61 +57,33 @@ fn main() -> std::io::Result<()> {
                 original_export_count,
                 module.exports.len()
             );
-            //after_pass("minimize_exports", &module)?;
+
+            let mut pass_manager = spirt::passes::PassManager::with_default_passes(layout_config);
+            pass_manager.time_passes = true;
 
             // HACK(eddyb) do this late enough to avoid spending time on unused
             // functions, which `link::minimize_exports` makes unreachable.
-            eprint_duration(|| spirt::passes::legalize::structurize_func_cfgs(&mut module));
-            eprintln!("legalize::structurize_func_cfgs");
-            //after_pass("structurize_func_cfgs", &module)?;
-
-            eprint_duration(|| spirt::passes::link::resolve_imports(&mut module));
-            eprintln!("link::resolve_imports");
-            //after_pass("resolve_imports", &module)?;
-
-            // HACK(eddyb)
-            after_pass("", &module)?;
-
-            // HACK(eddyb) this is roughly what Rust-GPU would need.
-            let layout_config = &spirt::qptr::LayoutConfig {
-                abstract_bool_size_align: (1, 1),
-                logical_ptr_size_align: (4, 4),
-                ..spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT
-            };
-
-            eprint_duration(|| {
-                spirt::passes::qptr::lower_from_spv_ptrs(&mut module, layout_config)
-            });
-            eprintln!("qptr::lower_from_spv_ptrs");
-            after_pass("qptr::lower_from_spv_ptrs", &module)?;
-
-            eprint_duration(|| spirt::passes::qptr::analyze_uses(&mut module, layout_config));
-            eprintln!("qptr::analyze_uses");
-            after_pass("qptr::analyze_uses", &module)?;
-
-            eprint_duration(|| spirt::passes::qptr::lift_to_spv_ptrs(&mut module, layout_config));
-            eprintln!("qptr::lift_to_spv_ptrs");
-            after_pass("qptr::lift_to_spv_ptrs", &module)?;
+            let report = pass_manager
+                .run(&mut module, "legalize::structurize_func_cfgs, link::resolve_imports")
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            eprint!("{}", report.timing_report());
+
+            // NOTE(eddyb) only the `qptr` passes are recorded (with the module
+            // before them as the "initial" version), for multi-version printing.
+            pass_manager.record_versions = true;
+            let report = pass_manager
+                .run(
+                    &mut module,
+                    "qptr::lower_from_spv_ptrs,
+                     qptr::analyze_uses,
+                     qptr::lift_to_spv_ptrs",
+                )
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            eprint!("{}", report.timing_report());
 
-            if multi_version_printing {
+            if let Some(plan) = report.print_plan_for_versions() {
                 // FIXME(eddyb) use a better suffix than `qptr` (or none).
-                save_print_plan(
-                    "qptr",
-                    spirt::print::Plan::for_versions(
-                        &cx,
-                        per_pass_module.iter().map(|(pass, module)| {
-                            (
-                                // HACK(eddyb)
-                                if pass.is_empty() {
-                                    "initial".into()
-                                } else {
-                                    format!("after {pass}")
-                                },
-                                module,
-                            )
-                        }),
-                    ),
-                )?;
+                save_print_plan("qptr", plan)?;
             }
 
             //let out_file_path = in_file_path.with_extension("qptr.spv");
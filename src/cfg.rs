@@ -1,15 +1,16 @@
 //! Control-flow graph (CFG) abstractions and utilities.
 
+use crate::transform::{InnerInPlaceTransform, ReplaceValueWith};
 use crate::{
     spv, AttrSet, Const, ConstDef, ConstKind, Context, ControlNode, ControlNodeDef,
     ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionDef,
-    EntityOrientedDenseMap, FuncDefBody, FxIndexMap, FxIndexSet, SelectionKind, Type, TypeKind,
-    Value,
+    ControlRegionInputDecl, DataInst, EntityOrientedDenseMap, FuncDefBody, FxIndexMap, FxIndexSet,
+    ScalarKind, SelectionKind, Type, TypeKind, Value,
 };
 use itertools::{Either, Itertools};
+use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::SmallVec;
 use std::mem;
-use std::rc::Rc;
 
 /// The control-flow graph (CFG) of a function, as control-flow instructions
 /// ([`ControlInst`]s) attached to [`ControlRegion`]s, as an "action on exit", i.e.
@@ -102,6 +103,557 @@ impl ControlFlowGraph {
     }
 }
 
+impl ControlFlowGraph {
+    /// Compute the dominator tree of `func_def_body`'s CFG, rooted in the
+    /// function body (i.e. the CFG's entry), see [`DomTree`].
+    pub fn dom_tree(&self, func_def_body: &FuncDefBody) -> DomTree {
+        let rev_post_order: FxIndexSet<_> = self.rev_post_order(func_def_body).collect();
+
+        // NOTE(eddyb) index `0` is reserved for the "virtual root" (see `DomTree::compute`).
+        let mut preds = vec![SmallVec::new(); rev_post_order.len() + 1];
+        preds[1].push(0);
+        for (i, &region) in rev_post_order.iter().enumerate() {
+            for &target in &self.control_inst_on_exit_from[region].targets {
+                preds[rev_post_order.get_index_of(&target).unwrap() + 1].push(i + 1);
+            }
+        }
+
+        DomTree::compute(rev_post_order, &preds)
+    }
+
+    /// Compute the post-dominator tree of `func_def_body`'s CFG, rooted in
+    /// all the regions exiting the function (i.e. [`ControlInst`]s without any
+    /// `targets`, such as [`ControlInstKind::Return`]), see [`DomTree`].
+    ///
+    /// Regions which can't reach any function exit (i.e. infinite loops) are
+    /// not post-dominated by any region, and so not part of the tree at all.
+    pub fn post_dom_tree(&self, func_def_body: &FuncDefBody) -> DomTree {
+        let rev_post_order: FxIndexSet<_> = self.rev_post_order(func_def_body).collect();
+
+        let mut cfg_preds: FxIndexMap<_, SmallVec<[_; 4]>> = FxIndexMap::default();
+        let mut exits = SmallVec::<[_; 4]>::new();
+        for &region in &rev_post_order {
+            let targets = &self.control_inst_on_exit_from[region].targets;
+            if targets.is_empty() {
+                exits.push(region);
+            }
+            for &target in targets {
+                cfg_preds.entry(target).or_default().push(region);
+            }
+        }
+
+        // Depth-first traversal of the reverse CFG, starting from all the exits.
+        let mut post_order = FxIndexSet::default();
+        {
+            let mut visited = FxHashSet::default();
+            let mut stack: SmallVec<[_; 8]> = exits
+                .iter()
+                .rev()
+                .filter(|&&exit| visited.insert(exit))
+                .map(|&exit| (exit, 0))
+                .collect();
+            while let Some((region, next_pred_idx)) = stack.pop() {
+                let preds = cfg_preds.get(&region).map_or(&[][..], |preds| &preds[..]);
+                match preds.get(next_pred_idx) {
+                    Some(&pred) => {
+                        stack.push((region, next_pred_idx + 1));
+                        if visited.insert(pred) {
+                            stack.push((pred, 0));
+                        }
+                    }
+                    None => {
+                        post_order.insert(region);
+                    }
+                }
+            }
+        }
+        let rev_post_order: FxIndexSet<_> = post_order.into_iter().rev().collect();
+
+        // NOTE(eddyb) index `0` is reserved for the "virtual root" (see `DomTree::compute`),
+        // and here it acts as the single exit all the actual exits lead to.
+        let mut preds = vec![SmallVec::new(); rev_post_order.len() + 1];
+        for &exit in &exits {
+            preds[rev_post_order.get_index_of(&exit).unwrap() + 1].push(0);
+        }
+        for (i, &region) in rev_post_order.iter().enumerate() {
+            for pred in cfg_preds.get(&region).into_iter().flatten() {
+                preds[rev_post_order.get_index_of(pred).unwrap() + 1].push(i + 1);
+            }
+        }
+
+        DomTree::compute(rev_post_order, &preds)
+    }
+}
+
+/// Dominator tree (or post-dominator tree) of a [`ControlFlowGraph`], i.e. the
+/// immediate dominator of each [`ControlRegion`] in the CFG (see also
+/// [`ControlFlowGraph::dom_tree`] and [`ControlFlowGraph::post_dom_tree`]).
+///
+/// A region `A` "dominates" a region `B` if all paths from the function entry
+/// to `B` go through `A`, and "post-dominates" `B` if all paths from `B` to
+/// any function exit go through `A` (with every region (post-)dominating itself),
+/// while the "immediate" (post-)dominator of `B` is the closest such `A != B`.
+pub struct DomTree {
+    /// All the regions in the tree, each mapped to the index (in this same map)
+    /// of its immediate dominator (or `None` for the root(s) of the tree).
+    ///
+    /// The order is a reverse post-order (of the reversed CFG, in the case of
+    /// post-dominators), guaranteeing that a region's immediate dominator comes
+    /// before it (i.e. it has a smaller index than the region itself).
+    regions: FxIndexMap<ControlRegion, Option<usize>>,
+}
+
+impl DomTree {
+    /// Compute the dominator tree of a graph, with the nodes in `rev_post_order`,
+    /// and the predecessors of each node in `preds`, where indices are offset
+    /// by `1` from those in `rev_post_order`, to make room for a "virtual root"
+    /// (at index `0`), which must be the only predecessor of the actual root(s).
+    ///
+    /// This uses the iterative algorithm from "A Simple, Fast Dominance Algorithm"
+    /// (by Keith D. Cooper, Timothy J. Harvey, and Ken Kennedy).
+    pub(crate) fn compute(
+        rev_post_order: FxIndexSet<ControlRegion>,
+        preds: &[SmallVec<[usize; 4]>],
+    ) -> Self {
+        let mut idoms = vec![None; rev_post_order.len() + 1];
+        idoms[0] = Some(0);
+
+        let intersect = |idoms: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while a > b {
+                    a = idoms[a].unwrap();
+                }
+                while b > a {
+                    b = idoms[b].unwrap();
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 1..idoms.len() {
+                let new_idom = preds[i]
+                    .iter()
+                    .copied()
+                    .filter(|&pred| idoms[pred].is_some())
+                    .reduce(|new_idom, pred| intersect(&idoms, new_idom, pred));
+                if idoms[i] != new_idom {
+                    idoms[i] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        Self {
+            regions: rev_post_order
+                .into_iter()
+                .zip(idoms.into_iter().skip(1))
+                // NOTE(eddyb) this undoes the offset from the "virtual root",
+                // which itself gets replaced with `None`.
+                .map(|(region, idom)| (region, idom.unwrap().checked_sub(1)))
+                .collect(),
+        }
+    }
+
+    /// Iterate over all the [`ControlRegion`]s in the tree, in an order that
+    /// guarantees that every region comes after all of its (post-)dominators.
+    pub fn regions(&self) -> impl DoubleEndedIterator<Item = ControlRegion> + '_ {
+        self.regions.keys().copied()
+    }
+
+    /// Whether `region` is part of the tree (i.e. it's reachable from the entry,
+    /// and, in the case of post-dominators, can also reach a function exit).
+    pub fn contains(&self, region: ControlRegion) -> bool {
+        self.regions.contains_key(&region)
+    }
+
+    /// Get the immediate (post-)dominator of `region`, if it has one (i.e. it's
+    /// part of the tree, but isn't a root of it).
+    pub fn idom(&self, region: ControlRegion) -> Option<ControlRegion> {
+        let idom = (*self.regions.get(&region)?)?;
+        Some(*self.regions.get_index(idom).unwrap().0)
+    }
+
+    /// Whether `a` (post-)dominates `b` (which is always the case if `a == b`,
+    /// as long as they're part of the tree).
+    pub fn dominates(&self, a: ControlRegion, b: ControlRegion) -> bool {
+        let (Some(a), Some(mut b)) = (self.regions.get_index_of(&a), self.regions.get_index_of(&b))
+        else {
+            return false;
+        };
+        while b > a {
+            match self.regions[b] {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+        a == b
+    }
+}
+
+/// A place in a function where [`Value`]s can be used (see [`StructuredDominance`]).
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum UseSite {
+    /// The `inputs` of a [`DataInst`].
+    DataInst(DataInst),
+
+    /// The inputs of a [`ControlNode`] (i.e. a `Select`'s `scrutinee`, or a
+    /// `Loop`'s `initial_inputs`), which are used just before entering it.
+    ControlNode(ControlNode),
+
+    /// The end of a [`ControlRegion`], i.e. its `outputs`, but also a `Loop`'s
+    /// `repeat_condition` (for the `Loop`'s `body`), or the [`ControlInst`]
+    /// leaving the region (for regions in a [`ControlFlowGraph`]).
+    RegionEnd(ControlRegion),
+}
+
+/// Dominance information for the [`Value`]s defined in a [`FuncDefBody`],
+/// following the structured control-flow rules (documented on [`ControlRegion`]),
+/// and the CFG [`DomTree`] for values defined in one of the [`ControlRegion`]s
+/// in a [`ControlFlowGraph`] (i.e. in functions with unstructured control-flow),
+/// but used in another one.
+pub struct StructuredDominance {
+    /// The parent `ControlNode` of each `ControlRegion` (other than the body,
+    /// or the regions in the CFG), and whether that region is its only child
+    /// (i.e. a `Loop` body, or the case of a single-case `Select`).
+    region_parent: EntityOrientedDenseMap<ControlRegion, (ControlNode, bool)>,
+
+    /// The parent `ControlRegion` of each `ControlNode`, and its position in it.
+    node_parent: EntityOrientedDenseMap<ControlNode, (ControlRegion, usize)>,
+
+    /// The parent `ControlNode` (`Block`) of each `DataInst`, and its position in it.
+    inst_parent: EntityOrientedDenseMap<DataInst, (ControlNode, usize)>,
+
+    cfg_dom_tree: Option<DomTree>,
+}
+
+impl StructuredDominance {
+    pub fn new(func_def_body: &FuncDefBody) -> Self {
+        let mut dominance = Self {
+            region_parent: EntityOrientedDenseMap::new(),
+            node_parent: EntityOrientedDenseMap::new(),
+            inst_parent: EntityOrientedDenseMap::new(),
+            cfg_dom_tree: None,
+        };
+        match &func_def_body.unstructured_cfg {
+            None => dominance.add_region(func_def_body, func_def_body.body),
+            Some(cfg) => {
+                for region in cfg.rev_post_order(func_def_body) {
+                    dominance.add_region(func_def_body, region);
+                }
+                dominance.cfg_dom_tree = Some(cfg.dom_tree(func_def_body));
+            }
+        }
+        dominance
+    }
+
+    fn add_region(&mut self, func_def_body: &FuncDefBody, region: ControlRegion) {
+        for (node_idx, func_at_node) in
+            func_def_body.at(region).at_children().into_iter().enumerate()
+        {
+            let node = func_at_node.position;
+            self.node_parent.insert(node, (region, node_idx));
+            match &func_at_node.def().kind {
+                &ControlNodeKind::Block { insts } => {
+                    for (inst_idx, func_at_inst) in func_def_body.at(insts).into_iter().enumerate()
+                    {
+                        self.inst_parent.insert(func_at_inst.position, (node, inst_idx));
+                    }
+                }
+                ControlNodeKind::Select { cases, .. } => {
+                    for &case in cases {
+                        self.region_parent.insert(case, (node, cases.len() == 1));
+                        self.add_region(func_def_body, case);
+                    }
+                }
+                &ControlNodeKind::Loop { body, .. } => {
+                    self.region_parent.insert(body, (node, true));
+                    self.add_region(func_def_body, body);
+                }
+            }
+        }
+    }
+
+    /// Whether the definition of `v` dominates `use_site` (i.e. whether `v`
+    /// can be used there), assuming both are part of the function.
+    pub fn value_dominates(&self, v: Value, use_site: UseSite) -> bool {
+        // All the regions `use_site` is (transitively) nested in, from the
+        // innermost one outwards, alongside the position of `use_site` in each
+        // (i.e. that of the `ControlNode` containing it, or `usize::MAX` for
+        // the end of the region).
+        let mut use_ancestors = SmallVec::<[_; 8]>::new();
+        {
+            let (mut region, mut pos) = match use_site {
+                UseSite::DataInst(inst) => self.node_parent[self.inst_parent[inst].0],
+                UseSite::ControlNode(node) => self.node_parent[node],
+                UseSite::RegionEnd(region) => (region, usize::MAX),
+            };
+            loop {
+                use_ancestors.push((region, pos));
+                match self.region_parent.get(region) {
+                    Some(&(parent_node, _)) => (region, pos) = self.node_parent[parent_node],
+                    None => break,
+                }
+            }
+        }
+
+        // The region `v` is defined in, alongside the position after which
+        // it's available (or `None` if it's available from the start).
+        let (mut def_region, mut def_pos) = match v {
+            Value::Const(_) => return true,
+            Value::ControlRegionInput { region, .. } => (region, None),
+            Value::ControlNodeOutput { control_node, .. } => {
+                let (region, pos) = self.node_parent[control_node];
+                (region, Some(pos))
+            }
+            Value::DataInstOutput(inst) => {
+                let (block, inst_pos) = self.inst_parent[inst];
+                if let UseSite::DataInst(use_inst) = use_site {
+                    let (use_block, use_inst_pos) = self.inst_parent[use_inst];
+                    if use_block == block {
+                        return inst_pos < use_inst_pos;
+                    }
+                }
+                let (region, pos) = self.node_parent[block];
+                (region, Some(pos))
+            }
+        };
+
+        loop {
+            let available_at = |&(region, pos): &(ControlRegion, usize)| {
+                region == def_region && def_pos.is_none_or(|def_pos| def_pos < pos)
+            };
+            if use_ancestors.iter().any(available_at) {
+                return true;
+            }
+
+            // Values defined in the only child region of a `ControlNode` are
+            // also available after that `ControlNode`.
+            match self.region_parent.get(def_region) {
+                Some(&(parent_node, true)) => {
+                    let (region, pos) = self.node_parent[parent_node];
+                    (def_region, def_pos) = (region, Some(pos));
+                }
+                Some(&(_, false)) => return false,
+                None => break,
+            }
+        }
+
+        // Only left is the case of `v` being available by the end of one of the
+        // regions in the CFG, and `use_site` being in another one of them.
+        let &(use_cfg_region, _) = use_ancestors.last().unwrap();
+        self.cfg_dom_tree.as_ref().is_some_and(|dom_tree| {
+            use_cfg_region != def_region && dom_tree.dominates(def_region, use_cfg_region)
+        })
+    }
+}
+
+/// Loop nesting information for a [`FuncDefBody`], covering both loops in its
+/// [`ControlFlowGraph`] (i.e. cycles, see [`CfgLoop`]), and structured loops
+/// (i.e. [`ControlNodeKind::Loop`], see [`StructuredLoop`]).
+pub struct LoopInfo {
+    /// All the loops in the CFG (if any), keyed by their header, and in an
+    /// order where outer loops always come before the loops nested in them.
+    cfg_loops: FxIndexMap<ControlRegion, CfgLoop>,
+
+    /// The header of the innermost CFG loop each CFG region is part of.
+    innermost_cfg_loop: EntityOrientedDenseMap<ControlRegion, ControlRegion>,
+
+    /// All the structured loops, in an order where outer loops always come
+    /// before the loops nested in them.
+    structured_loops: FxIndexMap<ControlNode, StructuredLoop>,
+}
+
+/// Loop (i.e. cycle) in a [`ControlFlowGraph`], with a single entry (its header).
+///
+/// Loops are found the same way [`Structurizer`] finds them, i.e. "minimal loops"
+/// (see also [`ControlFlowGraph::loop_merge_to_loop_header`]).
+pub struct CfgLoop {
+    /// The only region in the loop which can be entered from outside of it.
+    pub header: ControlRegion,
+
+    /// The header of the loop this loop is nested in (if any).
+    pub parent: Option<ControlRegion>,
+
+    /// How many loops (including itself) this loop is nested in (i.e. `1` for
+    /// loops which aren't nested in any other loop).
+    pub depth: u32,
+
+    /// All the regions in the loop (including `header`, and any nested loops),
+    /// in reverse post-order.
+    pub body: FxIndexSet<ControlRegion>,
+
+    /// All the regions in `body` with a backedge to `header`.
+    pub latches: FxIndexSet<ControlRegion>,
+
+    /// All the regions outside `body` targeted by any region in `body`.
+    pub exits: FxIndexSet<ControlRegion>,
+}
+
+/// Structured loop, i.e. a [`ControlNodeKind::Loop`] [`ControlNode`].
+pub struct StructuredLoop {
+    pub loop_node: ControlNode,
+
+    /// The structured loop this loop is nested in (if any).
+    ///
+    /// Note that loops in a [`ControlFlowGraph`] aren't taken into account here,
+    /// even when a structured loop is part of one (see also [`LoopInfo::cfg_loops`]).
+    pub parent: Option<ControlNode>,
+
+    /// How many structured loops (including itself) this loop is nested in
+    /// (i.e. `1` for loops which aren't nested in any other structured loop).
+    pub depth: u32,
+
+    pub body: ControlRegion,
+
+    /// The values carried across the loop iterations, one per `body.inputs`.
+    pub body_inputs: SmallVec<[LoopBodyInput; 2]>,
+}
+
+/// One of the `inputs` of a [`StructuredLoop`]'s `body`, with the values it
+/// takes in the first iteration, and in every subsequent one.
+#[derive(Copy, Clone)]
+pub struct LoopBodyInput {
+    /// The value of the input itself (i.e. [`Value::ControlRegionInput`]).
+    pub value: Value,
+
+    /// The value in the first iteration (from the `Loop`'s `initial_inputs`).
+    pub initial: Value,
+
+    /// The value in the next iteration (from the `body`'s `outputs`).
+    pub next: Value,
+}
+
+impl LoopBodyInput {
+    /// Whether this input has the same value in every iteration, i.e. it's
+    /// always passed along unchanged into the next iteration.
+    pub fn is_loop_invariant(&self) -> bool {
+        self.next == self.value
+    }
+}
+
+impl LoopInfo {
+    pub fn new(func_def_body: &FuncDefBody) -> Self {
+        let mut loop_info = Self {
+            cfg_loops: FxIndexMap::default(),
+            innermost_cfg_loop: EntityOrientedDenseMap::new(),
+            structured_loops: FxIndexMap::default(),
+        };
+
+        match &func_def_body.unstructured_cfg {
+            None => loop_info.add_structured_loops_in(func_def_body, func_def_body.body, None),
+            Some(cfg) => {
+                let mut loop_finder = LoopFinder::new(cfg);
+                loop_finder.find_earliest_scc_root_of(func_def_body.body);
+
+                for (header, exits) in loop_finder.loop_header_to_exit_targets {
+                    let parent = loop_finder.loop_header_to_parent_loop_header[&header];
+                    let depth = parent.map_or(1, |parent| loop_info.cfg_loops[&parent].depth + 1);
+                    loop_info.cfg_loops.insert(
+                        header,
+                        CfgLoop {
+                            header,
+                            parent,
+                            depth,
+                            body: FxIndexSet::default(),
+                            latches: FxIndexSet::default(),
+                            exits,
+                        },
+                    );
+                }
+                loop_info.innermost_cfg_loop = loop_finder.innermost_loop_header;
+
+                for region in cfg.rev_post_order(func_def_body) {
+                    let targets = &cfg.control_inst_on_exit_from[region].targets;
+                    let mut next_loop = loop_info.innermost_cfg_loop.get(region).copied();
+                    while let Some(header) = next_loop {
+                        let cfg_loop = &mut loop_info.cfg_loops[&header];
+                        cfg_loop.body.insert(region);
+                        if targets.contains(&header) {
+                            cfg_loop.latches.insert(region);
+                        }
+                        next_loop = cfg_loop.parent;
+                    }
+
+                    loop_info.add_structured_loops_in(func_def_body, region, None);
+                }
+            }
+        }
+
+        loop_info
+    }
+
+    fn add_structured_loops_in(
+        &mut self,
+        func_def_body: &FuncDefBody,
+        region: ControlRegion,
+        parent: Option<ControlNode>,
+    ) {
+        for func_at_node in func_def_body.at(region).at_children() {
+            match &func_at_node.def().kind {
+                ControlNodeKind::Block { .. } => {}
+                ControlNodeKind::Select { cases, .. } => {
+                    for &case in cases {
+                        self.add_structured_loops_in(func_def_body, case, parent);
+                    }
+                }
+                ControlNodeKind::Loop { initial_inputs, body, .. } => {
+                    let loop_node = func_at_node.position;
+                    let depth = parent.map_or(1, |parent| self.structured_loops[&parent].depth + 1);
+                    let body_outputs = &func_def_body.at(*body).def().outputs;
+                    let body_inputs = initial_inputs
+                        .iter()
+                        .zip_eq(body_outputs)
+                        .enumerate()
+                        .map(|(input_idx, (&initial, &next))| LoopBodyInput {
+                            value: Value::ControlRegionInput {
+                                region: *body,
+                                input_idx: input_idx.try_into().unwrap(),
+                            },
+                            initial,
+                            next,
+                        })
+                        .collect();
+                    self.structured_loops.insert(
+                        loop_node,
+                        StructuredLoop { loop_node, parent, depth, body: *body, body_inputs },
+                    );
+                    self.add_structured_loops_in(func_def_body, *body, Some(loop_node));
+                }
+            }
+        }
+    }
+
+    /// Iterate over all the loops in the CFG (if any), with outer loops always
+    /// coming before the loops nested in them.
+    pub fn cfg_loops(&self) -> impl ExactSizeIterator<Item = &CfgLoop> {
+        self.cfg_loops.values()
+    }
+
+    /// Get the CFG loop with the header `header`, if `header` is a loop header.
+    pub fn cfg_loop(&self, header: ControlRegion) -> Option<&CfgLoop> {
+        self.cfg_loops.get(&header)
+    }
+
+    /// Get the innermost CFG loop `region` is part of (if any).
+    pub fn innermost_cfg_loop_of(&self, region: ControlRegion) -> Option<&CfgLoop> {
+        Some(&self.cfg_loops[self.innermost_cfg_loop.get(region)?])
+    }
+
+    /// Iterate over all the structured loops, with outer loops always coming
+    /// before the loops nested in them.
+    pub fn structured_loops(&self) -> impl ExactSizeIterator<Item = &StructuredLoop> {
+        self.structured_loops.values()
+    }
+
+    /// Get the structured loop for `loop_node`, if it's a `ControlNodeKind::Loop`.
+    pub fn structured_loop(&self, loop_node: ControlNode) -> Option<&StructuredLoop> {
+        self.structured_loops.get(&loop_node)
+    }
+}
+
 // HACK(eddyb) this only serves to disallow accessing `private_count` field of
 // `IncomingEdgeCount`.
 mod sealed {
@@ -219,6 +771,12 @@ struct LoopFinder<'a> {
     // FIXME(eddyb) this feels a bit inefficient (are many-exit loops rare?).
     loop_header_to_exit_targets: FxIndexMap<ControlRegion, FxIndexSet<ControlRegion>>,
 
+    /// The header of the loop each loop (header) is nested in (if any).
+    loop_header_to_parent_loop_header: FxIndexMap<ControlRegion, Option<ControlRegion>>,
+
+    /// The header of the innermost loop each CFG node is part of (if any).
+    innermost_loop_header: EntityOrientedDenseMap<ControlRegion, ControlRegion>,
+
     /// SCC accumulation stack, where CFG nodes collect during the depth-first
     /// traversal, and are only popped when their "SCC root" (loop header) is
     /// (note that multiple SCCs on the stack does *not* indicate SCC nesting,
@@ -248,6 +806,8 @@ impl<'a> LoopFinder<'a> {
         Self {
             cfg,
             loop_header_to_exit_targets: FxIndexMap::default(),
+            loop_header_to_parent_loop_header: FxIndexMap::default(),
+            innermost_loop_header: EntityOrientedDenseMap::new(),
             scc_stack: vec![],
             scc_state: EntityOrientedDenseMap::new(),
         }
@@ -325,6 +885,15 @@ impl<'a> LoopFinder<'a> {
                     .collect(),
             );
 
+            // NOTE(eddyb) outer loops are always completed before any loops
+            // nested in them are found (see below), so the nodes of this SCC
+            // can only have been (so far) associated with an outer loop.
+            self.loop_header_to_parent_loop_header
+                .insert(node, self.innermost_loop_header.get(node).copied());
+            for &scc_node in &self.scc_stack[scc_start..] {
+                self.innermost_loop_header.insert(scc_node, node);
+            }
+
             // Find nested loops by marking *only* the loop header as complete,
             // clearing loop body nodes' state, and recursing on them: all the
             // nodes outside the loop (otherwise reachable from within), and the
@@ -357,6 +926,283 @@ impl<'a> LoopFinder<'a> {
     }
 }
 
+/// Changes made by [`ControlFlowGraph::make_reducible`] (see its docs).
+#[derive(Copy, Clone, Default)]
+pub struct ReducibilityChanges {
+    /// Number of "dispatch" regions introduced (one per multi-entry cycle).
+    pub dispatch_regions: usize,
+
+    /// Number of cycle entries (i.e. regions in multi-entry cycles, targeted by
+    /// edges from outside the cycle), now only entered through a dispatch region.
+    pub dispatched_entries: usize,
+}
+
+impl ControlFlowGraph {
+    /// Make the CFG of `func_def_body` (if any) reducible, by replacing every
+    /// cycle with multiple entries (i.e. irreducible control-flow, such as what
+    /// `goto`s can produce), with a single-entry cycle.
+    ///
+    /// The only entry of each such cycle is a newly introduced "dispatch" region,
+    /// which branches (via `OpSwitch`) to one of the original entries, based on
+    /// an extra input (the "dispatch variable"), with all edges targeting any
+    /// of the original entries redirected to the dispatch region instead (each
+    /// passing the index of its original target, and its original `target_inputs`).
+    ///
+    /// Unlike "node splitting", this never duplicates any code (at the cost of
+    /// the dynamic dispatch, i.e. the equivalent of a `loop`+`switch` state machine),
+    /// so the returned [`ReducibilityChanges`] only tracks dispatch regions.
+    pub fn make_reducible(cx: &Context, func_def_body: &mut FuncDefBody) -> ReducibilityChanges {
+        let mut changes = ReducibilityChanges::default();
+        if let Some(cfg) = &func_def_body.unstructured_cfg {
+            let regions = cfg.rev_post_order(func_def_body).collect();
+            Self::make_reducible_within(cx, func_def_body, &regions, &mut changes);
+        }
+        changes
+    }
+
+    /// Make all the cycles in the subgraph of `regions` single-entry (see also
+    /// `make_reducible`), recursing into each cycle (minus its entry).
+    fn make_reducible_within(
+        cx: &Context,
+        func_def_body: &mut FuncDefBody,
+        regions: &FxIndexSet<ControlRegion>,
+        changes: &mut ReducibilityChanges,
+    ) {
+        let cfg = func_def_body.unstructured_cfg.as_ref().unwrap();
+
+        let mut preds: FxIndexMap<_, FxIndexSet<_>> = FxIndexMap::default();
+        for region in cfg.rev_post_order(func_def_body) {
+            for &target in &cfg.control_inst_on_exit_from[region].targets {
+                preds.entry(target).or_default().insert(region);
+            }
+        }
+
+        let mut scc_finder =
+            SccFinder { cfg, regions, state: FxHashMap::default(), stack: vec![], sccs: vec![] };
+        for &region in regions {
+            scc_finder.visit(region);
+        }
+
+        for mut scc in scc_finder.sccs {
+            scc.sort_by_key(|region| regions.get_index_of(region).unwrap());
+            let scc: FxIndexSet<_> = scc.into_iter().collect();
+
+            let entries: SmallVec<[_; 4]> = scc
+                .iter()
+                .copied()
+                .filter(|&region| {
+                    region == func_def_body.body
+                        || preds.get(&region).into_iter().flatten().any(|pred| !scc.contains(pred))
+                })
+                .collect();
+
+            let header = match entries[..] {
+                [entry] => entry,
+                _ => {
+                    // NOTE(eddyb) the function body can't be one of several entries,
+                    // as anything that can reach it has to be reachable from it.
+                    assert!(!entries.contains(&func_def_body.body));
+
+                    let dispatch = Self::dispatch_to_entries(cx, func_def_body, &entries, &preds);
+                    changes.dispatch_regions += 1;
+                    changes.dispatched_entries += entries.len();
+                    dispatch
+                }
+            };
+
+            let mut nested_regions = scc;
+            nested_regions.shift_remove(&header);
+            Self::make_reducible_within(cx, func_def_body, &nested_regions, changes);
+        }
+    }
+
+    /// Introduce a new "dispatch" region, and redirect all the edges targeting
+    /// any of `entries` to it (see also `make_reducible`), returning it.
+    fn dispatch_to_entries(
+        cx: &Context,
+        func_def_body: &mut FuncDefBody,
+        entries: &[ControlRegion],
+        preds: &FxIndexMap<ControlRegion, FxIndexSet<ControlRegion>>,
+    ) -> ControlRegion {
+        let wk = &spv::spec::Spec::get().well_known;
+
+        let type_u32 = cx.intern(TypeKind::Scalar { kind: ScalarKind::UInt, width: 32 });
+        let const_u32 = |x: u32| {
+            cx.intern(ConstDef {
+                attrs: AttrSet::default(),
+                ty: type_u32,
+                kind: ConstKind::Scalar(x.into()),
+            })
+        };
+
+        // The inputs of the dispatch region are the dispatch variable, followed
+        // by the concatenation of the inputs of all the entries.
+        let mut dispatch_inputs: SmallVec<[_; 2]> =
+            [ControlRegionInputDecl { attrs: AttrSet::default(), ty: type_u32 }]
+                .into_iter()
+                .collect();
+        let mut entry_input_ranges = SmallVec::<[_; 4]>::new();
+        for &entry in entries {
+            let entry_inputs = &func_def_body.at(entry).def().inputs;
+            let start = dispatch_inputs.len();
+            dispatch_inputs.extend(
+                entry_inputs.iter().map(|input| ControlRegionInputDecl {
+                    attrs: AttrSet::default(),
+                    ty: input.ty,
+                }),
+            );
+            entry_input_ranges.push(start..dispatch_inputs.len());
+        }
+        let dispatch_input_types: SmallVec<[_; 2]> =
+            dispatch_inputs.iter().map(|input| input.ty).collect();
+
+        let dispatch = func_def_body
+            .control_regions
+            .define(cx, ControlRegionDef { inputs: dispatch_inputs, ..Default::default() });
+        let dispatch_input = |input_idx: usize| Value::ControlRegionInput {
+            region: dispatch,
+            input_idx: input_idx.try_into().unwrap(),
+        };
+
+        let cfg = func_def_body.unstructured_cfg.as_mut().unwrap();
+
+        // The first entry is the `OpSwitch` default, the rest have their own cases.
+        let switch_inst = spv::Inst {
+            opcode: wk.OpSwitch,
+            imms: (1..entries.len())
+                .map(|i| spv::Imm::Short(wk.LiteralContextDependentNumber, i.try_into().unwrap()))
+                .collect(),
+        };
+        cfg.control_inst_on_exit_from.insert(
+            dispatch,
+            ControlInst {
+                attrs: AttrSet::default(),
+                kind: ControlInstKind::SelectBranch(SelectionKind::SpvInst(switch_inst)),
+                inputs: [dispatch_input(0)].into_iter().collect(),
+                targets: entries.iter().copied().collect(),
+                target_inputs: entries
+                    .iter()
+                    .zip(&entry_input_ranges)
+                    .filter(|(_, input_range)| !input_range.is_empty())
+                    .map(|(&entry, input_range)| {
+                        (entry, input_range.clone().map(dispatch_input).collect())
+                    })
+                    .collect(),
+            },
+        );
+
+        for (entry_idx, &entry) in entries.iter().enumerate() {
+            for &pred in preds.get(&entry).into_iter().flatten() {
+                // Each edge is redirected through its own (empty) region, as
+                // the `target_inputs` for the dispatch region differ per-edge.
+                let forward = func_def_body.control_regions.define(cx, ControlRegionDef::default());
+
+                let pred_control_inst = &mut cfg.control_inst_on_exit_from[pred];
+                for target in &mut pred_control_inst.targets {
+                    if *target == entry {
+                        *target = forward;
+                    }
+                }
+                let mut original_target_inputs =
+                    pred_control_inst.target_inputs.shift_remove(&entry);
+
+                let forward_target_inputs =
+                    [Value::Const(const_u32(entry_idx.try_into().unwrap()))]
+                        .into_iter()
+                        .chain(entry_input_ranges.iter().enumerate().flat_map(
+                            |(other_entry_idx, input_range)| {
+                                let values = if other_entry_idx == entry_idx {
+                                    original_target_inputs.take().unwrap_or_default()
+                                } else {
+                                    SmallVec::new()
+                                };
+                                let undefs = dispatch_input_types[input_range.clone()]
+                                    .iter()
+                                    .skip(values.len())
+                                    .map(|&ty| {
+                                        Value::Const(cx.intern(ConstDef {
+                                            attrs: AttrSet::default(),
+                                            ty,
+                                            kind: ConstKind::Undef,
+                                        }))
+                                    });
+                                values.into_iter().chain(undefs)
+                            },
+                        ))
+                        .collect();
+                cfg.control_inst_on_exit_from.insert(
+                    forward,
+                    ControlInst {
+                        attrs: AttrSet::default(),
+                        kind: ControlInstKind::Branch,
+                        inputs: [].into_iter().collect(),
+                        targets: [dispatch].into_iter().collect(),
+                        target_inputs: [(dispatch, forward_target_inputs)].into_iter().collect(),
+                    },
+                );
+            }
+        }
+
+        dispatch
+    }
+}
+
+/// Tarjan's SCC algorithm, limited to the subgraph of `regions` (i.e. ignoring
+/// any edges leaving it), and only keeping SCCs which contain cycles.
+//
+// FIXME(eddyb) consider unifying with `LoopFinder` (which is more specialized).
+struct SccFinder<'a> {
+    cfg: &'a ControlFlowGraph,
+    regions: &'a FxIndexSet<ControlRegion>,
+
+    /// Per-region traversal state, i.e. `Some((index, lowlink))` while the
+    /// region is on `stack`, and `None` once it was added to an SCC.
+    state: FxHashMap<ControlRegion, Option<(usize, usize)>>,
+    stack: Vec<ControlRegion>,
+
+    sccs: Vec<Vec<ControlRegion>>,
+}
+
+impl SccFinder<'_> {
+    /// Visit `region` (if not already visited), returning its "lowlink" (if
+    /// it's still on the stack, i.e. not yet part of a completed SCC).
+    fn visit(&mut self, region: ControlRegion) -> Option<usize> {
+        if let Some(&state) = self.state.get(&region) {
+            return state.map(|(_, lowlink)| lowlink);
+        }
+
+        let index = self.stack.len();
+        let mut lowlink = index;
+        self.state.insert(region, Some((index, lowlink)));
+        self.stack.push(region);
+
+        let mut has_self_loop = false;
+        for &target in &self.cfg.control_inst_on_exit_from[region].targets {
+            if !self.regions.contains(&target) {
+                continue;
+            }
+            has_self_loop |= target == region;
+            if let Some(target_lowlink) = self.visit(target) {
+                lowlink = lowlink.min(target_lowlink);
+            }
+        }
+        self.state.insert(region, Some((index, lowlink)));
+
+        if lowlink == index {
+            let scc = self.stack.split_off(index);
+            for &scc_region in &scc {
+                self.state.insert(scc_region, None);
+            }
+            if scc.len() > 1 || has_self_loop {
+                self.sccs.push(scc);
+            }
+            return None;
+        }
+
+        Some(lowlink)
+    }
+}
+
 #[allow(rustdoc::private_intra_doc_links)]
 /// Control-flow "structurizer", which attempts to convert as much of the CFG
 /// as possible into structural control-flow (regions).
@@ -397,6 +1243,11 @@ pub struct Structurizer<'a> {
 
     func_def_body: &'a mut FuncDefBody,
 
+    /// The function's return type (i.e. `ret_type` of the `FuncDecl` owning
+    /// the `FuncDefBody`), used when the whole body is divergent (i.e. never
+    /// returns), to still provide a (structured) return value (as undef).
+    ret_type: Type,
+
     // FIXME(eddyb) this feels a bit inefficient (are many-exit loops rare?).
     loop_header_to_exit_targets: FxIndexMap<ControlRegion, FxIndexSet<ControlRegion>>,
 
@@ -592,32 +1443,19 @@ struct PartialControlRegion {
 }
 
 impl<'a> Structurizer<'a> {
-    pub fn new(cx: &'a Context, func_def_body: &'a mut FuncDefBody) -> Self {
-        // FIXME(eddyb) SPIR-T should have native booleans itself.
-        let wk = &spv::spec::Spec::get().well_known;
-        let type_bool = cx.intern(TypeKind::SpvInst {
-            spv_inst: wk.OpTypeBool.into(),
-            type_and_const_inputs: [].into_iter().collect(),
-        });
+    /// Prepare to structurize `func_def_body`, whose function has `ret_type`
+    /// as its return type (i.e. `ret_type` of the `FuncDecl` owning it).
+    pub fn new(cx: &'a Context, func_def_body: &'a mut FuncDefBody, ret_type: Type) -> Self {
+        let type_bool = cx.intern(TypeKind::Scalar { kind: ScalarKind::Bool, width: 1 });
         let const_true = cx.intern(ConstDef {
             attrs: AttrSet::default(),
             ty: type_bool,
-            kind: ConstKind::SpvInst {
-                spv_inst_and_const_inputs: Rc::new((
-                    wk.OpConstantTrue.into(),
-                    [].into_iter().collect(),
-                )),
-            },
+            kind: ConstKind::Scalar(1),
         });
         let const_false = cx.intern(ConstDef {
             attrs: AttrSet::default(),
             ty: type_bool,
-            kind: ConstKind::SpvInst {
-                spv_inst_and_const_inputs: Rc::new((
-                    wk.OpConstantFalse.into(),
-                    [].into_iter().collect(),
-                )),
-            },
+            kind: ConstKind::Scalar(0),
         });
 
         let (loop_header_to_exit_targets, incoming_edge_counts_including_loop_exits) =
@@ -625,12 +1463,30 @@ impl<'a> Structurizer<'a> {
                 .unstructured_cfg
                 .as_ref()
                 .map(|cfg| {
-                    let loop_header_to_exit_targets = {
+                    let mut loop_header_to_exit_targets = {
                         let mut loop_finder = LoopFinder::new(cfg);
                         loop_finder.find_earliest_scc_root_of(func_def_body.body);
                         loop_finder.loop_header_to_exit_targets
                     };
 
+                    // HACK(eddyb) loop exits which can only lead to returning
+                    // from the function (i.e. "early returns" from inside loops)
+                    // are *not* treated as "owned" by their loop header (see
+                    // below), but rather structurized inside the loop body,
+                    // where all the values they may use are still in scope,
+                    // and from where the structured return (`DeferredTarget::Return`)
+                    // can carry the return values out of the loop, through
+                    // region `outputs` (and with a "returned" condition).
+                    // This doesn't contradict the "minimal loops" approach
+                    // of `LoopFinder`, as the invocations which return from
+                    // inside the loop can't observe any reconvergence after it.
+                    let mut only_returns_cache = EntityOrientedDenseMap::new();
+                    for exit_targets in loop_header_to_exit_targets.values_mut() {
+                        exit_targets.retain(|&exit_target| {
+                            !Self::only_leads_to_return(cfg, exit_target, &mut only_returns_cache)
+                        });
+                    }
+
                     let mut state = TraversalState {
                         incoming_edge_counts: EntityOrientedDenseMap::new(),
 
@@ -665,6 +1521,8 @@ impl<'a> Structurizer<'a> {
 
             func_def_body,
 
+            ret_type,
+
             loop_header_to_exit_targets,
             incoming_edge_counts_including_loop_exits,
 
@@ -682,23 +1540,38 @@ impl<'a> Structurizer<'a> {
         let mut body_region =
             self.claim_or_defer_single_edge(self.func_def_body.body, SmallVec::new());
 
-        if body_region.deferred_edges.target_to_deferred.len() == 1 {
-            // Structured return, the function is fully structurized.
-            //
-            // FIXME(eddyb) also support structured return when the whole body
-            // is divergent, by generating undef constants (needs access to the
-            // whole `FuncDecl`, not just `FuncDefBody`, to get the right types).
-            if let Some(deferred_return) =
-                body_region.deferred_edges.target_to_deferred.swap_remove(&DeferredTarget::Return)
-            {
-                assert!(body_region.structured_body_holder == Some(self.func_def_body.body));
-                let body_def = self.func_def_body.at_mut_body().def();
-                body_def.outputs = deferred_return.edge_bundle.target_inputs;
-                self.func_def_body.unstructured_cfg = None;
-
-                self.apply_value_replacements();
-                return;
+        // Structured return, the function is fully structurized.
+        let return_values = match body_region.deferred_edges.target_to_deferred.len() {
+            // The whole body is divergent, but it still needs `outputs` (if
+            // the function doesn't return `void`), which can be undef.
+            0 => {
+                let wk = &spv::spec::Spec::get().well_known;
+                let returns_void = matches!(
+                    &self.cx[self.ret_type].kind,
+                    TypeKind::SpvInst { spv_inst, .. } if spv_inst.opcode == wk.OpTypeVoid
+                );
+                Some(if returns_void {
+                    [].into_iter().collect()
+                } else {
+                    [Value::Const(self.const_undef(self.ret_type))].into_iter().collect()
+                })
             }
+            1 => body_region
+                .deferred_edges
+                .target_to_deferred
+                .swap_remove(&DeferredTarget::Return)
+                .map(|deferred_return| deferred_return.edge_bundle.target_inputs),
+            _ => None,
+        };
+        if let Some(return_values) = return_values {
+            assert!(body_region.structured_body_holder == Some(self.func_def_body.body));
+            let body_def = self.func_def_body.at_mut_body().def();
+            body_def.outputs = return_values;
+            self.func_def_body.unstructured_cfg = None;
+
+            self.remove_empty_selects_in(self.func_def_body.body);
+            self.apply_value_replacements();
+            return;
         }
 
         // Repair all the regions that remain unclaimed, including the body.
@@ -724,21 +1597,85 @@ impl<'a> Structurizer<'a> {
             }
         }
 
+        let unstructured_regions: SmallVec<[_; 8]> = self
+            .func_def_body
+            .unstructured_cfg
+            .as_ref()
+            .unwrap()
+            .rev_post_order(self.func_def_body)
+            .collect();
+        for region in unstructured_regions {
+            self.remove_empty_selects_in(region);
+        }
+
         self.apply_value_replacements();
     }
 
-    /// The last step of structurization is processing bulk replacements
-    /// collected while structurizing (like `control_region_input_replacements`).
-    fn apply_value_replacements(self) {
-        // FIXME(eddyb) maybe this should be provided by `transform`.
-        use crate::transform::*;
-        struct ReplaceValueWith<F>(F);
-        impl<F: Fn(Value) -> Option<Value>> Transformer for ReplaceValueWith<F> {
-            fn transform_value_use(&mut self, v: &Value) -> Transformed<Value> {
-                self.0(*v).map_or(Transformed::Unchanged, Transformed::Changed)
+    /// Remove all `Select`s (nested anywhere in `region`) which have neither
+    /// any outputs, nor any `ControlNode`s in their cases, i.e. those that only
+    /// existed to provide conditions (for `LazyCond::MergeSelect`), which didn't
+    /// end up needing any `Select` outputs (e.g. a `BoolCond` scrutinee reused).
+    fn remove_empty_selects_in(&mut self, region: ControlRegion) {
+        let mut next_node = self.func_def_body.at(region).def().children.iter().first;
+        while let Some(node) = next_node {
+            next_node = self.func_def_body.control_nodes[node].next_in_list();
+
+            let child_regions = match &self.func_def_body.control_nodes[node].kind {
+                ControlNodeKind::Block { .. } => continue,
+                ControlNodeKind::Select { cases, .. } => cases.clone(),
+                &ControlNodeKind::Loop { body, .. } => [body].into_iter().collect(),
+            };
+            for &child_region in &child_regions {
+                self.remove_empty_selects_in(child_region);
+            }
+
+            let node_def = &self.func_def_body.control_nodes[node];
+            let is_empty_select = matches!(node_def.kind, ControlNodeKind::Select { .. })
+                && node_def.outputs.is_empty()
+                && child_regions.iter().all(|&case| {
+                    let case_def = &self.func_def_body.control_regions[case];
+                    case_def.children.is_empty() && case_def.outputs.is_empty()
+                });
+            if is_empty_select {
+                self.func_def_body.control_regions[region]
+                    .children
+                    .remove(node, &mut self.func_def_body.control_nodes);
             }
         }
+    }
+
+    /// Whether all paths starting at `region` lead to returning from the function
+    /// (i.e. there are no cycles, and every [`ControlInst`] along the way is either
+    /// a `Branch`/`SelectBranch`, or ends in a `Return`).
+    fn only_leads_to_return(
+        cfg: &ControlFlowGraph,
+        region: ControlRegion,
+        cache: &mut EntityOrientedDenseMap<ControlRegion, bool>,
+    ) -> bool {
+        if let Some(&cached) = cache.get(region) {
+            return cached;
+        }
+
+        // NOTE(eddyb) this also acts as a guard against cycles (which would
+        // otherwise cause infinite recursion), by treating them as `false`.
+        cache.insert(region, false);
+
+        let control_inst = &cfg.control_inst_on_exit_from[region];
+        let only_returns = match control_inst.kind {
+            ControlInstKind::Return => true,
+            ControlInstKind::Branch | ControlInstKind::SelectBranch(_) => control_inst
+                .targets
+                .iter()
+                .all(|&target| Self::only_leads_to_return(cfg, target, cache)),
+            ControlInstKind::Unreachable | ControlInstKind::ExitInvocation(_) => false,
+        };
+        cache.insert(region, only_returns);
+        only_returns
+    }
 
+    /// The last step of structurization is processing bulk replacements
+    /// collected while structurizing (like `control_region_input_replacements`).
+    fn apply_value_replacements(self) {
         self.func_def_body.inner_in_place_transform_with(&mut ReplaceValueWith(|v| match v {
             Value::ControlRegionInput { region, input_idx } => {
                 Some(self.control_region_input_replacements.get(region)?[input_idx as usize])
@@ -1209,21 +2146,42 @@ impl<'a> Structurizer<'a> {
             });
         }
 
+        // Cases without any `ControlNode`s of their own can only pass through
+        // values defined before the `Select` (i.e. as their `target_inputs`).
+        let case_is_empty: SmallVec<[_; 8]> = cases
+            .iter()
+            .map(|case| match case.structured_body_holder {
+                Some(region) => self.func_def_body.at(region).def().children.is_empty(),
+                None => true,
+            })
+            .collect();
+
         // Gather the full set of deferred edges (and returns), along with the
         // necessary information for the `Select`'s `ControlNodeOutputDecl`s.
         let mut deferred_edges_to_input_count_and_total_edge_count = FxIndexMap::default();
         let mut deferred_return_types = None;
-        for case in &cases {
+        for (case, &case_is_empty) in cases.iter().zip_eq(&case_is_empty) {
             for (&target, deferred) in &case.deferred_edges.target_to_deferred {
                 let input_count = deferred.edge_bundle.target_inputs.len();
 
-                let (old_input_count, accumulated_edge_count) =
-                    deferred_edges_to_input_count_and_total_edge_count
-                        .entry(target)
-                        .or_insert((input_count, IncomingEdgeCount::default()));
+                let (old_input_count, accumulated_edge_count, direct_target_inputs) =
+                    deferred_edges_to_input_count_and_total_edge_count.entry(target).or_insert((
+                        input_count,
+                        IncomingEdgeCount::default(),
+                        Some(deferred.edge_bundle.target_inputs.clone()),
+                    ));
                 assert_eq!(*old_input_count, input_count);
                 *accumulated_edge_count += deferred.edge_bundle.accumulated_count;
 
+                // The `target_inputs` can bypass the `Select` outputs entirely,
+                // if they're the same for all (empty) cases with this `target`
+                // (any other cases would've only used undef values, anyway).
+                if !case_is_empty
+                    || direct_target_inputs.as_ref() != Some(&deferred.edge_bundle.target_inputs)
+                {
+                    *direct_target_inputs = None;
+                }
+
                 if target == DeferredTarget::Return && deferred_return_types.is_none() {
                     // HACK(eddyb) because there's no `FuncDecl` available, take the
                     // types from the returned values and hope they match.
@@ -1242,25 +2200,39 @@ impl<'a> Structurizer<'a> {
         // each unique `deferred_edges` target.
         //
         // FIXME(eddyb) this `struct` only really exists for readability.
-        struct Deferred {
+        struct Deferred<'a> {
             target: DeferredTarget,
             target_input_count: usize,
 
             /// Sum of `accumulated_count` for this `target` across all `cases`.
             total_edge_count: IncomingEdgeCount,
+
+            /// `target_inputs` shared by all `cases` with this `target`, if
+            /// they don't need to go through the `Select` outputs at all.
+            direct_target_inputs: Option<&'a SmallVec<[Value; 2]>>,
         }
         let deferreds = || {
             deferred_edges_to_input_count_and_total_edge_count.iter().map(
-                |(&target, &(target_input_count, total_edge_count))| Deferred {
-                    target,
-                    target_input_count,
-                    total_edge_count,
+                |(&target, (target_input_count, total_edge_count, direct_target_inputs))| {
+                    Deferred {
+                        target,
+                        target_input_count: *target_input_count,
+                        total_edge_count: *total_edge_count,
+                        direct_target_inputs: direct_target_inputs.as_ref(),
+                    }
                 },
             )
         };
-        let mut output_decls: SmallVec<[_; 2]> =
-            SmallVec::with_capacity(deferreds().map(|deferred| deferred.target_input_count).sum());
+        let mut output_decls: SmallVec<[_; 2]> = SmallVec::with_capacity(
+            deferreds()
+                .filter(|deferred| deferred.direct_target_inputs.is_none())
+                .map(|deferred| deferred.target_input_count)
+                .sum(),
+        );
         for deferred in deferreds() {
+            if deferred.direct_target_inputs.is_some() {
+                continue;
+            }
             let target_input_types = match deferred.target {
                 DeferredTarget::Region(target) => {
                     Either::Left(self.func_def_body.at(target).def().inputs.iter().map(|i| i.ty))
@@ -1304,6 +2276,10 @@ impl<'a> Structurizer<'a> {
                         per_case_conditions.push(edge_condition);
                     }
 
+                    if deferred.direct_target_inputs.is_some() {
+                        continue;
+                    }
+
                     match values_or_count {
                         Ok(values) => outputs.extend(values),
                         Err(missing_value_count) => {
@@ -1343,7 +2319,9 @@ impl<'a> Structurizer<'a> {
             .map(|output_idx| Value::ControlNodeOutput { control_node: select_node, output_idx });
         let deferreds = deferreds().zip_eq(deferred_per_case_conditions).map(
             |(deferred, per_case_conditions)| {
-                let target_inputs = outputs.by_ref().take(deferred.target_input_count).collect();
+                let target_inputs = deferred.direct_target_inputs.cloned().unwrap_or_else(|| {
+                    outputs.by_ref().take(deferred.target_input_count).collect()
+                });
 
                 // Simplify `LazyCond`s eagerly, to reduce costs later on.
                 let condition =
@@ -1568,14 +2546,244 @@ impl<'a> Structurizer<'a> {
     /// Create an undefined constant (as a placeholder where a value needs to be
     /// present, but won't actually be used), of type `ty`.
     fn const_undef(&self, ty: Type) -> Const {
-        // FIXME(eddyb) SPIR-T should have native undef itself.
-        let wk = &spv::spec::Spec::get().well_known;
-        self.cx.intern(ConstDef {
-            attrs: AttrSet::default(),
-            ty,
-            kind: ConstKind::SpvInst {
-                spv_inst_and_const_inputs: Rc::new((wk.OpUndef.into(), [].into_iter().collect())),
-            },
-        })
+        self.cx.intern(ConstDef { attrs: AttrSet::default(), ty, kind: ConstKind::Undef })
+    }
+}
+
+/// Control-flow "destructurizer", the inverse of [`Structurizer`], which
+/// converts all structured control-flow (i.e. `Select`/`Loop` [`ControlNode`]s)
+/// into an unstructured [`ControlFlowGraph`], where every [`ControlRegion`]
+/// only contains `Block`s (and is akin to a SPIR-V "basic block").
+///
+/// The resulting CFG uses:
+/// * for `Select`: a `SelectBranch` to all the cases, which all `Branch` to
+///   a "merge" region, whose `inputs` replace the `Select`'s `outputs` (and
+///   which receive the `outputs` of each case, through `target_inputs`)
+/// * for `Loop`: a `Branch` to the `body` (passing `initial_inputs`), and a
+///   `SelectBranch` on `repeat_condition` at the end of the `body`, either back
+///   to the `body` (passing its `outputs`), or out to an "exit" region (which is
+///   also recorded in [`ControlFlowGraph::loop_merge_to_loop_header`])
+/// * for the function body: a `Return` (of its `outputs`) at its end
+pub struct Destructurizer<'a> {
+    cx: &'a Context,
+
+    func_def_body: &'a mut FuncDefBody,
+
+    /// Accumulated replacements, i.e.: `Value::ControlNodeOutput { control_node, output_idx }`
+    /// must be replaced with `Value::ControlRegionInput { region, input_idx: output_idx }`,
+    /// where `region` is `select_output_replacements[control_node]` (the "merge"
+    /// region of the `Select` `control_node`).
+    select_output_replacements: EntityOrientedDenseMap<ControlNode, ControlRegion>,
+}
+
+impl<'a> Destructurizer<'a> {
+    pub fn new(cx: &'a Context, func_def_body: &'a mut FuncDefBody) -> Self {
+        Self { cx, func_def_body, select_output_replacements: EntityOrientedDenseMap::new() }
+    }
+
+    pub fn destructurize_func(mut self) {
+        match self.func_def_body.unstructured_cfg {
+            None => {
+                self.func_def_body.unstructured_cfg = Some(ControlFlowGraph::default());
+
+                let body = self.func_def_body.body;
+                let return_values = mem::take(&mut self.func_def_body.at_mut(body).def().outputs);
+                let tail = self.destructurize_region(body);
+                self.set_control_inst_on_exit_from(
+                    tail,
+                    ControlInst {
+                        attrs: AttrSet::default(),
+                        kind: ControlInstKind::Return,
+                        inputs: return_values,
+                        targets: [].into_iter().collect(),
+                        target_inputs: FxIndexMap::default(),
+                    },
+                );
+            }
+
+            // Partially structured, so all the structured control-flow left
+            // is inside CFG regions (and only their `ControlInst`s need moving).
+            Some(ref cfg) => {
+                let regions: SmallVec<[_; 8]> = cfg.rev_post_order(self.func_def_body).collect();
+                for region in regions {
+                    let control_inst = self
+                        .func_def_body
+                        .unstructured_cfg
+                        .as_mut()
+                        .unwrap()
+                        .control_inst_on_exit_from
+                        .remove(region)
+                        .expect("cfg: missing `ControlInst` for CFG region");
+                    let tail = self.destructurize_region(region);
+                    self.set_control_inst_on_exit_from(tail, control_inst);
+                }
+            }
+        }
+
+        self.apply_value_replacements();
+    }
+
+    /// Destructurize `region` (ignoring its `outputs`, which the caller must
+    /// handle), which remains the entry of the resulting part of the CFG,
+    /// returning the region where control-flow ends up (i.e. the "tail"),
+    /// without any `ControlInst` attached to it.
+    fn destructurize_region(&mut self, region: ControlRegion) -> ControlRegion {
+        let children: SmallVec<[_; 8]> = self
+            .func_def_body
+            .at(region)
+            .at_children()
+            .into_iter()
+            .map(|func_at_node| func_at_node.position)
+            .collect();
+
+        let mut tail = region;
+        for node in children {
+            let kind = &self.func_def_body.at(node).def().kind;
+            if let ControlNodeKind::Block { .. } = kind {
+                // Move the `Block` to the current tail, if it's not `region`.
+                if tail != region {
+                    self.func_def_body.control_regions[region]
+                        .children
+                        .remove(node, &mut self.func_def_body.control_nodes);
+                    self.func_def_body.control_regions[tail]
+                        .children
+                        .insert_last(node, &mut self.func_def_body.control_nodes);
+                }
+                continue;
+            }
+
+            self.func_def_body.control_regions[region]
+                .children
+                .remove(node, &mut self.func_def_body.control_nodes);
+
+            let ControlNodeDef { kind, outputs: output_decls } =
+                &*self.func_def_body.control_nodes[node];
+            tail = match kind.clone() {
+                ControlNodeKind::Block { .. } => unreachable!(),
+
+                ControlNodeKind::Select { kind, scrutinee, cases } => {
+                    let merge = self.func_def_body.control_regions.define(
+                        self.cx,
+                        ControlRegionDef {
+                            inputs: output_decls
+                                .iter()
+                                .map(|output| ControlRegionInputDecl {
+                                    attrs: output.attrs,
+                                    ty: output.ty,
+                                })
+                                .collect(),
+                            ..Default::default()
+                        },
+                    );
+                    self.select_output_replacements.insert(node, merge);
+
+                    for &case in &cases {
+                        let case_outputs =
+                            mem::take(&mut self.func_def_body.at_mut(case).def().outputs);
+                        let case_tail = self.destructurize_region(case);
+                        self.set_control_inst_on_exit_from(
+                            case_tail,
+                            ControlInst {
+                                attrs: AttrSet::default(),
+                                kind: ControlInstKind::Branch,
+                                inputs: [].into_iter().collect(),
+                                targets: [merge].into_iter().collect(),
+                                target_inputs: [(merge, case_outputs)]
+                                    .into_iter()
+                                    .filter(|(_, inputs)| !inputs.is_empty())
+                                    .collect(),
+                            },
+                        );
+                    }
+
+                    // NOTE(eddyb) a `Select` without any cases can't be entered.
+                    let (kind, inputs) = if cases.is_empty() {
+                        (ControlInstKind::Unreachable, [].into_iter().collect())
+                    } else {
+                        (ControlInstKind::SelectBranch(kind), [scrutinee].into_iter().collect())
+                    };
+                    self.set_control_inst_on_exit_from(
+                        tail,
+                        ControlInst {
+                            attrs: AttrSet::default(),
+                            kind,
+                            inputs,
+                            targets: cases.into_iter().collect(),
+                            target_inputs: FxIndexMap::default(),
+                        },
+                    );
+
+                    merge
+                }
+
+                ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                    let exit = self
+                        .func_def_body
+                        .control_regions
+                        .define(self.cx, ControlRegionDef::default());
+
+                    self.set_control_inst_on_exit_from(
+                        tail,
+                        ControlInst {
+                            attrs: AttrSet::default(),
+                            kind: ControlInstKind::Branch,
+                            inputs: [].into_iter().collect(),
+                            targets: [body].into_iter().collect(),
+                            target_inputs: [(body, initial_inputs)]
+                                .into_iter()
+                                .filter(|(_, inputs)| !inputs.is_empty())
+                                .collect(),
+                        },
+                    );
+
+                    let body_outputs =
+                        mem::take(&mut self.func_def_body.at_mut(body).def().outputs);
+                    let body_tail = self.destructurize_region(body);
+                    self.set_control_inst_on_exit_from(
+                        body_tail,
+                        ControlInst {
+                            attrs: AttrSet::default(),
+                            kind: ControlInstKind::SelectBranch(SelectionKind::BoolCond),
+                            inputs: [repeat_condition].into_iter().collect(),
+                            targets: [body, exit].into_iter().collect(),
+                            target_inputs: [(body, body_outputs)]
+                                .into_iter()
+                                .filter(|(_, inputs)| !inputs.is_empty())
+                                .collect(),
+                        },
+                    );
+
+                    self.func_def_body
+                        .unstructured_cfg
+                        .as_mut()
+                        .unwrap()
+                        .loop_merge_to_loop_header
+                        .insert(exit, body);
+
+                    exit
+                }
+            };
+        }
+
+        tail
+    }
+
+    fn set_control_inst_on_exit_from(&mut self, region: ControlRegion, control_inst: ControlInst) {
+        let cfg = self.func_def_body.unstructured_cfg.as_mut().unwrap();
+        assert!(cfg.control_inst_on_exit_from.insert(region, control_inst).is_none());
+    }
+
+    /// The last step of destructurization is processing bulk replacements
+    /// collected while destructurizing (like `select_output_replacements`).
+    fn apply_value_replacements(self) {
+        self.func_def_body.inner_in_place_transform_with(&mut ReplaceValueWith(|v| match v {
+            Value::ControlNodeOutput { control_node, output_idx } => {
+                Some(Value::ControlRegionInput {
+                    region: *self.select_output_replacements.get(control_node)?,
+                    input_idx: output_idx,
+                })
+            }
+            _ => None,
+        }));
     }
 }
@@ -933,8 +933,9 @@ macro_rules! entities {
 
         $(
             // NOTE(eddyb) never derive `PartialOrd, Ord` for these types, as
-            // observing the entity index allocation order shouldn't be allowed.
-            #[derive(Copy, Clone, PartialEq, Eq, Hash)]
+            // observing the entity index allocation order shouldn't be allowed
+            // (`Debug` only exposes the index for debugging, e.g. `spv::Error`).
+            #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
             pub struct $name(#[doc(hidden)] NonZeroU32);
 
             impl sealed::Entity for $name {
@@ -55,6 +55,7 @@
 //!
 //! ##### Utilities and passes
 //! * [`print`](mod@print): pretty-printer with (styled and hyperlinked) HTML output
+//! * [`parse`]: parser for the (plain text) output of [`print`](mod@print)
 //! * [`spv::lower`]/[`spv::lift`]: conversion from/to SPIR-V
 //! * [`cfg::Structurizer`]: (re)structurization from arbitrary control-flow
 //!
@@ -155,17 +156,27 @@
 pub mod cfg;
 mod context;
 pub mod func_at;
+pub mod parse;
 pub mod print;
 pub mod transform;
 pub mod visit;
 pub mod passes {
     //! IR transformations (typically whole-[`Module`](crate::Module)).
     //
-    // NOTE(eddyb) inline `mod` to avoid adding APIs here, it's just namespacing.
+    // NOTE(eddyb) inline `mod` to avoid adding APIs here, it's just namespacing
+    // (other than the `PassManager` reexport, for convenience).
 
+    pub mod dce;
+    pub mod fold;
+    pub mod gvn;
+    pub mod inline;
     pub mod legalize;
     pub mod link;
+    pub mod manager;
     pub mod qptr;
+    pub mod verify;
+
+    pub use manager::PassManager;
 }
 pub mod qptr;
 pub mod spv;
@@ -453,8 +464,6 @@ impl<T: Eq> Ord for OrdAssertEq<T> {
 pub use context::Type;
 
 /// Definition for a [`Type`].
-//
-// FIXME(eddyb) maybe special-case some basic types like integers.
 #[derive(PartialEq, Eq, Hash)]
 pub struct TypeDef {
     pub attrs: AttrSet,
@@ -479,6 +488,14 @@ pub enum TypeKind {
     // separately in e.g. `ControlRegionInputDecl`, might be a better approach?
     QPtr,
 
+    /// Boolean, integer or floating-point scalar type, `width` bits wide
+    /// (booleans lack any inherent size, and always use a nominal `width` of `1`).
+    Scalar { kind: ScalarKind, width: u32 },
+
+    /// Vector type, with `elem_count` elements of the `elem` type, which is
+    /// always a [`TypeKind::Scalar`].
+    Vector { elem: Type, elem_count: u32 },
+
     SpvInst {
         spv_inst: spv::Inst,
         // FIXME(eddyb) find a better name.
@@ -490,6 +507,21 @@ pub enum TypeKind {
     SpvStringLiteralForExtInst,
 }
 
+/// How the bits of a [`TypeKind::Scalar`] type (and its values) are interpreted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ScalarKind {
+    Bool,
+
+    /// Signed (two's complement) integer.
+    SInt,
+
+    /// Unsigned integer.
+    UInt,
+
+    /// IEEE 754 binary floating-point number.
+    Float,
+}
+
 // HACK(eddyb) this behaves like an implicit conversion for `cx.intern(...)`.
 impl context::InternInCx<Type> for TypeKind {
     fn intern_in_cx(self, cx: &Context) -> Type {
@@ -509,8 +541,6 @@ pub enum TypeOrConst {
 pub use context::Const;
 
 /// Definition for a [`Const`]: a constant value.
-//
-// FIXME(eddyb) maybe special-case some basic consts like integer literals.
 #[derive(PartialEq, Eq, Hash)]
 pub struct ConstDef {
     pub attrs: AttrSet,
@@ -522,6 +552,18 @@ pub struct ConstDef {
 pub enum ConstKind {
     PtrToGlobalVar(GlobalVar),
 
+    /// Undefined value (of any type), i.e. a placeholder for a value that is
+    /// needed for well-formedness, but whose contents will never be observed
+    /// (equivalent to a SPIR-V `OpUndef`).
+    Undef,
+
+    /// Constant of a [`TypeKind::Scalar`] type, as its raw bits, zero-extended
+    /// past the `width` of the type (e.g. `true` is `Scalar(1)`, and `-1i8` is
+    /// `Scalar(0xff)`), making the representation of every value unique.
+    //
+    // FIXME(eddyb) support scalars wider than 64 bits.
+    Scalar(u64),
+
     // HACK(eddyb) this is a fallback case that should become increasingly rare
     // (especially wrt recursive consts), `Rc` means it can't bloat `ConstDef`.
     SpvInst {
@@ -876,6 +918,10 @@ pub enum DataInstKind {
     #[from]
     QPtr(qptr::QPtrOp),
 
+    /// Pure (i.e. side-effect-free) scalar/vector operations (see [`PureOp`]).
+    #[from]
+    Pure(PureOp),
+
     // FIXME(eddyb) should this have `#[from]`?
     SpvInst(spv::Inst),
     SpvExtInst {
@@ -884,7 +930,198 @@ pub enum DataInstKind {
     },
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+/// Classification of the effects a [`DataInst`] may have (other than producing
+/// its output), used by passes like `dce` to decide what can be removed.
+///
+/// Unknown (or not yet classified) instructions are always [`DataInstEffects::SideEffects`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DataInstEffects {
+    /// No effects at all, i.e. the output is determined by the `inputs` alone,
+    /// so the instruction can be removed (if unused) or deduplicated.
+    Pure,
+
+    /// May observe state (e.g. by reading memory) or create fresh state (e.g.
+    /// a function-local variable), but can't otherwise affect the execution,
+    /// so the instruction can be removed (if unused), but not deduplicated.
+    ReadOnly,
+
+    /// May write to memory, synchronize with other invocations, etc.
+    SideEffects,
+}
+
+impl DataInstKind {
+    /// Get the [`DataInstEffects`] of this kind of instruction, i.e. whether it's:
+    /// * [`Pure`](DataInstEffects::Pure): always the case for [`PureOp`]s, and
+    ///   for SPIR-V instructions without any memory or control-flow semantics
+    /// * [`ReadOnly`](DataInstEffects::ReadOnly): e.g. loads, or `OpVariable`
+    /// * [`SideEffects`](DataInstEffects::SideEffects): e.g. stores, barriers,
+    ///   and any unknown SPIR-V (extended) instructions
+    ///
+    /// [`FuncCall`](DataInstKind::FuncCall)s are conservatively considered
+    /// to have [`SideEffects`](DataInstEffects::SideEffects), regardless of
+    /// what the callee actually does (which would require inspecting its body).
+    pub fn effects(&self, cx: &Context) -> DataInstEffects {
+        match self {
+            // FIXME(eddyb) take into account the effects of the callee.
+            DataInstKind::FuncCall(_) => DataInstEffects::SideEffects,
+            DataInstKind::QPtr(op) => op.effects(),
+            DataInstKind::Pure(_) => DataInstEffects::Pure,
+            DataInstKind::SpvInst(spv_inst) => spv_inst.effects(),
+            &DataInstKind::SpvExtInst { ext_set, inst } => {
+                spv::ext_inst_effects(&cx[ext_set], inst)
+            }
+        }
+    }
+}
+
+macro_rules! def_pure_ops {
+    ($($(#[$attr:meta])* $op:ident),+ $(,)?) => {
+        /// Pure (i.e. side-effect-free) operations on scalars and vectors
+        /// ([`DataInstKind::Pure`]), each equivalent to the SPIR-V instruction
+        /// of the same name (i.e. `Op` followed by the variant name), and taking
+        /// the same inputs (in the same order) as that SPIR-V instruction.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum PureOp {
+            $($(#[$attr])* $op,)+
+
+            /// Extract the `idx`th element of a composite (`inputs[0]`).
+            CompositeExtract { idx: u32 },
+
+            /// Replace the `idx`th element of a composite (`inputs[1]`)
+            /// with a new value (`inputs[0]`).
+            CompositeInsert { idx: u32 },
+        }
+
+        impl PureOp {
+            /// Names of all operations (see [`PureOp::name`]), in the order
+            /// given by [`PureOp::index`].
+            pub(crate) const ALL_NAMES: &'static [&'static str] =
+                &[$(stringify!($op),)+ "CompositeExtract", "CompositeInsert"];
+
+            /// Get the index of this operation (ignoring any immediates),
+            /// in the order of declaration (and of [`PureOp::ALL_NAMES`]).
+            pub(crate) fn index(self) -> usize {
+                #[allow(non_camel_case_types)]
+                enum Index {
+                    $($op,)+
+                    CompositeExtract,
+                    CompositeInsert,
+                }
+                (match self {
+                    $(Self::$op => Index::$op,)+
+                    Self::CompositeExtract { .. } => Index::CompositeExtract,
+                    Self::CompositeInsert { .. } => Index::CompositeInsert,
+                }) as usize
+            }
+
+            /// Get the name of this operation (i.e. its SPIR-V opcode name,
+            /// without the `Op` prefix), ignoring any immediates.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$op => stringify!($op),)+
+                    Self::CompositeExtract { .. } => "CompositeExtract",
+                    Self::CompositeInsert { .. } => "CompositeInsert",
+                }
+            }
+
+            /// Inverse of [`PureOp::name`], limited to operations without immediates.
+            pub fn from_name_without_imms(name: &str) -> Option<Self> {
+                match name {
+                    $(stringify!($op) => Some(Self::$op),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// FIXME(eddyb) consider grouping these by "shape" (e.g. unary vs binary),
+// or by the kind of scalar they operate on (or even all the way to LLVM-like
+// `add`/`sub`/etc. with separate "signedness" and/or "overflow" flags).
+def_pure_ops! {
+    // Integer arithmetic.
+    SNegate,
+    IAdd,
+    ISub,
+    IMul,
+    UDiv,
+    SDiv,
+    UMod,
+    SRem,
+    SMod,
+
+    // Floating-point arithmetic.
+    FNegate,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FRem,
+    FMod,
+
+    // Bitwise operations.
+    ShiftRightLogical,
+    ShiftRightArithmetic,
+    ShiftLeftLogical,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    Not,
+
+    // Boolean operations.
+    LogicalEqual,
+    LogicalNotEqual,
+    LogicalOr,
+    LogicalAnd,
+    LogicalNot,
+
+    /// Choose between two values (`inputs[1]` if the condition `inputs[0]`
+    /// is `true`, `inputs[2]` otherwise), with vector conditions choosing
+    /// separately for each element.
+    Select,
+
+    // Integer comparisons.
+    IEqual,
+    INotEqual,
+    UGreaterThan,
+    SGreaterThan,
+    UGreaterThanEqual,
+    SGreaterThanEqual,
+    ULessThan,
+    SLessThan,
+    ULessThanEqual,
+    SLessThanEqual,
+
+    // Floating-point comparisons.
+    FOrdEqual,
+    FUnordEqual,
+    FOrdNotEqual,
+    FUnordNotEqual,
+    FOrdLessThan,
+    FUnordLessThan,
+    FOrdGreaterThan,
+    FUnordGreaterThan,
+    FOrdLessThanEqual,
+    FUnordLessThanEqual,
+    FOrdGreaterThanEqual,
+    FUnordGreaterThanEqual,
+
+    // Conversions.
+    ConvertFToU,
+    ConvertFToS,
+    ConvertSToF,
+    ConvertUToF,
+    UConvert,
+    SConvert,
+    FConvert,
+    Bitcast,
+
+    // Dynamic vector element access.
+    VectorExtractDynamic,
+    VectorInsertDynamic,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Value {
     Const(Const),
 
@@ -0,0 +1,2798 @@
+//! Parsing SPIR-T modules back from their (plain text) pretty-printed form.
+//!
+//! The accepted syntax is exactly what [`print`](crate::print) produces (for
+//! a single version of a [`Module`], without styles/anchors, i.e. the output of
+//! `print::Plan::for_module(&module).pretty_print().to_string()`), which allows
+//! writing e.g. inputs and expected outputs of passes, as `.spirt` text files.
+//!
+//! Not everything the printer outputs can be losslessly parsed back, however:
+//! * `ExportKey::SpvEntryPoint`'s `interface_global_vars` aren't printed, so
+//!   they're always parsed back as empty (as if they've been removed, and need
+//!   to be recomputed before lifting to SPIR-V)
+//! * `GlobalVarShape::TypedInterface` is indistinguishable from a SPIR-V pointer
+//!   type (`global_var GV0 in spv.StorageClass.X: T`), and the latter is assumed
+//! * `MemLayout`s only print their `align`, so `legacy_align` is set to it
+//! * `QPtrOp::DynOffset`'s `index_bounds` aren't printed, so they're lost
+//! * `qptr.usage(...)` attributes aren't supported (and produce an error)
+//! * diagnostics only keep their plain text (`/* ERR ... */` etc.), and
+//!   the location of `/* BUG ... */` diagnostics can't be recovered
+//! * `cfg::ControlFlowGraph`'s `loop_merge_to_loop_header` isn't printed
+
+use crate::qptr::{self, shapes};
+use crate::spv::{self, spec};
+// FIXME(eddyb) import more to avoid `crate::` everywhere.
+use crate::{
+    cfg, AddrSpace, Attr, AttrSet, AttrSetDef, Const, ConstDef, ConstKind, Context, ControlNode,
+    ControlNodeDef, ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionDef,
+    ControlRegionInputDecl, DataInstDef, DataInstFormDef, DataInstKind, DeclDef, Diag, EntityDefs,
+    EntityList, ExportKey, Exportee, Func, FuncDecl, FuncDefBody, FuncParam, FxIndexMap, GlobalVar,
+    GlobalVarDecl, GlobalVarDefBody, Import, Module, ModuleDebugInfo, ModuleDialect, OrdAssertEq,
+    PureOp, ScalarKind, SelectionKind, Type, TypeDef, TypeKind, TypeOrConst, Value,
+};
+use itertools::Either;
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::rc::Rc;
+use std::{fmt, fs, io, mem};
+
+/// Error encountered while parsing, with the (1-based) `line` and `col`umn
+/// (counted in `char`s) of the start of the offending token.
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub line: u32,
+    pub col: u32,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(err: ParseError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl Module {
+    pub fn parse_from_spirt_file(cx: Rc<Context>, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse_from_spirt_text(cx, &fs::read_to_string(path)?)?)
+    }
+
+    pub fn parse_from_spirt_text(cx: Rc<Context>, text: &str) -> Result<Self, ParseError> {
+        let tokens = Lexer::new(text).lex()?;
+        Parser::new(&cx, tokens).parse_module(cx.clone())
+    }
+}
+
+/// Source position (1-based line and column, the latter counted in `char`s).
+#[derive(Copy, Clone)]
+struct Pos {
+    line: u32,
+    col: u32,
+}
+
+impl Pos {
+    fn err(self, message: impl Into<String>) -> ParseError {
+        ParseError { line: self.line, col: self.col, message: message.into() }
+    }
+}
+
+#[derive(Clone)]
+enum TokenKind {
+    /// Identifier, optionally followed by a backtick-quoted name
+    /// (e.g. ``T`Foo` ``, used for definitions named by `OpName`).
+    Ident(String, Option<String>),
+
+    /// Numeric literal, always starting with a digit, but otherwise kept as-is
+    /// (e.g. `123`, `0x1_00000000`, `1.5f32`, `2D`), for the parser to interpret.
+    Num(String),
+
+    /// String literal, with all escapes already processed.
+    Str(String),
+
+    Punct(&'static str),
+
+    /// `// at file:line:col` comment.
+    DebugLine {
+        file_path: String,
+        line: u32,
+        // NOTE(eddyb) this is 0-based (unlike the printed form).
+        col: u32,
+    },
+
+    /// `/* ERR ... */` (or `WARN`/`BUG`) comment.
+    Diag(Diag),
+
+    /// Start of a `// spv.extinst...` comment, used by the printer for
+    /// debuginfo extended instructions (e.g. `DebugLine`), the rest of the
+    /// comment being lexed as normal tokens, up to `ExtInstCommentEnd`.
+    ExtInstCommentStart,
+    ExtInstCommentEnd,
+
+    Eof,
+}
+
+impl TokenKind {
+    fn descr(&self) -> String {
+        match self {
+            TokenKind::Ident(ident, None) => format!("`{ident}`"),
+            TokenKind::Ident(ident, Some(quoted)) => format!("`{ident}`{quoted}``"),
+            TokenKind::Num(num) => format!("`{num}`"),
+            TokenKind::Str(s) => format!("string literal {s:?}"),
+            TokenKind::Punct(punct) => format!("`{punct}`"),
+            TokenKind::DebugLine { .. } => "`// at ...` comment".into(),
+            TokenKind::Diag(_) => "diagnostic comment".into(),
+            TokenKind::ExtInstCommentStart => "`// spv.extinst...` comment".into(),
+            TokenKind::ExtInstCommentEnd => "end of `// spv.extinst...` comment".into(),
+            TokenKind::Eof => "end of input".into(),
+        }
+    }
+}
+
+struct Token {
+    kind: TokenKind,
+    pos: Pos,
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    idx: usize,
+    pos: Pos,
+    in_ext_inst_comment: bool,
+    tokens: Vec<Token>,
+}
+
+impl Lexer {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            idx: 0,
+            pos: Pos { line: 1, col: 1 },
+            in_ext_inst_comment: false,
+            tokens: vec![],
+        }
+    }
+
+    fn peek_char(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.idx + offset).copied()
+    }
+
+    fn rest_starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek_char(i) == Some(c))
+    }
+
+    fn bump_char(&mut self) -> Option<char> {
+        let c = self.peek_char(0)?;
+        self.idx += 1;
+        if c == '\n' {
+            self.pos = Pos { line: self.pos.line + 1, col: 1 };
+        } else {
+            self.pos.col += 1;
+        }
+        Some(c)
+    }
+
+    fn lex_while(&mut self, mut f: impl FnMut(char) -> bool) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek_char(0).filter(|&c| f(c)) {
+            s.push(c);
+            self.bump_char();
+        }
+        s
+    }
+
+    fn end_ext_inst_comment(&mut self) {
+        if mem::take(&mut self.in_ext_inst_comment) {
+            self.tokens.push(Token { kind: TokenKind::ExtInstCommentEnd, pos: self.pos });
+        }
+    }
+
+    fn lex(mut self) -> Result<Vec<Token>, ParseError> {
+        const PUNCTS: &[&str] = &[
+            "->", "<-", "=>", "#[", "×", "(", ")", "{", "}", "[", "]", ",", ":", "=", ".", "&",
+            "|", "+", "-",
+        ];
+
+        while let Some(c) = self.peek_char(0) {
+            let pos = self.pos;
+            if c.is_whitespace() {
+                if c == '\n' {
+                    self.end_ext_inst_comment();
+                }
+                self.bump_char();
+                continue;
+            }
+
+            let kind = match c {
+                '/' if self.peek_char(1) == Some('/') => {
+                    self.bump_char();
+                    self.bump_char();
+                    if !self.in_ext_inst_comment && self.rest_starts_with(" spv.extinst.") {
+                        self.in_ext_inst_comment = true;
+                        TokenKind::ExtInstCommentStart
+                    } else {
+                        let comment = self.lex_while(|c| c != '\n');
+                        match comment.strip_prefix(" at ") {
+                            Some(location) => debug_line_from_comment(location)
+                                .ok_or_else(|| pos.err("invalid `// at file:line:col` comment"))?,
+                            None => continue,
+                        }
+                    }
+                }
+                '/' if self.peek_char(1) == Some('*') => {
+                    self.bump_char();
+                    self.bump_char();
+                    let mut comment = String::new();
+                    loop {
+                        if self.rest_starts_with("*/") {
+                            self.bump_char();
+                            self.bump_char();
+                            break;
+                        }
+                        comment.push(
+                            self.bump_char()
+                                .ok_or_else(|| pos.err("unterminated block comment"))?,
+                        );
+                    }
+                    match diag_from_comment(&comment) {
+                        Some(diag) => TokenKind::Diag(diag),
+                        None => continue,
+                    }
+                }
+                '"' => TokenKind::Str(self.lex_str()?),
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let ident = self.lex_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                    let quoted = if self.peek_char(0) == Some('`') {
+                        self.bump_char();
+                        let name = self.lex_while(|c| !matches!(c, '`' | '\n' | '\0'));
+                        if self.bump_char() != Some('`') {
+                            return Err(pos.err("unterminated quoted name"));
+                        }
+                        Some(name)
+                    } else {
+                        None
+                    };
+                    TokenKind::Ident(ident, quoted)
+                }
+                c if c.is_ascii_digit() => {
+                    let is_hex = self.rest_starts_with("0x");
+                    let mut num = String::new();
+                    while let Some(c) = self.peek_char(0) {
+                        let is_float_dot =
+                            c == '.' && self.peek_char(1).is_some_and(|c| c.is_ascii_digit());
+                        let is_exp_sign = matches!(c, '+' | '-') && num.ends_with(['e', 'E']);
+                        if !(c.is_ascii_alphanumeric()
+                            || c == '_'
+                            || !is_hex && (is_float_dot || is_exp_sign))
+                        {
+                            break;
+                        }
+                        num.push(c);
+                        self.bump_char();
+                    }
+                    TokenKind::Num(num)
+                }
+                _ => match PUNCTS.iter().find(|punct| self.rest_starts_with(punct)) {
+                    Some(&punct) => {
+                        for _ in punct.chars() {
+                            self.bump_char();
+                        }
+                        TokenKind::Punct(punct)
+                    }
+                    None => return Err(pos.err(format!("unexpected character {c:?}"))),
+                },
+            };
+            self.tokens.push(Token { kind, pos });
+        }
+        self.end_ext_inst_comment();
+        self.tokens.push(Token { kind: TokenKind::Eof, pos: self.pos });
+        Ok(self.tokens)
+    }
+
+    /// Lex a string literal, using Rust syntax (as produced by `{:?}`).
+    fn lex_str(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        let mut s = String::new();
+        assert_eq!(self.bump_char(), Some('"'));
+        loop {
+            let pos = self.pos;
+            match self.bump_char() {
+                None => return Err(start.err("unterminated string literal")),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.bump_char() {
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('0') => s.push('\0'),
+                    Some(c @ ('\\' | '"' | '\'')) => s.push(c),
+                    Some('\n') => {
+                        self.lex_while(char::is_whitespace);
+                    }
+                    Some('u') if self.peek_char(0) == Some('{') => {
+                        self.bump_char();
+                        let hex = self.lex_while(|c| c.is_ascii_hexdigit());
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        match (c, self.bump_char()) {
+                            (Some(c), Some('}')) => s.push(c),
+                            _ => return Err(pos.err("invalid unicode escape")),
+                        }
+                    }
+                    _ => return Err(pos.err("invalid escape in string literal")),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+}
+
+/// Parse the `file:line:col` part of a `// at file:line:col` comment.
+fn debug_line_from_comment(location: &str) -> Option<TokenKind> {
+    let (rest, col) = location.rsplit_once(':')?;
+    let (file_path, line) = rest.rsplit_once(':')?;
+
+    // NOTE(eddyb) the printer only quotes paths when necessary.
+    let file_path = if file_path.starts_with('"') {
+        let mut lexer = Lexer::new(file_path);
+        let file_path = lexer.lex_str().ok()?;
+        if lexer.idx != lexer.chars.len() {
+            return None;
+        }
+        file_path
+    } else {
+        file_path.to_string()
+    };
+
+    Some(TokenKind::DebugLine {
+        file_path,
+        line: line.parse().ok()?,
+        col: col.parse::<u32>().ok()?.checked_sub(1)?,
+    })
+}
+
+/// Parse the contents of a `/* ERR ... */` (or `WARN`/`BUG`) comment,
+/// returning `None` for any other kind of block comment.
+fn diag_from_comment(comment: &str) -> Option<Diag> {
+    let comment = comment.trim();
+    let (level, message) = comment.split_once(char::is_whitespace).unwrap_or((comment, ""));
+    let message = message.trim_start();
+    let msg = |message: &str| [message.to_string().into()];
+    match level {
+        "ERR" => Some(Diag::err(msg(message))),
+        "WARN" => Some(Diag::warn(msg(message))),
+        "BUG" => {
+            // FIXME(eddyb) the original location can't be recovered, so this
+            // ends up pointing to the parser (`Diag::bug` tracks its caller).
+            let message = message
+                .strip_prefix('[')
+                .and_then(|message| Some(message.split_once("] ")?.1))
+                .unwrap_or(message);
+            Some(Diag::bug(msg(message)))
+        }
+        _ => None,
+    }
+}
+
+/// Name of a definition, either anonymous (e.g. `T0`), or derived from
+/// an `OpName` (e.g. ``T`Foo` ``), which is hidden from its attributes
+/// by the printer (and so it has to be added back by the parser).
+struct Name {
+    /// The whole name as written, used to resolve uses of the definition.
+    key: String,
+
+    /// The original `OpName`, if any.
+    spv_name: Option<String>,
+}
+
+/// Value declaration (e.g. `#[attrs] v1: T`), used for region inputs and
+/// control node outputs (with `_` instead of a name for unused values).
+struct ValueDecl {
+    name: Option<Name>,
+    attrs: AttrSet,
+    ty: Type,
+}
+
+/// Which ID operands of a SPIR-V instruction are implicit (i.e. not printed).
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum SpvIdOperands {
+    AllExplicit,
+
+    /// Only the first ID operand is implicit (and no others can be present),
+    /// e.g. the target of an `OpDecorate` attribute, or an `OpEntryPoint` export.
+    FirstImplicit,
+
+    /// All ID operands but the first are implicit, e.g. the targets of `OpSwitch`.
+    AllButFirstImplicit,
+}
+
+struct SpvInstOperands<ID> {
+    imms: SmallVec<[spv::Imm; 2]>,
+    ids: SmallVec<[ID; 4]>,
+
+    /// Number of implicit ID operands (see `SpvIdOperands`).
+    implicit_id_count: usize,
+}
+
+/// Cases of a `Select` (either structured, or as unstructured branches).
+enum SelectCases {
+    Structured(SmallVec<[ControlRegion; 2]>),
+    Unstructured {
+        targets: SmallVec<[ControlRegion; 4]>,
+        target_inputs: FxIndexMap<ControlRegion, SmallVec<[Value; 2]>>,
+    },
+}
+
+/// `parse_id` callback for SPIR-V operands which can't contain any IDs.
+fn reject_id(parser: &mut Parser<'_>) -> Result<Infallible, ParseError> {
+    Err(parser.err_expected("non-ID operand"))
+}
+
+struct Parser<'a> {
+    cx: &'a Context,
+    tokens: Vec<Token>,
+    cursor: usize,
+
+    types: FxHashMap<String, Type>,
+    consts: FxHashMap<String, Const>,
+    global_var_names: FxHashMap<String, GlobalVar>,
+    func_names: FxHashMap<String, Func>,
+
+    global_vars: EntityDefs<GlobalVar>,
+    funcs: EntityDefs<Func>,
+
+    /// Token index right after the name, in the `global_var` definition,
+    /// used to parse the header of a global variable ahead of its definition,
+    /// to compute the type of `&GV` constants (see `global_var_ptr_type`).
+    global_var_header_cursors: FxHashMap<GlobalVar, usize>,
+    global_var_ptr_types: FxHashMap<GlobalVar, Type>,
+
+    // Per-function state (reset for every function definition).
+    values: FxHashMap<String, Value>,
+    labels: FxHashMap<String, ControlRegion>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(cx: &'a Context, tokens: Vec<Token>) -> Self {
+        Self {
+            cx,
+            tokens,
+            cursor: 0,
+
+            types: FxHashMap::default(),
+            consts: FxHashMap::default(),
+            global_var_names: FxHashMap::default(),
+            func_names: FxHashMap::default(),
+
+            global_vars: EntityDefs::new(),
+            funcs: EntityDefs::new(),
+
+            global_var_header_cursors: FxHashMap::default(),
+            global_var_ptr_types: FxHashMap::default(),
+
+            values: FxHashMap::default(),
+            labels: FxHashMap::default(),
+        }
+    }
+
+    // Token-level helpers.
+
+    fn peek_nth(&self, n: usize) -> &TokenKind {
+        // NOTE(eddyb) the last token is always `Eof`.
+        &self.tokens[(self.cursor + n).min(self.tokens.len() - 1)].kind
+    }
+
+    fn peek(&self) -> &TokenKind {
+        self.peek_nth(0)
+    }
+
+    fn bump(&mut self) -> TokenKind {
+        let kind = self.peek().clone();
+        self.cursor = (self.cursor + 1).min(self.tokens.len() - 1);
+        kind
+    }
+
+    fn err_at(&self, cursor: usize, message: impl Into<String>) -> ParseError {
+        self.tokens[cursor.min(self.tokens.len() - 1)].pos.err(message)
+    }
+
+    fn err(&self, message: impl Into<String>) -> ParseError {
+        self.err_at(self.cursor, message)
+    }
+
+    fn err_expected(&self, expected: &str) -> ParseError {
+        self.err(format!("expected {expected}, found {}", self.peek().descr()))
+    }
+
+    fn is_punct_at(&self, n: usize, punct: &str) -> bool {
+        matches!(self.peek_nth(n), TokenKind::Punct(p) if *p == punct)
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        self.is_punct_at(0, punct)
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), ParseError> {
+        if self.eat_punct(punct) { Ok(()) } else { Err(self.err_expected(&format!("`{punct}`"))) }
+    }
+
+    fn is_keyword_at(&self, n: usize, keyword: &str) -> bool {
+        matches!(self.peek_nth(n), TokenKind::Ident(ident, None) if ident == keyword)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.is_keyword_at(0, keyword)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.err_expected(&format!("`{keyword}`")))
+        }
+    }
+
+    /// Expect `name:` (as used for named arguments, e.g. `size: 4`).
+    fn expect_named_arg(&mut self, name: &str) -> Result<(), ParseError> {
+        self.expect_keyword(name)?;
+        self.expect_punct(":")
+    }
+
+    fn expect_ident(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            TokenKind::Ident(ident, None) => {
+                let ident = ident.clone();
+                self.bump();
+                Ok(ident)
+            }
+            _ => Err(self.err_expected("identifier")),
+        }
+    }
+
+    fn expect_str(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            TokenKind::Str(s) => {
+                let s = s.clone();
+                self.bump();
+                Ok(s)
+            }
+            _ => Err(self.err_expected("string literal")),
+        }
+    }
+
+    fn expect_num(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            TokenKind::Num(num) => {
+                let num = num.clone();
+                self.bump();
+                Ok(num)
+            }
+            _ => Err(self.err_expected("number")),
+        }
+    }
+
+    fn expect_u32(&mut self) -> Result<u32, ParseError> {
+        let start = self.cursor;
+        let num = self.expect_num()?;
+        num.parse().map_err(|err| self.err_at(start, format!("invalid integer `{num}`: {err}")))
+    }
+
+    fn expect_nonzero_u32(&mut self) -> Result<NonZeroU32, ParseError> {
+        let start = self.cursor;
+        NonZeroU32::new(self.expect_u32()?)
+            .ok_or_else(|| self.err_at(start, "expected non-zero integer"))
+    }
+
+    /// Parse a comma-separated list (allowing a trailing comma), up to (and
+    /// including) `close`, with `parse_elem` called for each list element.
+    //
+    // NOTE(eddyb) the opening punctuation is expected to have been consumed.
+    fn parse_comma_sep(
+        &mut self,
+        close: &'static str,
+        mut parse_elem: impl FnMut(&mut Self) -> Result<(), ParseError>,
+    ) -> Result<(), ParseError> {
+        loop {
+            if self.eat_punct(close) {
+                return Ok(());
+            }
+            parse_elem(self)?;
+            if !self.eat_punct(",") {
+                return self.expect_punct(close);
+            }
+        }
+    }
+
+    /// Check for a definition name (see `Name`) with `prefix` (e.g. `T`).
+    fn name_at(&self, n: usize, prefix: &str) -> Option<Name> {
+        match self.peek_nth(n) {
+            TokenKind::Ident(ident, Some(quoted)) if ident == prefix => {
+                Some(Name { key: format!("{ident}`{quoted}`"), spv_name: Some(quoted.clone()) })
+            }
+            TokenKind::Ident(ident, None) => {
+                let idx = ident.strip_prefix(prefix)?;
+                (!idx.is_empty() && idx.bytes().all(|b| b.is_ascii_digit()))
+                    .then(|| Name { key: ident.clone(), spv_name: None })
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_name(&mut self, prefix: &str, descr: &str) -> Result<Name, ParseError> {
+        let name = self.name_at(0, prefix).ok_or_else(|| self.err_expected(descr))?;
+        self.bump();
+        Ok(name)
+    }
+
+    fn is_value_def_name_at(&self, n: usize) -> bool {
+        self.is_keyword_at(n, "_") || self.name_at(n, "v").is_some()
+    }
+
+    /// Parse the name of a value definition, i.e. `v1` or `_` (if unused).
+    fn parse_value_def_name(&mut self) -> Result<Option<Name>, ParseError> {
+        if self.eat_keyword("_") {
+            return Ok(None);
+        }
+        self.expect_name("v", "value name").map(Some)
+    }
+
+    fn intern_attrs(&self, mut attrs: AttrSetDef, spv_name: Option<String>) -> AttrSet {
+        if let Some(name) = spv_name {
+            let wk = &spec::Spec::get().well_known;
+            attrs.attrs.insert(Attr::SpvAnnotation(spv::Inst {
+                opcode: wk.OpName,
+                imms: spv::encode_literal_string(&name).collect(),
+            }));
+        }
+        self.cx.intern(attrs)
+    }
+
+    // Module-level parsing.
+
+    /// Allocate all `GlobalVar`s and `Func`s ahead of time, as they can be
+    /// used before their definitions (e.g. `&GV0` constants, or `call F1`).
+    fn declare_global_vars_and_funcs(&mut self) -> Result<(), ParseError> {
+        let cx = self.cx;
+
+        let mut depth = 0_usize;
+        for cursor in 0..self.tokens.len() {
+            self.cursor = cursor;
+            let is_global_var = self.is_keyword("global_var");
+            if depth == 0 && (is_global_var || self.is_keyword("func")) {
+                self.bump();
+                let start = self.cursor;
+
+                // HACK(eddyb) the placeholders get overwritten by the definitions.
+                let placeholder_type = cx.intern(TypeKind::QPtr);
+                let duplicate = if is_global_var {
+                    let name = self.expect_name("GV", "global variable name")?;
+                    let gv = self.global_vars.define(
+                        cx,
+                        GlobalVarDecl {
+                            attrs: AttrSet::default(),
+                            type_of_ptr_to: placeholder_type,
+                            shape: None,
+                            addr_space: AddrSpace::Handles,
+                            def: DeclDef::Imported(Import::LinkName(cx.intern(""))),
+                        },
+                    );
+                    self.global_var_header_cursors.insert(gv, self.cursor);
+                    self.global_var_names.insert(name.key, gv).is_some()
+                } else {
+                    let name = self.expect_name("F", "function name")?;
+                    let func = self.funcs.define(
+                        cx,
+                        FuncDecl {
+                            attrs: AttrSet::default(),
+                            ret_type: placeholder_type,
+                            params: SmallVec::new(),
+                            def: DeclDef::Imported(Import::LinkName(cx.intern(""))),
+                        },
+                    );
+                    self.func_names.insert(name.key, func).is_some()
+                };
+                if duplicate {
+                    return Err(self.err_at(start, "duplicate definition"));
+                }
+            }
+
+            match &self.tokens[cursor].kind {
+                TokenKind::Punct("(" | "[" | "{" | "#[") => depth += 1,
+                TokenKind::Punct(")" | "]" | "}") => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        self.cursor = 0;
+
+        Ok(())
+    }
+
+    fn parse_module(mut self, cx: Rc<Context>) -> Result<Module, ParseError> {
+        self.declare_global_vars_and_funcs()?;
+
+        let mut dialect = None;
+        let mut debug_info = None;
+        let mut exports = FxIndexMap::default();
+        while !matches!(self.peek(), TokenKind::Eof) {
+            let start = self.cursor;
+            let attrs = self.parse_attrs()?;
+
+            if self.eat_keyword("type") {
+                let name = self.expect_name("T", "type name")?;
+                self.expect_punct("=")?;
+                let attrs = self.intern_attrs(attrs, name.spv_name);
+                let ty = self.parse_type_with_attrs(attrs)?;
+                if self.types.insert(name.key, ty).is_some() {
+                    return Err(self.err_at(start, "duplicate definition"));
+                }
+            } else if self.eat_keyword("const") {
+                let name = self.expect_name("C", "constant name")?;
+                self.expect_punct("=")?;
+                let attrs = self.intern_attrs(attrs, name.spv_name);
+                let (ty, kind) = self.parse_const_def()?;
+                let ct = self.cx.intern(ConstDef { attrs, ty, kind });
+                if self.consts.insert(name.key, ct).is_some() {
+                    return Err(self.err_at(start, "duplicate definition"));
+                }
+            } else if self.eat_keyword("global_var") {
+                self.parse_global_var(attrs)?;
+            } else if self.eat_keyword("func") {
+                self.parse_func(attrs)?;
+            } else {
+                if !attrs.attrs.is_empty() {
+                    return Err(self.err_expected("definition (after attributes)"));
+                }
+
+                if self.eat_keyword("module") {
+                    self.expect_punct(".")?;
+                    if self.eat_keyword("dialect") {
+                        self.expect_punct("=")?;
+                        dialect = Some(ModuleDialect::Spv(self.parse_spv_dialect()?));
+                    } else {
+                        self.expect_keyword("debug_info")?;
+                        self.expect_punct("=")?;
+                        debug_info = Some(ModuleDebugInfo::Spv(self.parse_spv_debug_info()?));
+                    }
+                } else if self.eat_keyword("export") {
+                    self.expect_punct("{")?;
+                    self.parse_comma_sep("}", |this| {
+                        let key = this.parse_export_key()?;
+                        this.expect_punct(":")?;
+                        let start = this.cursor;
+                        let exportee = if let Some(name) = this.name_at(0, "GV") {
+                            this.bump();
+                            this.global_var_names.get(&name.key).copied().map(Exportee::GlobalVar)
+                        } else {
+                            let name = this.expect_name("F", "function name")?;
+                            this.func_names.get(&name.key).copied().map(Exportee::Func)
+                        };
+                        let exportee = exportee.ok_or_else(|| {
+                            this.err_at(start, "undefined global variable/function")
+                        })?;
+                        exports.insert(key, exportee);
+                        Ok(())
+                    })?;
+                } else {
+                    return Err(self.err_expected("definition"));
+                }
+            }
+        }
+
+        let dialect = dialect.ok_or_else(|| self.err("missing `module.dialect = ...`"))?;
+        let debug_info = debug_info.unwrap_or_else(|| {
+            ModuleDebugInfo::Spv(spv::ModuleDebugInfo {
+                original_generator_magic: None,
+                source_languages: BTreeMap::new(),
+                source_extensions: vec![],
+                module_processes: vec![],
+            })
+        });
+
+        let mut module = Module::new(cx, dialect, debug_info);
+        module.global_vars = self.global_vars;
+        module.funcs = self.funcs;
+        module.exports = exports;
+        Ok(module)
+    }
+
+    fn parse_spv_dialect(&mut self) -> Result<spv::Dialect, ParseError> {
+        let wk = &spec::Spec::get().well_known;
+
+        self.expect_keyword("spv")?;
+        self.expect_punct(".")?;
+        self.expect_keyword("Module")?;
+        self.expect_punct("(")?;
+
+        self.expect_named_arg("version")?;
+        let start = self.cursor;
+        let version = self.expect_num()?;
+        let (version_major, version_minor) = version
+            .split_once('.')
+            .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+            .ok_or_else(|| self.err_at(start, "expected `major.minor` version"))?;
+
+        let mut capabilities = BTreeSet::new();
+        let mut extensions = BTreeSet::new();
+        let mut addressing_model = wk.Logical;
+        let mut memory_model = None;
+        while self.eat_punct(",") && !self.is_punct(")") {
+            if self.eat_keyword("extensions") {
+                self.expect_punct(":")?;
+                self.expect_punct("{")?;
+                self.parse_comma_sep("}", |this| {
+                    extensions.insert(this.expect_str()?);
+                    Ok(())
+                })?;
+                continue;
+            }
+
+            let start = self.cursor;
+            self.expect_keyword("spv")?;
+            self.expect_punct(".")?;
+            let kind_name = self.expect_ident()?;
+            self.expect_punct(".")?;
+            match &kind_name[..] {
+                "Capability" => {
+                    // NOTE(eddyb) capabilities are printed like a `BitEnum`,
+                    // i.e. `spv.Capability.{A, B}`, when there's more than one.
+                    let is_set = self.eat_punct("{");
+                    loop {
+                        capabilities.insert(self.parse_spv_enumerand_word(wk.Capability)?);
+                        if !is_set || !self.eat_punct(",") || self.is_punct("}") {
+                            break;
+                        }
+                    }
+                    if is_set {
+                        self.expect_punct("}")?;
+                    }
+                }
+                "AddressingModel" => {
+                    addressing_model = self.parse_spv_enumerand_word(wk.AddressingModel)?;
+                }
+                "MemoryModel" => {
+                    memory_model = Some(self.parse_spv_enumerand_word(wk.MemoryModel)?);
+                }
+                _ => return Err(self.err_at(start, "unknown `spv.Module` field")),
+            }
+        }
+        self.expect_punct(")")?;
+
+        Ok(spv::Dialect {
+            version_major,
+            version_minor,
+            capabilities,
+            extensions,
+            addressing_model,
+            memory_model: memory_model.ok_or_else(|| self.err("missing `spv.MemoryModel`"))?,
+        })
+    }
+
+    fn parse_spv_debug_info(&mut self) -> Result<spv::ModuleDebugInfo, ParseError> {
+        let wk = &spec::Spec::get().well_known;
+
+        self.expect_keyword("spv")?;
+        self.expect_punct(".")?;
+        self.expect_keyword("Module")?;
+        self.expect_punct(".")?;
+        self.expect_keyword("DebugInfo")?;
+        self.expect_punct("(")?;
+
+        let mut debug_info = spv::ModuleDebugInfo {
+            original_generator_magic: None,
+            source_languages: BTreeMap::new(),
+            source_extensions: vec![],
+            module_processes: vec![],
+        };
+        self.parse_comma_sep(")", |this| {
+            let start = this.cursor;
+            let field = this.expect_ident()?;
+            this.expect_punct(":")?;
+            match &field[..] {
+                "generator" => {
+                    this.expect_keyword("spv")?;
+                    this.expect_punct(".")?;
+                    this.expect_keyword("Tool")?;
+                    this.expect_punct("(")?;
+                    this.expect_named_arg("id")?;
+                    let id = this.expect_u32()?;
+                    let version = if this.eat_punct(",") && this.eat_keyword("version") {
+                        this.expect_punct(":")?;
+                        this.expect_u32()?
+                    } else {
+                        0
+                    };
+                    this.eat_punct(",");
+                    this.expect_punct(")")?;
+                    if id > 0xffff || version > 0xffff {
+                        return Err(this.err_at(start, "invalid `spv.Tool` id/version"));
+                    }
+                    debug_info.original_generator_magic = NonZeroU32::new(id << 16 | version);
+                }
+                "source_languages" => {
+                    this.expect_punct("{")?;
+                    this.parse_comma_sep("}", |this| {
+                        let lang = this.parse_spv_enum_operand_word(wk.SourceLanguage)?;
+                        this.expect_punct("(")?;
+                        this.expect_named_arg("version")?;
+                        let version = this.expect_u32()?;
+                        this.expect_punct(")")?;
+                        this.expect_punct(":")?;
+
+                        let sources = debug_info
+                            .source_languages
+                            .entry(spv::DebugSourceLang { lang, version })
+                            .or_default();
+                        this.expect_punct("{")?;
+                        this.parse_comma_sep("}", |this| {
+                            let file = this.cx.intern(this.expect_str()?);
+                            this.expect_punct(":")?;
+                            sources.file_contents.insert(file, this.expect_str()?);
+                            Ok(())
+                        })
+                    })?;
+                }
+                "source_extensions" | "module_processes" => {
+                    let list = if field == "source_extensions" {
+                        &mut debug_info.source_extensions
+                    } else {
+                        &mut debug_info.module_processes
+                    };
+                    this.expect_punct("[")?;
+                    this.parse_comma_sep("]", |this| {
+                        list.push(this.expect_str()?);
+                        Ok(())
+                    })?;
+                }
+                _ => return Err(this.err_at(start, "unknown `spv.Module.DebugInfo` field")),
+            }
+            Ok(())
+        })?;
+
+        Ok(debug_info)
+    }
+
+    fn parse_export_key(&mut self) -> Result<ExportKey, ParseError> {
+        let wk = &spec::Spec::get().well_known;
+
+        if let TokenKind::Str(_) = self.peek() {
+            return Ok(ExportKey::LinkName(self.cx.intern(self.expect_str()?)));
+        }
+
+        let start = self.cursor;
+        if self.expect_spv_opcode()? != wk.OpEntryPoint {
+            return Err(self.err_at(start, "expected `spv.OpEntryPoint`"));
+        }
+        let operands = self.parse_spv_inst_operands(
+            wk.OpEntryPoint,
+            SpvIdOperands::FirstImplicit,
+            &mut reject_id,
+        )?;
+        Ok(ExportKey::SpvEntryPoint {
+            imms: operands.imms,
+            // FIXME(eddyb) these aren't printed, so they can't be parsed back.
+            interface_global_vars: SmallVec::new(),
+        })
+    }
+
+    fn parse_global_var(&mut self, attrs: AttrSetDef) -> Result<(), ParseError> {
+        let name = self.expect_name("GV", "global variable name")?;
+        let gv = self.global_var_names[&name.key];
+
+        let (type_of_ptr_to, shape, addr_space) = self.parse_global_var_header()?;
+        self.global_var_ptr_types.insert(gv, type_of_ptr_to);
+
+        let def = if self.eat_punct("=") {
+            if self.eat_keyword("import") {
+                DeclDef::Imported(Import::LinkName(self.cx.intern(self.expect_str()?)))
+            } else {
+                DeclDef::Present(GlobalVarDefBody { initializer: Some(self.parse_const()?) })
+            }
+        } else {
+            DeclDef::Present(GlobalVarDefBody { initializer: None })
+        };
+
+        self.global_vars[gv] = GlobalVarDecl {
+            attrs: self.intern_attrs(attrs, name.spv_name),
+            type_of_ptr_to,
+            shape,
+            addr_space,
+            def,
+        };
+        Ok(())
+    }
+
+    /// Get the type of a `&GV` constant (i.e. `GV`'s `type_of_ptr_to`).
+    fn global_var_ptr_type(&mut self, gv: GlobalVar) -> Result<Type, ParseError> {
+        if let Some(&ty) = self.global_var_ptr_types.get(&gv) {
+            return Ok(ty);
+        }
+
+        // HACK(eddyb) `&GV` constants can (and usually do) appear before the
+        // definition of `GV`, so the relevant part of it is parsed ahead of time.
+        let saved_cursor = mem::replace(&mut self.cursor, self.global_var_header_cursors[&gv]);
+        let result = self.parse_global_var_header();
+        self.cursor = saved_cursor;
+
+        let (ty, ..) = result?;
+        self.global_var_ptr_types.insert(gv, ty);
+        Ok(ty)
+    }
+
+    /// Parse everything after the name in a `global_var` definition, up to
+    /// (but not including) its initializer/import, i.e. ` in AS: T` (or shape).
+    fn parse_global_var_header(
+        &mut self,
+    ) -> Result<(Type, Option<shapes::GlobalVarShape>, AddrSpace), ParseError> {
+        let cx = self.cx;
+        let wk = &spec::Spec::get().well_known;
+
+        let addr_space = if self.eat_keyword("in") {
+            AddrSpace::SpvStorageClass(self.parse_spv_enum_operand_word(wk.StorageClass)?)
+        } else {
+            AddrSpace::Handles
+        };
+
+        if self.eat_keyword("layout") {
+            self.expect_punct("(")?;
+            self.expect_named_arg("size")?;
+            let size = self.expect_u32()?;
+            self.expect_punct(",")?;
+            self.expect_named_arg("align")?;
+            let align = self.expect_u32()?;
+            self.eat_punct(",");
+            self.expect_punct(")")?;
+
+            let layout = shapes::MemLayout { align, legacy_align: align, size };
+            return Ok((
+                cx.intern(TypeKind::QPtr),
+                Some(shapes::GlobalVarShape::UntypedData(layout)),
+                addr_space,
+            ));
+        }
+
+        self.expect_punct(":")?;
+
+        if self.eat_keyword("pointee_type_of") {
+            self.expect_punct("(")?;
+            let ty = self.parse_type()?;
+            self.expect_punct(")")?;
+            return Ok((ty, None, addr_space));
+        }
+
+        match addr_space {
+            // FIXME(eddyb) this is ambiguous with `GlobalVarShape::TypedInterface`,
+            // which gets parsed back as a SPIR-V pointer type (and no shape).
+            AddrSpace::SpvStorageClass(storage_class) => {
+                let pointee = self.parse_type()?;
+                let ty = cx.intern(TypeKind::SpvInst {
+                    spv_inst: spv::Inst {
+                        opcode: wk.OpTypePointer,
+                        imms: [spv::Imm::Short(wk.StorageClass, storage_class)]
+                            .into_iter()
+                            .collect(),
+                    },
+                    type_and_const_inputs: [TypeOrConst::Type(pointee)].into_iter().collect(),
+                });
+                Ok((ty, None, addr_space))
+            }
+            AddrSpace::Handles => {
+                let (handle, fixed_count) = if self.eat_punct("[") {
+                    let fixed_count = if let TokenKind::Num(_) = self.peek() {
+                        let count = self.expect_nonzero_u32()?;
+                        self.expect_punct("×")?;
+                        Some(count)
+                    } else {
+                        None
+                    };
+                    let handle = self.parse_handle()?;
+                    self.expect_punct("]")?;
+                    (handle, fixed_count)
+                } else {
+                    (self.parse_handle()?, NonZeroU32::new(1))
+                };
+                Ok((
+                    cx.intern(TypeKind::QPtr),
+                    Some(shapes::GlobalVarShape::Handles { handle, fixed_count }),
+                    addr_space,
+                ))
+            }
+        }
+    }
+
+    fn parse_handle(&mut self) -> Result<shapes::Handle, ParseError> {
+        if !self.eat_keyword("buffer") {
+            return Ok(shapes::Handle::Opaque(self.parse_type()?));
+        }
+
+        self.expect_punct("(")?;
+        let addr_space = self.parse_addr_space()?;
+        self.expect_punct(",")?;
+
+        // NOTE(eddyb) the size is one of `B`, `N × S`, or `B + N × S`.
+        self.expect_named_arg("size")?;
+        let (size, dyn_unit_stride) = if self.eat_keyword("N") {
+            self.expect_punct("×")?;
+            (0, Some(self.expect_nonzero_u32()?))
+        } else {
+            let size = self.expect_u32()?;
+            if self.eat_punct("+") {
+                self.expect_keyword("N")?;
+                self.expect_punct("×")?;
+                (size, Some(self.expect_nonzero_u32()?))
+            } else {
+                (size, None)
+            }
+        };
+        self.expect_punct(",")?;
+
+        self.expect_named_arg("align")?;
+        let align = self.expect_u32()?;
+        self.eat_punct(",");
+        self.expect_punct(")")?;
+
+        Ok(shapes::Handle::Buffer(
+            addr_space,
+            shapes::MaybeDynMemLayout {
+                fixed_base: shapes::MemLayout { align, legacy_align: align, size },
+                dyn_unit_stride,
+            },
+        ))
+    }
+
+    fn parse_addr_space(&mut self) -> Result<AddrSpace, ParseError> {
+        let wk = &spec::Spec::get().well_known;
+
+        if self.eat_keyword("handles") {
+            Ok(AddrSpace::Handles)
+        } else {
+            Ok(AddrSpace::SpvStorageClass(self.parse_spv_enum_operand_word(wk.StorageClass)?))
+        }
+    }
+
+    fn parse_func(&mut self, attrs: AttrSetDef) -> Result<(), ParseError> {
+        let name = self.expect_name("F", "function name")?;
+        let func = self.func_names[&name.key];
+
+        self.values.clear();
+        self.labels.clear();
+
+        let mut params = SmallVec::<[ValueDecl; 2]>::new();
+        self.expect_punct("(")?;
+        self.parse_comma_sep(")", |this| {
+            params.push(this.parse_value_decl()?);
+            Ok(())
+        })?;
+        self.expect_punct("->")?;
+        let ret_type = self.parse_type()?;
+
+        let def = if self.eat_punct("=") {
+            self.expect_keyword("import")?;
+            DeclDef::Imported(Import::LinkName(self.cx.intern(self.expect_str()?)))
+        } else {
+            self.expect_punct("{")?;
+            let func_def_body = self.parse_func_body(&mut params)?;
+            self.expect_punct("}")?;
+            DeclDef::Present(func_def_body)
+        };
+
+        self.funcs[func] = FuncDecl {
+            attrs: self.intern_attrs(attrs, name.spv_name),
+            ret_type,
+            params: params
+                .iter()
+                .map(|param| FuncParam { attrs: param.attrs, ty: param.ty })
+                .collect(),
+            def,
+        };
+        Ok(())
+    }
+
+    fn parse_func_body(&mut self, params: &mut [ValueDecl]) -> Result<FuncDefBody, ParseError> {
+        let cx = self.cx;
+
+        let mut control_regions = EntityDefs::new();
+        let body = control_regions.define(cx, ControlRegionDef::default());
+        let mut func_def_body = FuncDefBody {
+            control_regions,
+            control_nodes: EntityDefs::new(),
+            data_insts: EntityDefs::new(),
+            body,
+            unstructured_cfg: None,
+        };
+        for param in params {
+            let decl = ValueDecl { name: param.name.take(), attrs: param.attrs, ty: param.ty };
+            self.define_region_input(&mut func_def_body, body, decl)?;
+        }
+
+        // NOTE(eddyb) unstructured control-flow is indicated by the function
+        // body ending in a control-flow instruction (instead of just `}`),
+        // followed by the rest of the regions (each prefixed by a label).
+        let body_control_inst = self.parse_region_contents(&mut func_def_body, body, false)?;
+        if let Some(control_inst) = body_control_inst {
+            let mut cfg = cfg::ControlFlowGraph::default();
+            cfg.control_inst_on_exit_from.insert(body, control_inst);
+
+            let mut defined_labels = FxHashSet::default();
+            while self.eat_keyword("label") {
+                let start = self.cursor;
+                let name = self.expect_name("L", "label name")?;
+                let region = self.label_region(&mut func_def_body, name.key);
+                if !defined_labels.insert(region) {
+                    return Err(self.err_at(start, "duplicate definition"));
+                }
+
+                if self.eat_punct("(") {
+                    self.parse_comma_sep(")", |this| {
+                        let decl = this.parse_value_decl()?;
+                        this.define_region_input(&mut func_def_body, region, decl)
+                    })?;
+                }
+                self.expect_punct(":")?;
+
+                let control_inst = self
+                    .parse_region_contents(&mut func_def_body, region, false)?
+                    .ok_or_else(|| self.err_expected("control-flow instruction"))?;
+                cfg.control_inst_on_exit_from.insert(region, control_inst);
+            }
+
+            if let Some(label) =
+                self.labels.iter().find(|(_, region)| !defined_labels.contains(*region))
+            {
+                return Err(self.err(format!("undefined label `{}`", label.0)));
+            }
+
+            func_def_body.unstructured_cfg = Some(cfg);
+        }
+
+        Ok(func_def_body)
+    }
+
+    /// Get the region for the label `name`, allocating it on first use.
+    fn label_region(&mut self, func_def_body: &mut FuncDefBody, name: String) -> ControlRegion {
+        *self.labels.entry(name).or_insert_with(|| {
+            func_def_body.control_regions.define(self.cx, ControlRegionDef::default())
+        })
+    }
+
+    fn define_value(&mut self, name: Option<Name>, value: Value) -> Result<(), ParseError> {
+        if let Some(name) = name {
+            if self.values.insert(name.key.clone(), value).is_some() {
+                return Err(self.err(format!("`{}` defined multiple times", name.key)));
+            }
+        }
+        Ok(())
+    }
+
+    fn define_region_input(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+        region: ControlRegion,
+        decl: ValueDecl,
+    ) -> Result<(), ParseError> {
+        let inputs = &mut func_def_body.control_regions[region].inputs;
+        let input_idx = u32::try_from(inputs.len()).unwrap();
+        inputs.push(ControlRegionInputDecl { attrs: decl.attrs, ty: decl.ty });
+        self.define_value(decl.name, Value::ControlRegionInput { region, input_idx })
+    }
+
+    fn parse_value_decl(&mut self) -> Result<ValueDecl, ParseError> {
+        let attrs = self.parse_attrs()?;
+        self.parse_value_decl_after_attrs(attrs)
+    }
+
+    fn parse_value_decl_after_attrs(&mut self, attrs: AttrSetDef) -> Result<ValueDecl, ParseError> {
+        let name = self.parse_value_def_name()?;
+        self.expect_punct(":")?;
+        let ty = self.parse_type()?;
+        let attrs = self.intern_attrs(attrs, name.as_ref().and_then(|name| name.spv_name.clone()));
+        Ok(ValueDecl { name, attrs, ty })
+    }
+
+    // Function body parsing.
+
+    /// Parse the contents of `region` (statements, then any outputs), stopping
+    /// before `}` (or before `->`, for loop bodies), or right after a control-flow
+    /// instruction (only allowed in unstructured control-flow, which it starts).
+    fn parse_region_contents(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+        region: ControlRegion,
+        is_loop_body: bool,
+    ) -> Result<Option<cfg::ControlInst>, ParseError> {
+        let cx = self.cx;
+        let wk = &spec::Spec::get().well_known;
+
+        loop {
+            if self.is_punct("}") {
+                return Ok(None);
+            }
+
+            // NOTE(eddyb) region outputs can only appear at the very end of
+            // a region, so they're parsed speculatively (i.e. backtracking
+            // if they're not followed by the end of the region).
+            let start = self.cursor;
+            let is_value_name_not_def = self.name_at(0, "v").is_some()
+                && !self.is_punct_at(1, "=")
+                && !self.is_punct_at(1, ":");
+            match self.parse_values_tuple_or_single() {
+                Ok(outputs) if self.is_punct("}") || is_loop_body && self.is_punct("->") => {
+                    func_def_body.control_regions[region].outputs = outputs;
+                    return Ok(None);
+                }
+                Err(err) if is_value_name_not_def => return Err(err),
+                _ => {}
+            }
+            self.cursor = start;
+
+            let mut attrs = self.parse_attrs()?;
+
+            // Control nodes with outputs, i.e. `(v1: T, ...) = ...` or `v1: T = ...`.
+            let output_decls = if self.eat_punct("(") {
+                if !attrs.attrs.is_empty() {
+                    return Err(self.err_at(start, "unexpected attributes (before outputs)"));
+                }
+                let mut decls = SmallVec::<[ValueDecl; 2]>::new();
+                self.parse_comma_sep(")", |this| {
+                    decls.push(this.parse_value_decl()?);
+                    Ok(())
+                })?;
+                self.expect_punct("=")?;
+                Some(decls)
+            } else if self.is_value_def_name_at(0) && self.is_punct_at(1, ":") {
+                let decl = self.parse_value_decl_after_attrs(mem::take(&mut attrs))?;
+                self.expect_punct("=")?;
+                Some([decl].into_iter().collect())
+            } else {
+                None
+            };
+
+            let is_select = self.is_keyword("if") || self.peek_spv_opcode() == Some(wk.OpSwitch);
+            if output_decls.is_some() && !is_select {
+                return Err(self.err_expected("`if` or `spv.OpSwitch`"));
+            }
+            if is_select {
+                let select_start = self.cursor;
+                let (kind, scrutinee, cases) = self.parse_select(func_def_body)?;
+                match cases {
+                    SelectCases::Structured(cases) => {
+                        if !attrs.attrs.is_empty() {
+                            return Err(self.err_at(select_start, "unexpected attributes"));
+                        }
+                        let output_decls = output_decls.unwrap_or_default();
+                        let control_node = func_def_body.control_nodes.define(
+                            cx,
+                            ControlNodeDef {
+                                kind: ControlNodeKind::Select { kind, scrutinee, cases },
+                                outputs: output_decls
+                                    .iter()
+                                    .map(|decl| ControlNodeOutputDecl {
+                                        attrs: decl.attrs,
+                                        ty: decl.ty,
+                                    })
+                                    .collect(),
+                            }
+                            .into(),
+                        );
+                        func_def_body.control_regions[region]
+                            .children
+                            .insert_last(control_node, &mut func_def_body.control_nodes);
+
+                        for (output_idx, decl) in output_decls.into_iter().enumerate() {
+                            let output_idx = u32::try_from(output_idx).unwrap();
+                            self.define_value(
+                                decl.name,
+                                Value::ControlNodeOutput { control_node, output_idx },
+                            )?;
+                        }
+                    }
+                    SelectCases::Unstructured { targets, target_inputs } => {
+                        if output_decls.is_some() {
+                            return Err(self.err_at(select_start, "unexpected branches"));
+                        }
+                        return Ok(Some(cfg::ControlInst {
+                            attrs: cx.intern(attrs),
+                            kind: cfg::ControlInstKind::SelectBranch(kind),
+                            inputs: [scrutinee].into_iter().collect(),
+                            targets,
+                            target_inputs,
+                        }));
+                    }
+                }
+                continue;
+            }
+
+            if self.is_keyword("loop") {
+                if !attrs.attrs.is_empty() {
+                    return Err(self.err_expected("definition (after attributes)"));
+                }
+                let control_node = self.parse_loop(func_def_body)?;
+                func_def_body.control_regions[region]
+                    .children
+                    .insert_last(control_node, &mut func_def_body.control_nodes);
+                continue;
+            }
+
+            if let Some(control_inst) = self.parse_control_inst(func_def_body)? {
+                return Ok(Some(cfg::ControlInst { attrs: cx.intern(attrs), ..control_inst }));
+            }
+
+            // Data instructions, i.e. `v1 = ...`, `_ = ...`, or neither.
+            let output_name = if self.is_value_def_name_at(0) && self.is_punct_at(1, "=") {
+                let name = self.parse_value_def_name()?;
+                self.bump();
+                Some(name)
+            } else {
+                None
+            };
+            let def_start = self.cursor;
+            let (form, inputs) = self.parse_data_inst_def()?;
+            if output_name.is_some() && form.output_type.is_none() {
+                return Err(self.err_at(def_start, "missing type of instruction output"));
+            }
+
+            let output_name = output_name.flatten();
+            let inst = func_def_body.data_insts.define(
+                cx,
+                DataInstDef {
+                    attrs: self.intern_attrs(
+                        attrs,
+                        output_name.as_ref().and_then(|name| name.spv_name.clone()),
+                    ),
+                    form: cx.intern(form),
+                    inputs,
+                }
+                .into(),
+            );
+
+            // NOTE(eddyb) consecutive data instructions are grouped in blocks.
+            let children = func_def_body.control_regions[region].children;
+            let block = children
+                .iter()
+                .last
+                .filter(|&last| {
+                    matches!(func_def_body.control_nodes[last].kind, ControlNodeKind::Block { .. })
+                })
+                .unwrap_or_else(|| {
+                    let block = func_def_body.control_nodes.define(
+                        cx,
+                        ControlNodeDef {
+                            kind: ControlNodeKind::Block { insts: EntityList::empty() },
+                            outputs: SmallVec::new(),
+                        }
+                        .into(),
+                    );
+                    func_def_body.control_regions[region]
+                        .children
+                        .insert_last(block, &mut func_def_body.control_nodes);
+                    block
+                });
+            match &mut func_def_body.control_nodes[block].kind {
+                ControlNodeKind::Block { insts } => {
+                    insts.insert_last(inst, &mut func_def_body.data_insts);
+                }
+                _ => unreachable!(),
+            }
+
+            self.define_value(output_name, Value::DataInstOutput(inst))?;
+        }
+    }
+
+    /// Parse the contents of `region`, and its closing `}`, disallowing any
+    /// control-flow instructions (i.e. only structured control-flow).
+    fn parse_structured_region(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+        region: ControlRegion,
+        is_loop_body: bool,
+    ) -> Result<(), ParseError> {
+        let start = self.cursor;
+        if self.parse_region_contents(func_def_body, region, is_loop_body)?.is_some() {
+            return Err(
+                self.err_at(start, "unexpected control-flow instruction (in structured region)")
+            );
+        }
+        Ok(())
+    }
+
+    /// Parse `if v { ... } else { ... }` or `spv.OpSwitch(...) { case => { ... } ... }`,
+    /// with either structured regions, or unstructured branches, for cases.
+    fn parse_select(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+    ) -> Result<(SelectionKind, Value, SelectCases), ParseError> {
+        let cx = self.cx;
+        let wk = &spec::Spec::get().well_known;
+
+        let (kind, scrutinee, case_count) = if self.eat_keyword("if") {
+            (SelectionKind::BoolCond, self.parse_value()?, 2)
+        } else {
+            let start = self.cursor;
+            let opcode = self.expect_spv_opcode()?;
+            assert!(opcode == wk.OpSwitch);
+            let mut operands = self.parse_spv_inst_operands(
+                opcode,
+                SpvIdOperands::AllButFirstImplicit,
+                &mut |this| this.parse_value(),
+            )?;
+            let scrutinee = operands.ids[0];
+            let scrutinee_type = func_def_body.at(scrutinee).type_of(cx);
+            self.resize_contextual_literals(start, &mut operands.imms, scrutinee_type)?;
+            (
+                SelectionKind::SpvInst(spv::Inst { opcode, imms: operands.imms }),
+                scrutinee,
+                operands.implicit_id_count,
+            )
+        };
+        let is_switch = !matches!(kind, SelectionKind::BoolCond);
+
+        let mut cases = None;
+        if is_switch {
+            self.expect_punct("{")?;
+        }
+        for case_idx in 0..case_count {
+            if is_switch {
+                self.expect_keyword("case")?;
+                self.expect_punct("=>")?;
+            } else if case_idx > 0 {
+                self.expect_keyword("else")?;
+            }
+            self.expect_punct("{")?;
+
+            let is_branch = self.is_keyword("branch");
+            let cases = cases.get_or_insert_with(|| {
+                if is_branch {
+                    SelectCases::Unstructured {
+                        targets: SmallVec::new(),
+                        target_inputs: FxIndexMap::default(),
+                    }
+                } else {
+                    SelectCases::Structured(SmallVec::new())
+                }
+            });
+            match cases {
+                SelectCases::Structured(regions) => {
+                    let case =
+                        func_def_body.control_regions.define(cx, ControlRegionDef::default());
+                    self.parse_structured_region(func_def_body, case, false)?;
+                    regions.push(case);
+                }
+                SelectCases::Unstructured { targets, target_inputs } => {
+                    self.parse_branch_target(func_def_body, targets, target_inputs)?;
+                }
+            }
+            self.expect_punct("}")?;
+        }
+        if is_switch {
+            self.expect_punct("}")?;
+        }
+
+        Ok((kind, scrutinee, cases.ok_or_else(|| self.err("missing cases"))?))
+    }
+
+    /// Parse `loop(v1: T <- init, ...) { ... } while cond`.
+    fn parse_loop(&mut self, func_def_body: &mut FuncDefBody) -> Result<ControlNode, ParseError> {
+        let cx = self.cx;
+
+        self.expect_keyword("loop")?;
+        let body = func_def_body.control_regions.define(cx, ControlRegionDef::default());
+        let mut initial_inputs = SmallVec::new();
+        if self.eat_punct("(") {
+            self.parse_comma_sep(")", |this| {
+                let decl = this.parse_value_decl()?;
+                this.expect_punct("<-")?;
+                initial_inputs.push(this.parse_value()?);
+                this.define_region_input(func_def_body, body, decl)
+            })?;
+        }
+
+        self.expect_punct("{")?;
+        self.parse_structured_region(func_def_body, body, true)?;
+        if !initial_inputs.is_empty() {
+            self.expect_punct("->")?;
+            let start = self.cursor;
+            let dests = self.parse_values_tuple_or_single()?;
+            let expected_dests = (0..initial_inputs.len()).map(|input_idx| {
+                Value::ControlRegionInput { region: body, input_idx: input_idx as u32 }
+            });
+            if !dests.into_iter().eq(expected_dests) {
+                return Err(self.err_at(start, "expected loop body inputs, in order"));
+            }
+        }
+        self.expect_punct("}")?;
+        self.expect_keyword("while")?;
+        let repeat_condition = self.parse_value()?;
+
+        Ok(func_def_body.control_nodes.define(
+            cx,
+            ControlNodeDef {
+                kind: ControlNodeKind::Loop { initial_inputs, body, repeat_condition },
+                outputs: SmallVec::new(),
+            }
+            .into(),
+        ))
+    }
+
+    /// Parse a control-flow instruction (other than `if`/`spv.OpSwitch`, which
+    /// are handled by `parse_select`), returning `None` if there isn't one.
+    //
+    // NOTE(eddyb) the attributes of the `ControlInst` are left empty.
+    fn parse_control_inst(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+    ) -> Result<Option<cfg::ControlInst>, ParseError> {
+        let mut inputs = SmallVec::new();
+        let mut targets = SmallVec::new();
+        let mut target_inputs = FxIndexMap::default();
+        let kind = if self.eat_keyword("unreachable") {
+            cfg::ControlInstKind::Unreachable
+        } else if self.eat_keyword("return") {
+            if !self.is_punct("}") && !self.is_keyword("label") {
+                inputs.push(self.parse_value()?);
+            }
+            cfg::ControlInstKind::Return
+        } else if self.is_keyword("branch") {
+            self.parse_branch_target(func_def_body, &mut targets, &mut target_inputs)?;
+            cfg::ControlInstKind::Branch
+        } else if let Some(opcode) = self
+            .peek_spv_opcode()
+            .filter(|opcode| opcode.def().category == spec::InstructionCategory::ControlFlow)
+        {
+            self.expect_spv_opcode()?;
+            let operands =
+                self.parse_spv_inst_operands(opcode, SpvIdOperands::AllExplicit, &mut |this| {
+                    this.parse_value()
+                })?;
+            inputs = operands.ids.into_iter().collect();
+            cfg::ControlInstKind::ExitInvocation(cfg::ExitInvocationKind::SpvInst(spv::Inst {
+                opcode,
+                imms: operands.imms,
+            }))
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(cfg::ControlInst {
+            attrs: AttrSet::default(),
+            kind,
+            inputs,
+            targets,
+            target_inputs,
+        }))
+    }
+
+    /// Parse `branch L1` or `branch L1(v1, ...)`.
+    fn parse_branch_target(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+        targets: &mut SmallVec<[ControlRegion; 4]>,
+        target_inputs: &mut FxIndexMap<ControlRegion, SmallVec<[Value; 2]>>,
+    ) -> Result<(), ParseError> {
+        self.expect_keyword("branch")?;
+        let name = self.expect_name("L", "label name")?;
+        let target = self.label_region(func_def_body, name.key);
+        targets.push(target);
+        if self.is_punct("(") {
+            target_inputs.insert(target, self.parse_value_list()?);
+        }
+        Ok(())
+    }
+
+    /// Parse everything in a data instruction after `v1 =` (if present),
+    /// including the type of its output (either explicit, or implied).
+    fn parse_data_inst_def(
+        &mut self,
+    ) -> Result<(DataInstFormDef, SmallVec<[Value; 2]>), ParseError> {
+        let cx = self.cx;
+
+        // NOTE(eddyb) debuginfo extended instructions are printed as comments.
+        let in_comment = matches!(self.peek(), TokenKind::ExtInstCommentStart);
+        if in_comment {
+            self.bump();
+            if !(self.is_keyword("spv") && self.is_keyword_at(2, "extinst")) {
+                return Err(self.err_expected("`spv.extinst`"));
+            }
+        }
+
+        let start = self.cursor;
+        let (kind, inputs, default_output_type) = if self.eat_keyword("call") {
+            let name = self.expect_name("F", "function name")?;
+            let callee = *self.func_names.get(&name.key).ok_or_else(|| {
+                self.err_at(start + 1, format!("undefined function `{}`", name.key))
+            })?;
+            (DataInstKind::FuncCall(callee), self.parse_value_list()?, None)
+        } else if self.is_keyword("qptr") && self.is_punct_at(1, ".") {
+            self.bump();
+            self.bump();
+            let (op, inputs) = self.parse_qptr_op()?;
+            (op.into(), inputs, None)
+        } else if self.is_keyword("spv") && self.is_keyword_at(2, "extinst") {
+            self.bump();
+            self.bump();
+            self.bump();
+            self.expect_punct(".")?;
+            let (DataInstFormDef { kind, output_type }, inputs) =
+                self.parse_spv_ext_inst(in_comment)?;
+            (kind, inputs, output_type)
+        } else if self.is_keyword("spv") {
+            let opcode = self.expect_spv_opcode()?;
+            let operands =
+                self.parse_spv_inst_operands(opcode, SpvIdOperands::AllExplicit, &mut |this| {
+                    this.parse_value()
+                })?;
+            let inputs = operands.ids.into_iter().collect();
+            (DataInstKind::SpvInst(spv::Inst { opcode, imms: operands.imms }), inputs, None)
+        } else {
+            let name = self.expect_ident()?;
+            match &name[..] {
+                "CompositeExtract" | "CompositeInsert" => {
+                    // NOTE(eddyb) the index is printed last, after the inputs.
+                    self.expect_punct("(")?;
+                    let mut inputs = SmallVec::new();
+                    let idx = loop {
+                        if matches!(self.peek(), TokenKind::Num(n) if n.bytes().all(|b| b.is_ascii_digit()))
+                        {
+                            let idx = self.expect_u32()?;
+                            self.eat_punct(",");
+                            self.expect_punct(")")?;
+                            break idx;
+                        }
+                        inputs.push(self.parse_value()?);
+                        self.expect_punct(",")?;
+                    };
+                    let op = if name == "CompositeExtract" {
+                        PureOp::CompositeExtract { idx }
+                    } else {
+                        PureOp::CompositeInsert { idx }
+                    };
+                    (DataInstKind::Pure(op), inputs, None)
+                }
+                _ => {
+                    let op = PureOp::from_name_without_imms(&name).ok_or_else(|| {
+                        self.err_at(start, format!("expected instruction, found `{name}`"))
+                    })?;
+                    (DataInstKind::Pure(op), self.parse_value_list()?, None)
+                }
+            }
+        };
+
+        let output_type =
+            if self.eat_punct(":") { Some(self.parse_type()?) } else { default_output_type };
+
+        if in_comment && !matches!(self.bump(), TokenKind::ExtInstCommentEnd) {
+            return Err(self.err_at(self.cursor - 1, "expected end of `// spv.extinst...` comment"));
+        }
+
+        // NOTE(eddyb) this mirrors `spv::lower`, which only uses `PureOp`s
+        // for instructions with scalar/vector outputs.
+        let kind = match kind {
+            DataInstKind::SpvInst(spv_inst)
+                if output_type.is_some_and(|ty| {
+                    matches!(cx[ty].kind, TypeKind::Scalar { .. } | TypeKind::Vector { .. })
+                }) =>
+            {
+                spv::lower::pure_op_from_spv_inst(&spv_inst)
+                    .map_or(DataInstKind::SpvInst(spv_inst), DataInstKind::Pure)
+            }
+            kind => kind,
+        };
+
+        Ok((DataInstFormDef { kind, output_type }, inputs))
+    }
+
+    /// Parse the rest of a `qptr.*` instruction (i.e. after `qptr.`).
+    fn parse_qptr_op(&mut self) -> Result<(qptr::QPtrOp, SmallVec<[Value; 2]>), ParseError> {
+        let start = self.cursor;
+        let name = self.expect_ident()?;
+        self.expect_punct("(")?;
+
+        let mut inputs = SmallVec::new();
+        let op = match &name[..] {
+            "func_local_var" => {
+                self.expect_named_arg("size")?;
+                let size = self.expect_u32()?;
+                self.expect_punct(",")?;
+                self.expect_named_arg("align")?;
+                let align = self.expect_u32()?;
+                if self.eat_punct(",") && self.eat_keyword("initializer") {
+                    self.expect_punct(":")?;
+                    inputs.push(self.parse_value()?);
+                }
+                qptr::QPtrOp::FuncLocalVar(shapes::MemLayout { align, legacy_align: align, size })
+            }
+            "handle_array_index" => {
+                inputs.push(self.parse_value()?);
+                self.expect_punct(",")?;
+                inputs.push(self.parse_value()?);
+                qptr::QPtrOp::HandleArrayIndex
+            }
+            "buffer_data" => {
+                inputs.push(self.parse_value()?);
+                qptr::QPtrOp::BufferData
+            }
+            "buffer_dyn_len" => {
+                inputs.push(self.parse_value()?);
+                self.expect_punct(",")?;
+                self.expect_named_arg("fixed_base_size")?;
+                let fixed_base_size = self.expect_u32()?;
+                self.expect_punct(",")?;
+                self.expect_named_arg("dyn_unit_stride")?;
+                let dyn_unit_stride = self.expect_nonzero_u32()?;
+                qptr::QPtrOp::BufferDynLen { fixed_base_size, dyn_unit_stride }
+            }
+            "offset" => {
+                inputs.push(self.parse_value()?);
+                self.expect_punct(",")?;
+                let offset_start = self.cursor;
+                let negate = self.eat_punct("-");
+                let offset = i64::from(self.expect_u32()?);
+                let offset = i32::try_from(if negate { -offset } else { offset })
+                    .map_err(|err| self.err_at(offset_start, format!("invalid offset: {err}")))?;
+                qptr::QPtrOp::Offset(offset)
+            }
+            "dyn_offset" => {
+                inputs.push(self.parse_value()?);
+                self.expect_punct(",")?;
+                inputs.push(self.parse_value()?);
+                self.expect_punct("×")?;
+                let stride = self.expect_nonzero_u32()?;
+                // FIXME(eddyb) `index_bounds` aren't printed, so they're lost.
+                qptr::QPtrOp::DynOffset { stride, index_bounds: None }
+            }
+            "load" => {
+                inputs.push(self.parse_value()?);
+                qptr::QPtrOp::Load
+            }
+            "store" => {
+                inputs.push(self.parse_value()?);
+                self.expect_punct(",")?;
+                inputs.push(self.parse_value()?);
+                qptr::QPtrOp::Store
+            }
+            _ => return Err(self.err_at(start, format!("unknown instruction `qptr.{name}`"))),
+        };
+        self.eat_punct(",");
+        self.expect_punct(")")?;
+
+        Ok((op, inputs))
+    }
+
+    /// Parse the rest of a `spv.extinst."Set".Inst(...)` instruction
+    /// (i.e. after `spv.extinst.`).
+    fn parse_spv_ext_inst(
+        &mut self,
+        in_comment: bool,
+    ) -> Result<(DataInstFormDef, SmallVec<[Value; 2]>), ParseError> {
+        let cx = self.cx;
+        let spv_spec = spec::Spec::get();
+        let wk = &spv_spec.well_known;
+
+        let ext_set = self.expect_str()?;
+        self.expect_punct(".")?;
+
+        let lowercase_ext_set = ext_set.to_ascii_lowercase();
+        let ext_inst_set_desc = spv_spec
+            .get_ext_inst_set_by_lowercase_name(&lowercase_ext_set)
+            .or_else(|| cx.get_custom_ext_inst_set_by_lowercase_name(&lowercase_ext_set));
+
+        let start = self.cursor;
+        // FIXME(eddyb) this doesn't handle the `short_alias` that the printer
+        // may use instead of the full name of a (custom) extended instruction set.
+        let inst = match self.bump() {
+            TokenKind::Num(num) => num.parse().ok(),
+            TokenKind::Ident(name, None) => ext_inst_set_desc.and_then(|desc| {
+                desc.instructions
+                    .iter()
+                    .find(|(_, inst_desc)| inst_desc.name == name)
+                    .map(|(&inst, _)| inst)
+            }),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            self.err_at(
+                start,
+                format!("unknown instruction in extended instruction set {ext_set:?}"),
+            )
+        })?;
+
+        let mut inputs = SmallVec::new();
+        self.expect_punct("(")?;
+        self.parse_comma_sep(")", |this| {
+            this.skip_spv_operand_name();
+            let input = match this.peek() {
+                TokenKind::Str(_) => {
+                    let s = this.expect_str()?;
+                    Value::Const(cx.intern(ConstDef {
+                        attrs: AttrSet::default(),
+                        ty: cx.intern(TypeKind::SpvStringLiteralForExtInst),
+                        kind: ConstKind::SpvStringLiteralForExtInst(cx.intern(s)),
+                    }))
+                }
+
+                // NOTE(eddyb) debuginfo instructions use plain integers for
+                // operands that are constants (of type `u32`) in SPIR-V.
+                TokenKind::Num(num) if in_comment && num.bytes().all(|b| b.is_ascii_digit()) => {
+                    let x = this.expect_u32()?;
+                    Value::Const(cx.intern(ConstDef {
+                        attrs: AttrSet::default(),
+                        ty: cx.intern(TypeKind::Scalar { kind: ScalarKind::UInt, width: 32 }),
+                        kind: ConstKind::Scalar(x.into()),
+                    }))
+                }
+
+                _ => this.parse_value()?,
+            };
+            inputs.push(input);
+            Ok(())
+        })?;
+
+        // NOTE(eddyb) the printer omits `OpTypeVoid` output types.
+        let void_type = cx.intern(TypeKind::SpvInst {
+            spv_inst: wk.OpTypeVoid.into(),
+            type_and_const_inputs: SmallVec::new(),
+        });
+        let kind = DataInstKind::SpvExtInst { ext_set: cx.intern(ext_set), inst };
+        Ok((DataInstFormDef { kind, output_type: Some(void_type) }, inputs))
+    }
+
+    // Attribute parsing.
+
+    /// Parse any attributes (including `// at ...` and diagnostic comments).
+    fn parse_attrs(&mut self) -> Result<AttrSetDef, ParseError> {
+        let mut attrs = AttrSetDef::default();
+        loop {
+            match self.peek() {
+                TokenKind::DebugLine { file_path, line, col } => {
+                    let attr = Attr::SpvDebugLine {
+                        file_path: OrdAssertEq(self.cx.intern(&file_path[..])),
+                        line: *line,
+                        col: *col,
+                    };
+                    self.bump();
+                    attrs.attrs.insert(attr);
+                }
+                TokenKind::Diag(diag) => {
+                    let diag = diag.clone();
+                    self.bump();
+                    attrs.push_diag(diag);
+                }
+                TokenKind::Punct("#[") => {
+                    self.bump();
+                    let attr = self.parse_attr()?;
+                    self.expect_punct("]")?;
+                    attrs.attrs.insert(attr);
+                }
+                _ => return Ok(attrs),
+            }
+        }
+    }
+
+    /// Parse the contents of a `#[...]` attribute.
+    fn parse_attr(&mut self) -> Result<Attr, ParseError> {
+        let spv_spec = spec::Spec::get();
+        let wk = &spv_spec.well_known;
+
+        let start = self.cursor;
+
+        if self.eat_keyword("name") {
+            self.expect_punct("=")?;
+            let name = self.expect_str()?;
+            if name.contains('\0') {
+                return Err(self.err_at(start, "names cannot contain NUL"));
+            }
+            return Ok(Attr::SpvAnnotation(spv::Inst {
+                opcode: wk.OpName,
+                imms: spv::encode_literal_string(&name).collect(),
+            }));
+        }
+
+        if self.eat_keyword("qptr") {
+            self.expect_punct(".")?;
+            let name = self.expect_ident()?;
+            let attr = match &name[..] {
+                "to_spv_ptr_input" => {
+                    self.expect_punct("(")?;
+                    self.expect_named_arg("input_idx")?;
+                    let input_idx = self.expect_u32()?;
+                    self.expect_punct(",")?;
+                    let pointee = self.parse_type()?;
+                    self.eat_punct(",");
+                    self.expect_punct(")")?;
+                    qptr::QPtrAttr::ToSpvPtrInput { input_idx, pointee: OrdAssertEq(pointee) }
+                }
+                "from_spv_ptr_output" => {
+                    self.expect_punct("(")?;
+                    let addr_space = self.parse_addr_space()?;
+                    self.expect_punct(",")?;
+                    let pointee = self.parse_type()?;
+                    self.eat_punct(",");
+                    self.expect_punct(")")?;
+                    qptr::QPtrAttr::FromSpvPtrOutput {
+                        addr_space: OrdAssertEq(addr_space),
+                        pointee: OrdAssertEq(pointee),
+                    }
+                }
+                // FIXME(eddyb) support parsing `qptr::QPtrUsage`.
+                "usage" => {
+                    return Err(self.err_at(start, "`qptr.usage` attributes are not supported"));
+                }
+                _ => return Err(self.err_at(start, format!("unknown attribute `qptr.{name}`"))),
+            };
+            return Ok(Attr::QPtr(attr));
+        }
+
+        self.expect_keyword("spv")?;
+        self.expect_punct(".")?;
+        let name_start = self.cursor;
+        let name = self.expect_ident()?;
+
+        // NOTE(eddyb) attributes printed as whole instructions always have
+        // their first ID operand (i.e. the target of the annotation) implicit.
+        if let Some(opcode) = spv_spec.instructions.lookup(&name) {
+            let operands =
+                self.parse_spv_inst_operands(opcode, SpvIdOperands::FirstImplicit, &mut reject_id)?;
+            return Ok(Attr::SpvAnnotation(spv::Inst { opcode, imms: operands.imms }));
+        }
+
+        let kind = spv_spec
+            .operand_kinds
+            .lookup(&name)
+            .filter(|kind| {
+                matches!(
+                    kind.def(),
+                    spec::OperandKindDef::BitEnum { .. } | spec::OperandKindDef::ValueEnum { .. }
+                )
+            })
+            .ok_or_else(|| {
+                self.err_at(name_start, format!("unknown SPIR-V instruction or enum `{name}`"))
+            })?;
+        self.expect_punct(".")?;
+        let mut imms = SmallVec::new();
+        self.parse_spv_enumerand(kind, &mut imms, &mut SmallVec::new(), &mut reject_id)?;
+
+        if kind == wk.Decoration {
+            // HACK(eddyb) the printer doesn't distinguish `OpDecorateString`
+            // (required for string operands) from `OpDecorate`.
+            let has_string_operands = imms.iter().any(|imm| match *imm {
+                spv::Imm::Short(kind, _) | spv::Imm::LongStart(kind, _) => kind == wk.LiteralString,
+                spv::Imm::LongCont(..) => false,
+            });
+            let opcode = if has_string_operands { wk.OpDecorateString } else { wk.OpDecorate };
+            return Ok(Attr::SpvAnnotation(spv::Inst { opcode, imms }));
+        }
+        // NOTE(eddyb) `ExecutionMode` isn't "well-known" (as it's only used here).
+        if kind.name() == "ExecutionMode" {
+            return Ok(Attr::SpvAnnotation(spv::Inst { opcode: wk.OpExecutionMode, imms }));
+        }
+        match (kind.def(), &imms[..]) {
+            (spec::OperandKindDef::BitEnum { .. }, &[imm]) => Ok(Attr::SpvBitflagsOperand(imm)),
+            _ => Err(self.err_at(start, format!("unsupported `spv.{name}` attribute"))),
+        }
+    }
+
+    // Type/constant/value parsing.
+
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        self.parse_type_with_attrs(AttrSet::default())
+    }
+
+    /// Parse a type (either by name, or its definition, with `attrs` applied).
+    fn parse_type_with_attrs(&mut self, attrs: AttrSet) -> Result<Type, ParseError> {
+        let cx = self.cx;
+
+        let start = self.cursor;
+        let mut ty_or_kind =
+            match self.name_at(0, "T") {
+                Some(name) => {
+                    self.bump();
+                    Either::Left(*self.types.get(&name.key).ok_or_else(|| {
+                        self.err_at(start, format!("undefined type `{}`", name.key))
+                    })?)
+                }
+                None => Either::Right(self.parse_type_kind()?),
+            };
+
+        // NOTE(eddyb) vector types are printed as `T×N` (e.g. `f32×4`).
+        while self.eat_punct("×") {
+            let elem = match ty_or_kind {
+                Either::Left(ty) => ty,
+                Either::Right(kind) => cx.intern(kind),
+            };
+            let elem_count = self.expect_nonzero_u32()?.get();
+            let kind = match cx[elem].kind {
+                TypeKind::Scalar { .. } => TypeKind::Vector { elem, elem_count },
+                _ => TypeKind::SpvInst {
+                    spv_inst: spv::lift::vector_type_to_spv_inst(elem_count),
+                    type_and_const_inputs: [TypeOrConst::Type(elem)].into_iter().collect(),
+                },
+            };
+            ty_or_kind = Either::Right(kind);
+        }
+
+        match ty_or_kind {
+            Either::Left(ty) => {
+                if attrs != AttrSet::default() {
+                    return Err(self.err_at(start, "expected type definition, found type name"));
+                }
+                Ok(ty)
+            }
+            Either::Right(kind) => Ok(cx.intern(TypeDef { attrs, kind })),
+        }
+    }
+
+    fn parse_type_kind(&mut self) -> Result<TypeKind, ParseError> {
+        let cx = self.cx;
+        let wk = &spec::Spec::get().well_known;
+
+        let start = self.cursor;
+
+        if self.eat_keyword("qptr") {
+            return Ok(TypeKind::QPtr);
+        }
+        if let TokenKind::Ident(ident, None) = self.peek() {
+            if let Some((kind, width)) = scalar_type_from_name(ident) {
+                self.bump();
+                return Ok(TypeKind::Scalar { kind, width });
+            }
+        }
+        if self.eat_keyword("type_of") {
+            self.expect_punct("(")?;
+            if self.expect_spv_opcode()? != wk.OpString {
+                return Err(self.err_at(start + 2, "expected `spv.OpString`"));
+            }
+            self.expect_punct(")")?;
+            return Ok(TypeKind::SpvStringLiteralForExtInst);
+        }
+        if !self.is_keyword("spv") {
+            return Err(self.err_expected("type"));
+        }
+
+        let opcode = self.expect_spv_opcode()?;
+        if opcode.def().category != spec::InstructionCategory::Type {
+            return Err(self.err_at(start, format!("expected type, found `spv.{}`", opcode.name())));
+        }
+        let operands =
+            self.parse_spv_inst_operands(opcode, SpvIdOperands::AllExplicit, &mut |this| {
+                this.parse_type_or_const()
+            })?;
+        let spv_inst = spv::Inst { opcode, imms: operands.imms };
+
+        // NOTE(eddyb) this mirrors `spv::lower`, which uses `TypeKind::Scalar`
+        // and `TypeKind::Vector` whenever possible.
+        let native_kind = match operands.ids[..] {
+            [] => spv::lower::scalar_type_from_spv_inst(&spv_inst),
+            [TypeOrConst::Type(elem)] => spv::lower::vector_type_from_spv_inst(cx, &spv_inst, elem),
+            _ => None,
+        };
+        Ok(native_kind.unwrap_or(TypeKind::SpvInst {
+            spv_inst,
+            type_and_const_inputs: operands.ids.into_iter().collect(),
+        }))
+    }
+
+    fn is_type_start(&self) -> bool {
+        match self.peek() {
+            TokenKind::Ident(ident, None) => {
+                ident == "qptr"
+                    || ident == "type_of"
+                    || scalar_type_from_name(ident).is_some()
+                    || self.name_at(0, "T").is_some()
+                    || self.peek_spv_opcode().is_some_and(|opcode| {
+                        opcode.def().category == spec::InstructionCategory::Type
+                    })
+            }
+            TokenKind::Ident(_, Some(_)) => self.name_at(0, "T").is_some(),
+            _ => false,
+        }
+    }
+
+    fn parse_type_or_const(&mut self) -> Result<TypeOrConst, ParseError> {
+        if self.is_type_start() {
+            Ok(TypeOrConst::Type(self.parse_type()?))
+        } else {
+            Ok(TypeOrConst::Const(self.parse_const()?))
+        }
+    }
+
+    fn parse_const(&mut self) -> Result<Const, ParseError> {
+        let start = self.cursor;
+        if let Some(name) = self.name_at(0, "C") {
+            self.bump();
+            return self
+                .consts
+                .get(&name.key)
+                .copied()
+                .ok_or_else(|| self.err_at(start, format!("undefined constant `{}`", name.key)));
+        }
+
+        let (ty, kind) = self.parse_const_def()?;
+        Ok(self.cx.intern(ConstDef { attrs: AttrSet::default(), ty, kind }))
+    }
+
+    /// Parse a constant definition (i.e. not just its name), returning its
+    /// type and kind (see also `ConstDef`).
+    fn parse_const_def(&mut self) -> Result<(Type, ConstKind), ParseError> {
+        let cx = self.cx;
+        let wk = &spec::Spec::get().well_known;
+
+        let start = self.cursor;
+
+        // Scalar literals (e.g. `123u32`, `-1s8`, `1.5f32`, `-inff32`).
+        let negate = self.eat_punct("-");
+        let literal = match self.peek() {
+            TokenKind::Num(num) => Some(num.clone()),
+            TokenKind::Ident(ident, None)
+                if ident.starts_with("inf") || ident.starts_with("NaN") =>
+            {
+                Some(ident.clone())
+            }
+            _ => None,
+        };
+        if let Some(literal) = literal {
+            self.bump();
+            let (kind, width, bits) = scalar_literal_from_str(&literal, negate)
+                .map_err(|message| self.err_at(start, message))?;
+            return Ok((cx.intern(TypeKind::Scalar { kind, width }), ConstKind::Scalar(bits)));
+        }
+        if negate {
+            return Err(self.err_expected("numeric literal"));
+        }
+
+        let type_bool = || cx.intern(TypeKind::Scalar { kind: ScalarKind::Bool, width: 1 });
+        if self.eat_keyword("false") {
+            return Ok((type_bool(), ConstKind::Scalar(0)));
+        }
+        if self.eat_keyword("true") {
+            return Ok((type_bool(), ConstKind::Scalar(1)));
+        }
+
+        if self.eat_punct("&") {
+            let name = self.expect_name("GV", "global variable name")?;
+            let gv = *self.global_var_names.get(&name.key).ok_or_else(|| {
+                self.err_at(start + 1, format!("undefined global variable `{}`", name.key))
+            })?;
+            return Ok((self.global_var_ptr_type(gv)?, ConstKind::PtrToGlobalVar(gv)));
+        }
+
+        if self.eat_keyword("undef") {
+            self.expect_punct(":")?;
+            return Ok((self.parse_type()?, ConstKind::Undef));
+        }
+
+        if !self.is_keyword("spv") {
+            return Err(self.err_expected("constant"));
+        }
+        let opcode = self.expect_spv_opcode()?;
+        if opcode == wk.OpString {
+            self.expect_punct("(")?;
+            let s = self.expect_str()?;
+            self.expect_punct(")")?;
+            return Ok((
+                cx.intern(TypeKind::SpvStringLiteralForExtInst),
+                ConstKind::SpvStringLiteralForExtInst(cx.intern(s)),
+            ));
+        }
+        if opcode.def().category != spec::InstructionCategory::Const && opcode != wk.OpUndef {
+            return Err(
+                self.err_at(start, format!("expected constant, found `spv.{}`", opcode.name()))
+            );
+        }
+
+        let mut operands =
+            self.parse_spv_inst_operands(opcode, SpvIdOperands::AllExplicit, &mut |this| {
+                this.parse_const()
+            })?;
+        self.expect_punct(":")?;
+        let ty = self.parse_type()?;
+        self.resize_contextual_literals(start, &mut operands.imms, ty)?;
+        let spv_inst = spv::Inst { opcode, imms: operands.imms };
+
+        // NOTE(eddyb) this mirrors `spv::lower`, which uses `ConstKind::Undef`
+        // and `ConstKind::Scalar` whenever possible.
+        let native_kind = if operands.ids.is_empty() {
+            if opcode == wk.OpUndef {
+                Some(ConstKind::Undef)
+            } else {
+                spv::lower::scalar_const_from_spv_inst(cx, ty, &spv_inst)
+            }
+        } else {
+            None
+        };
+        let kind = native_kind.unwrap_or_else(|| ConstKind::SpvInst {
+            spv_inst_and_const_inputs: Rc::new((spv_inst, operands.ids.into_iter().collect())),
+        });
+        Ok((ty, kind))
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        let start = self.cursor;
+        if let Some(name) = self.name_at(0, "v") {
+            self.bump();
+            return self
+                .values
+                .get(&name.key)
+                .copied()
+                .ok_or_else(|| self.err_at(start, format!("undefined value `{}`", name.key)));
+        }
+        Ok(Value::Const(self.parse_const()?))
+    }
+
+    /// Parse `(v1, ...)` (including the parentheses).
+    fn parse_value_list(&mut self) -> Result<SmallVec<[Value; 2]>, ParseError> {
+        let mut values = SmallVec::new();
+        self.expect_punct("(")?;
+        self.parse_comma_sep(")", |this| {
+            values.push(this.parse_value()?);
+            Ok(())
+        })?;
+        Ok(values)
+    }
+
+    /// Parse either `(v1, ...)` or just `v1`, e.g. for region outputs.
+    fn parse_values_tuple_or_single(&mut self) -> Result<SmallVec<[Value; 2]>, ParseError> {
+        if self.is_punct("(") {
+            self.parse_value_list()
+        } else {
+            Ok([self.parse_value()?].into_iter().collect())
+        }
+    }
+
+    // SPIR-V instruction/operand parsing.
+
+    /// Check for `spv.OpFoo`, returning the opcode of `OpFoo`.
+    fn peek_spv_opcode(&self) -> Option<spec::Opcode> {
+        match (self.peek_nth(0), self.peek_nth(1), self.peek_nth(2)) {
+            (TokenKind::Ident(spv, None), TokenKind::Punct("."), TokenKind::Ident(name, None))
+                if spv == "spv" =>
+            {
+                spec::Spec::get().instructions.lookup(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_spv_opcode(&mut self) -> Result<spec::Opcode, ParseError> {
+        let opcode =
+            self.peek_spv_opcode().ok_or_else(|| self.err_expected("SPIR-V instruction"))?;
+        self.cursor += 3;
+        Ok(opcode)
+    }
+
+    /// Skip the name of an operand (e.g. `Name:`), if present.
+    fn skip_spv_operand_name(&mut self) {
+        if let TokenKind::Ident(name, None) = self.peek() {
+            // NOTE(eddyb) `undef: T` is the only other syntax with this shape.
+            if name != "undef" && self.is_punct_at(1, ":") {
+                self.cursor += 2;
+            }
+        }
+    }
+
+    /// Parse the (optional) parenthesized operands of SPIR-V instruction,
+    /// as printed by `spv::print` (with the implicit ID operands per `id_operands`).
+    fn parse_spv_inst_operands<ID>(
+        &mut self,
+        opcode: spec::Opcode,
+        id_operands: SpvIdOperands,
+        parse_id: &mut impl FnMut(&mut Self) -> Result<ID, ParseError>,
+    ) -> Result<SpvInstOperands<ID>, ParseError> {
+        let mut operands =
+            SpvInstOperands { imms: SmallVec::new(), ids: SmallVec::new(), implicit_id_count: 0 };
+
+        let has_parens = self.eat_punct("(");
+        let mut any_explicit_operands = false;
+        let mut id_operand_count = 0;
+        for (mode, name_and_kind) in opcode.def().all_operands_with_names() {
+            let (name, kind) = name_and_kind.name_and_kind();
+
+            if let spec::OperandKindDef::Id = kind.def() {
+                let id_idx = id_operand_count;
+                id_operand_count += 1;
+
+                let implicit = match id_operands {
+                    SpvIdOperands::AllExplicit => false,
+                    SpvIdOperands::FirstImplicit => id_idx == 0,
+                    SpvIdOperands::AllButFirstImplicit => id_idx > 0,
+                };
+                let disallowed = id_operands == SpvIdOperands::FirstImplicit && id_idx > 0;
+                if implicit || disallowed {
+                    if mode == spec::OperandMode::Optional {
+                        break;
+                    }
+                    if disallowed {
+                        return Err(self.err(format!(
+                            "unsupported `{name}` operand for `spv.{}`",
+                            opcode.name()
+                        )));
+                    }
+                    operands.implicit_id_count += 1;
+                    continue;
+                }
+            }
+
+            let at_end =
+                !has_parens || self.is_punct(")") || self.is_punct(",") && self.is_punct_at(1, ")");
+            if at_end {
+                if mode == spec::OperandMode::Optional {
+                    break;
+                }
+                return Err(self.err_expected(&format!("`{name}` operand")));
+            }
+            if any_explicit_operands {
+                self.expect_punct(",")?;
+            }
+            any_explicit_operands = true;
+
+            self.skip_spv_operand_name();
+            self.parse_spv_operand(kind, &mut operands.imms, &mut operands.ids, parse_id)?;
+        }
+        if has_parens {
+            self.eat_punct(",");
+            self.expect_punct(")")?;
+        }
+
+        Ok(operands)
+    }
+
+    fn parse_spv_operand<ID>(
+        &mut self,
+        kind: spec::OperandKind,
+        imms: &mut SmallVec<[spv::Imm; 2]>,
+        ids: &mut SmallVec<[ID; 4]>,
+        parse_id: &mut impl FnMut(&mut Self) -> Result<ID, ParseError>,
+    ) -> Result<(), ParseError> {
+        match kind.def() {
+            spec::OperandKindDef::Id => {
+                ids.push(parse_id(self)?);
+                Ok(())
+            }
+            spec::OperandKindDef::BitEnum { .. } | spec::OperandKindDef::ValueEnum { .. } => {
+                self.expect_keyword("spv")?;
+                self.expect_punct(".")?;
+                let start = self.cursor;
+                if self.expect_ident()? != kind.name() {
+                    return Err(self.err_at(start, format!("expected `spv.{}`", kind.name())));
+                }
+                self.expect_punct(".")?;
+                self.parse_spv_enumerand(kind, imms, ids, parse_id)
+            }
+            spec::OperandKindDef::Literal { size } => {
+                let start = self.cursor;
+                match (size, self.bump()) {
+                    (spec::LiteralSize::NulTerminated, TokenKind::Str(s)) => {
+                        if s.contains('\0') {
+                            return Err(self.err_at(start, "string literals cannot contain NUL"));
+                        }
+                        imms.extend(spv::encode_literal_string(&s));
+                    }
+                    (
+                        spec::LiteralSize::Word | spec::LiteralSize::FromContextualType,
+                        TokenKind::Num(num),
+                    ) => {
+                        let words = literal_words_from_str(&num)
+                            .filter(|words| {
+                                !matches!(size, spec::LiteralSize::Word) || words.len() == 1
+                            })
+                            .ok_or_else(|| {
+                                self.err_at(start, format!("invalid `{}` literal", kind.name()))
+                            })?;
+                        push_literal_words(imms, kind, &words);
+                    }
+                    _ => {
+                        return Err(
+                            self.err_at(start, format!("expected `{}` literal", kind.name()))
+                        );
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Parse the part of an enum operand after `spv.Kind.`, i.e. the name
+    /// of a `ValueEnum` variant, or `BitEnum` bit (or `{A, B}` for multiple),
+    /// and any parameters (e.g. `spv.Decoration.Location(0)`).
+    fn parse_spv_enumerand<ID>(
+        &mut self,
+        kind: spec::OperandKind,
+        imms: &mut SmallVec<[spv::Imm; 2]>,
+        ids: &mut SmallVec<[ID; 4]>,
+        parse_id: &mut impl FnMut(&mut Self) -> Result<ID, ParseError>,
+    ) -> Result<(), ParseError> {
+        let unknown = |this: &Self, start| {
+            this.err_at(start, format!("unknown `spv.{}` enumerand", kind.name()))
+        };
+        match kind.def() {
+            spec::OperandKindDef::BitEnum { empty_name, bits } => {
+                let is_set = self.eat_punct("{");
+                let mut word = 0;
+                let mut param_imms = SmallVec::<[spv::Imm; 2]>::new();
+                loop {
+                    let start = self.cursor;
+                    let name = self.expect_spv_enumerand_name()?;
+                    if !is_set && name == *empty_name {
+                        break;
+                    }
+                    let bit_idx = bits.lookup(&name).ok_or_else(|| unknown(self, start))?;
+                    let bit = 1 << bit_idx.0;
+                    if word >= bit {
+                        return Err(self.err_at(start, "bits must be in increasing order"));
+                    }
+                    word |= bit;
+                    self.parse_spv_enumerant_params(
+                        &bits[bit_idx],
+                        &mut param_imms,
+                        ids,
+                        parse_id,
+                    )?;
+
+                    if !is_set || !self.eat_punct(",") || self.is_punct("}") {
+                        break;
+                    }
+                }
+                if is_set {
+                    self.expect_punct("}")?;
+                }
+                imms.push(spv::Imm::Short(kind, word));
+                imms.extend(param_imms);
+            }
+            spec::OperandKindDef::ValueEnum { variants } => {
+                let start = self.cursor;
+                let name = self.expect_spv_enumerand_name()?;
+                let v = variants.lookup(&name).ok_or_else(|| unknown(self, start))?;
+                imms.push(spv::Imm::Short(kind, v.into()));
+                self.parse_spv_enumerant_params(&variants[v], imms, ids, parse_id)?;
+            }
+            spec::OperandKindDef::Id | spec::OperandKindDef::Literal { .. } => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Parse the name of an enumerand (which can also start with a digit, e.g. `2D`).
+    fn expect_spv_enumerand_name(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            TokenKind::Ident(name, None) | TokenKind::Num(name) => {
+                let name = name.clone();
+                self.bump();
+                Ok(name)
+            }
+            _ => Err(self.err_expected("enumerand name")),
+        }
+    }
+
+    fn parse_spv_enumerant_params<ID>(
+        &mut self,
+        enumerant: &spec::Enumerant,
+        imms: &mut SmallVec<[spv::Imm; 2]>,
+        ids: &mut SmallVec<[ID; 4]>,
+        parse_id: &mut impl FnMut(&mut Self) -> Result<ID, ParseError>,
+    ) -> Result<(), ParseError> {
+        let mut params = enumerant.all_params_with_names().peekable();
+        if params.peek().is_none() || !self.eat_punct("(") {
+            return match params.find(|(mode, _)| *mode == spec::OperandMode::Required) {
+                Some((_, name_and_kind)) => Err(self.err_expected(&format!(
+                    "`(` (for `{}` parameter)",
+                    name_and_kind.name_and_kind().0
+                ))),
+                None => Ok(()),
+            };
+        }
+
+        let mut any_params = false;
+        for (mode, name_and_kind) in params {
+            let (name, kind) = name_and_kind.name_and_kind();
+            if self.is_punct(")") || self.is_punct(",") && self.is_punct_at(1, ")") {
+                if mode == spec::OperandMode::Optional {
+                    break;
+                }
+                return Err(self.err_expected(&format!("`{name}` parameter")));
+            }
+            if any_params {
+                self.expect_punct(",")?;
+            }
+            any_params = true;
+
+            self.skip_spv_operand_name();
+            self.parse_spv_operand(kind, imms, ids, parse_id)?;
+        }
+        self.eat_punct(",");
+        self.expect_punct(")")
+    }
+
+    /// Parse an enum operand (e.g. `spv.StorageClass.Uniform`) without any
+    /// parameters, returning its single word.
+    fn parse_spv_enum_operand_word(&mut self, kind: spec::OperandKind) -> Result<u32, ParseError> {
+        self.expect_keyword("spv")?;
+        self.expect_punct(".")?;
+        let start = self.cursor;
+        if self.expect_ident()? != kind.name() {
+            return Err(self.err_at(start, format!("expected `spv.{}`", kind.name())));
+        }
+        self.expect_punct(".")?;
+        self.parse_spv_enumerand_word(kind)
+    }
+
+    /// Like `parse_spv_enum_operand_word`, but without the `spv.Kind.` prefix.
+    fn parse_spv_enumerand_word(&mut self, kind: spec::OperandKind) -> Result<u32, ParseError> {
+        let start = self.cursor;
+        let mut imms = SmallVec::new();
+        self.parse_spv_enumerand(kind, &mut imms, &mut SmallVec::new(), &mut reject_id)?;
+        match imms[..] {
+            [spv::Imm::Short(_, word)] => Ok(word),
+            _ => Err(self.err_at(start, format!("unsupported `spv.{}` parameters", kind.name()))),
+        }
+    }
+
+    /// Adjust the number of words of all contextual literals (i.e. those
+    /// sized by the type of the instruction, or that of its first input),
+    /// which were parsed using as few words as possible.
+    fn resize_contextual_literals(
+        &self,
+        start: usize,
+        imms: &mut SmallVec<[spv::Imm; 2]>,
+        ty: Type,
+    ) -> Result<(), ParseError> {
+        let wk = &spec::Spec::get().well_known;
+
+        let is_contextual = |imm: &spv::Imm| match *imm {
+            spv::Imm::Short(kind, _)
+            | spv::Imm::LongStart(kind, _)
+            | spv::Imm::LongCont(kind, _) => kind == wk.LiteralContextDependentNumber,
+        };
+        if !imms.iter().any(is_contextual) {
+            return Ok(());
+        }
+
+        let width = match &self.cx[ty].kind {
+            TypeKind::Scalar { width, .. } => Some(*width),
+            TypeKind::SpvInst { spv_inst, .. }
+                if [wk.OpTypeInt, wk.OpTypeFloat].contains(&spv_inst.opcode) =>
+            {
+                match spv_inst.imms[0] {
+                    spv::Imm::Short(_, width) => Some(width),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+        .ok_or_else(|| self.err_at(start, "contextual literals require integer/float types"))?;
+        let word_count = usize::try_from(width.div_ceil(32)).unwrap().max(1);
+
+        let mut resized_imms = SmallVec::new();
+        let mut imms_iter = mem::take(imms).into_iter().peekable();
+        while let Some(imm) = imms_iter.next() {
+            if !is_contextual(&imm) {
+                resized_imms.push(imm);
+                continue;
+            }
+            let mut words = SmallVec::<[u32; 2]>::new();
+            let (spv::Imm::Short(_, word)
+            | spv::Imm::LongStart(_, word)
+            | spv::Imm::LongCont(_, word)) = imm;
+            words.push(word);
+            while let Some(&spv::Imm::LongCont(_, word)) = imms_iter.peek() {
+                words.push(word);
+                imms_iter.next();
+            }
+            if words.len() > word_count && words[word_count..].iter().any(|&w| w != 0) {
+                return Err(self.err_at(start, "literal too large for its type"));
+            }
+            words.resize(word_count, 0);
+            push_literal_words(&mut resized_imms, wk.LiteralContextDependentNumber, &words);
+        }
+        *imms = resized_imms;
+
+        Ok(())
+    }
+}
+
+/// Get the `TypeKind::Scalar` `kind` and `width` for its printed name (e.g. `u32`).
+fn scalar_type_from_name(name: &str) -> Option<(ScalarKind, u32)> {
+    if name == "bool" {
+        return Some((ScalarKind::Bool, 1));
+    }
+    let kind = match name.as_bytes().first()? {
+        b's' => ScalarKind::SInt,
+        b'u' => ScalarKind::UInt,
+        b'f' => ScalarKind::Float,
+        _ => return None,
+    };
+    let width = &name[1..];
+    if width.is_empty() || width.starts_with('0') || !width.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((kind, width.parse().ok()?))
+}
+
+/// Parse a scalar literal, with its type as a suffix (e.g. `123u32`, `1.5f32`),
+/// returning its `kind`, `width`, and bits (see `ConstKind::Scalar`).
+fn scalar_literal_from_str(s: &str, negate: bool) -> Result<(ScalarKind, u32, u64), String> {
+    let suffix_start =
+        s.rfind(['s', 'u', 'f']).ok_or("missing type suffix (e.g. `u32`) for scalar literal")?;
+    let (value, suffix) = s.split_at(suffix_start);
+    let (kind, width) = scalar_type_from_name(suffix)
+        .filter(|&(_, width)| width <= 64)
+        .ok_or_else(|| format!("unsupported scalar literal type `{suffix}`"))?;
+
+    let invalid = |reason: &dyn fmt::Display| {
+        format!("invalid `{suffix}` literal `{}{s}`: {reason}", if negate { "-" } else { "" })
+    };
+    let mask = u64::MAX >> (64 - width);
+    let bits = match kind {
+        ScalarKind::SInt => {
+            let x = value.parse::<i128>().map_err(|err| invalid(&err))?;
+            let x = if negate { -x } else { x };
+            let half = 1_i128 << (width - 1);
+            if !(-half..half).contains(&x) {
+                return Err(invalid(&"out of range"));
+            }
+            (x as u64) & mask
+        }
+        ScalarKind::UInt => {
+            let x = value.parse::<u64>().map_err(|err| invalid(&err))?;
+            if negate || x > mask {
+                return Err(invalid(&"out of range"));
+            }
+            x
+        }
+        ScalarKind::Float => {
+            let value = if negate { format!("-{value}") } else { value.to_string() };
+            match width {
+                32 => value.parse::<f32>().map(|x| x.to_bits().into()),
+                64 => value.parse::<f64>().map(|x| x.to_bits()),
+                _ => return Err(format!("unsupported scalar literal type `{suffix}`")),
+            }
+            .map_err(|err| invalid(&err))?
+        }
+        ScalarKind::Bool => unreachable!(),
+    };
+    Ok((kind, width, bits))
+}
+
+/// Parse a (decimal, or `0x`-prefixed hexadecimal) SPIR-V literal, as printed
+/// by `spv::print`, into as few 32-bit words as possible (least significant first).
+fn literal_words_from_str(s: &str) -> Option<SmallVec<[u32; 2]>> {
+    let mut x = match s.strip_prefix("0x") {
+        Some(hex) => u128::from_str_radix(&hex.replace('_', ""), 16).ok()?,
+        None => s.parse::<u128>().ok()?,
+    };
+    let mut words = SmallVec::new();
+    loop {
+        words.push(x as u32);
+        x >>= 32;
+        if x == 0 {
+            return Some(words);
+        }
+    }
+}
+
+fn push_literal_words(imms: &mut SmallVec<[spv::Imm; 2]>, kind: spec::OperandKind, words: &[u32]) {
+    match *words {
+        [word] => imms.push(spv::Imm::Short(kind, word)),
+        _ => imms.extend(words.iter().enumerate().map(|(i, &word)| {
+            if i == 0 { spv::Imm::LongStart(kind, word) } else { spv::Imm::LongCont(kind, word) }
+        })),
+    }
+}
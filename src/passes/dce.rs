@@ -0,0 +1,336 @@
+//! Dead code elimination (of [`DataInst`]s and [`ControlNode`]s).
+
+use crate::passes::manager::ReachableDecls;
+use crate::transform::{InnerInPlaceTransform, ReplaceValueWith};
+use crate::{
+    Context, ControlNode, ControlNodeKind, ControlRegion, DataInst, DataInstEffects, DeclDef,
+    FuncDefBody, Module, Value,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+
+/// Remove dead code from all the function definitions in `module` (reachable
+/// from its exports), i.e.:
+/// * [`DataInst`]s without [`DataInstEffects::SideEffects`], whose outputs are unused
+/// * outputs of `Select` [`ControlNode`]s which are unused (alongside the
+///   corresponding `outputs` of all of the `Select`'s cases)
+/// * `Select`/`Loop` [`ControlNode`]s which don't contain any live code, and
+///   whose outputs (or, for `Loop`s, any values defined in their `body`) are unused
+/// * `Block` [`ControlNode`]s left empty (after removing all their [`DataInst`]s)
+///
+/// Note that `Loop`s are assumed to always terminate, i.e. a `Loop` without
+/// side-effects is removed, even if it might have looped forever (the same
+/// assumption that e.g. `spirv-opt`'s aggressive DCE makes, when removing loops).
+///
+/// In functions with unstructured control-flow, all values passed between
+/// regions (or used by their terminators) are conservatively kept alive.
+//
+// FIXME(eddyb) also remove unused `Loop` state (i.e. `body` inputs/outputs).
+pub fn eliminate_dead_code(module: &mut Module) {
+    let reachable = ReachableDecls::collect(module);
+    eliminate_dead_code_in_reachable(module, &reachable);
+}
+
+pub(crate) fn eliminate_dead_code_in_reachable(module: &mut Module, reachable: &ReachableDecls) {
+    let cx = &module.cx();
+
+    for &func in &reachable.funcs {
+        if let DeclDef::Present(func_def_body) = &mut module.funcs[func].def {
+            eliminate_dead_code_in_func(cx, func_def_body);
+        }
+    }
+}
+
+fn eliminate_dead_code_in_func(cx: &Context, func_def_body: &mut FuncDefBody) {
+    let top_level_regions: SmallVec<[_; 8]> = match &func_def_body.unstructured_cfg {
+        None => [func_def_body.body].into_iter().collect(),
+        Some(cfg) => cfg.rev_post_order(func_def_body).collect(),
+    };
+
+    let mut liveness = Liveness {
+        func_def_body,
+
+        parent_node_of_region: FxHashMap::default(),
+        parent_region_of_node: FxHashMap::default(),
+        parent_block_of_inst: FxHashMap::default(),
+
+        live_insts: FxHashSet::default(),
+        live_nodes: FxHashSet::default(),
+        live_node_outputs: FxHashSet::default(),
+
+        worklist: vec![],
+    };
+
+    // Side-effecting instructions, and values leaving the function (either
+    // as the structured return, or through unstructured control-flow), are
+    // the roots from which all the live code is reachable.
+    let mut root_insts = vec![];
+    for &region in &top_level_regions {
+        liveness.collect_parents_and_root_insts(cx, region, &mut root_insts);
+
+        let region_outputs = &func_def_body.at(region).def().outputs;
+        liveness.worklist.extend(region_outputs.iter().copied());
+    }
+    if let Some(cfg) = &func_def_body.unstructured_cfg {
+        for control_inst in
+            top_level_regions.iter().filter_map(|&region| cfg.control_inst_on_exit_from.get(region))
+        {
+            liveness.worklist.extend(control_inst.inputs.iter().copied());
+            for target_inputs in control_inst.target_inputs.values() {
+                liveness.worklist.extend(target_inputs.iter().copied());
+            }
+        }
+    }
+    for inst in root_insts {
+        liveness.mark_inst_live(inst);
+    }
+    while let Some(v) = liveness.worklist.pop() {
+        liveness.mark_value_live(v);
+    }
+
+    let Liveness { live_insts, live_nodes, live_node_outputs, .. } = liveness;
+    let mut remover = DeadCodeRemover {
+        live_insts,
+        live_nodes,
+        live_node_outputs,
+        node_output_remaps: FxHashMap::default(),
+    };
+    for region in top_level_regions {
+        remover.remove_dead_code_in_region(func_def_body, region);
+    }
+
+    if remover.node_output_remaps.is_empty() {
+        return;
+    }
+
+    let node_output_remaps = &remover.node_output_remaps;
+    func_def_body.inner_in_place_transform_with(&mut ReplaceValueWith(|v| match v {
+        Value::ControlNodeOutput { control_node, output_idx } => {
+            let new_output_idx = node_output_remaps.get(&control_node)?[output_idx as usize];
+            Some(Value::ControlNodeOutput {
+                control_node,
+                // NOTE(eddyb) only live outputs can still be used at this point.
+                output_idx: new_output_idx.unwrap(),
+            })
+        }
+        _ => None,
+    }));
+}
+
+/// Liveness analysis state, starting from "roots" (e.g. side-effecting
+/// instructions) and propagating backwards through uses.
+struct Liveness<'a> {
+    func_def_body: &'a FuncDefBody,
+
+    // FIXME(eddyb) use `EntityOrientedDenseMap` here.
+    parent_node_of_region: FxHashMap<ControlRegion, ControlNode>,
+    parent_region_of_node: FxHashMap<ControlNode, ControlRegion>,
+    parent_block_of_inst: FxHashMap<DataInst, ControlNode>,
+
+    live_insts: FxHashSet<DataInst>,
+    /// Only `Select`/`Loop` nodes (`Block`s are kept iff they have live insts).
+    live_nodes: FxHashSet<ControlNode>,
+    live_node_outputs: FxHashSet<(ControlNode, u32)>,
+
+    /// Values to mark as live (via `mark_value_live`), tracked separately
+    /// to limit recursion (which happens only for parent nodes).
+    worklist: Vec<Value>,
+}
+
+impl Liveness<'_> {
+    fn collect_parents_and_root_insts(
+        &mut self,
+        cx: &Context,
+        region: ControlRegion,
+        root_insts: &mut Vec<DataInst>,
+    ) {
+        let func = self.func_def_body;
+        for func_at_node in func.at(region).at_children() {
+            let node = func_at_node.position;
+            self.parent_region_of_node.insert(node, region);
+
+            match &func_at_node.def().kind {
+                &ControlNodeKind::Block { insts } => {
+                    for func_at_inst in func.at(insts) {
+                        let inst = func_at_inst.position;
+                        self.parent_block_of_inst.insert(inst, node);
+
+                        let effects = cx[func_at_inst.def().form].kind.effects(cx);
+                        if effects == DataInstEffects::SideEffects {
+                            root_insts.push(inst);
+                        }
+                    }
+                }
+                ControlNodeKind::Select { cases, .. } => {
+                    for &case in cases {
+                        self.parent_node_of_region.insert(case, node);
+                        self.collect_parents_and_root_insts(cx, case, root_insts);
+                    }
+                }
+                &ControlNodeKind::Loop { body, .. } => {
+                    self.parent_node_of_region.insert(body, node);
+                    self.collect_parents_and_root_insts(cx, body, root_insts);
+                }
+            }
+        }
+    }
+
+    fn mark_value_live(&mut self, v: Value) {
+        match v {
+            Value::Const(_) => {}
+
+            // NOTE(eddyb) only `Loop` bodies have inputs (other than the
+            // function body, and regions in unstructured control-flow).
+            Value::ControlRegionInput { region, input_idx: _ } => {
+                if let Some(&loop_node) = self.parent_node_of_region.get(&region) {
+                    self.mark_node_live(loop_node);
+                }
+            }
+
+            Value::ControlNodeOutput { control_node, output_idx } => {
+                if self.live_node_outputs.insert((control_node, output_idx)) {
+                    self.mark_node_live(control_node);
+
+                    let func = self.func_def_body;
+                    if let ControlNodeKind::Select { cases, .. } = &func.at(control_node).def().kind
+                    {
+                        for &case in cases {
+                            let case_outputs = &func.at(case).def().outputs;
+                            self.worklist.push(case_outputs[output_idx as usize]);
+                        }
+                    }
+                }
+            }
+
+            Value::DataInstOutput(inst) => self.mark_inst_live(inst),
+        }
+    }
+
+    fn mark_inst_live(&mut self, inst: DataInst) {
+        if self.live_insts.insert(inst) {
+            let inst_def = self.func_def_body.at(inst).def();
+            self.worklist.extend(inst_def.inputs.iter().copied());
+
+            self.mark_parent_node_live(self.parent_block_of_inst[&inst]);
+        }
+    }
+
+    /// Mark live the `Select`/`Loop` containing `node` (if any), as `node` is
+    /// live itself (and so it needs to be kept, alongside all its ancestors).
+    fn mark_parent_node_live(&mut self, node: ControlNode) {
+        let parent_region = self.parent_region_of_node[&node];
+        if let Some(&parent_node) = self.parent_node_of_region.get(&parent_region) {
+            self.mark_node_live(parent_node);
+        }
+    }
+
+    fn mark_node_live(&mut self, node: ControlNode) {
+        if !self.live_nodes.insert(node) {
+            return;
+        }
+
+        let func = self.func_def_body;
+        match &func.at(node).def().kind {
+            ControlNodeKind::Block { .. } => unreachable!(),
+            &ControlNodeKind::Select { scrutinee, .. } => self.worklist.push(scrutinee),
+            ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                self.worklist.extend(initial_inputs.iter().copied());
+                self.worklist.extend(func.at(*body).def().outputs.iter().copied());
+                self.worklist.push(*repeat_condition);
+            }
+        }
+
+        self.mark_parent_node_live(node);
+    }
+}
+
+struct DeadCodeRemover {
+    live_insts: FxHashSet<DataInst>,
+    live_nodes: FxHashSet<ControlNode>,
+    live_node_outputs: FxHashSet<(ControlNode, u32)>,
+
+    /// For each `Select` with some removed outputs, the new index of every
+    /// original output (or `None` for the removed ones).
+    node_output_remaps: FxHashMap<ControlNode, SmallVec<[Option<u32>; 2]>>,
+}
+
+impl DeadCodeRemover {
+    fn remove_dead_code_in_region(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+        region: ControlRegion,
+    ) {
+        let mut next_node = func_def_body.at(region).def().children.iter().first;
+        while let Some(node) = next_node {
+            next_node = func_def_body.control_nodes[node].next_in_list();
+
+            let is_dead = match func_def_body.control_nodes[node].kind {
+                ControlNodeKind::Block { mut insts } => {
+                    let mut next_inst = insts.iter().first;
+                    while let Some(inst) = next_inst {
+                        next_inst = func_def_body.data_insts[inst].next_in_list();
+                        if !self.live_insts.contains(&inst) {
+                            insts.remove(inst, &mut func_def_body.data_insts);
+                        }
+                    }
+                    func_def_body.control_nodes[node].kind = ControlNodeKind::Block { insts };
+
+                    insts.is_empty()
+                }
+                ControlNodeKind::Select { .. } | ControlNodeKind::Loop { .. } => {
+                    !self.live_nodes.contains(&node)
+                }
+            };
+            if is_dead {
+                func_def_body.control_regions[region]
+                    .children
+                    .remove(node, &mut func_def_body.control_nodes);
+                continue;
+            }
+
+            let child_regions = match &func_def_body.control_nodes[node].kind {
+                ControlNodeKind::Block { .. } => continue,
+                ControlNodeKind::Select { cases, .. } => cases.clone(),
+                &ControlNodeKind::Loop { body, .. } => [body].into_iter().collect(),
+            };
+            for &child_region in &child_regions {
+                self.remove_dead_code_in_region(func_def_body, child_region);
+            }
+
+            // Remove unused `Select` outputs (and the corresponding case outputs).
+            let output_count = func_def_body.control_nodes[node].outputs.len();
+            let is_output_live: SmallVec<[_; 2]> = (0..output_count)
+                .map(|output_idx| {
+                    self.live_node_outputs.contains(&(node, u32::try_from(output_idx).unwrap()))
+                })
+                .collect();
+            if is_output_live.iter().all(|&live| live) {
+                continue;
+            }
+
+            fn retain_live<T>(outputs: &mut SmallVec<[T; 2]>, is_output_live: &[bool]) {
+                let mut output_idx = 0;
+                outputs.retain(|_| {
+                    output_idx += 1;
+                    is_output_live[output_idx - 1]
+                });
+            }
+            retain_live(&mut func_def_body.control_nodes[node].outputs, &is_output_live);
+            for &case in &child_regions {
+                retain_live(&mut func_def_body.control_regions[case].outputs, &is_output_live);
+            }
+
+            let mut next_new_output_idx = 0;
+            let remap = is_output_live
+                .iter()
+                .map(|&live| {
+                    live.then(|| {
+                        next_new_output_idx += 1;
+                        next_new_output_idx - 1
+                    })
+                })
+                .collect();
+            self.node_output_remaps.insert(node, remap);
+        }
+    }
+}
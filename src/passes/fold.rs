@@ -0,0 +1,777 @@
+//! Constant folding and algebraic simplification.
+
+use crate::passes::manager::ReachableDecls;
+use crate::qptr::QPtrOp;
+use crate::spv::{self, spec};
+use crate::transform::{InnerInPlaceTransform, ReplaceValueWith};
+use crate::{
+    Attr, AttrSet, Const, ConstDef, ConstKind, Context, ControlNode, ControlNodeKind,
+    ControlRegion, DataInst, DataInstKind, DeclDef, EntityList, FuncDefBody, Module, PureOp,
+    ScalarKind, SelectionKind, Type, TypeKind, Value,
+};
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+use std::rc::Rc;
+
+/// Fold constants and simplify instructions/control-flow, in all the function
+/// definitions in `module` (reachable from its exports), i.e.:
+/// * [`PureOp`]s (and `OpCompositeConstruct`) with only constant inputs are
+///   evaluated (if their result is well-defined), and replaced with constants
+///   (specialization constants, i.e. `OpSpecConstant*`, are never evaluated)
+/// * some algebraic identities (e.g. `x + 0`, `(x + b) - b`, or `(x * a) / a`
+///   when `x * a` is known to not overflow, such as when `x` is the length of
+///   a buffer's dynamically-sized array, and `a` its stride) are used to
+///   simplify instructions
+/// * `Select`s with a constant `scrutinee` are replaced by the taken case
+/// * `Loop`s with a constant `false` `repeat_condition` are replaced by their
+///   body (which is always executed exactly once)
+//
+// FIXME(eddyb) repeat until nothing changes, as e.g. replacing a `Loop` with
+// its body, after it was processed, can make more constants available in it.
+pub fn fold_consts_and_simplify(module: &mut Module) {
+    let reachable = ReachableDecls::collect(module);
+    fold_consts_and_simplify_in_reachable(module, &reachable);
+}
+
+pub(crate) fn fold_consts_and_simplify_in_reachable(
+    module: &mut Module,
+    reachable: &ReachableDecls,
+) {
+    let cx = &module.cx();
+
+    for &func in &reachable.funcs {
+        if let DeclDef::Present(func_def_body) = &mut module.funcs[func].def {
+            fold_consts_and_simplify_in_func(cx, func_def_body);
+        }
+    }
+}
+
+fn fold_consts_and_simplify_in_func(cx: &Context, func_def_body: &mut FuncDefBody) {
+    let mut folder =
+        Folder { cx, wk: &spec::Spec::get().well_known, replacements: FxHashMap::default() };
+
+    let top_level_regions: SmallVec<[_; 8]> = match &func_def_body.unstructured_cfg {
+        None => [func_def_body.body].into_iter().collect(),
+        Some(cfg) => cfg.rev_post_order(func_def_body).collect(),
+    };
+    for region in top_level_regions {
+        folder.fold_in_region(func_def_body, region);
+    }
+
+    if folder.replacements.is_empty() {
+        return;
+    }
+
+    func_def_body.inner_in_place_transform_with(&mut ReplaceValueWith(|v| {
+        let resolved = folder.resolve(v);
+        (resolved != v).then_some(resolved)
+    }));
+}
+
+struct Folder<'a> {
+    cx: &'a Context,
+    wk: &'static spec::WellKnown,
+
+    /// Values (of removed [`DataInst`]s, `Select` outputs, `Loop` body inputs)
+    /// which need to be replaced (potentially with other replaced values).
+    replacements: FxHashMap<Value, Value>,
+}
+
+impl Folder<'_> {
+    fn resolve(&self, mut v: Value) -> Value {
+        while let Some(&replacement) = self.replacements.get(&v) {
+            v = replacement;
+        }
+        v
+    }
+
+    fn fold_in_region(&mut self, func_def_body: &mut FuncDefBody, region: ControlRegion) {
+        let mut next_node = func_def_body.at(region).def().children.iter().first;
+        while let Some(node) = next_node {
+            next_node = func_def_body.control_nodes[node].next_in_list();
+
+            match func_def_body.control_nodes[node].kind {
+                ControlNodeKind::Block { mut insts } => {
+                    let mut next_inst = insts.iter().first;
+                    while let Some(inst) = next_inst {
+                        next_inst = func_def_body.data_insts[inst].next_in_list();
+
+                        if let Some(v) = self.try_fold_inst(func_def_body, inst) {
+                            self.replacements.insert(Value::DataInstOutput(inst), v);
+                            insts.remove(inst, &mut func_def_body.data_insts);
+                        }
+                    }
+                    func_def_body.control_nodes[node].kind = ControlNodeKind::Block { insts };
+
+                    if insts.is_empty() {
+                        func_def_body.control_regions[region]
+                            .children
+                            .remove(node, &mut func_def_body.control_nodes);
+                    }
+                }
+
+                ControlNodeKind::Select { ref kind, scrutinee, ref cases } => {
+                    let taken_case_idx = self.taken_case_idx(kind, self.resolve(scrutinee));
+                    let cases = cases.clone();
+
+                    let taken_case = match taken_case_idx {
+                        Some(case_idx) => cases[case_idx],
+                        None => {
+                            for case in cases {
+                                self.fold_in_region(func_def_body, case);
+                            }
+                            continue;
+                        }
+                    };
+
+                    self.fold_in_region(func_def_body, taken_case);
+                    for (output_idx, &v) in
+                        func_def_body.at(taken_case).def().outputs.iter().enumerate()
+                    {
+                        self.replacements.insert(
+                            Value::ControlNodeOutput {
+                                control_node: node,
+                                output_idx: output_idx.try_into().unwrap(),
+                            },
+                            v,
+                        );
+                    }
+                    replace_node_with_region_children(func_def_body, region, node, taken_case);
+                }
+
+                ControlNodeKind::Loop { ref initial_inputs, body, repeat_condition } => {
+                    let initial_inputs = initial_inputs.clone();
+
+                    self.fold_in_region(func_def_body, body);
+
+                    let repeat_condition = self.resolve(repeat_condition);
+                    if self.const_scalar_bits(repeat_condition) != Some(0) {
+                        continue;
+                    }
+
+                    for (input_idx, v) in initial_inputs.into_iter().enumerate() {
+                        self.replacements.insert(
+                            Value::ControlRegionInput {
+                                region: body,
+                                input_idx: input_idx.try_into().unwrap(),
+                            },
+                            v,
+                        );
+                    }
+                    replace_node_with_region_children(func_def_body, region, node, body);
+                }
+            }
+        }
+    }
+
+    /// Returns the index of the case a `Select` (with `kind`) would choose,
+    /// if `scrutinee` is a constant.
+    fn taken_case_idx(&self, kind: &SelectionKind, scrutinee: Value) -> Option<usize> {
+        let cx = self.cx;
+
+        let (width, bits) = match scrutinee {
+            Value::Const(ct) => match (&cx[cx[ct].ty].kind, &cx[ct].kind) {
+                (&TypeKind::Scalar { width, .. }, &ConstKind::Scalar(bits)) => (width, bits),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match kind {
+            SelectionKind::BoolCond => Some(if bits != 0 { 0 } else { 1 }),
+
+            // NOTE(eddyb) `OpSwitch` cases are the default case, followed by
+            // one case for each literal (in the same order as the literals).
+            SelectionKind::SpvInst(spv_inst) if spv_inst.opcode == self.wk.OpSwitch => {
+                let mut literals = vec![];
+                let mut imms = spv_inst.imms.iter();
+                while let Some(&imm) = imms.next() {
+                    literals.push(match imm {
+                        spv::Imm::Short(_, x) => u64::from(x),
+                        spv::Imm::LongStart(_, lo) => match imms.next() {
+                            Some(&spv::Imm::LongCont(_, hi)) => {
+                                u64::from(lo) | (u64::from(hi) << 32)
+                            }
+                            _ => return None,
+                        },
+                        spv::Imm::LongCont(..) => return None,
+                    });
+                }
+
+                Some(
+                    literals
+                        .iter()
+                        .position(|&literal| (literal & mask(width)) == bits)
+                        .map_or(0, |literal_idx| 1 + literal_idx),
+                )
+            }
+
+            SelectionKind::SpvInst(_) => None,
+        }
+    }
+
+    fn const_scalar_bits(&self, v: Value) -> Option<u64> {
+        match v {
+            Value::Const(ct) => match self.cx[ct].kind {
+                ConstKind::Scalar(bits) => Some(bits),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of `ct`, if it's a (SPIR-V `OpConstantComposite`) composite.
+    fn const_composite_elems(&self, ct: Const) -> Option<&[Const]> {
+        match &self.cx[ct].kind {
+            ConstKind::SpvInst { spv_inst_and_const_inputs } => {
+                let (spv_inst, const_inputs) = &**spv_inst_and_const_inputs;
+                (spv_inst.opcode == self.wk.OpConstantComposite).then_some(&const_inputs[..])
+            }
+            _ => None,
+        }
+    }
+
+    fn intern_scalar_const(&self, ty: Type, bits: u64) -> Const {
+        self.cx.intern(ConstDef { attrs: AttrSet::default(), ty, kind: ConstKind::Scalar(bits) })
+    }
+
+    fn intern_composite_const(&self, ty: Type, elems: impl IntoIterator<Item = Const>) -> Const {
+        self.cx.intern(ConstDef {
+            attrs: AttrSet::default(),
+            ty,
+            kind: ConstKind::SpvInst {
+                spv_inst_and_const_inputs: Rc::new((
+                    self.wk.OpConstantComposite.into(),
+                    elems.into_iter().collect(),
+                )),
+            },
+        })
+    }
+
+    /// Returns the value that `inst` can be replaced with, if any.
+    fn try_fold_inst(&self, func_def_body: &FuncDefBody, inst: DataInst) -> Option<Value> {
+        let cx = self.cx;
+
+        let inst_def = func_def_body.at(inst).def();
+        let form = &cx[inst_def.form];
+        let output_type = form.output_type?;
+        let inputs: SmallVec<[_; 4]> = inst_def.inputs.iter().map(|&v| self.resolve(v)).collect();
+
+        match &form.kind {
+            &DataInstKind::Pure(op) => self
+                .simplify_pure_op(func_def_body, op, &inputs)
+                // NOTE(eddyb) SPIR-V allows mixed-signedness integer operands
+                // (e.g. `OpIAdd %uint %x_int %uint_0`), so an identity can't
+                // be used when `x` doesn't already have the output type.
+                .filter(|&x| func_def_body.at(x).type_of(cx) == output_type)
+                .or_else(|| self.fold_pure_op(op, output_type, &inputs).map(Value::Const)),
+
+            DataInstKind::SpvInst(spv_inst) if spv_inst.opcode == self.wk.OpCompositeConstruct => {
+                // NOTE(eddyb) vectors can also be constructed from smaller
+                // vectors, which isn't supported here (for simplicity).
+                if let TypeKind::Vector { elem_count, .. } = cx[output_type].kind {
+                    if inputs.len() != usize::try_from(elem_count).unwrap() {
+                        return None;
+                    }
+                }
+                // NOTE(eddyb) specialization constants (`OpSpecConstant*`) can't
+                // be used, as `OpConstantComposite` only allows constant inputs
+                // that aren't specializable (just like its own output).
+                let elems = inputs
+                    .iter()
+                    .map(|&v| match v {
+                        Value::Const(ct) => match &cx[ct].kind {
+                            ConstKind::Scalar(_) => Some(ct),
+                            ConstKind::SpvInst { spv_inst_and_const_inputs } => {
+                                let (spv_inst, _) = &**spv_inst_and_const_inputs;
+                                spv_inst.opcode.name().starts_with("OpConstant").then_some(ct)
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect::<Option<SmallVec<[_; 4]>>>()?;
+                Some(Value::Const(self.intern_composite_const(output_type, elems)))
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Apply algebraic identities to simplify `op` (without requiring all of its
+    /// inputs to be constant).
+    fn simplify_pure_op(
+        &self,
+        func_def_body: &FuncDefBody,
+        op: PureOp,
+        inputs: &[Value],
+    ) -> Option<Value> {
+        let cx = self.cx;
+        let wk = self.wk;
+
+        let is_zero = |v| self.const_scalar_bits(v) == Some(0);
+        let is_one = |v| self.const_scalar_bits(v) == Some(1);
+        let is_all_ones = |v| match v {
+            Value::Const(ct) => match (&cx[cx[ct].ty].kind, &cx[ct].kind) {
+                (&TypeKind::Scalar { width, .. }, &ConstKind::Scalar(bits)) => bits == mask(width),
+                _ => false,
+            },
+            _ => false,
+        };
+
+        // The `PureOp`, `attrs`, and (resolved) `inputs` of `v`'s definition.
+        let pure_op_def = |v| match v {
+            Value::DataInstOutput(inst) => {
+                let inst_def = func_def_body.at(inst).def();
+                match cx[inst_def.form].kind {
+                    DataInstKind::Pure(op) => Some((
+                        op,
+                        inst_def.attrs,
+                        inst_def
+                            .inputs
+                            .iter()
+                            .map(|&v| self.resolve(v))
+                            .collect::<SmallVec<[_; 2]>>(),
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let has_decoration = |attrs: AttrSet, decoration: u32| {
+            cx[attrs].attrs.contains(&Attr::SpvAnnotation(spv::Inst {
+                opcode: wk.OpDecorate,
+                imms: [spv::Imm::Short(wk.Decoration, decoration)].into_iter().collect(),
+            }))
+        };
+
+        match (op, inputs) {
+            (
+                PureOp::IAdd
+                | PureOp::ISub
+                | PureOp::BitwiseOr
+                | PureOp::BitwiseXor
+                | PureOp::ShiftLeftLogical
+                | PureOp::ShiftRightLogical
+                | PureOp::ShiftRightArithmetic,
+                &[x, zero],
+            ) if is_zero(zero) => Some(x),
+            (PureOp::IAdd | PureOp::BitwiseOr | PureOp::BitwiseXor, &[zero, x])
+                if is_zero(zero) =>
+            {
+                Some(x)
+            }
+            (PureOp::IMul | PureOp::UDiv | PureOp::SDiv, &[x, one]) if is_one(one) => Some(x),
+            (PureOp::IMul, &[one, x]) if is_one(one) => Some(x),
+            (PureOp::BitwiseAnd, &[x, all_ones] | &[all_ones, x]) if is_all_ones(all_ones) => {
+                Some(x)
+            }
+
+            // `Select` only needs a constant condition (if it's scalar).
+            (PureOp::Select, &[Value::Const(cond), t, f]) => match cx[cond].kind {
+                ConstKind::Scalar(cond) => Some(if cond != 0 { t } else { f }),
+                _ => None,
+            },
+
+            // `(x + b) - b` and `(x - b) + b` to `x` (regardless of overflow).
+            (PureOp::ISub, &[x_plus_b, b]) => match pure_op_def(x_plus_b)? {
+                (PureOp::IAdd, _, add_inputs) => match add_inputs[..] {
+                    [x, add_b] | [add_b, x] if add_b == b => Some(x),
+                    _ => None,
+                },
+                _ => None,
+            },
+            (PureOp::IAdd, &[a, b]) => [(a, b), (b, a)].into_iter().find_map(|(x_minus_b, b)| {
+                match pure_op_def(x_minus_b)? {
+                    (PureOp::ISub, _, sub_inputs) => match sub_inputs[..] {
+                        [x, sub_b] if sub_b == b => Some(x),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }),
+
+            // `(x * a) / a` to `x`, but only if `x * a` is known not to overflow,
+            // either from a `NoUnsignedWrap`/`NoSignedWrap` decoration, or (for
+            // `UDiv`) because `x` is a `QPtrOp::BufferDynLen` with `a` as its
+            // `dyn_unit_stride`, so `x * a` can't exceed the buffer's size.
+            (PureOp::UDiv | PureOp::SDiv, &[x_times_a, a]) => {
+                let no_wrap_decoration =
+                    if op == PureOp::UDiv { wk.NoUnsignedWrap } else { wk.NoSignedWrap };
+                let is_buffer_dyn_len_with_stride = |x, stride| match x {
+                    Value::DataInstOutput(inst) => match cx[func_def_body.at(inst).def().form].kind
+                    {
+                        DataInstKind::QPtr(QPtrOp::BufferDynLen { dyn_unit_stride, .. }) => {
+                            self.const_scalar_bits(stride) == Some(dyn_unit_stride.get().into())
+                        }
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                match pure_op_def(x_times_a)? {
+                    (PureOp::IMul, mul_attrs, mul_inputs) => match mul_inputs[..] {
+                        [x, mul_a] | [mul_a, x]
+                            if mul_a == a
+                                && (has_decoration(mul_attrs, no_wrap_decoration)
+                                    || op == PureOp::UDiv
+                                        && is_buffer_dyn_len_with_stride(x, a)) =>
+                        {
+                            Some(x)
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Evaluate `op` (with a `output_type` result), if all `inputs` are constant.
+    fn fold_pure_op(&self, op: PureOp, output_type: Type, inputs: &[Value]) -> Option<Const> {
+        let cx = self.cx;
+
+        let inputs = inputs
+            .iter()
+            .map(|&v| match v {
+                Value::Const(ct) => Some(ct),
+                _ => None,
+            })
+            .collect::<Option<SmallVec<[_; 4]>>>()?;
+
+        match (op, &inputs[..]) {
+            (PureOp::CompositeExtract { idx }, &[composite]) => {
+                self.const_composite_elems(composite)?.get(usize::try_from(idx).ok()?).copied()
+            }
+            (PureOp::CompositeInsert { idx }, &[new_elem, composite]) => {
+                let mut elems: SmallVec<[_; 4]> =
+                    self.const_composite_elems(composite)?.iter().copied().collect();
+                *elems.get_mut(usize::try_from(idx).ok()?)? = new_elem;
+                Some(self.intern_composite_const(output_type, elems))
+            }
+            (PureOp::VectorExtractDynamic, &[vector, idx]) => {
+                let idx = usize::try_from(self.const_scalar_bits(Value::Const(idx))?).ok()?;
+                self.const_composite_elems(vector)?.get(idx).copied()
+            }
+            (PureOp::VectorInsertDynamic, &[vector, new_elem, idx]) => {
+                let idx = usize::try_from(self.const_scalar_bits(Value::Const(idx))?).ok()?;
+                let mut elems: SmallVec<[_; 4]> =
+                    self.const_composite_elems(vector)?.iter().copied().collect();
+                *elems.get_mut(idx)? = new_elem;
+                Some(self.intern_composite_const(output_type, elems))
+            }
+            (
+                PureOp::CompositeExtract { .. }
+                | PureOp::CompositeInsert { .. }
+                | PureOp::VectorExtractDynamic
+                | PureOp::VectorInsertDynamic,
+                _,
+            ) => None,
+
+            // All other operations apply to each vector element independently.
+            _ => match cx[output_type].kind {
+                TypeKind::Scalar { .. } => self.fold_scalar_op(op, output_type, &inputs),
+                TypeKind::Vector { elem: output_elem_type, elem_count } => {
+                    let input_elems = inputs
+                        .iter()
+                        .map(|&ct| match cx[cx[ct].ty].kind {
+                            TypeKind::Vector { elem_count: input_elem_count, .. }
+                                if input_elem_count == elem_count =>
+                            {
+                                self.const_composite_elems(ct)
+                            }
+                            _ => None,
+                        })
+                        .collect::<Option<SmallVec<[_; 4]>>>()?;
+                    let elems = (0..usize::try_from(elem_count).unwrap())
+                        .map(|i| {
+                            let inputs: SmallVec<[_; 4]> =
+                                input_elems.iter().map(|elems| elems[i]).collect();
+                            self.fold_scalar_op(op, output_elem_type, &inputs)
+                        })
+                        .collect::<Option<SmallVec<[_; 4]>>>()?;
+                    Some(self.intern_composite_const(output_type, elems))
+                }
+                _ => None,
+            },
+        }
+    }
+
+    fn fold_scalar_op(&self, op: PureOp, output_type: Type, inputs: &[Const]) -> Option<Const> {
+        let cx = self.cx;
+
+        let (output_kind, output_width) = match cx[output_type].kind {
+            TypeKind::Scalar { kind, width } => (kind, width),
+            _ => return None,
+        };
+        let inputs = inputs
+            .iter()
+            .map(|&ct| match (&cx[cx[ct].ty].kind, &cx[ct].kind) {
+                (&TypeKind::Scalar { kind, width }, &ConstKind::Scalar(bits)) => {
+                    Some(Scalar { kind, width, bits })
+                }
+                _ => None,
+            })
+            .collect::<Option<SmallVec<[_; 3]>>>()?;
+
+        let bits = eval_scalar_op(op, output_kind, output_width, &inputs)?;
+        Some(self.intern_scalar_const(output_type, bits))
+    }
+}
+
+/// Move all the children of `from_region` into `region`, in place of `node`
+/// (which is removed, and must be the parent of `from_region`).
+fn replace_node_with_region_children(
+    func_def_body: &mut FuncDefBody,
+    region: ControlRegion,
+    node: ControlNode,
+    from_region: ControlRegion,
+) {
+    let mut children = std::mem::replace(
+        &mut func_def_body.control_regions[from_region].children,
+        EntityList::empty(),
+    );
+    while let Some(child) = children.iter().first {
+        children.remove(child, &mut func_def_body.control_nodes);
+        func_def_body.control_regions[region].children.insert_before(
+            child,
+            node,
+            &mut func_def_body.control_nodes,
+        );
+    }
+    func_def_body.control_regions[region].children.remove(node, &mut func_def_body.control_nodes);
+}
+
+/// Constant scalar value, with its type (see also [`ConstKind::Scalar`]).
+#[derive(Copy, Clone)]
+struct Scalar {
+    kind: ScalarKind,
+    width: u32,
+    bits: u64,
+}
+
+impl Scalar {
+    fn sext(self) -> i64 {
+        sext(self.bits, self.width)
+    }
+
+    /// Returns `None` for floating-point widths other than `32` and `64`.
+    //
+    // FIXME(eddyb) support `f16` (and maybe `bf16`).
+    fn float(self) -> Option<f64> {
+        match (self.kind, self.width) {
+            (ScalarKind::Float, 32) => Some(f64::from(f32::from_bits(self.bits as u32))),
+            (ScalarKind::Float, 64) => Some(f64::from_bits(self.bits)),
+            _ => None,
+        }
+    }
+}
+
+fn mask(width: u32) -> u64 {
+    u64::MAX >> (64 - width)
+}
+
+fn sext(bits: u64, width: u32) -> i64 {
+    ((bits << (64 - width)) as i64) >> (64 - width)
+}
+
+/// Returns `None` for floating-point widths other than `32` and `64`
+/// (see also `Scalar::float`).
+fn float_to_bits(width: u32, x: f64) -> Option<u64> {
+    match width {
+        32 => Some(u64::from((x as f32).to_bits())),
+        64 => Some(x.to_bits()),
+        _ => None,
+    }
+}
+
+/// Evaluate `op` (with a scalar `output_kind`/`output_width` result) on scalar
+/// `inputs`, returning `None` when the result isn't well-defined (e.g. division
+/// by zero, or out-of-range shifts/conversions), or `op` isn't supported.
+//
+// NOTE(eddyb) floating-point operations are performed with `f64`, which is
+// exact for `f32` operands (before rounding the result back to `f32`), as long
+// as only one basic arithmetic operation is performed before that rounding.
+fn eval_scalar_op(
+    op: PureOp,
+    output_kind: ScalarKind,
+    output_width: u32,
+    inputs: &[Scalar],
+) -> Option<u64> {
+    let output_mask = mask(output_width);
+    let bool_bits = |b: bool| u64::from(b);
+
+    // HACK(eddyb) wrapping signed results that don't fit `output_width`
+    // are treated like overflow (i.e. as undefined).
+    let signed_result = |x: i64| {
+        let bits = (x as u64) & output_mask;
+        (sext(bits, output_width) == x).then_some(bits)
+    };
+
+    let bits = match (op, inputs) {
+        // Integer arithmetic.
+        (PureOp::SNegate, &[a]) => a.bits.wrapping_neg(),
+        (PureOp::IAdd, &[a, b]) => a.bits.wrapping_add(b.bits),
+        (PureOp::ISub, &[a, b]) => a.bits.wrapping_sub(b.bits),
+        (PureOp::IMul, &[a, b]) => a.bits.wrapping_mul(b.bits),
+        (PureOp::UDiv, &[a, b]) => a.bits.checked_div(b.bits)?,
+        (PureOp::UMod, &[a, b]) => a.bits.checked_rem(b.bits)?,
+        (PureOp::SDiv, &[a, b]) => signed_result(a.sext().checked_div(b.sext())?)?,
+        (PureOp::SRem, &[a, b]) => signed_result(a.sext().checked_rem(b.sext())?)?,
+        (PureOp::SMod, &[a, b]) => {
+            let (a, b) = (a.sext(), b.sext());
+            let rem = a.checked_rem(b)?;
+            signed_result(if rem != 0 && (rem < 0) != (b < 0) { rem + b } else { rem })?
+        }
+
+        // Floating-point arithmetic.
+        (PureOp::FNegate, &[a]) => float_to_bits(output_width, -a.float()?)?,
+        (
+            PureOp::FAdd | PureOp::FSub | PureOp::FMul | PureOp::FDiv | PureOp::FRem | PureOp::FMod,
+            &[a, b],
+        ) => {
+            let (a, b) = (a.float()?, b.float()?);
+            let is_division = matches!(op, PureOp::FDiv | PureOp::FRem | PureOp::FMod);
+            if is_division && b == 0.0 {
+                return None;
+            }
+            float_to_bits(
+                output_width,
+                match op {
+                    PureOp::FAdd => a + b,
+                    PureOp::FSub => a - b,
+                    PureOp::FMul => a * b,
+                    PureOp::FDiv => a / b,
+                    PureOp::FRem => a % b,
+                    PureOp::FMod => {
+                        let rem = a % b;
+                        if rem != 0.0 && (rem < 0.0) != (b < 0.0) { rem + b } else { rem }
+                    }
+                    _ => unreachable!(),
+                },
+            )?
+        }
+
+        // Bitwise operations.
+        (
+            PureOp::ShiftRightLogical | PureOp::ShiftRightArithmetic | PureOp::ShiftLeftLogical,
+            &[base, shift],
+        ) => {
+            if shift.bits >= u64::from(base.width) {
+                return None;
+            }
+            let shift = shift.bits as u32;
+            match op {
+                PureOp::ShiftRightLogical => base.bits >> shift,
+                PureOp::ShiftRightArithmetic => (base.sext() >> shift) as u64,
+                PureOp::ShiftLeftLogical => base.bits << shift,
+                _ => unreachable!(),
+            }
+        }
+        // NOTE(eddyb) booleans are always `0` or `1`, so they can reuse these.
+        (PureOp::BitwiseOr | PureOp::LogicalOr, &[a, b]) => a.bits | b.bits,
+        (PureOp::BitwiseXor, &[a, b]) => a.bits ^ b.bits,
+        (PureOp::BitwiseAnd | PureOp::LogicalAnd, &[a, b]) => a.bits & b.bits,
+        (PureOp::Not, &[a]) => !a.bits,
+        (PureOp::LogicalNot, &[a]) => a.bits ^ 1,
+
+        (PureOp::Select, &[cond, t, f]) => {
+            if cond.bits != 0 {
+                t.bits
+            } else {
+                f.bits
+            }
+        }
+
+        // Integer (and boolean) comparisons.
+        (PureOp::IEqual | PureOp::LogicalEqual, &[a, b]) => bool_bits(a.bits == b.bits),
+        (PureOp::INotEqual | PureOp::LogicalNotEqual, &[a, b]) => bool_bits(a.bits != b.bits),
+        (PureOp::UGreaterThan, &[a, b]) => bool_bits(a.bits > b.bits),
+        (PureOp::SGreaterThan, &[a, b]) => bool_bits(a.sext() > b.sext()),
+        (PureOp::UGreaterThanEqual, &[a, b]) => bool_bits(a.bits >= b.bits),
+        (PureOp::SGreaterThanEqual, &[a, b]) => bool_bits(a.sext() >= b.sext()),
+        (PureOp::ULessThan, &[a, b]) => bool_bits(a.bits < b.bits),
+        (PureOp::SLessThan, &[a, b]) => bool_bits(a.sext() < b.sext()),
+        (PureOp::ULessThanEqual, &[a, b]) => bool_bits(a.bits <= b.bits),
+        (PureOp::SLessThanEqual, &[a, b]) => bool_bits(a.sext() <= b.sext()),
+
+        // Floating-point comparisons.
+        (
+            PureOp::FOrdEqual
+            | PureOp::FUnordEqual
+            | PureOp::FOrdNotEqual
+            | PureOp::FUnordNotEqual
+            | PureOp::FOrdLessThan
+            | PureOp::FUnordLessThan
+            | PureOp::FOrdGreaterThan
+            | PureOp::FUnordGreaterThan
+            | PureOp::FOrdLessThanEqual
+            | PureOp::FUnordLessThanEqual
+            | PureOp::FOrdGreaterThanEqual
+            | PureOp::FUnordGreaterThanEqual,
+            &[a, b],
+        ) => {
+            let (a, b) = (a.float()?, b.float()?);
+            let unordered = a.is_nan() || b.is_nan();
+            bool_bits(match op {
+                PureOp::FOrdEqual => !unordered && a == b,
+                PureOp::FUnordEqual => unordered || a == b,
+                PureOp::FOrdNotEqual => !unordered && a != b,
+                PureOp::FUnordNotEqual => unordered || a != b,
+                PureOp::FOrdLessThan => !unordered && a < b,
+                PureOp::FUnordLessThan => unordered || a < b,
+                PureOp::FOrdGreaterThan => !unordered && a > b,
+                PureOp::FUnordGreaterThan => unordered || a > b,
+                PureOp::FOrdLessThanEqual => !unordered && a <= b,
+                PureOp::FUnordLessThanEqual => unordered || a <= b,
+                PureOp::FOrdGreaterThanEqual => !unordered && a >= b,
+                PureOp::FUnordGreaterThanEqual => unordered || a >= b,
+                _ => unreachable!(),
+            })
+        }
+
+        // Conversions.
+        (PureOp::ConvertFToU, &[a]) => {
+            let x = a.float()?.trunc();
+            // NOTE(eddyb) `2^width` is exactly representable (unlike `2^width - 1`).
+            if !(x >= 0.0 && x < 2.0f64.powi(output_width.try_into().unwrap())) {
+                return None;
+            }
+            x as u64
+        }
+        (PureOp::ConvertFToS, &[a]) => {
+            let x = a.float()?.trunc();
+            let limit = 2.0f64.powi((output_width - 1).try_into().unwrap());
+            if !(x >= -limit && x < limit) {
+                return None;
+            }
+            (x as i64) as u64
+        }
+        // NOTE(eddyb) these convert to `f32` directly (instead of through `f64`),
+        // as 64-bit integers can't always be exactly represented as `f64`.
+        (PureOp::ConvertSToF, &[a]) => match output_width {
+            32 => u64::from((a.sext() as f32).to_bits()),
+            64 => (a.sext() as f64).to_bits(),
+            _ => return None,
+        },
+        (PureOp::ConvertUToF, &[a]) => match output_width {
+            32 => u64::from((a.bits as f32).to_bits()),
+            64 => (a.bits as f64).to_bits(),
+            _ => return None,
+        },
+        (PureOp::UConvert, &[a]) => a.bits,
+        (PureOp::SConvert, &[a]) => a.sext() as u64,
+        (PureOp::FConvert, &[a]) => float_to_bits(output_width, a.float()?)?,
+        (PureOp::Bitcast, &[a]) if a.width == output_width => a.bits,
+
+        _ => return None,
+    };
+
+    // NOTE(eddyb) this is only a sanity check for the `ScalarKind` of the
+    // result (actual type-checking is left to e.g. `passes::verify`).
+    if output_kind == ScalarKind::Bool && bits > 1 {
+        return None;
+    }
+
+    Some(bits & output_mask)
+}
@@ -0,0 +1,346 @@
+//! Global value numbering (i.e. deduplication of pure [`DataInst`]s).
+
+use crate::passes::manager::ReachableDecls;
+use crate::transform::{InnerInPlaceTransform, ReplaceValueWith};
+use crate::{
+    AttrSet, Context, ControlNode, ControlNodeDef, ControlNodeKind, ControlRegion, DataInst,
+    DataInstEffects, DataInstForm, DeclDef, EntityList, FuncDefBody, FxIndexMap, Module, Value,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+use std::collections::hash_map::Entry;
+
+/// Deduplicate pure [`DataInst`]s (i.e. [`DataInstEffects::Pure`]), in all the
+/// function definitions in `module` (reachable from its exports).
+///
+/// A [`DataInst`] is replaced with another one, with identical `attrs`, `form`
+/// and `inputs`, which dominates it (under structured control-flow, that's any
+/// [`DataInst`] found earlier in the same [`ControlRegion`], or in any of its
+/// ancestor regions, before the [`ControlNode`] containing that region).
+///
+/// Additionally, identical [`DataInst`]s found in all the cases of a `Select`
+/// (directly, i.e. not nested in further `Select`s/`Loop`s), which only depend
+/// on values defined outside that `Select`, are hoisted into its parent region,
+/// (allowing their deduplication both across cases, and after the `Select`).
+///
+/// In functions with unstructured control-flow, each region is processed
+/// independently (i.e. only [`DataInst`]s in the same region, or in the same
+/// `Select`/`Loop` nested in it, can be deduplicated).
+//
+// FIXME(eddyb) use dominance for unstructured control-flow as well.
+pub fn deduplicate_pure_data_insts(module: &mut Module) {
+    let reachable = ReachableDecls::collect(module);
+    deduplicate_pure_data_insts_in_reachable(module, &reachable);
+}
+
+pub(crate) fn deduplicate_pure_data_insts_in_reachable(
+    module: &mut Module,
+    reachable: &ReachableDecls,
+) {
+    let cx = &module.cx();
+
+    for &func in &reachable.funcs {
+        if let DeclDef::Present(func_def_body) = &mut module.funcs[func].def {
+            deduplicate_pure_data_insts_in_func(cx, func_def_body);
+        }
+    }
+}
+
+fn deduplicate_pure_data_insts_in_func(cx: &Context, func_def_body: &mut FuncDefBody) {
+    let top_level_regions: SmallVec<[_; 8]> = match &func_def_body.unstructured_cfg {
+        None => [func_def_body.body].into_iter().collect(),
+        Some(cfg) => cfg.rev_post_order(func_def_body).collect(),
+    };
+
+    let mut gvn = Gvn {
+        cx,
+        available: FxHashMap::default(),
+        available_log: vec![],
+        replacements: FxHashMap::default(),
+    };
+    for region in top_level_regions {
+        gvn.in_new_scope(|gvn| gvn.visit_region(func_def_body, region));
+    }
+
+    if gvn.replacements.is_empty() {
+        return;
+    }
+
+    let replacements = &gvn.replacements;
+    func_def_body.inner_in_place_transform_with(&mut ReplaceValueWith(|v| match v {
+        Value::DataInstOutput(inst) => replacements.get(&inst).copied(),
+        _ => None,
+    }));
+}
+
+/// Everything a pure [`DataInst`]'s output depends on (with `inputs` already
+/// taking into account replacements of previously deduplicated [`DataInst`]s).
+type DataInstKey = (AttrSet, DataInstForm, SmallVec<[Value; 2]>);
+
+struct Gvn<'a> {
+    cx: &'a Context,
+
+    /// Pure [`DataInst`]s dominating the current position, by their "key".
+    available: FxHashMap<DataInstKey, DataInst>,
+
+    /// Keys added to `available`, in order, to allow removing the ones added
+    /// in a scope (see `in_new_scope`), once leaving it.
+    available_log: Vec<DataInstKey>,
+
+    /// Removed [`DataInst`]s, and the (dominating) values replacing them.
+    replacements: FxHashMap<DataInst, Value>,
+}
+
+impl Gvn<'_> {
+    fn in_new_scope(&mut self, f: impl FnOnce(&mut Self)) {
+        let scope_start = self.available_log.len();
+        f(self);
+        for key in self.available_log.drain(scope_start..) {
+            self.available.remove(&key);
+        }
+    }
+
+    fn make_available(&mut self, key: DataInstKey, inst: DataInst) {
+        self.available_log.push(key.clone());
+        self.available.insert(key, inst);
+    }
+
+    /// Returns `None` for [`DataInst`]s which aren't pure (and so can't be deduplicated).
+    fn key_for_inst(&self, func_def_body: &FuncDefBody, inst: DataInst) -> Option<DataInstKey> {
+        let inst_def = func_def_body.at(inst).def();
+        if self.cx[inst_def.form].kind.effects(self.cx) != DataInstEffects::Pure {
+            return None;
+        }
+        let inputs = inst_def
+            .inputs
+            .iter()
+            .map(|&v| match v {
+                Value::DataInstOutput(inst) => self.replacements.get(&inst).copied().unwrap_or(v),
+                _ => v,
+            })
+            .collect();
+        Some((inst_def.attrs, inst_def.form, inputs))
+    }
+
+    fn visit_region(&mut self, func_def_body: &mut FuncDefBody, region: ControlRegion) {
+        let mut next_node = func_def_body.at(region).def().children.iter().first;
+        while let Some(node) = next_node {
+            next_node = func_def_body.control_nodes[node].next_in_list();
+
+            match func_def_body.control_nodes[node].kind {
+                ControlNodeKind::Block { mut insts } => {
+                    let mut next_inst = insts.iter().first;
+                    while let Some(inst) = next_inst {
+                        next_inst = func_def_body.data_insts[inst].next_in_list();
+
+                        if let Some(key) = self.key_for_inst(func_def_body, inst) {
+                            match self.available.entry(key) {
+                                Entry::Occupied(entry) => {
+                                    self.replacements
+                                        .insert(inst, Value::DataInstOutput(*entry.get()));
+                                    insts.remove(inst, &mut func_def_body.data_insts);
+                                }
+                                Entry::Vacant(entry) => {
+                                    self.available_log.push(entry.key().clone());
+                                    entry.insert(inst);
+                                }
+                            }
+                        }
+                    }
+                    func_def_body.control_nodes[node].kind = ControlNodeKind::Block { insts };
+
+                    // NOTE(eddyb) this also cleans up after `hoist_common_insts_from_cases`.
+                    if insts.is_empty() {
+                        func_def_body.control_regions[region]
+                            .children
+                            .remove(node, &mut func_def_body.control_nodes);
+                    }
+                }
+                ControlNodeKind::Select { ref cases, .. } => {
+                    let cases = cases.clone();
+                    self.hoist_common_insts_from_cases(func_def_body, region, node, &cases);
+                    for case in cases {
+                        self.in_new_scope(|gvn| gvn.visit_region(func_def_body, case));
+                    }
+                }
+                ControlNodeKind::Loop { body, .. } => {
+                    self.in_new_scope(|gvn| gvn.visit_region(func_def_body, body));
+                }
+            }
+        }
+    }
+
+    /// Move pure [`DataInst`]s found in all `cases` of the `select_node` `Select`
+    /// into `parent_region` (right before `select_node`), replacing all copies
+    /// with a single one (see also [`deduplicate_pure_data_insts`]'s docs).
+    fn hoist_common_insts_from_cases(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+        parent_region: ControlRegion,
+        select_node: ControlNode,
+        cases: &[ControlRegion],
+    ) {
+        if cases.len() < 2 {
+            return;
+        }
+
+        let mut defined_inside_select = DefinedInside::default();
+        for &case in cases {
+            defined_inside_select.collect_from_region(func_def_body, case);
+        }
+
+        let mut hoisted_insts_block = None;
+
+        // NOTE(eddyb) hoisting a `DataInst` can make others (which use it)
+        // hoistable as well, so this repeats until no more can be hoisted.
+        loop {
+            // Hoistable `DataInst`s (alongside their parent `Block`s), in all cases.
+            let mut common: Option<FxIndexMap<DataInstKey, SmallVec<[_; 2]>>> = None;
+            for &case in cases {
+                let mut case_candidates = FxIndexMap::default();
+                for func_at_node in func_def_body.at(case).at_children() {
+                    let block = func_at_node.position;
+                    if let ControlNodeKind::Block { insts } = func_at_node.def().kind {
+                        for func_at_inst in func_def_body.at(insts) {
+                            let inst = func_at_inst.position;
+                            if let Some(key) = self.key_for_inst(func_def_body, inst) {
+                                if !key.2.iter().any(|&v| defined_inside_select.contains(v)) {
+                                    case_candidates.entry(key).or_insert((block, inst));
+                                }
+                            }
+                        }
+                    }
+                }
+
+                common = Some(match common {
+                    None => case_candidates
+                        .into_iter()
+                        .map(|(key, block_and_inst)| (key, [block_and_inst].into_iter().collect()))
+                        .collect(),
+                    Some(mut common) => {
+                        common.retain(|key, blocks_and_insts| match case_candidates.get(key) {
+                            Some(&block_and_inst) => {
+                                blocks_and_insts.push(block_and_inst);
+                                true
+                            }
+                            None => false,
+                        });
+                        common
+                    }
+                });
+            }
+            let common = common.unwrap_or_default();
+            if common.is_empty() {
+                break;
+            }
+
+            for (key, blocks_and_insts) in common {
+                // Only the first copy needs to be moved, and only if there isn't
+                // already an identical `DataInst` dominating the whole `Select`.
+                let (hoisted_inst, needs_moving) = match self.available.get(&key) {
+                    Some(&existing_inst) => (existing_inst, false),
+                    None => (blocks_and_insts[0].1, true),
+                };
+
+                for (block, inst) in blocks_and_insts {
+                    let block_insts = match &mut func_def_body.control_nodes[block].kind {
+                        ControlNodeKind::Block { insts } => insts,
+                        _ => unreachable!(),
+                    };
+                    block_insts.remove(inst, &mut func_def_body.data_insts);
+                    defined_inside_select.insts.remove(&inst);
+
+                    if inst != hoisted_inst {
+                        self.replacements.insert(inst, Value::DataInstOutput(hoisted_inst));
+                    }
+                }
+
+                if needs_moving {
+                    let hoisted_insts_block = *hoisted_insts_block.get_or_insert_with(|| {
+                        block_before(self.cx, func_def_body, parent_region, select_node)
+                    });
+                    match &mut func_def_body.control_nodes[hoisted_insts_block].kind {
+                        ControlNodeKind::Block { insts } => {
+                            insts.insert_last(hoisted_inst, &mut func_def_body.data_insts);
+                        }
+                        _ => unreachable!(),
+                    }
+                    self.make_available(key, hoisted_inst);
+                }
+            }
+        }
+    }
+}
+
+/// Get the `Block` right before `node` in `region` (inserting a new one if needed).
+fn block_before(
+    cx: &Context,
+    func_def_body: &mut FuncDefBody,
+    region: ControlRegion,
+    node: ControlNode,
+) -> ControlNode {
+    if let Some(prev_node) = func_def_body.control_nodes[node].prev_in_list() {
+        if let ControlNodeKind::Block { .. } = func_def_body.control_nodes[prev_node].kind {
+            return prev_node;
+        }
+    }
+
+    let block = func_def_body.control_nodes.define(
+        cx,
+        ControlNodeDef {
+            kind: ControlNodeKind::Block { insts: EntityList::empty() },
+            outputs: [].into_iter().collect(),
+        }
+        .into(),
+    );
+    func_def_body.control_regions[region].children.insert_before(
+        block,
+        node,
+        &mut func_def_body.control_nodes,
+    );
+    block
+}
+
+/// All the values defined inside some [`ControlRegion`]s (including nested ones).
+#[derive(Default)]
+struct DefinedInside {
+    regions: FxHashSet<ControlRegion>,
+    nodes: FxHashSet<ControlNode>,
+    insts: FxHashSet<DataInst>,
+}
+
+impl DefinedInside {
+    fn collect_from_region(&mut self, func_def_body: &FuncDefBody, region: ControlRegion) {
+        self.regions.insert(region);
+        for func_at_node in func_def_body.at(region).at_children() {
+            self.nodes.insert(func_at_node.position);
+            match &func_at_node.def().kind {
+                &ControlNodeKind::Block { insts } => {
+                    self.insts.extend(
+                        func_def_body
+                            .at(insts)
+                            .into_iter()
+                            .map(|func_at_inst| func_at_inst.position),
+                    );
+                }
+                ControlNodeKind::Select { cases, .. } => {
+                    for &case in cases {
+                        self.collect_from_region(func_def_body, case);
+                    }
+                }
+                &ControlNodeKind::Loop { body, .. } => {
+                    self.collect_from_region(func_def_body, body);
+                }
+            }
+        }
+    }
+
+    fn contains(&self, v: Value) -> bool {
+        match v {
+            Value::Const(_) => false,
+            Value::ControlRegionInput { region, .. } => self.regions.contains(&region),
+            Value::ControlNodeOutput { control_node, .. } => self.nodes.contains(&control_node),
+            Value::DataInstOutput(inst) => self.insts.contains(&inst),
+        }
+    }
+}
@@ -0,0 +1,506 @@
+//! Function inlining (of [`DataInstKind::FuncCall`]s).
+
+use crate::passes::manager::{CallGraph, ReachableDecls};
+use crate::spv::{self, spec};
+use crate::transform::{InnerInPlaceTransform, ReplaceValueWith};
+use crate::{
+    Attr, AttrSet, AttrSetDef, ConstDef, ConstKind, Context, ControlNode, ControlNodeDef,
+    ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionDef,
+    ControlRegionInputDecl, DataInst, DataInstDef, DataInstFormDef, DataInstKind, DeclDef,
+    EntityList, Exportee, Func, FuncDecl, FuncDefBody, Module, Value,
+};
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+use std::collections::hash_map::Entry;
+
+/// Inlining hint from the SPIR-V `FunctionControl` operand of `OpFunction`
+/// (which is kept as an [`Attr::SpvBitflagsOperand`] on the [`FuncDecl`]).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpvInlineHint {
+    Inline,
+    DontInline,
+}
+
+/// Get the [`SpvInlineHint`] of `func_decl`, if it has either the `Inline` or
+/// the `DontInline` SPIR-V `FunctionControl` bit set (`DontInline` taking
+/// precedence, in the unlikely case that both are set).
+pub fn spv_inline_hint(cx: &Context, func_decl: &FuncDecl) -> Option<SpvInlineHint> {
+    let wk = &spec::Spec::get().well_known;
+
+    let function_control = cx[func_decl.attrs].attrs.iter().find_map(|attr| match *attr {
+        Attr::SpvBitflagsOperand(spv::Imm::Short(kind, word)) if kind == wk.FunctionControl => {
+            Some(word)
+        }
+        _ => None,
+    })?;
+
+    let bits = match wk.FunctionControl.name_and_def().1 {
+        spec::OperandKindDef::BitEnum { bits, .. } => bits,
+        _ => unreachable!(),
+    };
+    let has_bit =
+        |name| bits.lookup(name).is_some_and(|spec::BitIdx(i)| (function_control >> i) & 1 != 0);
+
+    if has_bit("DontInline") {
+        Some(SpvInlineHint::DontInline)
+    } else if has_bit("Inline") {
+        Some(SpvInlineHint::Inline)
+    } else {
+        None
+    }
+}
+
+/// Inline every call (in functions reachable from `module.exports`) to a
+/// function definition for which `should_inline(cx, callee_decl)` returns `true`
+/// (e.g. always `true`, or based on [`spv_inline_hint`]).
+///
+/// Callees are processed before their callers, so calls inlined from a callee
+/// are themselves inlined into the caller (if `should_inline` allows them).
+///
+/// Some calls are never inlined (and are left in place):
+/// * recursive calls (i.e. to any function which is still being processed)
+/// * calls to functions with any unstructured control-flow (which can be
+///   avoided by first using [`structurize_func_cfgs`](super::legalize::structurize_func_cfgs),
+///   that also merges all the `return`s of a function into its `body.outputs`)
+///
+/// For each inlined call, the callee's `body` is copied in place of the call
+/// (i.e. its `children` are spliced into the caller's [`ControlRegion`]), with
+/// `body.inputs` replaced by the call's arguments, and `body.outputs` replacing
+/// the call's output (for non-`void` callees), with multiple `body.outputs`
+/// being combined (using `OpCompositeConstruct`) into the call's output (i.e.
+/// they're treated as the components of a composite return type).
+///
+/// Any [`QPtrAttr::Usage`](crate::qptr::QPtrAttr::Usage) attributes are removed
+/// from the copies of the callee's instructions (as their usage now depends on
+/// the caller), so [`qptr::analyze_uses`](super::qptr::analyze_uses) should be
+/// used after inlining (which is needed anyway, to remove all the `qptr`-typed
+/// function parameters, that [`qptr::lift_to_spv_ptrs`](super::qptr::lift_to_spv_ptrs)
+/// doesn't support).
+pub fn inline_calls(module: &mut Module, should_inline: impl FnMut(&Context, &FuncDecl) -> bool) {
+    let call_graph = CallGraph::collect(module, &ReachableDecls::collect(module));
+    inline_calls_with_call_graph(module, &call_graph, should_inline);
+}
+
+pub(crate) fn inline_calls_with_call_graph(
+    module: &mut Module,
+    call_graph: &CallGraph,
+    should_inline: impl FnMut(&Context, &FuncDecl) -> bool,
+) {
+    let cx = &module.cx();
+
+    let roots: SmallVec<[_; 4]> = module
+        .exports
+        .values()
+        .filter_map(|&exportee| match exportee {
+            Exportee::GlobalVar(_) => None,
+            Exportee::Func(func) => Some(func),
+        })
+        .collect();
+
+    let mut inliner = Inliner { cx, call_graph, should_inline, func_states: FxHashMap::default() };
+    for func in roots {
+        inliner.inline_calls_in_func(module, func);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FuncState {
+    InProgress,
+    Done,
+}
+
+struct Inliner<'a, P> {
+    cx: &'a Context,
+
+    /// Call graph of the module *before* any inlining, which is sufficient for
+    /// finding the callees of a function (as inlining only changes a function
+    /// after all of its original callees have been processed).
+    call_graph: &'a CallGraph,
+
+    should_inline: P,
+
+    func_states: FxHashMap<Func, FuncState>,
+}
+
+impl<P: FnMut(&Context, &FuncDecl) -> bool> Inliner<'_, P> {
+    fn inline_calls_in_func(&mut self, module: &mut Module, func: Func) {
+        match self.func_states.entry(func) {
+            Entry::Occupied(_) => return,
+            Entry::Vacant(entry) => {
+                entry.insert(FuncState::InProgress);
+            }
+        }
+
+        let callees = self.call_graph.callees.get(&func).cloned().unwrap_or_default();
+
+        for &callee in &callees {
+            self.inline_calls_in_func(module, callee);
+        }
+
+        // FIXME(eddyb) avoid cloning the callee bodies (this is only done to
+        // allow mutating the caller, while also reading from the callees).
+        let callee_bodies: FxHashMap<_, _> = callees
+            .into_iter()
+            .filter(|callee| {
+                // NOTE(eddyb) any callee not yet done has to be (mutually)
+                // recursive with `func` (as it's still on the "call stack").
+                self.func_states[callee] == FuncState::Done
+            })
+            .filter_map(|callee| {
+                let callee_decl = &module.funcs[callee];
+                match &callee_decl.def {
+                    DeclDef::Present(callee_body)
+                        if callee_body.unstructured_cfg.is_none()
+                            && (self.should_inline)(self.cx, callee_decl) =>
+                    {
+                        Some((callee, callee_body.clone()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+
+        if !callee_bodies.is_empty() {
+            let func_def_body = match &mut module.funcs[func].def {
+                DeclDef::Present(func_def_body) => func_def_body,
+                DeclDef::Imported(_) => unreachable!(),
+            };
+            FuncInliner {
+                cx: self.cx,
+                callee_bodies: &callee_bodies,
+                func_def_body,
+                call_output_replacements: FxHashMap::default(),
+            }
+            .inline_calls_in_func_def_body();
+        }
+
+        self.func_states.insert(func, FuncState::Done);
+    }
+}
+
+struct FuncInliner<'a> {
+    cx: &'a Context,
+    callee_bodies: &'a FxHashMap<Func, FuncDefBody>,
+    func_def_body: &'a mut FuncDefBody,
+
+    /// Values replacing the outputs of inlined calls (applied at the very end,
+    /// but also used for the arguments of later calls, as they're inlined).
+    call_output_replacements: FxHashMap<DataInst, Value>,
+}
+
+impl FuncInliner<'_> {
+    fn inline_calls_in_func_def_body(mut self) {
+        let regions: SmallVec<[_; 8]> = match &self.func_def_body.unstructured_cfg {
+            None => [self.func_def_body.body].into_iter().collect(),
+            Some(cfg) => cfg.rev_post_order(self.func_def_body).collect(),
+        };
+        for region in regions {
+            self.inline_calls_in_region(region);
+        }
+
+        if self.call_output_replacements.is_empty() {
+            return;
+        }
+
+        let call_output_replacements = &self.call_output_replacements;
+        self.func_def_body.inner_in_place_transform_with(&mut ReplaceValueWith(|v| match v {
+            Value::DataInstOutput(inst) => call_output_replacements.get(&inst).copied(),
+            _ => None,
+        }));
+    }
+
+    fn inline_calls_in_region(&mut self, region: ControlRegion) {
+        let mut next_node = self.func_def_body.at(region).def().children.iter().first;
+        while let Some(node) = next_node {
+            next_node = self.func_def_body.control_nodes[node].next_in_list();
+
+            match &self.func_def_body.control_nodes[node].kind {
+                &ControlNodeKind::Block { insts } => {
+                    let call =
+                        self.func_def_body.at(insts).into_iter().find_map(
+                            |func_at_inst| match self.cx[func_at_inst.def().form].kind {
+                                DataInstKind::FuncCall(callee)
+                                    if self.callee_bodies.contains_key(&callee) =>
+                                {
+                                    Some((func_at_inst.position, callee))
+                                }
+                                _ => None,
+                            },
+                        );
+                    if let Some((call_inst, callee)) = call {
+                        // NOTE(eddyb) the rest of the block (after the call)
+                        // is always the next node to be processed.
+                        next_node = self.inline_call(region, node, call_inst, callee);
+                    }
+                }
+                ControlNodeKind::Select { cases, .. } => {
+                    for case in cases.clone() {
+                        self.inline_calls_in_region(case);
+                    }
+                }
+                &ControlNodeKind::Loop { body, .. } => {
+                    self.inline_calls_in_region(body);
+                }
+            }
+        }
+    }
+
+    /// Inline `call_inst` (calling `callee`, in the `block` child of `region`),
+    /// returning the [`ControlNode`] that follows the inlined callee body (which
+    /// will be the new `Block` holding all of `block`'s instructions after the
+    /// call, if there were any).
+    fn inline_call(
+        &mut self,
+        region: ControlRegion,
+        block: ControlNode,
+        call_inst: DataInst,
+        callee: Func,
+    ) -> Option<ControlNode> {
+        let cx = self.cx;
+        let callee_bodies = self.callee_bodies;
+        let callee_body = &callee_bodies[&callee];
+
+        let call_def = self.func_def_body.at(call_inst).def();
+        let call_output_type = cx[call_def.form].output_type;
+        let args = call_def
+            .inputs
+            .iter()
+            .map(|&v| match v {
+                Value::DataInstOutput(inst) => {
+                    self.call_output_replacements.get(&inst).copied().unwrap_or(v)
+                }
+                _ => v,
+            })
+            .collect();
+
+        // Split `block` into the instructions before and after the call.
+        let FuncDefBody { control_regions, control_nodes, data_insts, .. } =
+            &mut *self.func_def_body;
+        let mut insts_after = EntityList::empty();
+        let block_is_empty = match &mut control_nodes[block].kind {
+            ControlNodeKind::Block { insts } => {
+                while let Some(inst) = data_insts[call_inst].next_in_list() {
+                    insts.remove(inst, data_insts);
+                    insts_after.insert_last(inst, data_insts);
+                }
+                insts.remove(call_inst, data_insts);
+                insts.is_empty()
+            }
+            _ => unreachable!(),
+        };
+        let original_next_node = control_nodes[block].next_in_list();
+        if block_is_empty {
+            control_regions[region].children.remove(block, control_nodes);
+        }
+
+        let mut copier = CalleeCopier {
+            cx,
+            callee_body,
+            args,
+            region_map: FxHashMap::default(),
+            control_node_map: FxHashMap::default(),
+            data_inst_map: FxHashMap::default(),
+        };
+        let mut new_nodes: SmallVec<[_; 8]> = callee_body
+            .at(callee_body.body)
+            .at_children()
+            .into_iter()
+            .map(|func_at_node| copier.copy_control_node(self.func_def_body, func_at_node.position))
+            .collect();
+
+        let replacement = match callee_body.at_body().def().outputs[..] {
+            [] => call_output_type.map(|ty| {
+                // NOTE(eddyb) this is only reachable for `void` callees (and
+                // any uses of their outputs would themselves be invalid).
+                Value::Const(cx.intern(ConstDef {
+                    attrs: AttrSet::default(),
+                    ty,
+                    kind: ConstKind::Undef,
+                }))
+            }),
+            [v] => Some(copier.copy_value(v)),
+
+            // NOTE(eddyb) multiple outputs are the components of the (composite)
+            // return type, which has to be constructed from them (after the
+            // inlined callee body, i.e. at the start of the instructions that
+            // followed the call, which are the only ones that could use it).
+            ref outputs => call_output_type.map(|ty| {
+                let wk = &spec::Spec::get().well_known;
+                let composite_inst = self.func_def_body.data_insts.define(
+                    cx,
+                    DataInstDef {
+                        attrs: AttrSet::default(),
+                        form: cx.intern(DataInstFormDef {
+                            kind: DataInstKind::SpvInst(wk.OpCompositeConstruct.into()),
+                            output_type: Some(ty),
+                        }),
+                        inputs: outputs.iter().map(|&v| copier.copy_value(v)).collect(),
+                    }
+                    .into(),
+                );
+                insts_after.insert_first(composite_inst, &mut self.func_def_body.data_insts);
+                Value::DataInstOutput(composite_inst)
+            }),
+        };
+        if let Some(replacement) = replacement {
+            self.call_output_replacements.insert(call_inst, replacement);
+        }
+
+        let FuncDefBody { control_regions, control_nodes, .. } = &mut *self.func_def_body;
+        let block_after = (!insts_after.is_empty()).then(|| {
+            control_nodes.define(
+                cx,
+                ControlNodeDef {
+                    kind: ControlNodeKind::Block { insts: insts_after },
+                    outputs: [].into_iter().collect(),
+                }
+                .into(),
+            )
+        });
+        new_nodes.extend(block_after);
+
+        let children = &mut control_regions[region].children;
+        for new_node in new_nodes {
+            match original_next_node {
+                Some(next_node) => children.insert_before(new_node, next_node, control_nodes),
+                None => children.insert_last(new_node, control_nodes),
+            }
+        }
+
+        block_after.or(original_next_node)
+    }
+}
+
+/// Copier of (parts of) a callee's body, into a caller (see `inline_call`).
+struct CalleeCopier<'a> {
+    cx: &'a Context,
+    callee_body: &'a FuncDefBody,
+
+    /// Call arguments, replacing the callee's `body.inputs`.
+    args: SmallVec<[Value; 2]>,
+
+    // FIXME(eddyb) use `EntityOrientedDenseMap` here.
+    region_map: FxHashMap<ControlRegion, ControlRegion>,
+    control_node_map: FxHashMap<ControlNode, ControlNode>,
+    data_inst_map: FxHashMap<DataInst, DataInst>,
+}
+
+impl CalleeCopier<'_> {
+    fn copy_attrs(&self, attrs: AttrSet) -> AttrSet {
+        let cx = self.cx;
+        let has_qptr_usage = cx[attrs]
+            .attrs
+            .iter()
+            .any(|attr| matches!(attr, Attr::QPtr(crate::qptr::QPtrAttr::Usage(_))));
+        if !has_qptr_usage {
+            return attrs;
+        }
+        cx.intern(AttrSetDef {
+            attrs: cx[attrs]
+                .attrs
+                .iter()
+                .filter(|attr| !matches!(attr, Attr::QPtr(crate::qptr::QPtrAttr::Usage(_))))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    fn copy_value(&self, v: Value) -> Value {
+        match v {
+            Value::Const(_) => v,
+            Value::ControlRegionInput { region, input_idx } => {
+                if region == self.callee_body.body {
+                    self.args[input_idx as usize]
+                } else {
+                    Value::ControlRegionInput { region: self.region_map[&region], input_idx }
+                }
+            }
+            Value::ControlNodeOutput { control_node, output_idx } => Value::ControlNodeOutput {
+                control_node: self.control_node_map[&control_node],
+                output_idx,
+            },
+            Value::DataInstOutput(inst) => Value::DataInstOutput(self.data_inst_map[&inst]),
+        }
+    }
+
+    fn copy_region(&mut self, caller: &mut FuncDefBody, region: ControlRegion) -> ControlRegion {
+        let cx = self.cx;
+        let callee_body = self.callee_body;
+
+        // NOTE(eddyb) the new region has to be defined before its contents are
+        // copied, as `Value::ControlRegionInput`s refer to it from within.
+        let new_region = caller.control_regions.define(cx, ControlRegionDef::default());
+        self.region_map.insert(region, new_region);
+
+        let region_def = callee_body.at(region).def();
+        let inputs = region_def
+            .inputs
+            .iter()
+            .map(|&ControlRegionInputDecl { attrs, ty }| ControlRegionInputDecl {
+                attrs: self.copy_attrs(attrs),
+                ty,
+            })
+            .collect();
+        let mut children = EntityList::empty();
+        for func_at_node in callee_body.at(region).at_children() {
+            let new_node = self.copy_control_node(caller, func_at_node.position);
+            children.insert_last(new_node, &mut caller.control_nodes);
+        }
+        let outputs = region_def.outputs.iter().map(|&v| self.copy_value(v)).collect();
+
+        caller.control_regions[new_region] = ControlRegionDef { inputs, children, outputs };
+        new_region
+    }
+
+    fn copy_control_node(&mut self, caller: &mut FuncDefBody, node: ControlNode) -> ControlNode {
+        let cx = self.cx;
+        let callee_body = self.callee_body;
+
+        let ControlNodeDef { kind, outputs } = callee_body.at(node).def();
+        let kind = match kind {
+            &ControlNodeKind::Block { insts } => {
+                let mut new_insts = EntityList::empty();
+                for func_at_inst in callee_body.at(insts) {
+                    let DataInstDef { attrs, form, inputs } = func_at_inst.def();
+                    let new_inst = caller.data_insts.define(
+                        cx,
+                        DataInstDef {
+                            attrs: self.copy_attrs(*attrs),
+                            form: *form,
+                            inputs: inputs.iter().map(|&v| self.copy_value(v)).collect(),
+                        }
+                        .into(),
+                    );
+                    new_insts.insert_last(new_inst, &mut caller.data_insts);
+                    self.data_inst_map.insert(func_at_inst.position, new_inst);
+                }
+                ControlNodeKind::Block { insts: new_insts }
+            }
+            ControlNodeKind::Select { kind, scrutinee, cases } => ControlNodeKind::Select {
+                kind: kind.clone(),
+                scrutinee: self.copy_value(*scrutinee),
+                cases: cases.iter().map(|&case| self.copy_region(caller, case)).collect(),
+            },
+            ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                let initial_inputs = initial_inputs.iter().map(|&v| self.copy_value(v)).collect();
+                let body = self.copy_region(caller, *body);
+                ControlNodeKind::Loop {
+                    initial_inputs,
+                    body,
+                    repeat_condition: self.copy_value(*repeat_condition),
+                }
+            }
+        };
+        let outputs = outputs
+            .iter()
+            .map(|&ControlNodeOutputDecl { attrs, ty }| ControlNodeOutputDecl {
+                attrs: self.copy_attrs(attrs),
+                ty,
+            })
+            .collect();
+
+        let new_node = caller.control_nodes.define(cx, ControlNodeDef { kind, outputs }.into());
+        self.control_node_map.insert(node, new_node);
+        new_node
+    }
+}
@@ -1,76 +1,75 @@
-use crate::visit::{InnerVisit, Visitor};
-use crate::{
-    cfg, AttrSet, Const, Context, DataInstForm, DeclDef, Func, FxIndexSet, GlobalVar, Module, Type,
-};
+use crate::passes::manager::ReachableDecls;
+use crate::{cfg, DeclDef, Diag, Module};
 
 /// Apply the [`cfg::Structurizer`] algorithm to all function definitions in `module`.
 pub fn structurize_func_cfgs(module: &mut Module) {
-    let cx = &module.cx();
+    let reachable = ReachableDecls::collect(module);
+    structurize_func_cfgs_in_reachable(module, &reachable);
+}
 
-    // FIXME(eddyb) reuse this collection work in some kind of "pass manager".
-    let mut collector = ReachableUseCollector {
-        cx,
-        module,
+pub(crate) fn structurize_func_cfgs_in_reachable(module: &mut Module, reachable: &ReachableDecls) {
+    let cx = &module.cx();
 
-        seen_types: FxIndexSet::default(),
-        seen_consts: FxIndexSet::default(),
-        seen_data_inst_forms: FxIndexSet::default(),
-        seen_global_vars: FxIndexSet::default(),
-        seen_funcs: FxIndexSet::default(),
-    };
-    for (export_key, &exportee) in &module.exports {
-        export_key.inner_visit_with(&mut collector);
-        exportee.inner_visit_with(&mut collector);
+    for &func in &reachable.funcs {
+        let func_decl = &mut module.funcs[func];
+        let ret_type = func_decl.ret_type;
+        if let DeclDef::Present(func_def_body) = &mut func_decl.def {
+            cfg::Structurizer::new(cx, func_def_body, ret_type).structurize_func();
+        }
     }
+}
 
-    for &func in &collector.seen_funcs {
+/// Apply the [`cfg::Destructurizer`] algorithm to all function definitions in
+/// `module`, i.e. convert all structured control-flow into an unstructured CFG.
+pub fn destructurize_funcs(module: &mut Module) {
+    let reachable = ReachableDecls::collect(module);
+    destructurize_funcs_in_reachable(module, &reachable);
+}
+
+pub(crate) fn destructurize_funcs_in_reachable(module: &mut Module, reachable: &ReachableDecls) {
+    let cx = &module.cx();
+
+    for &func in &reachable.funcs {
         if let DeclDef::Present(func_def_body) = &mut module.funcs[func].def {
-            cfg::Structurizer::new(cx, func_def_body).structurize_func();
+            cfg::Destructurizer::new(cx, func_def_body).destructurize_func();
         }
     }
 }
 
-struct ReachableUseCollector<'a> {
-    cx: &'a Context,
-    module: &'a Module,
-
-    // FIXME(eddyb) build some automation to avoid ever repeating these.
-    seen_types: FxIndexSet<Type>,
-    seen_consts: FxIndexSet<Const>,
-    seen_data_inst_forms: FxIndexSet<DataInstForm>,
-    seen_global_vars: FxIndexSet<GlobalVar>,
-    seen_funcs: FxIndexSet<Func>,
+/// Like [`structurize_func_cfgs`], but first making all irreducible CFGs
+/// reducible (see [`cfg::ControlFlowGraph::make_reducible`]), so that they can
+/// also be fully structurized, and attaching a `Diag::warn` to every function
+/// which required such changes.
+pub fn structurize_func_cfgs_including_irreducible(module: &mut Module) {
+    let reachable = ReachableDecls::collect(module);
+    structurize_func_cfgs_including_irreducible_in_reachable(module, &reachable);
 }
 
-impl Visitor<'_> for ReachableUseCollector<'_> {
-    // FIXME(eddyb) build some automation to avoid ever repeating these.
-    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {
-        // FIXME(eddyb) if `AttrSet`s are ignored, why not `Type`s too?
-    }
-    fn visit_type_use(&mut self, ty: Type) {
-        if self.seen_types.insert(ty) {
-            self.visit_type_def(&self.cx[ty]);
-        }
-    }
-    fn visit_const_use(&mut self, ct: Const) {
-        if self.seen_consts.insert(ct) {
-            self.visit_const_def(&self.cx[ct]);
-        }
-    }
-    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
-        if self.seen_data_inst_forms.insert(data_inst_form) {
-            self.visit_data_inst_form_def(&self.cx[data_inst_form]);
-        }
-    }
+pub(crate) fn structurize_func_cfgs_including_irreducible_in_reachable(
+    module: &mut Module,
+    reachable: &ReachableDecls,
+) {
+    let cx = &module.cx();
 
-    fn visit_global_var_use(&mut self, gv: GlobalVar) {
-        if self.seen_global_vars.insert(gv) {
-            self.visit_global_var_decl(&self.module.global_vars[gv]);
-        }
-    }
-    fn visit_func_use(&mut self, func: Func) {
-        if self.seen_funcs.insert(func) {
-            self.visit_func_decl(&self.module.funcs[func]);
+    for &func in &reachable.funcs {
+        let func_decl = &mut module.funcs[func];
+        let ret_type = func_decl.ret_type;
+        if let DeclDef::Present(func_def_body) = &mut func_decl.def {
+            let changes = cfg::ControlFlowGraph::make_reducible(cx, func_def_body);
+            cfg::Structurizer::new(cx, func_def_body, ret_type).structurize_func();
+
+            if changes.dispatch_regions > 0 {
+                func_decl.attrs.push_diag(
+                    cx,
+                    Diag::warn([format!(
+                        "irreducible control-flow made reducible by dispatching \
+                         {} cycle entries through {} new region(s), \
+                         without duplicating any code",
+                        changes.dispatched_entries, changes.dispatch_regions
+                    )
+                    .into()]),
+                );
+            }
         }
     }
 }
@@ -1,11 +1,15 @@
+use crate::passes::manager::ReachableDecls;
+use crate::spv::{self, spec};
 use crate::transform::{InnerTransform, Transformed, Transformer};
 use crate::visit::{InnerVisit, Visitor};
 use crate::{
-    AttrSet, Const, Context, DataInstForm, DeclDef, ExportKey, Exportee, Func, FxIndexSet,
-    GlobalVar, Import, Module, Type,
+    Attr, AttrSet, Const, Context, DataInstForm, DeclDef, Diag, ExportKey, Exportee, Func,
+    FxIndexSet, GlobalVar, Import, Module, ModuleDebugInfo, ModuleDialect, Type,
 };
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
 
 // FIXME(eddyb) maybe make an export pruning pass that keeps some exports as
 // roots and then only other exports if they're used by imports.
@@ -22,8 +26,8 @@ use std::collections::VecDeque;
 /// Note that the "dead" definitions are not removed from the module, and any
 /// external references to them could still be used (e.g. from a clone of the
 /// `module.exports` map, before calling `minimize_exports`).
-//
-// FIXME(eddyb) make this operate on multiple modules.
+///
+/// Multiple modules can be merged into one with [`link_modules`], beforehand.
 pub fn minimize_exports(module: &mut Module, is_root: impl Fn(&ExportKey) -> bool) {
     let mut collector = LiveExportCollector {
         cx: module.cx_ref(),
@@ -114,18 +118,40 @@ impl Visitor<'_> for LiveExportCollector<'_> {
 
 /// Remap [`Import::LinkName`] to definitions exported as [`ExportKey::LinkName`].
 ///
+/// Imports which can't be resolved (i.e. with no matching export, an export of
+/// the wrong kind, or a mismatched function signature or global variable type
+/// or address space) are left in place, and get a [`Diag::err`] attached to
+/// their declaration (see also [`resolve_imports_strict`], to fail instead).
+///
 /// To reduce the work performed, calling [`minimize_exports`] first is recommended.
-//
-// FIXME(eddyb) make this operate on multiple modules.
+///
+/// See also [`link_modules`], for resolving imports across multiple modules.
 pub fn resolve_imports(module: &mut Module) {
-    let (resolved_global_vars, resolved_funcs) = {
+    resolve_imports_and_count_errors(module);
+}
+
+/// Like [`resolve_imports`], but returning [`LinkError::UnresolvedImports`]
+/// if any import couldn't be resolved (after attaching all the [`Diag`]s).
+pub fn resolve_imports_strict(module: &mut Module) -> Result<(), LinkError> {
+    match resolve_imports_and_count_errors(module) {
+        0 => Ok(()),
+        count => Err(LinkError::UnresolvedImports(count)),
+    }
+}
+
+fn resolve_imports_and_count_errors(module: &mut Module) -> usize {
+    let cx = &module.cx();
+
+    let (resolved_global_vars, resolved_funcs, diags) = {
         let mut collector = ImportResolutionCollector {
-            cx: module.cx_ref(),
+            cx,
             module,
 
             resolved_global_vars: FxHashMap::default(),
             resolved_funcs: FxHashMap::default(),
 
+            diags: vec![],
+
             seen_types: FxHashSet::default(),
             seen_consts: FxHashSet::default(),
             seen_data_inst_forms: FxHashSet::default(),
@@ -133,11 +159,30 @@ pub fn resolve_imports(module: &mut Module) {
             seen_funcs: FxHashSet::default(),
         };
         collector.visit_module(module);
-        (collector.resolved_global_vars, collector.resolved_funcs)
+        (collector.resolved_global_vars, collector.resolved_funcs, collector.diags)
     };
 
+    let error_count = diags.len();
+    for (import, diag) in diags {
+        let attrs = match import {
+            Exportee::GlobalVar(gv) => &mut module.global_vars[gv].attrs,
+            Exportee::Func(func) => &mut module.funcs[func].attrs,
+        };
+
+        // NOTE(eddyb) imports can be left unresolved by several calls (e.g.
+        // `link_modules` followed by `resolve_imports_strict`), which should
+        // still only attach each `Diag` once.
+        let already_attached = cx[*attrs].attrs.iter().any(|attr| match attr {
+            Attr::Diagnostics(diags) => diags.0.contains(&diag),
+            _ => false,
+        });
+        if !already_attached {
+            attrs.push_diag(cx, diag);
+        }
+    }
+
     let mut resolver = ImportResolver {
-        cx: &module.cx(),
+        cx,
 
         resolved_global_vars: &resolved_global_vars,
         resolved_funcs: &resolved_funcs,
@@ -165,6 +210,8 @@ pub fn resolve_imports(module: &mut Module) {
             resolver.in_place_transform_func_decl(&mut module.funcs[func]);
         }
     }
+
+    error_count
 }
 
 // FIXME(eddyb) figure out if this step can be skipped by somehow letting
@@ -176,6 +223,10 @@ struct ImportResolutionCollector<'a> {
     resolved_global_vars: FxHashMap<GlobalVar, GlobalVar>,
     resolved_funcs: FxHashMap<Func, Func>,
 
+    /// Errors for imports which couldn't be resolved, to attach to their
+    /// declarations (once the module can be mutated again).
+    diags: Vec<(Exportee, Diag)>,
+
     // FIXME(eddyb) build some automation to avoid ever repeating these.
     seen_types: FxHashSet<Type>,
     seen_consts: FxHashSet<Const>,
@@ -210,14 +261,48 @@ impl Visitor<'_> for ImportResolutionCollector<'_> {
             let gv_decl = &self.module.global_vars[gv];
             self.visit_global_var_decl(gv_decl);
 
-            // FIXME(eddyb) if the export is missing (or the wrong kind), it will
-            // simply not get remapped - perhaps some kind of diagnostic is in
-            // order? (maybe an entire pass infrastructure that can report errors)
             if let DeclDef::Imported(Import::LinkName(name)) = gv_decl.def {
-                if let Some(&Exportee::GlobalVar(def_gv)) =
-                    self.module.exports.get(&ExportKey::LinkName(name))
-                {
-                    self.resolved_global_vars.insert(gv, def_gv);
+                match self.module.exports.get(&ExportKey::LinkName(name)) {
+                    Some(&Exportee::GlobalVar(def_gv)) => {
+                        let def_gv_decl = &self.module.global_vars[def_gv];
+                        let mismatch = if gv_decl.type_of_ptr_to != def_gv_decl.type_of_ptr_to {
+                            Some(Diag::err([
+                                format!("`{}` imported with type `", &self.cx[name]).into(),
+                                gv_decl.type_of_ptr_to.into(),
+                                "`, but exported with type `".into(),
+                                def_gv_decl.type_of_ptr_to.into(),
+                                "`".into(),
+                            ]))
+                        } else if gv_decl.addr_space != def_gv_decl.addr_space {
+                            Some(Diag::err([format!(
+                                "`{}` imported and exported in different address spaces",
+                                &self.cx[name]
+                            )
+                            .into()]))
+                        } else {
+                            None
+                        };
+                        match mismatch {
+                            Some(diag) => self.diags.push((Exportee::GlobalVar(gv), diag)),
+                            None => {
+                                self.resolved_global_vars.insert(gv, def_gv);
+                            }
+                        }
+                    }
+                    Some(Exportee::Func(_)) => self.diags.push((
+                        Exportee::GlobalVar(gv),
+                        Diag::err([format!(
+                            "`{}` imported as a global variable, but exported as a function",
+                            &self.cx[name]
+                        )
+                        .into()]),
+                    )),
+                    None => self.diags.push((
+                        Exportee::GlobalVar(gv),
+                        Diag::err([
+                            format!("no export found for import `{}`", &self.cx[name]).into()
+                        ]),
+                    )),
                 }
             }
         }
@@ -227,20 +312,74 @@ impl Visitor<'_> for ImportResolutionCollector<'_> {
             let func_decl = &self.module.funcs[func];
             self.visit_func_decl(func_decl);
 
-            // FIXME(eddyb) if the export is missing (or the wrong kind), it will
-            // simply not get remapped - perhaps some kind of diagnostic is in
-            // order? (maybe an entire pass infrastructure that can report errors)
             if let DeclDef::Imported(Import::LinkName(name)) = func_decl.def {
-                if let Some(&Exportee::Func(def_func)) =
-                    self.module.exports.get(&ExportKey::LinkName(name))
-                {
-                    self.resolved_funcs.insert(func, def_func);
+                match self.module.exports.get(&ExportKey::LinkName(name)) {
+                    Some(&Exportee::Func(def_func)) => {
+                        match self.func_signature_mismatch(&self.cx[name], func, def_func) {
+                            Some(diag) => self.diags.push((Exportee::Func(func), diag)),
+                            None => {
+                                self.resolved_funcs.insert(func, def_func);
+                            }
+                        }
+                    }
+                    Some(Exportee::GlobalVar(_)) => self.diags.push((
+                        Exportee::Func(func),
+                        Diag::err([format!(
+                            "`{}` imported as a function, but exported as a global variable",
+                            &self.cx[name]
+                        )
+                        .into()]),
+                    )),
+                    None => self.diags.push((
+                        Exportee::Func(func),
+                        Diag::err([
+                            format!("no export found for import `{}`", &self.cx[name]).into()
+                        ]),
+                    )),
                 }
             }
         }
     }
 }
 
+impl ImportResolutionCollector<'_> {
+    fn func_signature_mismatch(&self, name: &str, import: Func, export: Func) -> Option<Diag> {
+        let import_decl = &self.module.funcs[import];
+        let export_decl = &self.module.funcs[export];
+
+        if import_decl.ret_type != export_decl.ret_type {
+            return Some(Diag::err([
+                format!("`{name}` imported with return type `").into(),
+                import_decl.ret_type.into(),
+                "`, but exported with return type `".into(),
+                export_decl.ret_type.into(),
+                "`".into(),
+            ]));
+        }
+        if import_decl.params.len() != export_decl.params.len() {
+            return Some(Diag::err([format!(
+                "`{name}` imported with {} parameter(s), but exported with {}",
+                import_decl.params.len(),
+                export_decl.params.len()
+            )
+            .into()]));
+        }
+        let (i, (import_param, export_param)) = import_decl
+            .params
+            .iter()
+            .zip(&export_decl.params)
+            .enumerate()
+            .find(|(_, (import_param, export_param))| import_param.ty != export_param.ty)?;
+        Some(Diag::err([
+            format!("`{name}` imported with parameter #{i} of type `").into(),
+            import_param.ty.into(),
+            "`, but exported with type `".into(),
+            export_param.ty.into(),
+            "`".into(),
+        ]))
+    }
+}
+
 struct ImportResolver<'a> {
     cx: &'a Context,
 
@@ -326,3 +465,280 @@ impl Transformer for ImportResolver<'_> {
         transformed
     }
 }
+
+/// Error encountered while merging modules (see [`link_modules`]).
+#[derive(Clone, Debug)]
+pub enum LinkError {
+    /// The modules' dialects are incompatible (e.g. different SPIR-V memory models).
+    DialectMismatch(String),
+
+    /// The same [`ExportKey::LinkName`] is exported by more than one module.
+    DuplicateExport(String),
+
+    /// The same [`ExportKey::SpvEntryPoint`] (i.e. with the same execution
+    /// model and name) is exported by more than one module.
+    DuplicateEntryPoint(String),
+
+    /// Some imports couldn't be resolved (see [`resolve_imports_strict`]),
+    /// with the [`Diag`]s explaining why attached to their declarations.
+    UnresolvedImports(usize),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DialectMismatch(msg) => write!(f, "incompatible module dialects: {msg}"),
+            Self::DuplicateExport(name) => {
+                write!(f, "`{name}` exported by more than one module")
+            }
+            Self::DuplicateEntryPoint(descr) => {
+                write!(f, "{descr} entry point exported by more than one module")
+            }
+            Self::UnresolvedImports(count) => write!(f, "{count} import(s) couldn't be resolved"),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Merge all of `modules` (which must share the same [`Context`]) into one
+/// module, then resolve [`Import::LinkName`]s across them (see [`resolve_imports`]).
+///
+/// Definitions are moved into the first module (getting new entity handles,
+/// unless they were already in the first module), but only those reachable
+/// from `module.exports` (as no others could ever be used, after merging).
+///
+/// The module dialects and debuginfo are also merged, e.g. for SPIR-V, taking
+/// the union of capabilities/extensions, and the highest version, but requiring
+/// all modules to use the same addressing and memory models.
+///
+/// Any imports left unresolved get a [`Diag::err`] attached (see [`resolve_imports`]),
+/// and [`merge_module_into`] followed by [`resolve_imports_strict`] can be used
+/// instead of `link_modules`, to fail in that case.
+///
+/// # Panics
+///
+/// Panics if `modules` is empty, or if not all `modules` share one [`Context`].
+pub fn link_modules(modules: impl IntoIterator<Item = Module>) -> Result<Module, LinkError> {
+    let mut modules = modules.into_iter();
+    let mut linked = modules.next().expect("link_modules: no modules to link");
+    for module in modules {
+        merge_module_into(&mut linked, module)?;
+    }
+    resolve_imports(&mut linked);
+    Ok(linked)
+}
+
+/// Move all definitions reachable from the exports of `module` into `dst`,
+/// alongside its exports (without resolving any imports, see [`link_modules`]).
+pub fn merge_module_into(dst: &mut Module, module: Module) -> Result<(), LinkError> {
+    let cx = &dst.cx();
+    assert!(
+        Rc::ptr_eq(cx, module.cx_ref()),
+        "merge_module_into: modules must share the same `Context`"
+    );
+
+    // Check everything that can fail, before making any changes to `dst`.
+    let dialect = merge_dialects(&dst.dialect, &module.dialect)?;
+    for export_key in module.exports.keys() {
+        match export_key {
+            &ExportKey::LinkName(name) => {
+                if dst.exports.contains_key(export_key) {
+                    return Err(LinkError::DuplicateExport(cx[name].to_string()));
+                }
+            }
+
+            // NOTE(eddyb) entry points are only identified by their execution
+            // model and name, *not* their interface (which is per-module).
+            ExportKey::SpvEntryPoint { imms, .. } => {
+                let is_same_entry_point = |dst_export_key: &ExportKey| match dst_export_key {
+                    ExportKey::SpvEntryPoint { imms: dst_imms, .. } => dst_imms == imms,
+                    ExportKey::LinkName(_) => false,
+                };
+                if dst.exports.keys().any(is_same_entry_point) {
+                    return Err(LinkError::DuplicateEntryPoint(spv_entry_point_descr(imms)));
+                }
+            }
+        }
+    }
+
+    let reachable = ReachableDecls::collect(&module);
+
+    dst.dialect = dialect;
+    merge_debug_info(&mut dst.debug_info, module.debug_info);
+
+    // FIXME(eddyb) avoid cloning the definitions (`EntityDefs` doesn't support
+    // moving definitions out, even if `module` is otherwise discarded).
+    let mut remapper = EntityRemapper {
+        cx,
+
+        global_vars: reachable
+            .global_vars
+            .iter()
+            .map(|&gv| (gv, dst.global_vars.define(cx, module.global_vars[gv].clone())))
+            .collect(),
+        funcs: reachable
+            .funcs
+            .iter()
+            .map(|&func| (func, dst.funcs.define(cx, module.funcs[func].clone())))
+            .collect(),
+
+        transformed_types: FxHashMap::default(),
+        transformed_consts: FxHashMap::default(),
+        transformed_data_inst_forms: FxHashMap::default(),
+    };
+
+    for &gv in &reachable.global_vars {
+        let new_gv = remapper.global_vars[&gv];
+        remapper.in_place_transform_global_var_decl(&mut dst.global_vars[new_gv]);
+    }
+    for &func in &reachable.funcs {
+        let new_func = remapper.funcs[&func];
+        remapper.in_place_transform_func_decl(&mut dst.funcs[new_func]);
+    }
+    for (mut export_key, mut exportee) in module.exports {
+        export_key.inner_transform_with(&mut remapper).apply_to(&mut export_key);
+        exportee.inner_transform_with(&mut remapper).apply_to(&mut exportee);
+        dst.exports.insert(export_key, exportee);
+    }
+
+    Ok(())
+}
+
+fn merge_dialects(a: &ModuleDialect, b: &ModuleDialect) -> Result<ModuleDialect, LinkError> {
+    let (ModuleDialect::Spv(a), ModuleDialect::Spv(b)) = (a, b);
+
+    let wk = &spec::Spec::get().well_known;
+    for (kind, a_value, b_value) in [
+        (wk.AddressingModel, a.addressing_model, b.addressing_model),
+        (wk.MemoryModel, a.memory_model, b.memory_model),
+    ] {
+        if a_value != b_value {
+            return Err(LinkError::DialectMismatch(format!(
+                "SPIR-V {} `{}` vs `{}`",
+                kind.name(),
+                enumerant_name(kind, a_value),
+                enumerant_name(kind, b_value)
+            )));
+        }
+    }
+
+    let (version_major, version_minor) =
+        (a.version_major, a.version_minor).max((b.version_major, b.version_minor));
+    Ok(ModuleDialect::Spv(spv::Dialect {
+        version_major,
+        version_minor,
+
+        capabilities: a.capabilities.union(&b.capabilities).copied().collect(),
+        extensions: a.extensions.union(&b.extensions).cloned().collect(),
+
+        addressing_model: a.addressing_model,
+        memory_model: a.memory_model,
+    }))
+}
+
+/// Name of the `value` enumerant of the SPIR-V operand `kind` (or just `value`
+/// printed as a number, if no such enumerant exists).
+fn enumerant_name(kind: spec::OperandKind, value: u32) -> String {
+    let name = match kind.def() {
+        spec::OperandKindDef::ValueEnum { variants } => u16::try_from(value)
+            .ok()
+            .and_then(|value| variants.get_named(value))
+            .map(|(name, _)| name),
+        _ => None,
+    };
+    name.map_or_else(|| value.to_string(), |name| name.to_string())
+}
+
+/// Human-readable description of an [`ExportKey::SpvEntryPoint`] (with `imms`),
+/// e.g. ``GLCompute `main` ``, for use in [`LinkError::DuplicateEntryPoint`].
+fn spv_entry_point_descr(imms: &[spv::Imm]) -> String {
+    match imms {
+        &[spv::Imm::Short(kind, execution_model), ref name_imms @ ..] => {
+            let name = spv::extract_literal_string(name_imms)
+                .unwrap_or_else(|_| "<invalid UTF-8>".to_string());
+            format!("{} `{name}`", enumerant_name(kind, execution_model))
+        }
+        _ => "SPIR-V".to_string(),
+    }
+}
+
+fn merge_debug_info(dst: &mut ModuleDebugInfo, debug_info: ModuleDebugInfo) {
+    let (ModuleDebugInfo::Spv(dst), ModuleDebugInfo::Spv(debug_info)) = (dst, debug_info);
+
+    if dst.original_generator_magic != debug_info.original_generator_magic {
+        dst.original_generator_magic = None;
+    }
+    for (lang, sources) in debug_info.source_languages {
+        let dst_sources = dst.source_languages.entry(lang).or_default();
+        for (file, contents) in sources.file_contents {
+            dst_sources.file_contents.entry(file).or_insert(contents);
+        }
+    }
+    for ext in debug_info.source_extensions {
+        if !dst.source_extensions.contains(&ext) {
+            dst.source_extensions.push(ext);
+        }
+    }
+    for process in debug_info.module_processes {
+        if !dst.module_processes.contains(&process) {
+            dst.module_processes.push(process);
+        }
+    }
+}
+
+/// Replaces all uses of the `GlobalVar`s/`Func`s of a module being merged into
+/// another one, with their counterparts in the latter (see [`merge_module_into`]).
+struct EntityRemapper<'a> {
+    cx: &'a Context,
+
+    global_vars: FxHashMap<GlobalVar, GlobalVar>,
+    funcs: FxHashMap<Func, Func>,
+
+    // FIXME(eddyb) build some automation to avoid ever repeating these.
+    transformed_types: FxHashMap<Type, Transformed<Type>>,
+    transformed_consts: FxHashMap<Const, Transformed<Const>>,
+    transformed_data_inst_forms: FxHashMap<DataInstForm, Transformed<DataInstForm>>,
+}
+
+impl Transformer for EntityRemapper<'_> {
+    // FIXME(eddyb) build some automation to avoid ever repeating these.
+    fn transform_type_use(&mut self, ty: Type) -> Transformed<Type> {
+        if let Some(&cached) = self.transformed_types.get(&ty) {
+            return cached;
+        }
+        let transformed =
+            self.transform_type_def(&self.cx[ty]).map(|ty_def| self.cx.intern(ty_def));
+        self.transformed_types.insert(ty, transformed);
+        transformed
+    }
+    fn transform_const_use(&mut self, ct: Const) -> Transformed<Const> {
+        if let Some(&cached) = self.transformed_consts.get(&ct) {
+            return cached;
+        }
+        let transformed =
+            self.transform_const_def(&self.cx[ct]).map(|ct_def| self.cx.intern(ct_def));
+        self.transformed_consts.insert(ct, transformed);
+        transformed
+    }
+    fn transform_data_inst_form_use(
+        &mut self,
+        data_inst_form: DataInstForm,
+    ) -> Transformed<DataInstForm> {
+        if let Some(&cached) = self.transformed_data_inst_forms.get(&data_inst_form) {
+            return cached;
+        }
+        let transformed = self
+            .transform_data_inst_form_def(&self.cx[data_inst_form])
+            .map(|data_inst_form_def| self.cx.intern(data_inst_form_def));
+        self.transformed_data_inst_forms.insert(data_inst_form, transformed);
+        transformed
+    }
+
+    fn transform_global_var_use(&mut self, gv: GlobalVar) -> Transformed<GlobalVar> {
+        self.global_vars.get(&gv).copied().map_or(Transformed::Unchanged, Transformed::Changed)
+    }
+    fn transform_func_use(&mut self, func: Func) -> Transformed<Func> {
+        self.funcs.get(&func).copied().map_or(Transformed::Unchanged, Transformed::Changed)
+    }
+}
@@ -0,0 +1,397 @@
+//! Pass management (i.e. running passes by name, sharing analyses between them).
+
+use crate::visit::{InnerVisit, Visitor};
+use crate::{
+    print, qptr, AttrSet, Const, Context, DataInstForm, DataInstKind, ExportKey, Func, FxIndexMap,
+    FxIndexSet, GlobalVar, Module, Type,
+};
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// All the global variables and functions reachable from `module.exports`
+/// (which most passes limit themselves to, as all other definitions are dead).
+#[derive(Clone, Default)]
+pub struct ReachableDecls {
+    pub global_vars: FxIndexSet<GlobalVar>,
+    pub funcs: FxIndexSet<Func>,
+}
+
+impl ReachableDecls {
+    pub fn collect(module: &Module) -> Self {
+        let mut collector = ReachableUseCollector {
+            cx: module.cx_ref(),
+            module,
+
+            seen_types: FxIndexSet::default(),
+            seen_consts: FxIndexSet::default(),
+            seen_data_inst_forms: FxIndexSet::default(),
+            seen_global_vars: FxIndexSet::default(),
+            seen_funcs: FxIndexSet::default(),
+        };
+        for (export_key, &exportee) in &module.exports {
+            export_key.inner_visit_with(&mut collector);
+            exportee.inner_visit_with(&mut collector);
+        }
+        Self { global_vars: collector.seen_global_vars, funcs: collector.seen_funcs }
+    }
+}
+
+struct ReachableUseCollector<'a> {
+    cx: &'a Context,
+    module: &'a Module,
+
+    // FIXME(eddyb) build some automation to avoid ever repeating these.
+    seen_types: FxIndexSet<Type>,
+    seen_consts: FxIndexSet<Const>,
+    seen_data_inst_forms: FxIndexSet<DataInstForm>,
+    seen_global_vars: FxIndexSet<GlobalVar>,
+    seen_funcs: FxIndexSet<Func>,
+}
+
+impl Visitor<'_> for ReachableUseCollector<'_> {
+    // FIXME(eddyb) build some automation to avoid ever repeating these.
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {
+        // FIXME(eddyb) if `AttrSet`s are ignored, why not `Type`s too?
+    }
+    fn visit_type_use(&mut self, ty: Type) {
+        if self.seen_types.insert(ty) {
+            self.visit_type_def(&self.cx[ty]);
+        }
+    }
+    fn visit_const_use(&mut self, ct: Const) {
+        if self.seen_consts.insert(ct) {
+            self.visit_const_def(&self.cx[ct]);
+        }
+    }
+    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        if self.seen_data_inst_forms.insert(data_inst_form) {
+            self.visit_data_inst_form_def(&self.cx[data_inst_form]);
+        }
+    }
+
+    fn visit_global_var_use(&mut self, gv: GlobalVar) {
+        if self.seen_global_vars.insert(gv) {
+            self.visit_global_var_decl(&self.module.global_vars[gv]);
+        }
+    }
+    fn visit_func_use(&mut self, func: Func) {
+        if self.seen_funcs.insert(func) {
+            self.visit_func_decl(&self.module.funcs[func]);
+        }
+    }
+}
+
+/// Call graph of all the functions in [`ReachableDecls`], i.e. the functions
+/// called (via [`DataInstKind::FuncCall`]) by each of them (in call order).
+#[derive(Clone, Default)]
+pub struct CallGraph {
+    pub callees: FxIndexMap<Func, FxIndexSet<Func>>,
+}
+
+impl CallGraph {
+    pub fn collect(module: &Module, reachable: &ReachableDecls) -> Self {
+        let callees = reachable
+            .funcs
+            .iter()
+            .map(|&func| {
+                let mut collector =
+                    CalleeCollector { cx: module.cx_ref(), callees: FxIndexSet::default() };
+                module.funcs[func].inner_visit_with(&mut collector);
+                (func, collector.callees)
+            })
+            .collect();
+        Self { callees }
+    }
+}
+
+struct CalleeCollector<'a> {
+    cx: &'a Context,
+
+    callees: FxIndexSet<Func>,
+}
+
+impl Visitor<'_> for CalleeCollector<'_> {
+    fn visit_attr_set_use(&mut self, _attrs: AttrSet) {}
+    fn visit_type_use(&mut self, _ty: Type) {}
+    fn visit_const_use(&mut self, _ct: Const) {}
+    fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        if let DataInstKind::FuncCall(callee) = self.cx[data_inst_form].kind {
+            self.callees.insert(callee);
+        }
+    }
+
+    fn visit_global_var_use(&mut self, _gv: GlobalVar) {}
+    fn visit_func_use(&mut self, _func: Func) {}
+}
+
+/// Cache of analyses (computed on demand), shared between passes, and kept
+/// by [`PassManager`] across passes which preserve them (see [`Pass`]).
+#[derive(Default)]
+pub struct Analyses {
+    reachable: Option<Rc<ReachableDecls>>,
+    call_graph: Option<Rc<CallGraph>>,
+}
+
+impl Analyses {
+    pub fn reachable(&mut self, module: &Module) -> Rc<ReachableDecls> {
+        self.reachable.get_or_insert_with(|| Rc::new(ReachableDecls::collect(module))).clone()
+    }
+
+    pub fn call_graph(&mut self, module: &Module) -> Rc<CallGraph> {
+        if let Some(call_graph) = &self.call_graph {
+            return call_graph.clone();
+        }
+        let reachable = self.reachable(module);
+        self.call_graph.insert(Rc::new(CallGraph::collect(module, &reachable))).clone()
+    }
+
+    /// Discard all cached analyses (e.g. after `module` was changed in ways
+    /// that would make them out of date).
+    pub fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Whole-[`Module`] pass, runnable by [`PassManager`] (by its `name`).
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    fn run(&self, module: &mut Module, analyses: &mut Analyses);
+
+    /// Whether this pass never changes anything [`Analyses`] depend on (i.e.
+    /// which definitions are reachable, or which functions call which), so that
+    /// [`PassManager`] can keep the cached analyses after running this pass.
+    fn preserves_analyses(&self) -> bool {
+        false
+    }
+}
+
+/// [`Pass`] implemented by a closure (used for all the passes in `passes`).
+struct FnPass<F> {
+    name: &'static str,
+    preserves_analyses: bool,
+    run: F,
+}
+
+impl<F: Fn(&mut Module, &mut Analyses)> Pass for FnPass<F> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn run(&self, module: &mut Module, analyses: &mut Analyses) {
+        (self.run)(module, analyses);
+    }
+    fn preserves_analyses(&self) -> bool {
+        self.preserves_analyses
+    }
+}
+
+/// Registry of [`Pass`]es (by name), able to run pipelines of passes, given in
+/// textual form (i.e. comma-separated pass names, e.g. `"dce, fold"`).
+#[derive(Default)]
+pub struct PassManager {
+    passes: FxIndexMap<&'static str, Box<dyn Pass>>,
+
+    /// Whether to measure the time each pass takes (see [`PipelineReport`]).
+    pub time_passes: bool,
+
+    /// Whether to keep a copy of the module after each pass (see [`PipelineReport`]).
+    pub record_versions: bool,
+}
+
+/// Error returned by [`PassManager::run`] for a pipeline using unknown passes.
+#[derive(Debug)]
+pub struct UnknownPassError {
+    pub name: String,
+}
+
+impl fmt::Display for UnknownPassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown pass `{}`", self.name)
+    }
+}
+
+impl std::error::Error for UnknownPassError {}
+
+/// Information optionally collected by [`PassManager::run`].
+#[derive(Default)]
+pub struct PipelineReport {
+    /// The time taken by each pass (only if [`PassManager::time_passes`] is set).
+    pub timings: Vec<(&'static str, Duration)>,
+
+    /// The module before the first pass (as `"initial"`), followed by its state
+    /// after each pass (as `"after {pass}"`), only if [`PassManager::record_versions`]
+    /// is set (see also [`PipelineReport::print_plan_for_versions`]).
+    pub versions: Vec<(String, Module)>,
+}
+
+impl PipelineReport {
+    /// Format [`PipelineReport::timings`] as one line per pass (e.g. for `eprint!`).
+    pub fn timing_report(&self) -> String {
+        self.timings
+            .iter()
+            .map(|(pass, duration)| format!("[{:8.3}ms] {pass}\n", duration.as_secs_f64() * 1000.0))
+            .collect()
+    }
+
+    /// Create a [`print::Plan`] for all of [`PipelineReport::versions`] (if any).
+    pub fn print_plan_for_versions(&self) -> Option<print::Plan<'_>> {
+        let (_, first_module) = self.versions.first()?;
+        Some(print::Plan::for_versions(
+            first_module.cx_ref(),
+            self.versions.iter().map(|(name, module)| (name.clone(), module)),
+        ))
+    }
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a [`PassManager`] with all the passes in `passes` registered,
+    /// named after their function (including the module, e.g. `"link::resolve_imports"`):
+    /// * `link::minimize_exports` (keeping only [`ExportKey::SpvEntryPoint`]s as roots)
+    /// * `link::resolve_imports`
+    /// * `legalize::structurize_func_cfgs`
+    /// * `legalize::structurize_func_cfgs_including_irreducible`
+    /// * `legalize::destructurize_funcs`
+    /// * `inline::inline_calls` (inlining all calls not marked `DontInline`)
+    /// * `dce::eliminate_dead_code`
+    /// * `fold::fold_consts_and_simplify`
+    /// * `gvn::deduplicate_pure_data_insts`
+    /// * `qptr::lower_from_spv_ptrs` (using `layout_config`)
+    /// * `qptr::analyze_uses` (using `layout_config`)
+    /// * `qptr::promote_func_local_vars`
+    /// * `qptr::lift_to_spv_ptrs` (using `layout_config`)
+    /// * `verify::verify_funcs`
+    pub fn with_default_passes(layout_config: qptr::LayoutConfig) -> Self {
+        use super::{dce, fold, gvn, inline, legalize, link, qptr, verify};
+
+        let layout_config = Rc::new(layout_config);
+        let mut pm = Self::new();
+
+        pm.register_fn("link::minimize_exports", false, |module, _| {
+            link::minimize_exports(module, |export_key| {
+                matches!(export_key, ExportKey::SpvEntryPoint { .. })
+            });
+        });
+        pm.register_fn("link::resolve_imports", false, |module, _| {
+            link::resolve_imports(module);
+        });
+        pm.register_fn("legalize::structurize_func_cfgs", true, |module, analyses| {
+            let reachable = analyses.reachable(module);
+            legalize::structurize_func_cfgs_in_reachable(module, &reachable);
+        });
+        pm.register_fn(
+            "legalize::structurize_func_cfgs_including_irreducible",
+            true,
+            |module, analyses| {
+                let reachable = analyses.reachable(module);
+                legalize::structurize_func_cfgs_including_irreducible_in_reachable(
+                    module, &reachable,
+                );
+            },
+        );
+        pm.register_fn("legalize::destructurize_funcs", true, |module, analyses| {
+            let reachable = analyses.reachable(module);
+            legalize::destructurize_funcs_in_reachable(module, &reachable);
+        });
+        pm.register_fn("inline::inline_calls", false, |module, analyses| {
+            let call_graph = analyses.call_graph(module);
+            inline::inline_calls_with_call_graph(module, &call_graph, |cx, callee_decl| {
+                inline::spv_inline_hint(cx, callee_decl) != Some(inline::SpvInlineHint::DontInline)
+            });
+        });
+        pm.register_fn("dce::eliminate_dead_code", false, |module, analyses| {
+            let reachable = analyses.reachable(module);
+            dce::eliminate_dead_code_in_reachable(module, &reachable);
+        });
+        pm.register_fn("fold::fold_consts_and_simplify", false, |module, analyses| {
+            let reachable = analyses.reachable(module);
+            fold::fold_consts_and_simplify_in_reachable(module, &reachable);
+        });
+        pm.register_fn("gvn::deduplicate_pure_data_insts", true, |module, analyses| {
+            let reachable = analyses.reachable(module);
+            gvn::deduplicate_pure_data_insts_in_reachable(module, &reachable);
+        });
+        pm.register_fn("qptr::lower_from_spv_ptrs", true, {
+            let layout_config = layout_config.clone();
+            move |module, analyses| {
+                let reachable = analyses.reachable(module);
+                qptr::lower_from_spv_ptrs_in_reachable(module, &layout_config, &reachable);
+            }
+        });
+        pm.register_fn("qptr::analyze_uses", true, {
+            let layout_config = layout_config.clone();
+            move |module, _| qptr::analyze_uses(module, &layout_config)
+        });
+        pm.register_fn("qptr::promote_func_local_vars", true, |module, analyses| {
+            let reachable = analyses.reachable(module);
+            qptr::promote_func_local_vars_in_reachable(module, &reachable);
+        });
+        pm.register_fn("qptr::lift_to_spv_ptrs", true, move |module, analyses| {
+            let reachable = analyses.reachable(module);
+            qptr::lift_to_spv_ptrs_in_reachable(module, &layout_config, &reachable);
+        });
+        pm.register_fn("verify::verify_funcs", true, |module, analyses| {
+            let reachable = analyses.reachable(module);
+            verify::verify_funcs_in_reachable(module, &reachable);
+        });
+
+        pm
+    }
+
+    fn register_fn(
+        &mut self,
+        name: &'static str,
+        preserves_analyses: bool,
+        run: impl Fn(&mut Module, &mut Analyses) + 'static,
+    ) {
+        self.register(FnPass { name, preserves_analyses, run });
+    }
+
+    /// Register `pass` (replacing any previously registered pass with the same name).
+    pub fn register(&mut self, pass: impl Pass + 'static) {
+        self.passes.insert(pass.name(), Box::new(pass));
+    }
+
+    /// Run all the passes in `pipeline` (comma-separated pass names) on `module`,
+    /// in order, after checking that they're all registered.
+    pub fn run(
+        &self,
+        module: &mut Module,
+        pipeline: &str,
+    ) -> Result<PipelineReport, UnknownPassError> {
+        let passes = pipeline
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                self.passes.get(name).ok_or_else(|| UnknownPassError { name: name.to_string() })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut report = PipelineReport::default();
+        if self.record_versions {
+            report.versions.push(("initial".into(), module.clone()));
+        }
+
+        let mut analyses = Analyses::default();
+        for pass in passes {
+            let start = Instant::now();
+            pass.run(module, &mut analyses);
+            if self.time_passes {
+                report.timings.push((pass.name(), start.elapsed()));
+            }
+
+            if !pass.preserves_analyses() {
+                analyses.invalidate();
+            }
+            if self.record_versions {
+                report.versions.push((format!("after {}", pass.name()), module.clone()));
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,1205 @@
+//! Verification of SPIR-T invariants (e.g. those documented on [`ControlRegion`]).
+//!
+//! Malformed IR would otherwise only be detected much later (if at all), e.g.
+//! by `spv::lift` panicking, far away from the pass that produced it.
+
+use crate::cfg::{ControlFlowGraph, ControlInstKind, DomTree};
+use crate::func_at::FuncAt;
+use crate::passes::manager::ReachableDecls;
+use crate::spv::{self, spec};
+use crate::{
+    Context, ControlNode, ControlNodeKind, ControlRegion, DataInst, DataInstKind, DeclDef, Diag,
+    DiagMsgPart, EntityListIter, FuncDecl, FuncDefBody, FxIndexSet, Module, PureOp, ScalarKind,
+    SelectionKind, Type, TypeKind, TypeOrConst, Value,
+};
+use itertools::Itertools;
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+use std::hash::Hash;
+
+/// Check all the function definitions in `module` (reachable from its exports)
+/// for violations of SPIR-T invariants, attaching a [`Diag`] (via `push_diag`)
+/// for each violation found, and returning the total number of violations.
+///
+/// The checked invariants include:
+/// * the [`ControlRegion`]/[`ControlNode`] "mutual tree" (i.e. every region,
+///   control node, and data instruction, appearing in exactly one place)
+/// * the integrity of the `EntityList` links (`ControlRegionDef::children`,
+///   and `ControlNodeKind::Block`'s `insts`)
+/// * SSA dominance (i.e. every [`Value`] use being preceded by its definition,
+///   following the structured control-flow rules documented on [`ControlRegion`])
+/// * the count and types of `outputs`/`inputs` of regions and control nodes
+///   (e.g. `Select` cases vs the `Select`'s own outputs, or `Loop`'s
+///   `initial_inputs` vs its `body`'s `inputs`)
+/// * [`cfg::ControlInst`](crate::cfg::ControlInst) `targets` vs `target_inputs`
+/// * the input/output types of [`PureOp`]s (following the rules of the SPIR-V
+///   instructions they correspond to), and of SPIR-V `OpLoad`/`OpStore`
+///   (vs the pointee type of their pointer input)
+///
+/// Diagnostics are attached to the closest entity that has an [`AttrSet`](crate::AttrSet)
+/// (the [`DataInst`], or [`ControlInst`](crate::cfg::ControlInst), involved),
+/// falling back to the [`FuncDecl`] (e.g. for structural violations).
+pub fn verify_funcs(module: &mut Module) -> usize {
+    let reachable = ReachableDecls::collect(module);
+    verify_funcs_in_reachable(module, &reachable)
+}
+
+pub(crate) fn verify_funcs_in_reachable(module: &mut Module, reachable: &ReachableDecls) -> usize {
+    let cx = &module.cx();
+
+    let mut violation_count = 0;
+    for &func in &reachable.funcs {
+        let func_decl = &module.funcs[func];
+        let func_def_body = match &func_decl.def {
+            DeclDef::Imported(_) => continue,
+            DeclDef::Present(func_def_body) => func_def_body,
+        };
+
+        let diags = {
+            let mut verifier = FuncVerifier {
+                cx,
+                module,
+                func_decl,
+                func_def_body,
+
+                seen_regions: FxHashSet::default(),
+                seen_control_nodes: FxHashSet::default(),
+                seen_data_insts: FxHashSet::default(),
+
+                in_scope: FxHashSet::default(),
+                scope_log: vec![],
+
+                diags: vec![],
+            };
+            verifier.verify_func();
+            verifier.diags
+        };
+        violation_count += diags.len();
+
+        let func_decl = &mut module.funcs[func];
+        for (target, diag) in diags {
+            match target {
+                DiagTarget::Func => func_decl.attrs.push_diag(cx, diag),
+                DiagTarget::DataInst(inst) => match &mut func_decl.def {
+                    DeclDef::Present(func_def_body) => {
+                        func_def_body.data_insts[inst].attrs.push_diag(cx, diag);
+                    }
+                    DeclDef::Imported(_) => unreachable!(),
+                },
+                DiagTarget::ControlInstOnExitFrom(region) => match &mut func_decl.def {
+                    DeclDef::Present(FuncDefBody { unstructured_cfg: Some(cfg), .. }) => {
+                        cfg.control_inst_on_exit_from[region].attrs.push_diag(cx, diag);
+                    }
+                    _ => unreachable!(),
+                },
+            }
+        }
+    }
+    violation_count
+}
+
+/// Where to attach a [`Diag`] (see also [`verify_funcs`]'s documentation).
+#[derive(Copy, Clone)]
+enum DiagTarget {
+    Func,
+    DataInst(DataInst),
+    ControlInstOnExitFrom(ControlRegion),
+}
+
+/// Any intra-function definition of [`Value`]s (which can go in/out of scope).
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum ValueDef {
+    RegionInputs(ControlRegion),
+    ControlNodeOutputs(ControlNode),
+    DataInstOutput(DataInst),
+}
+
+struct FuncVerifier<'a> {
+    cx: &'a Context,
+    module: &'a Module,
+    func_decl: &'a FuncDecl,
+    func_def_body: &'a FuncDefBody,
+
+    // Every entity seen so far (to detect violations of the "mutual tree").
+    seen_regions: FxHashSet<ControlRegion>,
+    seen_control_nodes: FxHashSet<ControlNode>,
+    seen_data_insts: FxHashSet<DataInst>,
+
+    /// All the [`ValueDef`]s which dominate the current point in the function.
+    in_scope: FxHashSet<ValueDef>,
+
+    /// Every [`ValueDef`] in `in_scope`, in the order it was added, allowing
+    /// [`FuncVerifier::leave_scope`] to remove them (e.g. when leaving a `Select` case).
+    scope_log: Vec<ValueDef>,
+
+    diags: Vec<(DiagTarget, Diag)>,
+}
+
+impl<'a> FuncVerifier<'a> {
+    fn func_at<P: Copy>(&self, position: P) -> FuncAt<'a, P> {
+        self.func_def_body.at(position)
+    }
+
+    fn err(&mut self, target: DiagTarget, message: impl IntoIterator<Item = DiagMsgPart>) {
+        self.diags.push((target, Diag::err(message)));
+    }
+
+    fn enter_def(&mut self, def: ValueDef) {
+        if self.in_scope.insert(def) {
+            self.scope_log.push(def);
+        }
+    }
+
+    /// Remove all [`ValueDef`]s added since `scope_log` had `scope_start` entries.
+    fn leave_scope(&mut self, scope_start: usize) {
+        for def in self.scope_log.drain(scope_start..) {
+            self.in_scope.remove(&def);
+        }
+    }
+
+    fn verify_func(&mut self) {
+        let func_def_body = self.func_def_body;
+        let body = func_def_body.body;
+
+        // Function parameters are the inputs of the body.
+        let body_inputs = &self.func_at(body).def().inputs;
+        if body_inputs.len() != self.func_decl.params.len() {
+            self.err(
+                DiagTarget::Func,
+                [format!(
+                    "function body has {} inputs, but the function has {} parameters",
+                    body_inputs.len(),
+                    self.func_decl.params.len()
+                )
+                .into()],
+            );
+        }
+        for (i, (input, param)) in body_inputs.iter().zip(&self.func_decl.params).enumerate() {
+            if input.ty != param.ty {
+                self.err(
+                    DiagTarget::Func,
+                    [
+                        format!("function body input #{i} has type `").into(),
+                        input.ty.into(),
+                        "`, but the corresponding parameter has type `".into(),
+                        param.ty.into(),
+                        "`".into(),
+                    ],
+                );
+            }
+        }
+
+        match &func_def_body.unstructured_cfg {
+            None => {
+                self.verify_region(body);
+
+                // Structured return, from the function body's `outputs`.
+                let body_outputs = &self.func_at(body).def().outputs;
+                if body_outputs.len() > 1 {
+                    self.err(
+                        DiagTarget::Func,
+                        [format!(
+                            "function body has {} outputs, but at most one \
+                         (the structured return value) is allowed",
+                            body_outputs.len()
+                        )
+                        .into()],
+                    );
+                }
+                if let Some(&v) = body_outputs.first() {
+                    if let Some(ty) = self.verify_value_use(DiagTarget::Func, "function body", v) {
+                        let ret_type = self.func_decl.ret_type;
+                        if ty != ret_type {
+                            self.err(
+                                DiagTarget::Func,
+                                [
+                                    "function body output (structured return value) has type `"
+                                        .into(),
+                                    ty.into(),
+                                    "`, but the function's return type is `".into(),
+                                    ret_type.into(),
+                                    "`".into(),
+                                ],
+                            );
+                        }
+                    }
+                }
+            }
+            Some(cfg) => self.verify_cfg(cfg),
+        }
+    }
+
+    fn verify_cfg(&mut self, cfg: &ControlFlowGraph) {
+        let body = self.func_def_body.body;
+
+        // Compute a reverse post-order (RPO) of the CFG, without relying on
+        // any of the `ControlFlowGraph` methods (which may panic on malformed IR).
+        let mut post_order = vec![];
+        {
+            let mut visited = FxHashSet::default();
+            visited.insert(body);
+            let mut stack = vec![(body, 0)];
+            while let Some((region, next_target_idx)) = stack.pop() {
+                let targets = cfg
+                    .control_inst_on_exit_from
+                    .get(region)
+                    .map_or(&[][..], |control_inst| &control_inst.targets[..]);
+                match targets.get(next_target_idx) {
+                    Some(&target) => {
+                        stack.push((region, next_target_idx + 1));
+                        if visited.insert(target) {
+                            stack.push((target, 0));
+                        }
+                    }
+                    None => post_order.push(region),
+                }
+            }
+        }
+
+        // NOTE(eddyb) the dominator tree is computed from the RPO above (instead
+        // of using `ControlFlowGraph::dom_tree`, which may panic on malformed IR),
+        // with regions lacking a `ControlInst` treated as having no targets.
+        let rev_post_order: FxIndexSet<_> = post_order.into_iter().rev().collect();
+        let dom_tree = {
+            // NOTE(eddyb) index `0` is reserved for the "virtual root" (see `DomTree::compute`).
+            let mut preds = vec![SmallVec::new(); rev_post_order.len() + 1];
+            preds[1].push(0);
+            for (i, &region) in rev_post_order.iter().enumerate() {
+                let targets = cfg
+                    .control_inst_on_exit_from
+                    .get(region)
+                    .map_or(&[][..], |control_inst| &control_inst.targets[..]);
+                for &target in targets {
+                    preds[rev_post_order.get_index_of(&target).unwrap() + 1].push(i + 1);
+                }
+            }
+            DomTree::compute(rev_post_order, &preds)
+        };
+        let mut dom_children = FxHashMap::<_, SmallVec<[_; 4]>>::default();
+        for region in dom_tree.regions() {
+            if let Some(idom) = dom_tree.idom(region) {
+                dom_children.entry(idom).or_default().push(region);
+            }
+        }
+
+        // Visit the dominator tree in pre-order, keeping in scope only the
+        // definitions from each region and all of its (CFG) dominators.
+        enum Step {
+            Enter(ControlRegion),
+            Leave { scope_start: usize },
+        }
+        let mut stack = vec![Step::Enter(body)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Enter(region) => {
+                    let scope_start = self.scope_log.len();
+                    self.verify_region(region);
+                    self.verify_control_inst_on_exit_from(cfg, region);
+
+                    stack.push(Step::Leave { scope_start });
+                    let children = dom_children.get(&region).map_or(&[][..], |c| &c[..]);
+                    stack.extend(children.iter().rev().map(|&child| Step::Enter(child)));
+                }
+                Step::Leave { scope_start } => self.leave_scope(scope_start),
+            }
+        }
+    }
+
+    /// Verify the [`ControlInst`](crate::cfg::ControlInst) on exit from `region`
+    /// (whose definitions, and those of its dominators, must be in scope).
+    fn verify_control_inst_on_exit_from(&mut self, cfg: &ControlFlowGraph, region: ControlRegion) {
+        let control_inst = match cfg.control_inst_on_exit_from.get(region) {
+            Some(control_inst) => control_inst,
+            None => {
+                self.err(
+                    DiagTarget::Func,
+                    ["CFG contains a region without any `ControlInst` on exit".into()],
+                );
+                return;
+            }
+        };
+        let target = DiagTarget::ControlInstOnExitFrom(region);
+
+        for &v in &control_inst.inputs {
+            self.verify_value_use(target, "`ControlInst` input", v);
+        }
+
+        let (expected_inputs, expected_targets) = match &control_inst.kind {
+            ControlInstKind::Unreachable => (Some(0), Some(0)),
+            ControlInstKind::Return | ControlInstKind::ExitInvocation(_) => (None, Some(0)),
+            ControlInstKind::Branch => (Some(0), Some(1)),
+            ControlInstKind::SelectBranch(SelectionKind::BoolCond) => (Some(1), Some(2)),
+            ControlInstKind::SelectBranch(SelectionKind::SpvInst(_)) => (Some(1), None),
+        };
+        if let Some(expected) = expected_inputs {
+            if control_inst.inputs.len() != expected {
+                self.err(
+                    target,
+                    [format!(
+                        "`ControlInst` has {} inputs, expected {expected}",
+                        control_inst.inputs.len()
+                    )
+                    .into()],
+                );
+            }
+        }
+        if let Some(expected) = expected_targets {
+            if control_inst.targets.len() != expected {
+                self.err(
+                    target,
+                    [format!(
+                        "`ControlInst` has {} targets, expected {expected}",
+                        control_inst.targets.len()
+                    )
+                    .into()],
+                );
+            }
+        }
+
+        for (&target_region, target_inputs) in &control_inst.target_inputs {
+            if !control_inst.targets.contains(&target_region) {
+                self.err(
+                    target,
+                    ["`ControlInst` has `target_inputs` for a region not in `targets`".into()],
+                );
+                continue;
+            }
+            for &v in target_inputs {
+                self.verify_value_use(target, "`ControlInst` target input", v);
+            }
+        }
+
+        let mut seen_targets = FxHashSet::default();
+        for &target_region in &control_inst.targets {
+            if !seen_targets.insert(target_region) {
+                continue;
+            }
+            let region_inputs = &self.func_at(target_region).def().inputs;
+            let target_inputs =
+                control_inst.target_inputs.get(&target_region).map_or(&[][..], |v| &v[..]);
+            if target_inputs.len() != region_inputs.len() {
+                self.err(
+                    target,
+                    [format!(
+                        "`ControlInst` passes {} `target_inputs` to a target region \
+                     which has {} inputs",
+                        target_inputs.len(),
+                        region_inputs.len()
+                    )
+                    .into()],
+                );
+                continue;
+            }
+            for (i, (&v, input)) in target_inputs.iter().zip(region_inputs).enumerate() {
+                let ty = match v {
+                    Value::Const(ct) => Some(self.cx[ct].ty),
+                    _ => self.value_type_if_in_scope(v),
+                };
+                if let Some(ty) = ty {
+                    if ty != input.ty {
+                        self.err(
+                            target,
+                            [
+                                format!("`ControlInst` target input #{i} has type `").into(),
+                                ty.into(),
+                                "`, but the target region input has type `".into(),
+                                input.ty.into(),
+                                "`".into(),
+                            ],
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Verify `region` and its contents, leaving all of its definitions in scope
+    /// (it's up to the caller to remove them, e.g. with [`FuncVerifier::leave_scope`]).
+    fn verify_region(&mut self, region: ControlRegion) {
+        if !self.seen_regions.insert(region) {
+            self.err(
+                DiagTarget::Func,
+                ["`ControlRegion` used in more than one place \
+                 (violates the region/node \"mutual tree\")"
+                    .into()],
+            );
+            return;
+        }
+        self.enter_def(ValueDef::RegionInputs(region));
+
+        let region_def = self.func_at(region).def();
+        let EntityListIter { first, last } = region_def.children.iter();
+        let control_nodes = &self.func_def_body.control_nodes;
+        let children = collect_list_nodes(
+            first,
+            last,
+            |node| control_nodes[node].prev_in_list(),
+            |node| control_nodes[node].next_in_list(),
+        );
+        let children = match children {
+            Ok(children) => children,
+            Err(msg) => {
+                self.err(
+                    DiagTarget::Func,
+                    [format!("invalid `ControlRegion` children: {msg}").into()],
+                );
+                return;
+            }
+        };
+        for control_node in children {
+            self.verify_control_node(control_node);
+        }
+
+        for &v in &region_def.outputs {
+            self.verify_value_use(DiagTarget::Func, "`ControlRegion` output", v);
+        }
+    }
+
+    fn verify_control_node(&mut self, control_node: ControlNode) {
+        if !self.seen_control_nodes.insert(control_node) {
+            self.err(
+                DiagTarget::Func,
+                ["`ControlNode` used in more than one place \
+                 (violates the region/node \"mutual tree\")"
+                    .into()],
+            );
+            return;
+        }
+
+        let control_node_def = self.func_at(control_node).def();
+        match &control_node_def.kind {
+            ControlNodeKind::Block { insts } => {
+                let EntityListIter { first, last } = insts.iter();
+                let data_insts = &self.func_def_body.data_insts;
+                let insts = collect_list_nodes(
+                    first,
+                    last,
+                    |inst| data_insts[inst].prev_in_list(),
+                    |inst| data_insts[inst].next_in_list(),
+                );
+                match insts {
+                    Ok(insts) => {
+                        for inst in insts {
+                            self.verify_data_inst(inst);
+                        }
+                    }
+                    Err(msg) => {
+                        self.err(
+                            DiagTarget::Func,
+                            [format!("invalid `ControlNodeKind::Block` insts: {msg}").into()],
+                        );
+                    }
+                }
+
+                if !control_node_def.outputs.is_empty() {
+                    self.err(DiagTarget::Func, ["`ControlNodeKind::Block` has outputs".into()]);
+                }
+            }
+
+            ControlNodeKind::Select { kind, scrutinee, cases } => {
+                let scrutinee_type =
+                    self.verify_value_use(DiagTarget::Func, "`Select` scrutinee", *scrutinee);
+                if let SelectionKind::BoolCond = kind {
+                    if cases.len() != 2 {
+                        self.err(
+                            DiagTarget::Func,
+                            [format!(
+                                "`Select` with `SelectionKind::BoolCond` has {} cases, expected 2",
+                                cases.len()
+                            )
+                            .into()],
+                        );
+                    }
+                    if let Some(ty) = scrutinee_type {
+                        self.verify_bool_type(DiagTarget::Func, "`Select` scrutinee", ty);
+                    }
+                }
+
+                for &case in cases {
+                    let scope_start = self.scope_log.len();
+                    self.verify_region(case);
+                    self.verify_region_outputs_against(
+                        case,
+                        "`Select` case",
+                        control_node_def.outputs.iter().map(|output| output.ty),
+                    );
+
+                    // NOTE(eddyb) values defined in a region can only be used
+                    // outside of it, if it's the only child region of its parent.
+                    if cases.len() != 1 {
+                        self.leave_scope(scope_start);
+                    }
+                }
+            }
+
+            ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                let body_inputs = &self.func_at(*body).def().inputs;
+                if initial_inputs.len() != body_inputs.len() {
+                    self.err(
+                        DiagTarget::Func,
+                        [format!(
+                            "`Loop` has {} `initial_inputs`, but its body has {} inputs",
+                            initial_inputs.len(),
+                            body_inputs.len()
+                        )
+                        .into()],
+                    );
+                }
+                for (i, (&v, input)) in initial_inputs.iter().zip(body_inputs).enumerate() {
+                    if let Some(ty) =
+                        self.verify_value_use(DiagTarget::Func, "`Loop` initial input", v)
+                    {
+                        if ty != input.ty {
+                            self.err(
+                                DiagTarget::Func,
+                                [
+                                    format!("`Loop` initial input #{i} has type `").into(),
+                                    ty.into(),
+                                    "`, but the body input has type `".into(),
+                                    input.ty.into(),
+                                    "`".into(),
+                                ],
+                            );
+                        }
+                    }
+                }
+
+                self.verify_region(*body);
+                self.verify_region_outputs_against(
+                    *body,
+                    "`Loop` body",
+                    body_inputs.iter().map(|input| input.ty),
+                );
+
+                if let Some(ty) = self.verify_value_use(
+                    DiagTarget::Func,
+                    "`Loop` repeat condition",
+                    *repeat_condition,
+                ) {
+                    self.verify_bool_type(DiagTarget::Func, "`Loop` repeat condition", ty);
+                }
+
+                if !control_node_def.outputs.is_empty() {
+                    self.err(DiagTarget::Func, ["`ControlNodeKind::Loop` has outputs".into()]);
+                }
+            }
+        }
+
+        self.enter_def(ValueDef::ControlNodeOutputs(control_node));
+    }
+
+    fn verify_data_inst(&mut self, inst: DataInst) {
+        let target = DiagTarget::DataInst(inst);
+        if !self.seen_data_insts.insert(inst) {
+            self.err(
+                target,
+                ["`DataInst` used in more than one place \
+                 (violates the region/node \"mutual tree\")"
+                    .into()],
+            );
+            return;
+        }
+
+        let data_inst_def = self.func_at(inst).def();
+        let input_types: Vec<_> = data_inst_def
+            .inputs
+            .iter()
+            .map(|&v| self.verify_value_use(target, "`DataInst` input", v))
+            .collect();
+
+        let form = &self.cx[data_inst_def.form];
+
+        // NOTE(eddyb) invalid inputs were already reported above.
+        if let Some(input_types) = input_types.iter().copied().collect::<Option<Vec<_>>>() {
+            match &form.kind {
+                &DataInstKind::Pure(op) => {
+                    self.verify_pure_op_types(target, op, &input_types, form.output_type);
+                }
+                DataInstKind::SpvInst(spv_inst) => {
+                    self.verify_spv_inst_types(target, spv_inst, &input_types, form.output_type);
+                }
+                _ => {}
+            }
+        }
+
+        if let DataInstKind::FuncCall(callee) = form.kind {
+            let params = &self.module.funcs[callee].params;
+            if input_types.len() != params.len() {
+                self.err(
+                    target,
+                    [format!(
+                        "call passes {} arguments to a function with {} parameters",
+                        input_types.len(),
+                        params.len()
+                    )
+                    .into()],
+                );
+            }
+            for (i, (ty, param)) in input_types.into_iter().zip(params).enumerate() {
+                if let Some(ty) = ty {
+                    if ty != param.ty {
+                        self.err(
+                            target,
+                            [
+                                format!("call argument #{i} has type `").into(),
+                                ty.into(),
+                                "`, but the corresponding parameter has type `".into(),
+                                param.ty.into(),
+                                "`".into(),
+                            ],
+                        );
+                    }
+                }
+            }
+        }
+
+        self.enter_def(ValueDef::DataInstOutput(inst));
+    }
+
+    /// Check that the `outputs` of `region` (already verified, and still in scope)
+    /// match `expected_types` (e.g. the outputs of its parent [`ControlNode`]).
+    fn verify_region_outputs_against(
+        &mut self,
+        region: ControlRegion,
+        descr: &str,
+        expected_types: impl ExactSizeIterator<Item = Type>,
+    ) {
+        let outputs = &self.func_at(region).def().outputs;
+        if outputs.len() != expected_types.len() {
+            self.err(
+                DiagTarget::Func,
+                [format!(
+                    "{descr} has {} outputs, expected {}",
+                    outputs.len(),
+                    expected_types.len()
+                )
+                .into()],
+            );
+            return;
+        }
+        for (i, (&v, expected)) in outputs.iter().zip(expected_types).enumerate() {
+            let ty = match v {
+                Value::Const(ct) => Some(self.cx[ct].ty),
+                _ => self.value_type_if_in_scope(v),
+            };
+            if let Some(ty) = ty {
+                if ty != expected {
+                    self.err(
+                        DiagTarget::Func,
+                        [
+                            format!("{descr} output #{i} has type `").into(),
+                            ty.into(),
+                            "`, expected `".into(),
+                            expected.into(),
+                            "`".into(),
+                        ],
+                    );
+                }
+            }
+        }
+    }
+
+    /// Check the types of the inputs and output of a [`PureOp`], against the
+    /// rules of the SPIR-V instruction it's equivalent to (e.g. `IAdd` allows
+    /// integer inputs of either signedness, as long as their width and element
+    /// count match the output's, while `FAdd` requires identical types).
+    fn verify_pure_op_types(
+        &mut self,
+        target: DiagTarget,
+        op: PureOp,
+        input_types: &[Type],
+        output_type: Option<Type>,
+    ) {
+        let cx = self.cx;
+
+        let expected_input_count = match op {
+            PureOp::SNegate
+            | PureOp::FNegate
+            | PureOp::Not
+            | PureOp::LogicalNot
+            | PureOp::ConvertFToU
+            | PureOp::ConvertFToS
+            | PureOp::ConvertSToF
+            | PureOp::ConvertUToF
+            | PureOp::UConvert
+            | PureOp::SConvert
+            | PureOp::FConvert
+            | PureOp::Bitcast
+            | PureOp::CompositeExtract { .. } => 1,
+            PureOp::Select | PureOp::VectorInsertDynamic => 3,
+            _ => 2,
+        };
+        if input_types.len() != expected_input_count {
+            self.err(
+                target,
+                [format!(
+                    "`{}` has {} inputs, expected {expected_input_count}",
+                    op.name(),
+                    input_types.len()
+                )
+                .into()],
+            );
+            return;
+        }
+
+        let Some(output_type) = output_type else {
+            self.err(target, [format!("`{}` has no output", op.name()).into()]);
+            return;
+        };
+
+        // NOTE(eddyb) composite types (other than vectors) aren't native to
+        // SPIR-T yet, and `Bitcast` can change the shape of its input entirely.
+        if let PureOp::CompositeExtract { .. } | PureOp::CompositeInsert { .. } | PureOp::Bitcast =
+            op
+        {
+            return;
+        }
+
+        let Some(output) = ScalarOrVector::of(cx, output_type) else {
+            self.err(
+                target,
+                [
+                    format!("`{}` has output type `", op.name()).into(),
+                    output_type.into(),
+                    "`, expected a scalar or vector type".into(),
+                ],
+            );
+            return;
+        };
+        let count = Some(output.elem_count);
+
+        const INT: &[ScalarKind] = &[ScalarKind::SInt, ScalarKind::UInt];
+        const FLOAT: &[ScalarKind] = &[ScalarKind::Float];
+        const BOOL: &[ScalarKind] = &[ScalarKind::Bool];
+        const ANY: &[ScalarKind] =
+            &[ScalarKind::Bool, ScalarKind::SInt, ScalarKind::UInt, ScalarKind::Float];
+
+        // The expectations for the output, and for each input, in order.
+        let (output_expectation, input_expectations): (_, SmallVec<[_; 3]>) = match op {
+            PureOp::SNegate
+            | PureOp::IAdd
+            | PureOp::ISub
+            | PureOp::IMul
+            | PureOp::UDiv
+            | PureOp::SDiv
+            | PureOp::UMod
+            | PureOp::SRem
+            | PureOp::SMod
+            | PureOp::BitwiseOr
+            | PureOp::BitwiseXor
+            | PureOp::BitwiseAnd
+            | PureOp::Not => {
+                // NOTE(eddyb) SPIR-V allows mixing signedness, but not widths.
+                let same_shape = Expect::Shape(INT, Some(output.width), count);
+                (Expect::Shape(INT, None, None), input_types.iter().map(|_| same_shape).collect())
+            }
+
+            PureOp::ShiftRightLogical | PureOp::ShiftRightArithmetic | PureOp::ShiftLeftLogical => {
+                (
+                    Expect::Shape(INT, None, None),
+                    [
+                        Expect::Shape(INT, Some(output.width), count),
+                        Expect::Shape(INT, None, count),
+                    ]
+                    .into_iter()
+                    .collect(),
+                )
+            }
+
+            PureOp::FNegate
+            | PureOp::FAdd
+            | PureOp::FSub
+            | PureOp::FMul
+            | PureOp::FDiv
+            | PureOp::FRem
+            | PureOp::FMod => (
+                Expect::Shape(FLOAT, None, None),
+                input_types.iter().map(|_| Expect::Type(output_type)).collect(),
+            ),
+
+            PureOp::LogicalEqual
+            | PureOp::LogicalNotEqual
+            | PureOp::LogicalOr
+            | PureOp::LogicalAnd
+            | PureOp::LogicalNot => (
+                Expect::Shape(BOOL, None, None),
+                input_types.iter().map(|_| Expect::Type(output_type)).collect(),
+            ),
+
+            // NOTE(eddyb) a scalar condition can also select between vectors.
+            PureOp::Select => (
+                Expect::Shape(ANY, None, None),
+                [
+                    Expect::BoolCondFor(output.elem_count),
+                    Expect::Type(output_type),
+                    Expect::Type(output_type),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+
+            PureOp::IEqual
+            | PureOp::INotEqual
+            | PureOp::UGreaterThan
+            | PureOp::SGreaterThan
+            | PureOp::UGreaterThanEqual
+            | PureOp::SGreaterThanEqual
+            | PureOp::ULessThan
+            | PureOp::SLessThan
+            | PureOp::ULessThanEqual
+            | PureOp::SLessThanEqual => {
+                let width = ScalarOrVector::of(cx, input_types[0]).map(|input| input.width);
+                let same_shape = Expect::Shape(INT, width, count);
+                (Expect::Shape(BOOL, None, None), [same_shape, same_shape].into_iter().collect())
+            }
+
+            PureOp::FOrdEqual
+            | PureOp::FUnordEqual
+            | PureOp::FOrdNotEqual
+            | PureOp::FUnordNotEqual
+            | PureOp::FOrdLessThan
+            | PureOp::FUnordLessThan
+            | PureOp::FOrdGreaterThan
+            | PureOp::FUnordGreaterThan
+            | PureOp::FOrdLessThanEqual
+            | PureOp::FUnordLessThanEqual
+            | PureOp::FOrdGreaterThanEqual
+            | PureOp::FUnordGreaterThanEqual => (
+                Expect::Shape(BOOL, None, None),
+                [Expect::Shape(FLOAT, None, count), Expect::Type(input_types[0])]
+                    .into_iter()
+                    .collect(),
+            ),
+
+            PureOp::ConvertFToU | PureOp::ConvertFToS => (
+                Expect::Shape(INT, None, None),
+                [Expect::Shape(FLOAT, None, count)].into_iter().collect(),
+            ),
+            PureOp::ConvertSToF | PureOp::ConvertUToF => (
+                Expect::Shape(FLOAT, None, None),
+                [Expect::Shape(INT, None, count)].into_iter().collect(),
+            ),
+            PureOp::UConvert | PureOp::SConvert => (
+                Expect::Shape(INT, None, None),
+                [Expect::Shape(INT, None, count)].into_iter().collect(),
+            ),
+            PureOp::FConvert => (
+                Expect::Shape(FLOAT, None, None),
+                [Expect::Shape(FLOAT, None, count)].into_iter().collect(),
+            ),
+
+            PureOp::VectorExtractDynamic => (
+                Expect::Shape(ANY, None, Some(1)),
+                [Expect::VectorOf(output_type), Expect::Shape(INT, None, Some(1))]
+                    .into_iter()
+                    .collect(),
+            ),
+            PureOp::VectorInsertDynamic => {
+                let elem_type = match cx[output_type].kind {
+                    TypeKind::Vector { elem, .. } => elem,
+                    _ => {
+                        self.err(
+                            target,
+                            [
+                                "`VectorInsertDynamic` has output type `".into(),
+                                output_type.into(),
+                                "`, expected a vector type".into(),
+                            ],
+                        );
+                        return;
+                    }
+                };
+                (
+                    Expect::Shape(ANY, None, None),
+                    [
+                        Expect::Type(output_type),
+                        Expect::Type(elem_type),
+                        Expect::Shape(INT, None, Some(1)),
+                    ]
+                    .into_iter()
+                    .collect(),
+                )
+            }
+
+            PureOp::CompositeExtract { .. } | PureOp::CompositeInsert { .. } | PureOp::Bitcast => {
+                unreachable!()
+            }
+        };
+
+        let expectations = [(None, output_type, output_expectation)].into_iter().chain(
+            input_types
+                .iter()
+                .zip(input_expectations)
+                .enumerate()
+                .map(|(i, (&ty, expected))| (Some(i), ty, expected)),
+        );
+        for (input_idx, ty, expected) in expectations {
+            if expected.matches(cx, ty) {
+                continue;
+            }
+            let mut message: SmallVec<[DiagMsgPart; 5]> = [
+                match input_idx {
+                    Some(i) => format!("`{}` input #{i} has type `", op.name()),
+                    None => format!("`{}` has output type `", op.name()),
+                }
+                .into(),
+                ty.into(),
+            ]
+            .into_iter()
+            .collect();
+            match expected {
+                Expect::Type(expected) => {
+                    message.extend(["`, expected `".into(), expected.into(), "`".into()]);
+                }
+                _ => message.push(format!("`, expected {}", expected.descr()).into()),
+            }
+            self.err(target, message);
+        }
+    }
+
+    /// Check the value types of SPIR-V `OpLoad`/`OpStore` against the pointee
+    /// type of their pointer input (other SPIR-V instructions aren't checked).
+    fn verify_spv_inst_types(
+        &mut self,
+        target: DiagTarget,
+        spv_inst: &spv::Inst,
+        input_types: &[Type],
+        output_type: Option<Type>,
+    ) {
+        let wk = &spec::Spec::get().well_known;
+
+        let (descr, ptr_type, value_type) = match (input_types, output_type) {
+            (&[ptr_type], Some(value_type)) if spv_inst.opcode == wk.OpLoad => {
+                ("`OpLoad` output", ptr_type, value_type)
+            }
+            (&[ptr_type, value_type], None) if spv_inst.opcode == wk.OpStore => {
+                ("`OpStore` value input", ptr_type, value_type)
+            }
+            _ => return,
+        };
+        let pointee_type = match &self.cx[ptr_type].kind {
+            TypeKind::SpvInst { spv_inst, type_and_const_inputs }
+                if spv_inst.opcode == wk.OpTypePointer =>
+            {
+                match type_and_const_inputs[..] {
+                    [TypeOrConst::Type(pointee_type)] => pointee_type,
+                    _ => return,
+                }
+            }
+            // NOTE(eddyb) other pointers (e.g. `qptr`) don't have a pointee type.
+            _ => return,
+        };
+        if value_type != pointee_type {
+            self.err(
+                target,
+                [
+                    format!("{descr} has type `").into(),
+                    value_type.into(),
+                    "`, but the pointer input points to `".into(),
+                    pointee_type.into(),
+                    "`".into(),
+                ],
+            );
+        }
+    }
+
+    fn verify_bool_type(&mut self, target: DiagTarget, descr: &str, ty: Type) {
+        if !matches!(self.cx[ty].kind, TypeKind::Scalar { kind: ScalarKind::Bool, .. }) {
+            self.err(
+                target,
+                [format!("{descr} has type `").into(), ty.into(), "`, expected `bool`".into()],
+            );
+        }
+    }
+
+    /// Get the type of `v`, but only if it's valid and in scope at this point.
+    fn value_type_if_in_scope(&self, v: Value) -> Option<Type> {
+        self.check_value_use(v).ok()
+    }
+
+    /// Check that `v` can be used at this point (i.e. it's valid, and its
+    /// definition dominates the current point), returning its type if so.
+    fn check_value_use(&self, v: Value) -> Result<Type, String> {
+        let (def, ty) = match v {
+            Value::Const(ct) => return Ok(self.cx[ct].ty),
+            Value::ControlRegionInput { region, input_idx } => {
+                let inputs = &self.func_at(region).def().inputs;
+                let input = inputs.get(input_idx as usize).ok_or_else(|| {
+                    format!("region input #{input_idx} (out of {} inputs)", inputs.len())
+                })?;
+                (ValueDef::RegionInputs(region), input.ty)
+            }
+            Value::ControlNodeOutput { control_node, output_idx } => {
+                let outputs = &self.func_at(control_node).def().outputs;
+                let output = outputs.get(output_idx as usize).ok_or_else(|| {
+                    format!("`ControlNode` output #{output_idx} (out of {} outputs)", outputs.len())
+                })?;
+                (ValueDef::ControlNodeOutputs(control_node), output.ty)
+            }
+            Value::DataInstOutput(inst) => {
+                let output_type = self.cx[self.func_at(inst).def().form]
+                    .output_type
+                    .ok_or("output of a `DataInst` without any output")?;
+                (ValueDef::DataInstOutput(inst), output_type)
+            }
+        };
+        if !self.in_scope.contains(&def) {
+            return Err(match v {
+                Value::Const(_) => unreachable!(),
+                Value::ControlRegionInput { input_idx, .. } => {
+                    format!("region input #{input_idx}, which is not in scope")
+                }
+                Value::ControlNodeOutput { output_idx, .. } => {
+                    format!("`ControlNode` output #{output_idx}, which is not in scope")
+                }
+                Value::DataInstOutput(_) => "`DataInst` output, which is not in scope".into(),
+            } + " (violates SSA dominance)");
+        }
+        Ok(ty)
+    }
+
+    /// Like [`FuncVerifier::check_value_use`], but reporting any violation
+    /// as an error (with `descr` describing the use of `v`).
+    fn verify_value_use(&mut self, target: DiagTarget, descr: &str, v: Value) -> Option<Type> {
+        match self.check_value_use(v) {
+            Ok(ty) => Some(ty),
+            Err(msg) => {
+                self.err(target, [format!("{descr} uses invalid value: {msg}").into()]);
+                None
+            }
+        }
+    }
+}
+
+/// The shape of a [`TypeKind::Scalar`], or of a [`TypeKind::Vector`] of them
+/// (with `elem_count == 1` indicating a scalar, as vectors have at least 2).
+#[derive(Copy, Clone)]
+struct ScalarOrVector {
+    kind: ScalarKind,
+    width: u32,
+    elem_count: u32,
+}
+
+impl ScalarOrVector {
+    fn of(cx: &Context, ty: Type) -> Option<Self> {
+        match cx[ty].kind {
+            TypeKind::Scalar { kind, width } => Some(Self { kind, width, elem_count: 1 }),
+            TypeKind::Vector { elem, elem_count } => match cx[elem].kind {
+                TypeKind::Scalar { kind, width } => Some(Self { kind, width, elem_count }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Expectation for the type of a [`PureOp`] input or output
+/// (see `FuncVerifier::verify_pure_op_types`).
+#[derive(Copy, Clone)]
+enum Expect {
+    /// Exactly this type.
+    Type(Type),
+
+    /// A scalar or vector of any of these `ScalarKind`s, with the given width
+    /// and element count (`1` meaning scalar), or any of them, if `None`.
+    Shape(&'static [ScalarKind], Option<u32>, Option<u32>),
+
+    /// The condition of a `Select` with this output element count, i.e. a
+    /// `bool` scalar, or a vector of `bool`s with the same element count.
+    BoolCondFor(u32),
+
+    /// A vector with this element type.
+    VectorOf(Type),
+}
+
+impl Expect {
+    fn matches(self, cx: &Context, ty: Type) -> bool {
+        let shape = ScalarOrVector::of(cx, ty);
+        match self {
+            Expect::Type(expected) => ty == expected,
+            Expect::Shape(kinds, width, elem_count) => shape.is_some_and(|shape| {
+                kinds.contains(&shape.kind)
+                    && width.unwrap_or(shape.width) == shape.width
+                    && elem_count.unwrap_or(shape.elem_count) == shape.elem_count
+            }),
+            Expect::BoolCondFor(elem_count) => shape.is_some_and(|shape| {
+                shape.kind == ScalarKind::Bool && [1, elem_count].contains(&shape.elem_count)
+            }),
+            Expect::VectorOf(elem_type) => {
+                matches!(cx[ty].kind, TypeKind::Vector { elem, .. } if elem == elem_type)
+            }
+        }
+    }
+
+    /// Describe this expectation in plain text (which `Expect::Type` can't be,
+    /// as it has to be printed as a `DiagMsgPart`, by the caller).
+    fn descr(self) -> String {
+        match self {
+            Expect::Type(_) => unreachable!(),
+            Expect::Shape(kinds, width, elem_count) => {
+                let kinds = kinds
+                    .iter()
+                    .map(|kind| match kind {
+                        ScalarKind::Bool => "`bool`",
+                        ScalarKind::SInt | ScalarKind::UInt => "integer",
+                        ScalarKind::Float => "float",
+                    })
+                    .dedup()
+                    .join("/");
+                let width = width.map(|width| format!(" (of width {width})")).unwrap_or_default();
+                match elem_count {
+                    None => format!("{kinds} scalar or vector{width}"),
+                    Some(1) => format!("{kinds} scalar{width}"),
+                    Some(n) => format!("vector of {n} {kinds} elements{width}"),
+                }
+            }
+            Expect::BoolCondFor(1) => "`bool` scalar".into(),
+            Expect::BoolCondFor(elem_count) => {
+                format!("`bool` scalar, or vector of {elem_count} `bool` elements")
+            }
+            Expect::VectorOf(_) => "vector with the output type as its element type".into(),
+        }
+    }
+}
+
+/// Collect all the nodes in an `EntityList` (described by its `first` and
+/// `last` nodes), by following the `next` links (and checking that the `prev`
+/// links agree), returning an error instead of panicking on invalid links.
+fn collect_list_nodes<E: Copy + Eq + Hash>(
+    first: Option<E>,
+    last: Option<E>,
+    prev_of: impl Fn(E) -> Option<E>,
+    next_of: impl Fn(E) -> Option<E>,
+) -> Result<Vec<E>, &'static str> {
+    if first.is_some() != last.is_some() {
+        return Err("only one of `first` and `last` present");
+    }
+    if first.and_then(&prev_of).is_some() {
+        return Err("`first->prev != None`");
+    }
+
+    let mut nodes = vec![];
+    let mut seen = FxHashSet::default();
+    let mut current = first;
+    while let Some(node) = current {
+        if !seen.insert(node) {
+            return Err("cycle in `next` links");
+        }
+        nodes.push(node);
+
+        if Some(node) == last {
+            return if next_of(node).is_some() { Err("`last->next != None`") } else { Ok(nodes) };
+        }
+
+        current = next_of(node);
+        if let Some(next) = current {
+            if prev_of(next) != Some(node) {
+                return Err("`node->next->prev != node`");
+            }
+        }
+    }
+    if first.is_some() {
+        return Err("`last` not reachable from `first`");
+    }
+    Ok(nodes)
+}
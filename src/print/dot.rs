@@ -0,0 +1,422 @@
+//! Graphviz DOT output, for visualizing the control-flow of functions.
+//!
+//! Unlike the rest of [`print`](crate::print), this isn't meant as a complete
+//! (or round-trippable) textual form of the IR, but rather as a debugging aid,
+//! especially for transformations like [`cfg::Structurizer`], and so it reuses
+//! the names from a [`Plan`] (e.g. `L1` for [`ControlRegion`]s, `v2` for values),
+//! such that the graph can be easily cross-referenced with the text output.
+
+// FIXME(eddyb) stop using `itertools` for methods like `intersperse` when they
+// get stabilized on `Iterator` instead.
+#![allow(unstable_name_collisions)]
+use itertools::Itertools as _;
+
+use crate::print::{pretty, Node, NodeDef, Options, Plan, Print, Printer, Use};
+use crate::{
+    cfg, spv, ControlNode, ControlNodeKind, ControlRegion, DeclDef, Func, FuncDecl, FuncDefBody,
+    SelectionKind, Value,
+};
+use std::fmt::Write as _;
+
+/// Options for [`func_to_dot`].
+#[derive(Copy, Clone, Default)]
+pub struct DotOptions {
+    /// Whether to expand structured control-flow (i.e. `Select`/`Loop` nodes,
+    /// and the [`ControlRegion`]s nested in them) into (nested) clusters, with
+    /// one graph node per `Block` (listing its instructions), instead of
+    /// summarizing it as text, inside the graph node of its parent region.
+    pub structured_clusters: bool,
+
+    /// Options for printing everything shown inside graph nodes (and edges),
+    /// with the same effects as for the text output (see [`Options`]).
+    pub print_options: Options,
+}
+
+/// Render the control-flow of `func` as a Graphviz DOT `digraph`.
+///
+/// `func` must be part of `plan` (and only its definition from the last version
+/// will be used, for multi-version [`Plan`]s), as all the names (of the function,
+/// its regions and values, and e.g. types) are the same ones that would be used
+/// by `plan.pretty_print()`.
+///
+/// Each [`ControlRegion`] in the function's unstructured CFG (if any) becomes a
+/// graph node (or a cluster, with [`DotOptions::structured_clusters`]), with
+/// graph edges following its [`cfg::ControlInst`] (i.e. one per target).
+pub fn func_to_dot(plan: &Plan<'_>, func: Func, options: DotOptions) -> String {
+    let printer = Printer::new(plan, &options.print_options);
+
+    let mut w = DotWriter { printer: &printer, options, out: String::new(), depth: 1, next_id: 0 };
+
+    let func_name = w.text(Use::Node(Node::Func(func)).print(&printer));
+    writeln!(w.out, "digraph \"{}\" {{", escape(&func_name)).unwrap();
+    w.line(format_args!("label=\"{}\";", escape(&func_name)));
+    w.line(format_args!("node [shape=box, fontname=\"monospace\"];"));
+    w.line(format_args!("edge [fontname=\"monospace\"];"));
+
+    let func_def_body = plan.versions.last().and_then(|version| {
+        match version.node_defs.get(&Node::Func(func))? {
+            NodeDef::Func(FuncDecl { def: DeclDef::Present(func_def_body), .. }) => {
+                Some(func_def_body)
+            }
+            _ => None,
+        }
+    });
+    if let Some(func_def_body) = func_def_body {
+        w.write_func_def_body(func_def_body);
+    }
+
+    w.out += "}\n";
+    w.out
+}
+
+/// Escape `s` for use in a quoted DOT string, using `\l` for line breaks (i.e.
+/// making all lines left-justified, which works best for code), if it has any.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let multi_line = s.contains('\n');
+    for line in s.lines() {
+        for c in line.chars() {
+            match c {
+                '"' | '\\' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                _ => escaped.push(c),
+            }
+        }
+        if multi_line {
+            escaped += "\\l";
+        }
+    }
+    escaped
+}
+
+struct DotWriter<'a, 'b> {
+    printer: &'b Printer<'a>,
+    options: DotOptions,
+
+    out: String,
+
+    /// Indentation depth (only used to make the output more readable).
+    depth: usize,
+
+    /// Source of unique IDs for both graph nodes and clusters.
+    next_id: usize,
+}
+
+/// The graph nodes control-flow enters through, and exits from, respectively,
+/// for some part of the function (which may be as small as a single graph node).
+struct EntryExit {
+    entry: String,
+    exit: String,
+}
+
+impl DotWriter<'_, '_> {
+    /// Lay out `fragment` as plain text.
+    fn text(&self, fragment: pretty::Fragment) -> String {
+        self.options.print_options.layout(fragment).to_string()
+    }
+
+    fn line(&mut self, line: std::fmt::Arguments<'_>) {
+        for _ in 0..self.depth {
+            self.out += "    ";
+        }
+        writeln!(self.out, "{line}").unwrap();
+    }
+
+    fn fresh_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Define a new graph node, showing `label` (or just a point, if `None`).
+    fn node(&mut self, label: Option<String>) -> String {
+        let node = format!("n{}", self.fresh_id());
+        match label.filter(|label| !label.trim().is_empty()) {
+            Some(label) => self.line(format_args!("{node} [label=\"{}\"];", escape(&label))),
+            None => self.line(format_args!("{node} [shape=point];")),
+        }
+        node
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: &str) {
+        if label.is_empty() {
+            self.line(format_args!("{from} -> {to};"));
+        } else {
+            self.line(format_args!("{from} -> {to} [label=\"{}\"];", escape(label)));
+        }
+    }
+
+    fn begin_cluster(&mut self, style: &str) {
+        let id = self.fresh_id();
+        self.line(format_args!("subgraph cluster_{id} {{"));
+        self.depth += 1;
+        self.line(format_args!("style={style};"));
+    }
+
+    fn end_cluster(&mut self) {
+        self.depth -= 1;
+        self.line(format_args!("}}"));
+    }
+
+    /// Per-case edge labels, for both structured and unstructured selections.
+    fn case_labels(kind: &SelectionKind, case_count: usize) -> Vec<String> {
+        match kind {
+            SelectionKind::BoolCond => vec!["true".into(), "false".into()],
+            SelectionKind::SpvInst(_) => (0..case_count).map(|i| format!("case {i}")).collect(),
+        }
+    }
+
+    fn write_func_def_body(&mut self, func_def_body: &FuncDefBody) {
+        let printer = self.printer;
+
+        let Some(cfg) = &func_def_body.unstructured_cfg else {
+            self.write_region(func_def_body, func_def_body.body, None, None);
+            return;
+        };
+
+        // NOTE(eddyb) all regions are written before any of the CFG edges, as
+        // graph nodes are placed in the first cluster they're mentioned in.
+        let mut region_entry_exits = vec![];
+        for region in cfg.rev_post_order(func_def_body) {
+            // NOTE(eddyb) this matches the label header used by the text output.
+            let label = Use::ControlRegionLabel(region);
+            let header = printer.use_styles.contains_key(&label).then(|| {
+                let inputs = &func_def_body.at(region).def().inputs;
+                let label_inputs = if !inputs.is_empty() {
+                    pretty::join_comma_sep(
+                        "(",
+                        inputs.iter().enumerate().map(|(input_idx, input)| {
+                            input.print(printer).insert_name_before_def(
+                                Value::ControlRegionInput {
+                                    region,
+                                    input_idx: input_idx.try_into().unwrap(),
+                                }
+                                .print_as_def(printer),
+                            )
+                        }),
+                        ")",
+                    )
+                } else {
+                    pretty::Fragment::default()
+                };
+                self.text(pretty::Fragment::new([
+                    label.print_as_def(printer),
+                    label_inputs,
+                    ":".into(),
+                ]))
+            });
+
+            let control_inst = &cfg.control_inst_on_exit_from[region];
+            let entry_exit = self.write_region(func_def_body, region, header, Some(control_inst));
+            region_entry_exits.push((region, entry_exit));
+        }
+
+        for (region, EntryExit { exit, .. }) in &region_entry_exits {
+            let cfg::ControlInst { kind, targets, target_inputs, .. } =
+                &cfg.control_inst_on_exit_from[*region];
+
+            let case_labels = match kind {
+                cfg::ControlInstKind::SelectBranch(kind) => Self::case_labels(kind, targets.len()),
+                _ => vec![],
+            };
+            for (i, target) in targets.iter().enumerate() {
+                let Some((_, target_entry_exit)) =
+                    region_entry_exits.iter().find(|(r, _)| r == target)
+                else {
+                    continue;
+                };
+                let inputs = target_inputs.get(target).map(|inputs| {
+                    self.text(pretty::join_comma_sep(
+                        "(",
+                        inputs.iter().map(|v| v.print(printer)),
+                        ")",
+                    ))
+                });
+                let label = case_labels.get(i).into_iter().cloned().chain(inputs).join(" ");
+                self.edge(exit, &target_entry_exit.entry, &label);
+            }
+        }
+    }
+
+    /// Write `region` either as a single graph node summarizing all of it, or
+    /// (with [`DotOptions::structured_clusters`]) as a cluster, which contains
+    /// one graph node per `Block`, and nested clusters for `Select`s/`Loop`s.
+    fn write_region(
+        &mut self,
+        func_def_body: &FuncDefBody,
+        region: ControlRegion,
+        header: Option<String>,
+        cfg_exit: Option<&cfg::ControlInst>,
+    ) -> EntryExit {
+        let printer = self.printer;
+        let func_at_region = func_def_body.at(region);
+
+        let footer = cfg_exit.map(|control_inst| self.text(control_inst.print(printer)));
+
+        if !self.options.structured_clusters {
+            let label = [header, Some(self.text(func_at_region.print(printer))), footer]
+                .into_iter()
+                .flatten()
+                .filter(|part| !part.trim().is_empty())
+                .join("\n");
+            let node = self.node(Some(label));
+            return EntryExit { entry: node.clone(), exit: node };
+        }
+
+        // FIXME(eddyb) consider showing the `inputs` of non-CFG regions, too
+        // (though they're already shown by e.g. the parent `Loop`'s header).
+        self.begin_cluster("solid");
+
+        let entry = self.node(header);
+        let mut prev = entry.clone();
+        for control_node in func_at_region.at_children() {
+            let EntryExit { entry: child_entry, exit: child_exit } =
+                self.write_control_node(func_def_body, control_node.position);
+            self.edge(&prev, &child_entry, "");
+            prev = child_exit;
+        }
+
+        let outputs = &func_at_region.def().outputs;
+        let outputs = (!outputs.is_empty()).then(|| {
+            self.text(pretty::join_comma_sep("(", outputs.iter().map(|v| v.print(printer)), ")"))
+        });
+        let exit = self.node(
+            Some([outputs, footer].into_iter().flatten().join("\n")).filter(|s| !s.is_empty()),
+        );
+        self.edge(&prev, &exit, "");
+
+        self.end_cluster();
+
+        EntryExit { entry, exit }
+    }
+
+    fn write_control_node(
+        &mut self,
+        func_def_body: &FuncDefBody,
+        control_node: ControlNode,
+    ) -> EntryExit {
+        let printer = self.printer;
+        let func_at_control_node = func_def_body.at(control_node);
+        let kw_style = printer.imperative_keyword_style();
+
+        match &func_at_control_node.def().kind {
+            ControlNodeKind::Block { insts } => {
+                let label = func_at_control_node
+                    .at(*insts)
+                    .into_iter()
+                    .map(|func_at_inst| self.text(func_at_inst.print(printer)))
+                    .join("\n");
+                let node = self.node(Some(label));
+                EntryExit { entry: node.clone(), exit: node }
+            }
+
+            ControlNodeKind::Select { kind, scrutinee, cases } => {
+                // NOTE(eddyb) unlike the text output, the cases aren't nested
+                // in the header, and are instead distinguished by edge labels.
+                let header = self.text(match kind {
+                    SelectionKind::BoolCond => pretty::Fragment::new([
+                        kw_style.apply("if").into(),
+                        " ".into(),
+                        scrutinee.print(printer),
+                    ]),
+                    SelectionKind::SpvInst(spv::Inst { opcode, imms }) => printer.pretty_spv_inst(
+                        kw_style,
+                        *opcode,
+                        imms,
+                        [Some(scrutinee.print(printer))]
+                            .into_iter()
+                            .chain((0..cases.len()).map(|_| None)),
+                    ),
+                });
+
+                self.begin_cluster("dashed");
+
+                let entry = self.node(Some(header));
+                let case_exits: Vec<_> = cases
+                    .iter()
+                    .zip(Self::case_labels(kind, cases.len()))
+                    .map(|(&case, case_label)| {
+                        let case_entry_exit = self.write_region(func_def_body, case, None, None);
+                        self.edge(&entry, &case_entry_exit.entry, &case_label);
+                        case_entry_exit.exit
+                    })
+                    .collect();
+
+                // NOTE(eddyb) the `Select` outputs are only defined on merge.
+                let outputs = &func_at_control_node.def().outputs;
+                let outputs = (!outputs.is_empty()).then(|| {
+                    self.text(pretty::join_comma_sep(
+                        "(",
+                        outputs.iter().enumerate().map(|(output_idx, output)| {
+                            output.print(printer).insert_name_before_def(
+                                Value::ControlNodeOutput {
+                                    control_node,
+                                    output_idx: output_idx.try_into().unwrap(),
+                                }
+                                .print_as_def(printer),
+                            )
+                        }),
+                        ")",
+                    ))
+                });
+                let exit = self.node(outputs);
+                for case_exit in case_exits {
+                    self.edge(&case_exit, &exit, "");
+                }
+
+                self.end_cluster();
+
+                EntryExit { entry, exit }
+            }
+
+            ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                let inputs = &func_def_body.at(*body).def().inputs;
+                let header = self.text(pretty::Fragment::new([
+                    kw_style.apply("loop").into(),
+                    if !inputs.is_empty() {
+                        pretty::join_comma_sep(
+                            "(",
+                            inputs.iter().zip(initial_inputs).enumerate().map(
+                                |(input_idx, (input, initial))| {
+                                    pretty::Fragment::new([
+                                        input.print(printer).insert_name_before_def(
+                                            Value::ControlRegionInput {
+                                                region: *body,
+                                                input_idx: input_idx.try_into().unwrap(),
+                                            }
+                                            .print_as_def(printer),
+                                        ),
+                                        " <- ".into(),
+                                        initial.print(printer),
+                                    ])
+                                },
+                            ),
+                            ")",
+                        )
+                    } else {
+                        pretty::Fragment::default()
+                    },
+                ]));
+
+                self.begin_cluster("dashed");
+
+                let entry = self.node(Some(header));
+                let body_entry_exit = self.write_region(func_def_body, *body, None, None);
+                let exit = self.node(Some(self.text(pretty::Fragment::new([
+                    kw_style.apply("while").into(),
+                    " ".into(),
+                    repeat_condition.print(printer),
+                ]))));
+                self.edge(&entry, &body_entry_exit.entry, "");
+                self.edge(&body_entry_exit.exit, &exit, "");
+                self.edge(&exit, &body_entry_exit.entry, "true");
+
+                self.end_cluster();
+
+                EntryExit { entry, exit }
+            }
+        }
+    }
+}
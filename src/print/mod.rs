@@ -6,13 +6,19 @@
 //! which will track the entire (transitive) set of (interned/entity) dependencies
 //! required to produce complete pretty-printing outputs.
 //!
-//! On a [`Plan`], use [`.pretty_print()`](Plan::pretty_print) to print everything,
+//! On a [`Plan`], use [`.pretty_print()`](Plan::pretty_print) to print everything
+//! (or [`.pretty_print_with_options(...)`](Plan::pretty_print_with_options), to
+//! customize e.g. line width or which attributes are shown, through [`Options`]),
 //! and get a "pretty document", with layout (inline-vs-multi-line decisions,
 //! auto-indentation, etc.) already performed, and which supports outputting:
 //! * plain text: `fmt::Display` (`{}` formatting) or `.to_string()`
 //! * HTML (styled and hyperlinked): [`.render_to_html()`](Versions::render_to_html)
 #![allow(rustdoc::private_intra_doc_links)]
 //!   (returning a [`pretty::HtmlSnippet`])
+//! * ANSI-styled text (for terminals): [`.render_to_ansi()`](Versions::render_to_ansi)
+//!
+//! Separately, [`dot::func_to_dot`] can render the control-flow of a function
+//! (using the same names as the above outputs) as a Graphviz DOT graph.
 
 // FIXME(eddyb) stop using `itertools` for methods like `intersperse` when they
 // get stabilized on `Iterator` instead.
@@ -29,8 +35,8 @@ use crate::{
     ControlRegionDef, ControlRegionInputDecl, DataInst, DataInstDef, DataInstForm, DataInstFormDef,
     DataInstKind, DeclDef, Diag, DiagLevel, DiagMsgPart, EntityListIter, ExportKey, Exportee, Func,
     FuncDecl, FuncParam, FxIndexMap, FxIndexSet, GlobalVar, GlobalVarDecl, GlobalVarDefBody,
-    Import, Module, ModuleDebugInfo, ModuleDialect, OrdAssertEq, SelectionKind, Type, TypeDef,
-    TypeKind, TypeOrConst, Value,
+    Import, Module, ModuleDebugInfo, ModuleDialect, OrdAssertEq, PureOp, ScalarKind, SelectionKind,
+    Type, TypeDef, TypeKind, TypeOrConst, Value,
 };
 use arrayvec::ArrayVec;
 use itertools::Either;
@@ -42,6 +48,7 @@ use std::fmt::{self, Write as _};
 use std::hash::Hash;
 use std::mem;
 
+pub mod dot;
 mod multiversion;
 mod pretty;
 
@@ -603,20 +610,87 @@ impl<'a> Visitor<'a> for Plan<'a> {
     }
 }
 
-// FIXME(eddyb) make max line width configurable.
-const MAX_LINE_WIDTH: usize = 120;
+/// Options controlling both the layout and the contents of pretty-printing
+/// outputs (e.g. [`Plan::pretty_print_with_options`]).
+///
+/// The [`Default`] options are those used by [`Plan::pretty_print`], and favor
+/// verbosity (i.e. everything that could be useful is shown), so options that
+/// produce more compact outputs (e.g. for CI logs) are opt-in.
+#[derive(Copy, Clone)]
+pub struct Options {
+    /// Maximum number of columns per line, which layout will try to stay within
+    /// (by choosing multi-line layouts over inline ones), where possible.
+    ///
+    /// Default: `120`.
+    pub max_line_width: usize,
+
+    /// Number of spaces used for each level of indentation.
+    ///
+    /// Default: `2`.
+    pub indent_width: usize,
+
+    /// Whether interned definitions (i.e. [`Type`]s and [`Const`]s) with only
+    /// one use should be printed inline at their use site, instead of being
+    /// given a name (e.g. `T123`) and printed separately (like all others).
+    ///
+    /// Default: `true`.
+    pub inline_single_use_interned: bool,
+
+    /// Whether to print SPIR-V debuginfo attributes, i.e. `Attr::SpvDebugLine`
+    /// (as `// at file:line:col` comments) and `OpName` (as `#[name = "..."]`).
+    ///
+    /// Note that `OpName`s can still be used for naming definitions, even when
+    /// not shown as attributes (where they would've been hidden anyway).
+    ///
+    /// Default: `true`.
+    pub show_spv_debug_info_attrs: bool,
+
+    /// Whether to print `Attr::Diagnostics` (i.e. errors/warnings attached to
+    /// definitions, e.g. by passes), as comments before the definition.
+    ///
+    /// Default: `true`.
+    pub show_diags: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_line_width: 120,
+            indent_width: 2,
+            inline_single_use_interned: true,
+            show_spv_debug_info_attrs: true,
+            show_diags: true,
+        }
+    }
+}
+
+impl Options {
+    fn layout(&self, fragment: pretty::Fragment) -> pretty::FragmentPostLayout {
+        fragment.layout(self.max_line_width, self.indent_width)
+    }
+}
 
 impl Plan<'_> {
-    #[allow(rustdoc::private_intra_doc_links)]
     /// Print the whole [`Plan`] to a [`Versions<pretty::Fragment>`] and perform
     /// layout on its [`pretty::Fragment`]s.
     ///
     /// The resulting [`Versions<pretty::FragmentPostLayout>`] value supports
     /// [`fmt::Display`] for convenience, but also more specific methods
     /// (e.g. HTML output).
+    ///
+    /// Shorthand for `plan.pretty_print_with_options(&Options::default())`.
     pub fn pretty_print(&self) -> Versions<pretty::FragmentPostLayout> {
-        self.print(&Printer::new(self))
-            .map_pretty_fragments(|fragment| fragment.layout_with_max_line_width(MAX_LINE_WIDTH))
+        self.pretty_print_with_options(&Options::default())
+    }
+
+    /// Like [`Plan::pretty_print`], but using `options` to customize the output
+    /// (instead of the default [`Options`]).
+    pub fn pretty_print_with_options(
+        &self,
+        options: &Options,
+    ) -> Versions<pretty::FragmentPostLayout> {
+        self.print(&Printer::new(self, options))
+            .map_pretty_fragments(|fragment| options.layout(fragment))
     }
 
     /// Like `pretty_print`, but separately pretty-printing "root dependencies"
@@ -624,20 +698,28 @@ impl Plan<'_> {
     pub fn pretty_print_deps_and_root_separately(
         &self,
     ) -> (Versions<pretty::FragmentPostLayout>, Versions<pretty::FragmentPostLayout>) {
-        let printer = Printer::new(self);
+        self.pretty_print_deps_and_root_separately_with_options(&Options::default())
+    }
+
+    /// Like `pretty_print_deps_and_root_separately`, but using `options` to
+    /// customize the output (instead of the default [`Options`]).
+    pub fn pretty_print_deps_and_root_separately_with_options(
+        &self,
+        options: &Options,
+    ) -> (Versions<pretty::FragmentPostLayout>, Versions<pretty::FragmentPostLayout>) {
+        let printer = Printer::new(self, options);
         (
-            self.print_all_nodes_and_or_root(&printer, true, false).map_pretty_fragments(
-                |fragment| fragment.layout_with_max_line_width(MAX_LINE_WIDTH),
-            ),
-            self.print_all_nodes_and_or_root(&printer, false, true).map_pretty_fragments(
-                |fragment| fragment.layout_with_max_line_width(MAX_LINE_WIDTH),
-            ),
+            self.print_all_nodes_and_or_root(&printer, true, false)
+                .map_pretty_fragments(|fragment| options.layout(fragment)),
+            self.print_all_nodes_and_or_root(&printer, false, true)
+                .map_pretty_fragments(|fragment| options.layout(fragment)),
         )
     }
 }
 
 pub struct Printer<'a> {
     cx: &'a Context,
+    options: Options,
     use_styles: FxIndexMap<Use, UseStyle>,
 
     /// Subset of the `Plan`'s original `attrs_to_unique_spv_name` map, only
@@ -671,7 +753,7 @@ enum UseStyle {
 }
 
 impl<'a> Printer<'a> {
-    fn new(plan: &Plan<'a>) -> Self {
+    fn new(plan: &Plan<'a>, options: &Options) -> Self {
         let cx = plan.cx;
         let wk = &spv::spec::Spec::get().well_known;
 
@@ -808,7 +890,7 @@ impl<'a> Printer<'a> {
 
                 let inline = match use_kind {
                     Use::CxInterned(interned) => {
-                        use_count == 1
+                        (options.inline_single_use_interned && use_count == 1)
                             || match interned {
                                 CxInterned::Type(ty) => {
                                     let ty_def = &cx[ty];
@@ -817,19 +899,14 @@ impl<'a> Printer<'a> {
                                     // here and `TypeDef`'s `Print` impl.
                                     let has_compact_print_or_is_leaf = match &ty_def.kind {
                                         TypeKind::SpvInst { spv_inst, type_and_const_inputs } => {
-                                            [
-                                                wk.OpTypeBool,
-                                                wk.OpTypeInt,
-                                                wk.OpTypeFloat,
-                                                wk.OpTypeVector,
-                                            ]
-                                            .contains(&spv_inst.opcode)
+                                            spv_inst.opcode == wk.OpTypeVector
                                                 || type_and_const_inputs.is_empty()
                                         }
 
-                                        TypeKind::QPtr | TypeKind::SpvStringLiteralForExtInst => {
-                                            true
-                                        }
+                                        TypeKind::QPtr
+                                        | TypeKind::Scalar { .. }
+                                        | TypeKind::Vector { .. }
+                                        | TypeKind::SpvStringLiteralForExtInst => true,
                                     };
 
                                     ty_def.attrs == AttrSet::default()
@@ -842,18 +919,11 @@ impl<'a> Printer<'a> {
                                     // here and `ConstDef`'s `Print` impl.
                                     let (has_compact_print, has_nested_consts) = match &ct_def.kind
                                     {
+                                        ConstKind::Scalar(_) => (true, false),
                                         ConstKind::SpvInst { spv_inst_and_const_inputs } => {
-                                            let (spv_inst, const_inputs) =
+                                            let (_spv_inst, const_inputs) =
                                                 &**spv_inst_and_const_inputs;
-                                            (
-                                                [
-                                                    wk.OpConstantFalse,
-                                                    wk.OpConstantTrue,
-                                                    wk.OpConstant,
-                                                ]
-                                                .contains(&spv_inst.opcode),
-                                                !const_inputs.is_empty(),
-                                            )
+                                            (false, !const_inputs.is_empty())
                                         }
                                         _ => (false, false),
                                     };
@@ -1065,7 +1135,7 @@ impl<'a> Printer<'a> {
             }
         }
 
-        Self { cx, use_styles, attrs_with_spv_name_in_use }
+        Self { cx, options: *options, use_styles, attrs_with_spv_name_in_use }
     }
 
     pub fn cx(&self) -> &'a Context {
@@ -2107,11 +2177,19 @@ impl Print for AttrSet {
         // name of the definition (but keep it in all other cases).
         let spv_name_to_hide = printer.attrs_with_spv_name_in_use.get(self).copied();
 
+        let wk = &spv::spec::Spec::get().well_known;
+        let options = &printer.options;
+
         pretty::Fragment::new(
             attrs
                 .iter()
                 .filter(|attr| match attr {
-                    Attr::SpvAnnotation(spv_inst) => Some(spv_inst) != spv_name_to_hide,
+                    Attr::SpvAnnotation(spv_inst) => {
+                        Some(spv_inst) != spv_name_to_hide
+                            && (options.show_spv_debug_info_attrs || spv_inst.opcode != wk.OpName)
+                    }
+                    Attr::SpvDebugLine { .. } => options.show_spv_debug_info_attrs,
+                    Attr::Diagnostics(_) => options.show_diags,
                     _ => true,
                 })
                 .map(|attr| attr.print(printer))
@@ -2371,6 +2449,16 @@ impl Print for QPtrMemUsage {
     }
 }
 
+/// Name for a [`TypeKind::Scalar`] type, also used as a literal suffix.
+fn scalar_type_name(kind: ScalarKind, width: u32) -> String {
+    match kind {
+        ScalarKind::Bool => "bool".into(),
+        ScalarKind::SInt => format!("s{width}"),
+        ScalarKind::UInt => format!("u{width}"),
+        ScalarKind::Float => format!("f{width}"),
+    }
+}
+
 impl Print for TypeDef {
     type Output = AttrsAndDef;
     fn print(&self, printer: &Printer<'_>) -> AttrsAndDef {
@@ -2378,50 +2466,28 @@ impl Print for TypeDef {
 
         let wk = &spv::spec::Spec::get().well_known;
 
-        // FIXME(eddyb) should this be done by lowering SPIR-V types to SPIR-T?
-        let kw = |kw| printer.declarative_keyword_style().apply(kw).into();
-        let compact_def = if let &TypeKind::SpvInst {
-            spv_inst: spv::Inst { opcode, ref imms },
-            ref type_and_const_inputs,
-        } = kind
-        {
-            if opcode == wk.OpTypeBool {
-                Some(kw("bool".into()))
-            } else if opcode == wk.OpTypeInt {
-                let (width, signed) = match imms[..] {
-                    [spv::Imm::Short(_, width), spv::Imm::Short(_, signedness)] => {
-                        (width, signedness != 0)
-                    }
-                    _ => unreachable!(),
-                };
-
-                Some(if signed { kw(format!("s{width}")) } else { kw(format!("u{width}")) })
-            } else if opcode == wk.OpTypeFloat {
-                let width = match imms[..] {
-                    [spv::Imm::Short(_, width)] => width,
-                    _ => unreachable!(),
-                };
-
-                Some(kw(format!("f{width}")))
-            } else if opcode == wk.OpTypeVector {
-                let (elem_ty, elem_count) = match (&imms[..], &type_and_const_inputs[..]) {
-                    (&[spv::Imm::Short(_, elem_count)], &[TypeOrConst::Type(elem_ty)]) => {
-                        (elem_ty, elem_count)
-                    }
-                    _ => unreachable!(),
-                };
-
-                Some(pretty::Fragment::new([
-                    elem_ty.print(printer),
-                    "×".into(),
-                    printer.numeric_literal_style().apply(format!("{elem_count}")).into(),
-                ]))
-            } else {
-                None
-            }
-        } else {
-            None
+        // NOTE(eddyb) non-native SPIR-V vectors (i.e. of non-scalar elements)
+        // also use the compact printing style of native vectors.
+        let vector_elem_ty_and_count = match *kind {
+            TypeKind::Vector { elem, elem_count } => Some((elem, elem_count)),
+            TypeKind::SpvInst {
+                spv_inst: spv::Inst { opcode, ref imms },
+                ref type_and_const_inputs,
+            } if opcode == wk.OpTypeVector => match (&imms[..], &type_and_const_inputs[..]) {
+                (&[spv::Imm::Short(_, elem_count)], &[TypeOrConst::Type(elem_ty)]) => {
+                    Some((elem_ty, elem_count))
+                }
+                _ => unreachable!(),
+            },
+            _ => None,
         };
+        let compact_def = vector_elem_ty_and_count.map(|(elem_ty, elem_count)| {
+            pretty::Fragment::new([
+                elem_ty.print(printer),
+                "×".into(),
+                printer.numeric_literal_style().apply(format!("{elem_count}")).into(),
+            ])
+        });
 
         AttrsAndDef {
             attrs: attrs.print(printer),
@@ -2432,6 +2498,14 @@ impl Print for TypeDef {
                     // FIXME(eddyb) should this be shortened to `qtr`?
                     TypeKind::QPtr => printer.declarative_keyword_style().apply("qptr").into(),
 
+                    &TypeKind::Scalar { kind, width } => printer
+                        .declarative_keyword_style()
+                        .apply(scalar_type_name(kind, width))
+                        .into(),
+
+                    // Handled above (as `compact_def`).
+                    TypeKind::Vector { .. } => unreachable!(),
+
                     TypeKind::SpvInst { spv_inst, type_and_const_inputs } => printer
                         .pretty_spv_inst(
                             printer.spv_op_style(),
@@ -2471,108 +2545,57 @@ impl Print for ConstDef {
             }
             .apply(ty)
         };
-        let compact_def = if let ConstKind::SpvInst { spv_inst_and_const_inputs } = kind {
-            let (spv_inst, _const_inputs) = &**spv_inst_and_const_inputs;
-            let &spv::Inst { opcode, ref imms } = spv_inst;
-
-            if opcode == wk.OpConstantFalse {
-                Some(kw("false"))
-            } else if opcode == wk.OpConstantTrue {
-                Some(kw("true"))
-            } else if opcode == wk.OpConstant {
-                // HACK(eddyb) it's simpler to only handle a limited subset of
-                // integer/float bit-widths, for now.
-                let raw_bits = match imms[..] {
-                    [spv::Imm::Short(_, x)] => Some(u64::from(x)),
-                    [spv::Imm::LongStart(_, lo), spv::Imm::LongCont(_, hi)] => {
-                        Some(u64::from(lo) | (u64::from(hi) << 32))
+        let compact_def = if let (&ConstKind::Scalar(bits), &TypeKind::Scalar { kind, width }) =
+            (kind, &printer.cx[*ty].kind)
+        {
+            match kind {
+                ScalarKind::Bool => Some(kw(if bits != 0 { "true" } else { "false" })),
+                ScalarKind::SInt | ScalarKind::UInt => {
+                    let printed_value = if kind == ScalarKind::SInt {
+                        let sext_bits = (bits as i64) << (64 - width) >> (64 - width);
+                        format!("{sext_bits}")
+                    } else {
+                        format!("{bits}")
+                    };
+                    Some(pretty::Fragment::new([
+                        printer.numeric_literal_style().apply(printed_value),
+                        literal_ty_suffix(scalar_type_name(kind, width)),
+                    ]))
+                }
+                ScalarKind::Float => {
+                    /// Check that parsing the result of printing produces
+                    /// the original bits of the floating-point value, and
+                    /// only return `Some` if that is the case.
+                    fn bitwise_roundtrip_float_print<
+                        BITS: Copy + PartialEq,
+                        FLOAT: std::fmt::Debug + std::str::FromStr,
+                    >(
+                        bits: BITS,
+                        float_from_bits: impl FnOnce(BITS) -> FLOAT,
+                        float_to_bits: impl FnOnce(FLOAT) -> BITS,
+                    ) -> Option<String> {
+                        let float = float_from_bits(bits);
+                        Some(format!("{float:?}")).filter(|s| {
+                            s.parse::<FLOAT>()
+                                .map(float_to_bits)
+                                .map_or(false, |roundtrip_bits| roundtrip_bits == bits)
+                        })
                     }
-                    _ => None,
-                };
 
-                if let (
-                    Some(raw_bits),
-                    &TypeKind::SpvInst {
-                        spv_inst: spv::Inst { opcode: ty_opcode, imms: ref ty_imms },
-                        ..
-                    },
-                ) = (raw_bits, &printer.cx[*ty].kind)
-                {
-                    if ty_opcode == wk.OpTypeInt {
-                        let (width, signed) = match ty_imms[..] {
-                            [spv::Imm::Short(_, width), spv::Imm::Short(_, signedness)] => {
-                                (width, signedness != 0)
-                            }
-                            _ => unreachable!(),
-                        };
-
-                        if width <= 64 {
-                            let (printed_value, ty) = if signed {
-                                let sext_raw_bits =
-                                    (raw_bits as u128 as i128) << (128 - width) >> (128 - width);
-                                (format!("{sext_raw_bits}"), format!("s{width}"))
-                            } else {
-                                (format!("{raw_bits}"), format!("u{width}"))
-                            };
-                            Some(pretty::Fragment::new([
-                                printer.numeric_literal_style().apply(printed_value),
-                                literal_ty_suffix(ty),
-                            ]))
-                        } else {
-                            None
-                        }
-                    } else if ty_opcode == wk.OpTypeFloat {
-                        let width = match ty_imms[..] {
-                            [spv::Imm::Short(_, width)] => width,
-                            _ => unreachable!(),
-                        };
-
-                        /// Check that parsing the result of printing produces
-                        /// the original bits of the floating-point value, and
-                        /// only return `Some` if that is the case.
-                        fn bitwise_roundtrip_float_print<
-                            BITS: Copy + PartialEq,
-                            FLOAT: std::fmt::Debug + std::str::FromStr,
-                        >(
-                            bits: BITS,
-                            float_from_bits: impl FnOnce(BITS) -> FLOAT,
-                            float_to_bits: impl FnOnce(FLOAT) -> BITS,
-                        ) -> Option<String> {
-                            let float = float_from_bits(bits);
-                            Some(format!("{float:?}")).filter(|s| {
-                                s.parse::<FLOAT>()
-                                    .map(float_to_bits)
-                                    .map_or(false, |roundtrip_bits| roundtrip_bits == bits)
-                            })
+                    let printed_value = match width {
+                        32 => {
+                            bitwise_roundtrip_float_print(bits as u32, f32::from_bits, f32::to_bits)
                         }
-
-                        let printed_value = match width {
-                            32 => bitwise_roundtrip_float_print(
-                                raw_bits as u32,
-                                f32::from_bits,
-                                f32::to_bits,
-                            ),
-                            64 => bitwise_roundtrip_float_print(
-                                raw_bits,
-                                f64::from_bits,
-                                f64::to_bits,
-                            ),
-                            _ => None,
-                        };
-                        printed_value.map(|s| {
-                            pretty::Fragment::new([
-                                printer.numeric_literal_style().apply(s),
-                                literal_ty_suffix(format!("f{width}")),
-                            ])
-                        })
-                    } else {
-                        None
-                    }
-                } else {
-                    None
+                        64 => bitwise_roundtrip_float_print(bits, f64::from_bits, f64::to_bits),
+                        _ => None,
+                    };
+                    printed_value.map(|s| {
+                        pretty::Fragment::new([
+                            printer.numeric_literal_style().apply(s),
+                            literal_ty_suffix(scalar_type_name(kind, width)),
+                        ])
+                    })
                 }
-            } else {
-                None
             }
         } else {
             None
@@ -2584,6 +2607,27 @@ impl Print for ConstDef {
                 &ConstKind::PtrToGlobalVar(gv) => {
                     pretty::Fragment::new(["&".into(), gv.print(printer)])
                 }
+                ConstKind::Undef => {
+                    pretty::Fragment::new([kw("undef"), printer.pretty_type_ascription_suffix(*ty)])
+                }
+                // HACK(eddyb) fall back to the SPIR-V form (e.g. for `f16`).
+                &ConstKind::Scalar(bits) => {
+                    let spv_inst = match printer.cx[*ty].kind {
+                        TypeKind::Scalar { kind, width } => {
+                            spv::lift::scalar_const_to_spv_inst(kind, width, bits)
+                        }
+                        _ => wk.OpConstant.into(),
+                    };
+                    pretty::Fragment::new([
+                        printer.pretty_spv_inst(
+                            printer.spv_op_style(),
+                            spv_inst.opcode,
+                            &spv_inst.imms,
+                            [None::<pretty::Fragment>; 0],
+                        ),
+                        printer.pretty_type_ascription_suffix(*ty),
+                    ])
+                }
                 ConstKind::SpvInst { spv_inst_and_const_inputs } => {
                     let (spv_inst, const_inputs) = &**spv_inst_and_const_inputs;
                     pretty::Fragment::new([
@@ -3232,6 +3276,23 @@ impl Print for FuncAt<'_, DataInst> {
                 ])
             }
 
+            &DataInstKind::Pure(op) => {
+                let idx_imm = match op {
+                    PureOp::CompositeExtract { idx } | PureOp::CompositeInsert { idx } => {
+                        Some(printer.numeric_literal_style().apply(format!("{idx}")).into())
+                    }
+                    _ => None,
+                };
+                pretty::Fragment::new([
+                    printer.declarative_keyword_style().apply(op.name()).into(),
+                    pretty::join_comma_sep(
+                        "(",
+                        inputs.iter().map(|v| v.print(printer)).chain(idx_imm),
+                        ")",
+                    ),
+                ])
+            }
+
             DataInstKind::SpvInst(inst) => printer.pretty_spv_inst(
                 printer.spv_op_style(),
                 inst.opcode,
@@ -3297,18 +3358,24 @@ impl Print for FuncAt<'_, DataInst> {
                             &ConstKind::SpvStringLiteralForExtInst(s) => {
                                 return Some(PseudoImm::Str(&printer.cx[s]));
                             }
-                            ConstKind::SpvInst { spv_inst_and_const_inputs } => {
-                                let (spv_inst, _const_inputs) = &**spv_inst_and_const_inputs;
-                                if spv_inst.opcode == wk.OpConstant {
-                                    if let [spv::Imm::Short(_, x)] = spv_inst.imms[..] {
-                                        // HACK(eddyb) only allow unambiguously positive values.
-                                        if i32::try_from(x).and_then(u32::try_from) == Ok(x) {
-                                            return Some(PseudoImm::U32(x));
-                                        }
+                            &ConstKind::Scalar(bits) => {
+                                let is_int = matches!(
+                                    printer.cx[printer.cx[ct].ty].kind,
+                                    TypeKind::Scalar {
+                                        kind: ScalarKind::SInt | ScalarKind::UInt,
+                                        ..
+                                    }
+                                );
+                                // HACK(eddyb) only allow unambiguously positive values.
+                                if let Ok(x) = i32::try_from(bits).and_then(u32::try_from) {
+                                    if is_int {
+                                        return Some(PseudoImm::U32(x));
                                     }
                                 }
                             }
-                            ConstKind::PtrToGlobalVar(_) => {}
+                            ConstKind::PtrToGlobalVar(_)
+                            | ConstKind::Undef
+                            | ConstKind::SpvInst { .. } => {}
                         }
                     }
                     None
@@ -6,6 +6,7 @@ use internal_iterator::{
     FromInternalIterator, InternalIterator, IntoInternalIterator, IteratorExt,
 };
 use itertools::{Either, Itertools};
+use rustc_hash::FxHashSet;
 use smallvec::SmallVec;
 use std::fmt::Write;
 use std::{fmt, iter, mem};
@@ -93,6 +94,16 @@ impl Versions<pretty::FragmentPostLayout> {
         match self {
             Self::Single(fragment) => fragment.render_to_html(),
             Self::Multiple { version_names, per_row_versions_with_repeat_count } => {
+                // HACK(eddyb) all fragments are laid out with the same options,
+                // so any one of them can be used to get e.g. `max_line_width`.
+                let any_fragment = per_row_versions_with_repeat_count
+                    .iter()
+                    .flatten()
+                    .map(|(fragment, _)| fragment)
+                    .next();
+                let max_line_width = any_fragment.map_or(0, |f| f.max_line_width());
+                let indent = any_fragment.map_or("", |f| f.indent());
+
                 // HACK(eddyb) using an UUID as a class name in lieu of "scoped <style>".
                 const TABLE_CLASS_NAME: &str = "spirt-table-90c2056d-5b38-4644-824a-b4be1c82f14d";
 
@@ -132,7 +143,7 @@ impl Versions<pretty::FragmentPostLayout> {
 </style>
         "
                     .replace("SCOPE", &format!("table.{TABLE_CLASS_NAME}"))
-                    .replace("MAX_LINE_WIDTH", &super::MAX_LINE_WIDTH.to_string()),
+                    .replace("MAX_LINE_WIDTH", &max_line_width.to_string()),
                 );
 
                 let headings = {
@@ -229,25 +240,9 @@ impl Versions<pretty::FragmentPostLayout> {
                                     // were removed (red "hashed" background?).
                                     let diff = |other: Option<_>| {
                                         // Ignore indendation-only changes.
-                                        fn strip_indents<'a, 'b>(
-                                            mut line: &'b [TextOp<'a>],
-                                        ) -> &'b [TextOp<'a>]
-                                        {
-                                            // HACK(eddyb) also ignore helper anchors,
-                                            // which can go before indents.
-                                            while let [TextOp::Text(pretty::INDENT), rest @ ..]
-                                            | [
-                                                TextOp::PushAnchor { .. },
-                                                TextOp::PopAnchor { .. },
-                                                rest @ ..,
-                                            ] = line
-                                            {
-                                                line = rest;
-                                            }
-                                            line
-                                        }
                                         other.map_or(false, |other| {
-                                            strip_indents(line) != strip_indents(other)
+                                            strip_indents(line, indent)
+                                                != strip_indents(other, indent)
                                         })
                                     };
                                     let line_style = if !diff(prev_line) && !diff(next_line) {
@@ -288,6 +283,133 @@ impl Versions<pretty::FragmentPostLayout> {
             }
         }
     }
+
+    /// Render to plain text interspersed with ANSI escape codes, for e.g.
+    /// terminal output (see also [`pretty::FragmentPostLayout::render_to_ansi`]).
+    ///
+    /// For multiple versions, the layout is the same as the [`fmt::Display`]
+    /// output (i.e. sequential, instead of tabular like the HTML output), but
+    /// lines unchanged (compared to the previous and next versions) are dimmed
+    /// and desaturated, just like in the HTML output (so that changes stand out).
+    //
+    // FIXME(eddyb) provide a non-allocating version.
+    pub fn render_to_ansi(&self) -> String {
+        match self {
+            Self::Single(fragment) => fragment.render_to_ansi(),
+            Self::Multiple { version_names, per_row_versions_with_repeat_count } => {
+                let heading_style =
+                    pretty::Styles { thickness: Some(2), italic: true, ..Default::default() };
+                let unchanged_line_style = pretty::Styles {
+                    desaturate_and_dim_for_unchanged_multiversion_line: true,
+                    ..Default::default()
+                };
+
+                let render_text_ops = |text_ops: &[TextOp<'_>]| {
+                    text_ops.iter().copied().into_internal().collect::<pretty::AnsiText>().0
+                };
+
+                let mut ansi = String::new();
+                for (row_idx, versions_with_repeat_count) in
+                    per_row_versions_with_repeat_count.iter().enumerate()
+                {
+                    if row_idx > 0 {
+                        ansi += "\n";
+                    }
+
+                    // No headings (or diffing) for anything uniform across versions.
+                    if let [(fragment, repeat_count)] = &versions_with_repeat_count[..] {
+                        if *repeat_count == version_names.len() {
+                            ansi += &fragment.render_to_ansi();
+                            ansi += "\n";
+                            continue;
+                        }
+                    }
+
+                    let columns: SmallVec<[(AAColumn<'_>, &str); 4]> = versions_with_repeat_count
+                        .iter()
+                        .map(|(fragment, _)| {
+                            (fragment.render_to_text_ops().collect(), fragment.indent())
+                        })
+                        .collect();
+
+                    // NOTE(eddyb) unlike the HTML output, there is no alignment
+                    // between versions, so (indentation-insensitive) line text
+                    // is compared instead, regardless of the line's position.
+                    let line_texts_per_column: SmallVec<[FxHashSet<String>; 4]> = columns
+                        .iter()
+                        .map(|(column, indent)| {
+                            column
+                                .lines(column.line_lengths.iter().copied())
+                                .map(|line| line_text(strip_indents(line, indent)))
+                                .collect()
+                        })
+                        .collect();
+
+                    let mut next_version_idx = 0;
+                    for (column_idx, ((column, indent), &(_, repeat_count))) in
+                        columns.iter().zip(versions_with_repeat_count).enumerate()
+                    {
+                        let heading = format!(
+                            "//#{} {}",
+                            if column_idx == 0 { "IF" } else { "ELSEIF" },
+                            version_names[next_version_idx..][..repeat_count]
+                                .iter()
+                                .map(|name| format!("`{name}`"))
+                                .join(" | ")
+                        );
+                        next_version_idx += repeat_count;
+
+                        ansi += &render_text_ops(&[
+                            TextOp::PushStyles(&heading_style),
+                            TextOp::Text(&heading),
+                            TextOp::PopStyles(&heading_style),
+                            TextOp::Text("\n"),
+                        ]);
+
+                        let prev_line_texts = column_idx
+                            .checked_sub(1)
+                            .and_then(|prev_idx| line_texts_per_column.get(prev_idx));
+                        let next_line_texts = line_texts_per_column.get(column_idx + 1);
+                        for line in column.lines(column.line_lengths.iter().copied()) {
+                            let text = line_text(strip_indents(line, indent));
+                            let unchanged = [prev_line_texts, next_line_texts]
+                                .into_iter()
+                                .flatten()
+                                .all(|other_line_texts| other_line_texts.contains(&text));
+                            let line_style = unchanged.then_some(&unchanged_line_style);
+
+                            let line_text_ops: SmallVec<[_; 16]> = line_style
+                                .map(TextOp::PushStyles)
+                                .into_iter()
+                                .chain(line.iter().copied())
+                                .chain(line_style.map(TextOp::PopStyles))
+                                .chain([TextOp::Text("\n")])
+                                .collect();
+                            ansi += &render_text_ops(&line_text_ops);
+                        }
+                    }
+                    ansi += &render_text_ops(&[
+                        TextOp::PushStyles(&heading_style),
+                        TextOp::Text("//#ENDIF"),
+                        TextOp::PopStyles(&heading_style),
+                        TextOp::Text("\n"),
+                    ]);
+                }
+
+                ansi
+            }
+        }
+    }
+}
+
+/// Concatenate all the text in `line` (ignoring styles and anchors).
+fn line_text(line: &[TextOp<'_>]) -> String {
+    line.iter()
+        .filter_map(|op| match op {
+            TextOp::Text(text) => Some(*text),
+            _ => None,
+        })
+        .collect()
 }
 
 impl<PF> Versions<PF> {
@@ -312,6 +434,18 @@ impl<PF> Versions<PF> {
     }
 }
 
+/// Strip the indentation (i.e. any number of `indent`s) from the start of `line`.
+fn strip_indents<'a, 'b>(mut line: &'b [TextOp<'a>], indent: &str) -> &'b [TextOp<'a>] {
+    // HACK(eddyb) also ignore helper anchors, which can go before indents.
+    loop {
+        match line {
+            [TextOp::Text(text), rest @ ..] if *text == indent => line = rest,
+            [TextOp::PushAnchor { .. }, TextOp::PopAnchor { .. }, rest @ ..] => line = rest,
+            _ => return line,
+        }
+    }
+}
+
 /// Tool for adjusting pretty-printed columns, so that their anchors line up
 /// (by adding empty lines to whichever side "is behind").
 #[derive(Default)]
@@ -1,9 +1,7 @@
 //! Pretty-printing functionality (such as automatic indentation).
 
 use indexmap::IndexSet;
-use internal_iterator::{
-    FromInternalIterator, InternalIterator, IntoInternalIterator, IteratorExt,
-};
+use internal_iterator::{FromInternalIterator, InternalIterator, IntoInternalIterator, IteratorExt};
 use smallvec::SmallVec;
 use std::borrow::Cow;
 use std::fmt::Write as _;
@@ -86,6 +84,8 @@ pub struct Styles {
     pub subscript: bool,
     pub superscript: bool,
 
+    pub italic: bool,
+
     // FIXME(eddyb) maybe a more general `filter` system would be better?
     pub desaturate_and_dim_for_unchanged_multiversion_line: bool,
 }
@@ -149,19 +149,41 @@ impl Fragment {
     }
 
     /// Perform layout on the [`Fragment`], limiting lines to `max_line_width`
-    /// columns where possible.
-    pub fn layout_with_max_line_width(mut self, max_line_width: usize) -> FragmentPostLayout {
+    /// columns where possible, and using `indent_width` spaces per indentation level.
+    pub fn layout(mut self, max_line_width: usize, indent_width: usize) -> FragmentPostLayout {
+        let indent = " ".repeat(indent_width);
+
         // FIXME(eddyb) maybe make this a method on `Columns`?
-        let max_line_width =
+        let max_line_width_columns =
             Columns { char_width_tenths: max_line_width.try_into().unwrap_or(u16::MAX) * 10 };
 
-        self.approx_layout(MaxWidths { inline: max_line_width, block: max_line_width });
-        FragmentPostLayout(self)
+        self.approx_layout(MaxWidths {
+            inline: max_line_width_columns,
+            block: max_line_width_columns,
+            indent: Columns::text_width(&indent),
+        });
+        FragmentPostLayout { fragment: self, max_line_width, indent }
+    }
+
+    /// Perform layout on the [`Fragment`], limiting lines to `max_line_width`
+    /// columns where possible (using the default of 2 spaces per indentation level).
+    #[deprecated(note = "use `Fragment::layout` (which also takes `indent_width`) instead")]
+    pub fn layout_with_max_line_width(self, max_line_width: usize) -> FragmentPostLayout {
+        self.layout(max_line_width, 2)
     }
 }
 
 // HACK(eddyb) simple wrapper to avoid misuse externally.
-pub struct FragmentPostLayout(Fragment);
+pub struct FragmentPostLayout {
+    fragment: Fragment,
+
+    /// The `max_line_width` that was used for layout (which may still be
+    /// exceeded by the result, but is useful as a hint for e.g. HTML).
+    max_line_width: usize,
+
+    /// The text of one level of indentation.
+    indent: String,
+}
 
 impl fmt::Display for FragmentPostLayout {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -182,9 +204,19 @@ impl fmt::Display for FragmentPostLayout {
 }
 
 impl FragmentPostLayout {
+    pub(super) fn max_line_width(&self) -> usize {
+        self.max_line_width
+    }
+
+    /// The text of one level of indentation, which [`TextOp::Text`]s produced
+    /// by `render_to_text_ops` will use (i.e. once per indentation level).
+    pub(super) fn indent(&self) -> &str {
+        &self.indent
+    }
+
     /// Flatten the [`Fragment`] to [`TextOp`]s.
     pub(super) fn render_to_text_ops(&self) -> impl InternalIterator<Item = TextOp<'_>> {
-        self.0.render_to_text_ops()
+        self.fragment.render_to_text_ops(&self.indent)
     }
 
     /// Flatten the [`Fragment`] to HTML, producing a [`HtmlSnippet`].
@@ -193,6 +225,14 @@ impl FragmentPostLayout {
     pub fn render_to_html(&self) -> HtmlSnippet {
         self.render_to_text_ops().collect()
     }
+
+    /// Flatten the [`Fragment`] to plain text interspersed with ANSI escape
+    /// codes (i.e. "SGR" sequences) for styling, for e.g. terminal output.
+    //
+    // FIXME(eddyb) provide a non-allocating version.
+    pub fn render_to_ansi(&self) -> String {
+        self.render_to_text_ops().collect::<AnsiText>().0
+    }
 }
 
 #[derive(Default)]
@@ -357,6 +397,7 @@ impl<'a> FromInternalIterator<TextOp<'a>> for HtmlSnippet {
                         size: _,
                         subscript,
                         superscript,
+                        italic,
                         desaturate_and_dim_for_unchanged_multiversion_line,
                     } = *styles;
 
@@ -372,6 +413,9 @@ impl<'a> FromInternalIterator<TextOp<'a>> for HtmlSnippet {
                         write!(css_style, "font-weight:{};", 500 + (thickness as i32) * 100)
                             .unwrap();
                     }
+                    if italic {
+                        css_style += "font-style:italic;";
+                    }
                     if let Some(size) = styles.effective_size() {
                         write!(css_style, "font-size:{}em;", 1.0 + (size as f64) * 0.1).unwrap();
                         if !(subscript || superscript) {
@@ -426,6 +470,135 @@ impl<'a> FromInternalIterator<TextOp<'a>> for HtmlSnippet {
     }
 }
 
+/// Plain text with ANSI escape codes, built from [`TextOp`]s (see also
+/// [`FragmentPostLayout::render_to_ansi`]).
+pub(super) struct AnsiText(pub(super) String);
+
+/// The subset of [`Styles`] which can be expressed with ANSI escape codes,
+/// after combining all the [`Styles`] that apply to some text (from outermost
+/// to innermost, similar to how CSS inheritance works for HTML output).
+#[derive(Copy, Clone, Default, PartialEq)]
+struct AnsiStyle {
+    color: Option<[u8; 3]>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+}
+
+impl AnsiStyle {
+    fn from_nested_styles<'a>(nested_styles: impl Iterator<Item = &'a Styles>) -> Self {
+        let mut color = None;
+        let mut color_opacity = None;
+        let mut thickness = None;
+        let mut italic = false;
+        let mut desaturate_and_dim = false;
+        for styles in nested_styles {
+            // NOTE(eddyb) `color_opacity` is tied to its `color` (as in HTML).
+            if styles.color.is_some() {
+                color = styles.color;
+                color_opacity = styles.color_opacity;
+            }
+            thickness = styles.thickness.or(thickness);
+            italic |= styles.italic;
+            desaturate_and_dim |= styles.desaturate_and_dim_for_unchanged_multiversion_line;
+        }
+
+        // HACK(eddyb) terminals only have "dim" (aka "faint") for both thinner
+        // and more transparent text, and only "bold" for thicker text.
+        let thickness = thickness.unwrap_or(0);
+        let dim = thickness < 0 || color_opacity.is_some_and(|a| a < 1.0) || desaturate_and_dim;
+
+        // NOTE(eddyb) this approximates the CSS `filter: saturate(0.3)` used
+        // for the same purpose by the HTML output (see `HtmlSnippet`).
+        let color = color.map(|[r, g, b]| {
+            if !desaturate_and_dim {
+                return [r, g, b];
+            }
+            let luma = (u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000;
+            [r, g, b].map(|c| ((luma * 7 + u32::from(c) * 3) / 10) as u8)
+        });
+
+        Self { color, bold: thickness > 0, dim, italic }
+    }
+
+    /// Write the ANSI escape code which resets all styling, then applies `self`.
+    fn write_escape_code_to(&self, out: &mut String) {
+        let Self { color, bold, dim, italic } = *self;
+
+        *out += "\x1b[0";
+        if bold {
+            *out += ";1";
+        }
+        if dim {
+            *out += ";2";
+        }
+        if italic {
+            *out += ";3";
+        }
+        if let Some([r, g, b]) = color {
+            write!(out, ";38;2;{r};{g};{b}").unwrap();
+        }
+        *out += "m";
+    }
+}
+
+// FIXME(eddyb) is this impl the best way? (maybe it should be a inherent method)
+impl<'a> FromInternalIterator<TextOp<'a>> for AnsiText {
+    fn from_iter<T>(text_ops: T) -> Self
+    where
+        T: IntoInternalIterator<Item = TextOp<'a>>,
+    {
+        let mut out = String::new();
+
+        let mut styles_stack = vec![];
+
+        // NOTE(eddyb) escape codes are only emitted right before text, and only
+        // if the style changed, to avoid needlessly bloating the output (and
+        // all styling is reset at the end of each line, to play well with
+        // e.g. pagers, which tend to not keep any state between lines).
+        let mut current_style = AnsiStyle::default();
+
+        text_ops.into_internal_iter().for_each(|op| match op {
+            TextOp::PushStyles(styles) => styles_stack.push(styles),
+            TextOp::PopStyles(styles) => {
+                let popped = styles_stack.pop();
+                assert!(popped == Some(styles));
+            }
+
+            // FIXME(eddyb) consider using OSC 8 hyperlinks for anchors, though
+            // they're intra-document, which terminals are unlikely to support.
+            TextOp::PushAnchor { .. } | TextOp::PopAnchor { .. } => {}
+
+            TextOp::Text(text) => {
+                let style = AnsiStyle::from_nested_styles(styles_stack.iter().copied());
+                for (i, line) in text.split('\n').enumerate() {
+                    if i > 0 {
+                        if current_style != AnsiStyle::default() {
+                            current_style = AnsiStyle::default();
+                            current_style.write_escape_code_to(&mut out);
+                        }
+                        out += "\n";
+                    }
+                    if line.is_empty() {
+                        continue;
+                    }
+                    if current_style != style {
+                        current_style = style;
+                        current_style.write_escape_code_to(&mut out);
+                    }
+                    out += line;
+                }
+            }
+        });
+
+        if current_style != AnsiStyle::default() {
+            AnsiStyle::default().write_escape_code_to(&mut out);
+        }
+
+        AnsiText(out)
+    }
+}
+
 // Rendering implementation details (including approximate layout).
 
 /// Fractional number of columns, used here to account for `Node::StyledText`
@@ -537,10 +710,10 @@ impl ApproxLayout {
 struct MaxWidths {
     inline: Columns,
     block: Columns,
-}
 
-// FIXME(eddyb) make this configurable.
-pub(super) const INDENT: &str = "  ";
+    /// The width of one level of indentation (constant throughout layout).
+    indent: Columns,
+}
 
 impl Node {
     /// Determine the "rigid" component of the [`ApproxLayout`] of this [`Node`].
@@ -625,14 +798,14 @@ impl Node {
         match self {
             Self::IndentedBlock(fragments) => {
                 // Apply one more level of indentation to the block layout.
-                let indented_block_max_width =
-                    max_widths.block.saturating_sub(Columns::text_width(INDENT));
+                let indented_block_max_width = max_widths.block.saturating_sub(max_widths.indent);
 
                 // Recurse on `fragments`, so they can compute their own layouts.
                 for fragment in &mut fragments[..] {
                     fragment.approx_layout(MaxWidths {
                         inline: indented_block_max_width,
                         block: indented_block_max_width,
+                        indent: max_widths.indent,
                     });
                 }
 
@@ -644,8 +817,7 @@ impl Node {
 
             Self::InlineOrIndentedBlock(fragments) => {
                 // Apply one more level of indentation to the block layout.
-                let indented_block_max_width =
-                    max_widths.block.saturating_sub(Columns::text_width(INDENT));
+                let indented_block_max_width = max_widths.block.saturating_sub(max_widths.indent);
 
                 // Maximize the inline width available to `fragments`, usually
                 // increasing it to the maximum allowed by the block layout.
@@ -655,6 +827,7 @@ impl Node {
                 let inner_max_widths = MaxWidths {
                     inline: max_widths.inline.max(indented_block_max_width),
                     block: indented_block_max_width,
+                    indent: max_widths.indent,
                 };
 
                 let mut layout = ApproxLayout::Inline {
@@ -741,6 +914,7 @@ impl Fragment {
                 }
             },
             block: max_widths.block,
+            indent: max_widths.indent,
         };
 
         // Compute rigid `ApproxLayout`s as long as they remain inline, only
@@ -924,9 +1098,13 @@ impl Fragment {
             .flat_map(move |node| node.render_to_line_ops(directly_in_block))
     }
 
-    /// Flatten the [`Fragment`] to [`TextOp`]s.
-    fn render_to_text_ops(&self) -> impl InternalIterator<Item = TextOp<'_>> {
-        LineOp::interpret(self.render_to_line_ops(false))
+    /// Flatten the [`Fragment`] to [`TextOp`]s, using `indent` for each level
+    /// of indentation.
+    fn render_to_text_ops<'a>(
+        &'a self,
+        indent: &'a str,
+    ) -> impl InternalIterator<Item = TextOp<'a>> {
+        LineOp::interpret(self.render_to_line_ops(false), indent)
     }
 }
 
@@ -945,25 +1123,27 @@ impl<'a> LineOp<'a> {
     /// Expand [`LineOp`]s to [`TextOp`]s.
     fn interpret(
         line_ops: impl InternalIterator<Item = LineOp<'a>>,
+        indent_text: &'a str,
     ) -> impl InternalIterator<Item = TextOp<'a>> {
         // FIXME(eddyb) a better helper for this may require type-generic closures.
-        struct Interpret<I>(I);
-        impl<'a, I: InternalIterator<Item = LineOp<'a>>> InternalIterator for Interpret<I> {
+        struct Interpret<'a, I>(I, &'a str);
+        impl<'a, I: InternalIterator<Item = LineOp<'a>>> InternalIterator for Interpret<'a, I> {
             type Item = TextOp<'a>;
 
             fn try_for_each<T, F>(self, f: F) -> ControlFlow<T>
             where
                 F: FnMut(TextOp<'a>) -> ControlFlow<T>,
             {
-                LineOp::interpret_try_for_each_helper(self.0, f)
+                LineOp::interpret_try_for_each_helper(self.0, self.1, f)
             }
         }
-        Interpret(line_ops)
+        Interpret(line_ops, indent_text)
     }
 
     // HACK(eddyb) helper for `interpret` returning a `InternalIterator`.
     fn interpret_try_for_each_helper<T>(
         line_ops: impl InternalIterator<Item = LineOp<'a>>,
+        indent_text: &'a str,
         mut each_text_op: impl FnMut(TextOp<'a>) -> ControlFlow<T>,
     ) -> ControlFlow<T> {
         let mut indent = 0;
@@ -1059,7 +1239,7 @@ impl<'a> LineOp<'a> {
                         LineState::HasText => unreachable!(),
                     };
                     for _ in indent_so_far..target_indent {
-                        each_text_op(TextOp::Text(INDENT))?;
+                        each_text_op(TextOp::Text(indent_text))?;
                     }
                     line_state = LineState::OnlyIndentedOrAnchored { indent_so_far: target_indent };
                 }
@@ -1155,7 +1155,9 @@ impl<'a> InferUsage<'a> {
                         );
                     }
 
-                    DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => {
+                    DataInstKind::Pure(_)
+                    | DataInstKind::SpvInst(_)
+                    | DataInstKind::SpvExtInst { .. } => {
                         let mut has_from_spv_ptr_output_attr = false;
                         for attr in &cx[data_inst_def.attrs].attrs {
                             match *attr {
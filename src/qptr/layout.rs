@@ -2,7 +2,8 @@
 
 use crate::qptr::shapes;
 use crate::{
-    spv, AddrSpace, Attr, Const, ConstKind, Context, Diag, FxIndexMap, Type, TypeKind, TypeOrConst,
+    spv, AddrSpace, Attr, Const, ConstKind, Context, Diag, FxIndexMap, ScalarKind, Type, TypeKind,
+    TypeOrConst,
 };
 use itertools::Either;
 use smallvec::SmallVec;
@@ -184,16 +185,14 @@ impl<'a> LayoutCache<'a> {
 
     // FIXME(eddyb) properly distinguish between zero-extension and sign-extension.
     fn const_as_u32(&self, ct: Const) -> Option<u32> {
-        if let ConstKind::SpvInst { spv_inst_and_const_inputs } = &self.cx[ct].kind {
-            let (spv_inst, _const_inputs) = &**spv_inst_and_const_inputs;
-            if spv_inst.opcode == self.wk.OpConstant && spv_inst.imms.len() == 1 {
-                match spv_inst.imms[..] {
-                    [spv::Imm::Short(_, x)] => return Some(x),
-                    _ => unreachable!(),
-                }
-            }
+        let ct_def = &self.cx[ct];
+        match (&ct_def.kind, &self.cx[ct_def.ty].kind) {
+            (
+                &ConstKind::Scalar(bits),
+                TypeKind::Scalar { kind: ScalarKind::SInt | ScalarKind::UInt, .. },
+            ) => u32::try_from(bits).ok(),
+            _ => None,
         }
-        None
     }
 
     /// Attempt to compute a `TypeLayout` for a given (SPIR-V) `Type`.
@@ -205,24 +204,6 @@ impl<'a> LayoutCache<'a> {
         let cx = &self.cx;
         let wk = self.wk;
 
-        let ty_def = &cx[ty];
-        let (spv_inst, type_and_const_inputs) = match &ty_def.kind {
-            // FIXME(eddyb) treat `QPtr`s as scalars.
-            TypeKind::QPtr => {
-                return Err(LayoutError(Diag::bug(
-                    ["`layout_of(qptr)` (already lowered?)".into()],
-                )));
-            }
-            TypeKind::SpvInst { spv_inst, type_and_const_inputs } => {
-                (spv_inst, type_and_const_inputs)
-            }
-            TypeKind::SpvStringLiteralForExtInst => {
-                return Err(LayoutError(Diag::bug([
-                    "`layout_of(type_of(OpString<\"...\">))`".into()
-                ])));
-            }
-        };
-
         let scalar_with_size_and_align = |(size, align)| {
             TypeLayout::Concrete(Rc::new(MemTypeLayout {
                 original_type: ty,
@@ -239,6 +220,7 @@ impl<'a> LayoutCache<'a> {
             assert_eq!(size * 8, width);
             scalar_with_size_and_align((size, size))
         };
+
         let align_to = |size: u32, align: u32| {
             assert!(align.is_power_of_two() && align > 0);
             Ok(size.checked_add(align - 1).ok_or_else(|| {
@@ -340,6 +322,47 @@ impl<'a> LayoutCache<'a> {
                 }
             }
         };
+        let ty_def = &cx[ty];
+        let (spv_inst, type_and_const_inputs) = match &ty_def.kind {
+            // FIXME(eddyb) treat `QPtr`s as scalars.
+            TypeKind::QPtr => {
+                return Err(LayoutError(Diag::bug(
+                    ["`layout_of(qptr)` (already lowered?)".into()],
+                )));
+            }
+            &TypeKind::Scalar { kind, width } => {
+                let layout = if kind == ScalarKind::Bool {
+                    // FIXME(eddyb) make this properly abstract instead of only configurable.
+                    scalar_with_size_and_align(self.config.abstract_bool_size_align)
+                } else {
+                    scalar(width)
+                };
+                self.cache.borrow_mut().insert(ty, layout.clone());
+                return Ok(layout);
+            }
+            &TypeKind::Vector { elem, elem_count } => {
+                let layout = array(
+                    elem,
+                    ArrayParams {
+                        fixed_len: Some(elem_count),
+                        known_stride: None,
+                        // NOTE(eddyb) this is specifically Vulkan "base alignment".
+                        min_legacy_align: 1,
+                        legacy_align_multiplier: if elem_count <= 2 { 2 } else { 4 },
+                    },
+                )?;
+                self.cache.borrow_mut().insert(ty, layout.clone());
+                return Ok(layout);
+            }
+            TypeKind::SpvInst { spv_inst, type_and_const_inputs } => {
+                (spv_inst, type_and_const_inputs)
+            }
+            TypeKind::SpvStringLiteralForExtInst => {
+                return Err(LayoutError(Diag::bug([
+                    "`layout_of(type_of(OpString<\"...\">))`".into()
+                ])));
+            }
+        };
         let short_imm_at = |i| match spv_inst.imms[i] {
             spv::Imm::Short(_, x) => x,
             _ => unreachable!(),
@@ -349,16 +372,11 @@ impl<'a> LayoutCache<'a> {
         // that would allow surrounding offsets to limit their size... but... ugh...
         // ugh this doesn't make any sense. maybe if the front-end specifies
         // offsets with "abstract types", it must configure `qptr::layout`?
-        let layout = if spv_inst.opcode == wk.OpTypeBool {
-            // FIXME(eddyb) make this properly abstract instead of only configurable.
-            scalar_with_size_and_align(self.config.abstract_bool_size_align)
-        } else if spv_inst.opcode == wk.OpTypePointer {
+        let layout = if spv_inst.opcode == wk.OpTypePointer {
             // FIXME(eddyb) make this properly abstract instead of only configurable.
             // FIXME(eddyb) categorize `OpTypePointer` by storage class and split on
             // logical vs physical here.
             scalar_with_size_and_align(self.config.logical_ptr_size_align)
-        } else if [wk.OpTypeInt, wk.OpTypeFloat].contains(&spv_inst.opcode) {
-            scalar(short_imm_at(0))
         } else if [wk.OpTypeVector, wk.OpTypeMatrix].contains(&spv_inst.opcode) {
             let len = short_imm_at(0);
             let (min_legacy_align, legacy_align_multiplier) = if spv_inst.opcode == wk.OpTypeVector
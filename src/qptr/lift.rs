@@ -10,7 +10,7 @@ use crate::{
     spv, AddrSpace, Attr, AttrSet, AttrSetDef, Const, ConstDef, ConstKind, Context, ControlNode,
     ControlNodeKind, DataInst, DataInstDef, DataInstFormDef, DataInstKind, DeclDef, Diag,
     DiagLevel, EntityDefs, EntityOrientedDenseMap, Func, FuncDecl, FxIndexMap, GlobalVar,
-    GlobalVarDecl, Module, Type, TypeDef, TypeKind, TypeOrConst, Value,
+    GlobalVarDecl, Module, ScalarKind, Type, TypeDef, TypeKind, TypeOrConst, Value,
 };
 use smallvec::SmallVec;
 use std::cell::Cell;
@@ -334,40 +334,16 @@ impl<'a> LiftToSpvPtrs<'a> {
         if let Some(cached) = self.cached_u32_type.get() {
             return cached;
         }
-        let wk = self.wk;
-        let ty = self.cx.intern(TypeKind::SpvInst {
-            spv_inst: spv::Inst {
-                opcode: wk.OpTypeInt,
-                imms: [
-                    spv::Imm::Short(wk.LiteralInteger, 32),
-                    spv::Imm::Short(wk.LiteralInteger, 0),
-                ]
-                .into_iter()
-                .collect(),
-            },
-            type_and_const_inputs: [].into_iter().collect(),
-        });
+        let ty = self.cx.intern(TypeKind::Scalar { kind: ScalarKind::UInt, width: 32 });
         self.cached_u32_type.set(Some(ty));
         ty
     }
 
     fn const_u32(&self, x: u32) -> Const {
-        let wk = self.wk;
-
         self.cx.intern(ConstDef {
             attrs: AttrSet::default(),
             ty: self.u32_type(),
-            kind: ConstKind::SpvInst {
-                spv_inst_and_const_inputs: Rc::new((
-                    spv::Inst {
-                        opcode: wk.OpConstant,
-                        imms: [spv::Imm::Short(wk.LiteralContextDependentNumber, x)]
-                            .into_iter()
-                            .collect(),
-                    },
-                    [].into_iter().collect(),
-                )),
-            },
+            kind: ConstKind::Scalar(x.into()),
         })
     }
 
@@ -741,7 +717,7 @@ impl LiftToSpvPtrInstsInFunc<'_> {
                             }
                             // FIXME(eddyb) obsolete this case entirely,
                             // by removing stores of ZSTs, and replacing
-                            // loads of ZSTs with `OpUndef` constants.
+                            // loads of ZSTs with `ConstKind::Undef` constants.
                             (Some(_), Some(_)) => {
                                 return Err(LiftError(Diag::bug([
                                     "ambiguity due to ZSTs in pointee type layout".into(),
@@ -839,7 +815,7 @@ impl LiftToSpvPtrInstsInFunc<'_> {
                 new_data_inst_def
             }
 
-            DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => {
+            DataInstKind::Pure(_) | DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => {
                 let mut to_spv_ptr_input_adjustments = vec![];
                 let mut from_spv_ptr_output = None;
                 for attr in &cx[data_inst_def.attrs].attrs {
@@ -999,7 +975,7 @@ impl LiftToSpvPtrInstsInFunc<'_> {
                             }
                             // FIXME(eddyb) obsolete this case entirely,
                             // by removing stores of ZSTs, and replacing
-                            // loads of ZSTs with `OpUndef` constants.
+                            // loads of ZSTs with `ConstKind::Undef` constants.
                             (Some(_), Some(_)) => {
                                 return Err(LiftError(Diag::bug([
                                     "ambiguity due to ZSTs in pointee type layout".into(),
@@ -9,7 +9,7 @@ use crate::transform::{InnerInPlaceTransform, Transformed, Transformer};
 use crate::{
     spv, AddrSpace, AttrSet, AttrSetDef, Const, ConstDef, ConstKind, Context, ControlNode,
     ControlNodeKind, DataInst, DataInstDef, DataInstForm, DataInstFormDef, DataInstKind, Diag,
-    FuncDecl, GlobalVarDecl, OrdAssertEq, Type, TypeKind, TypeOrConst, Value,
+    FuncDecl, GlobalVarDecl, OrdAssertEq, ScalarKind, Type, TypeKind, TypeOrConst, Value,
 };
 use smallvec::SmallVec;
 use std::cell::Cell;
@@ -173,16 +173,14 @@ impl<'a> LowerFromSpvPtrs<'a> {
 
     // FIXME(eddyb) properly distinguish between zero-extension and sign-extension.
     fn const_as_u32(&self, ct: Const) -> Option<u32> {
-        if let ConstKind::SpvInst { spv_inst_and_const_inputs } = &self.cx[ct].kind {
-            let (spv_inst, _const_inputs) = &**spv_inst_and_const_inputs;
-            if spv_inst.opcode == self.wk.OpConstant && spv_inst.imms.len() == 1 {
-                match spv_inst.imms[..] {
-                    [spv::Imm::Short(_, x)] => return Some(x),
-                    _ => unreachable!(),
-                }
-            }
+        let ct_def = &self.cx[ct];
+        match (&ct_def.kind, &self.cx[ct_def.ty].kind) {
+            (
+                &ConstKind::Scalar(bits),
+                TypeKind::Scalar { kind: ScalarKind::SInt | ScalarKind::UInt, .. },
+            ) => u32::try_from(bits).ok(),
+            _ => None,
         }
-        None
     }
 
     /// Get the (likely cached) `QPtr` type.
@@ -626,7 +624,9 @@ impl LowerFromSpvPtrInstsInFunc<'_> {
             // Known semantics, no need to preserve SPIR-V pointer information.
             DataInstKind::FuncCall(_) | DataInstKind::QPtr(_) => return,
 
-            DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => {}
+            // NOTE(eddyb) `PureOp`s can still have SPIR-V pointer inputs (e.g.
+            // a pointer-to-integer `OpBitcast`), which need the same treatment.
+            DataInstKind::Pure(_) | DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => {}
         }
 
         let mut old_and_new_attrs = None;
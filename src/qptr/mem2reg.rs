@@ -0,0 +1,456 @@
+//! [`QPtr`](crate::TypeKind::QPtr) function-local variable promotion to SSA
+//! values (also known as "mem2reg").
+
+use crate::qptr::{QPtrAttr, QPtrMemUsage, QPtrMemUsageKind, QPtrOp, QPtrUsage};
+use crate::transform::{InnerInPlaceTransform, ReplaceValueWith};
+use crate::{
+    Attr, AttrSet, ConstDef, ConstKind, Context, ControlNodeKind, ControlNodeOutputDecl,
+    ControlRegion, ControlRegionInputDecl, DataInst, DataInstKind, DeclDef, FuncDecl, FuncDefBody,
+    FxIndexMap, FxIndexSet, Type, Value,
+};
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Context for promoting [`QPtrOp::FuncLocalVar`]s to SSA values.
+///
+/// Only variables which don't "escape" are promoted, i.e. all their uses must
+/// be [`QPtrOp::Load`]s/[`QPtrOp::Store`]s through the variable pointer, or
+/// through constant [`QPtrOp::Offset`]s of it, with every such access exactly
+/// matching one of the "leaves" (i.e. [`QPtrMemUsageKind::DirectAccess`]es)
+/// in the usage inferred by [`InferUsage`](crate::qptr::analyze::InferUsage),
+/// which allows splitting aggregates into one SSA value per leaf.
+///
+/// The SSA values are threaded through the structured control-flow, using
+/// `Select` outputs and `Loop` body inputs/outputs (instead of SSA φ nodes).
+///
+/// See also `passes::qptr::promote_func_local_vars` (which drives this).
+pub struct PromoteFuncLocalVars {
+    cx: Rc<Context>,
+}
+
+impl PromoteFuncLocalVars {
+    pub fn new(cx: Rc<Context>) -> Self {
+        Self { cx }
+    }
+
+    pub fn promote_in_func(&self, func_decl: &mut FuncDecl) {
+        let func_def_body = match &mut func_decl.def {
+            DeclDef::Imported(_) => return,
+            DeclDef::Present(func_def_body) => func_def_body,
+        };
+
+        // FIXME(eddyb) support unstructured control-flow as well (this would
+        // require the equivalent of SSA φ node insertion, i.e. region inputs).
+        if func_def_body.unstructured_cfg.is_some() {
+            return;
+        }
+
+        let mut promoter = FuncPromoter {
+            cx: &self.cx,
+            vars: FxIndexMap::default(),
+            ptrs: FxHashMap::default(),
+            replacements: FxHashMap::default(),
+        };
+
+        promoter.collect_in_region(func_def_body, func_def_body.body);
+        if !promoter.vars.values().any(|info| info.is_some()) {
+            return;
+        }
+        promoter.promote_in_region(func_def_body, func_def_body.body, &mut FxIndexMap::default());
+
+        func_def_body.inner_in_place_transform_with(&mut ReplaceValueWith(|v| {
+            let resolved = promoter.resolve(v);
+            (resolved != v).then_some(resolved)
+        }));
+    }
+}
+
+/// Promotable [`QPtrOp::FuncLocalVar`].
+struct VarInfo {
+    /// Types of all the leaves (i.e. separately promoted parts) of the variable,
+    /// by their offset (in bytes) within the variable.
+    leaves: BTreeMap<u32, Type>,
+
+    /// Initial value for the only leaf (at offset `0`), if the variable had one.
+    //
+    // FIXME(eddyb) support splitting aggregate initializers into their leaves.
+    initializer: Option<Value>,
+}
+
+/// A leaf of a variable (see `VarInfo`), i.e. the variable, and the leaf offset.
+type LeafKey = (DataInst, u32);
+
+struct FuncPromoter<'a> {
+    cx: &'a Context,
+
+    /// All [`QPtrOp::FuncLocalVar`]s, with `None` for unpromotable ones.
+    vars: FxIndexMap<DataInst, Option<VarInfo>>,
+
+    /// All `QPtr` values known to point into some variable in `vars` (i.e.
+    /// the variable itself, or constant `QPtrOp::Offset`s from it).
+    ptrs: FxHashMap<Value, LeafKey>,
+
+    /// Removed `QPtrOp::Load`s, and the values to replace them with.
+    replacements: FxHashMap<DataInst, Value>,
+}
+
+impl FuncPromoter<'_> {
+    fn resolve(&self, mut v: Value) -> Value {
+        while let Value::DataInstOutput(inst) = v {
+            match self.replacements.get(&inst) {
+                Some(&replacement) => v = replacement,
+                None => break,
+            }
+        }
+        v
+    }
+
+    fn disqualify_if_ptr(&mut self, v: Value) {
+        if let Some(&(var, _)) = self.ptrs.get(&v) {
+            self.vars[&var] = None;
+        }
+    }
+
+    /// Disqualify the variable `ptr` points into, unless `ptr` points exactly
+    /// at a leaf of type `ty`.
+    fn require_leaf_access(&mut self, ptr: Value, ty: Type) {
+        if let Some(&(var, offset)) = self.ptrs.get(&ptr) {
+            let leaf_type = self.vars[&var].as_ref().and_then(|info| info.leaves.get(&offset));
+            if leaf_type != Some(&ty) {
+                self.vars[&var] = None;
+            }
+        }
+    }
+
+    /// Returns the leaf `ptr` points at, if it's in a promoted variable.
+    fn promoted_leaf(&self, ptr: Value) -> Option<LeafKey> {
+        self.ptrs.get(&ptr).copied().filter(|(var, _)| self.vars[var].is_some())
+    }
+
+    fn leaf_type(&self, (var, offset): LeafKey) -> Type {
+        self.vars[&var].as_ref().unwrap().leaves[&offset]
+    }
+
+    fn var_info(
+        &self,
+        func_def_body: &FuncDefBody,
+        attrs: AttrSet,
+        initializer: Option<Value>,
+    ) -> Option<VarInfo> {
+        let cx = self.cx;
+
+        let usage = cx[attrs].attrs.iter().find_map(|attr| match attr {
+            Attr::QPtr(QPtrAttr::Usage(usage)) => Some(&usage.0),
+            _ => None,
+        })?;
+        let mem_usage = match usage {
+            QPtrUsage::Memory(mem_usage) => mem_usage,
+            QPtrUsage::Handles(_) => return None,
+        };
+
+        fn collect_leaves(
+            usage: &QPtrMemUsage,
+            offset: u32,
+            leaves: &mut BTreeMap<u32, Type>,
+        ) -> Option<()> {
+            match &usage.kind {
+                QPtrMemUsageKind::Unused => {}
+                &QPtrMemUsageKind::DirectAccess(ty) => {
+                    if leaves.insert(offset, ty).is_some() {
+                        return None;
+                    }
+                }
+                QPtrMemUsageKind::OffsetBase(entries) => {
+                    for (&sub_offset, sub_usage) in &**entries {
+                        collect_leaves(sub_usage, offset.checked_add(sub_offset)?, leaves)?;
+                    }
+                }
+                QPtrMemUsageKind::StrictlyTyped(_) | QPtrMemUsageKind::DynOffsetBase { .. } => {
+                    return None;
+                }
+            }
+            Some(())
+        }
+        let mut leaves = BTreeMap::new();
+        collect_leaves(mem_usage, 0, &mut leaves)?;
+
+        if let Some(initializer) = initializer {
+            let initializer_type = func_def_body.at(initializer).type_of(cx);
+            let whole_var_leaf = leaves.len() == 1 && leaves.get(&0) == Some(&initializer_type);
+            if !(leaves.is_empty() || whole_var_leaf) {
+                return None;
+            }
+        }
+
+        Some(VarInfo { leaves, initializer })
+    }
+
+    /// Find all variables, and all the pointers into them, disqualifying
+    /// any variables which can't be promoted.
+    fn collect_in_region(&mut self, func_def_body: &FuncDefBody, region: ControlRegion) {
+        let cx = self.cx;
+
+        for func_at_node in func_def_body.at(region).at_children() {
+            match &func_at_node.def().kind {
+                &ControlNodeKind::Block { insts } => {
+                    for func_at_inst in func_def_body.at(insts) {
+                        let inst = func_at_inst.position;
+                        let inst_def = func_at_inst.def();
+                        let form_def = &cx[inst_def.form];
+                        let output = Value::DataInstOutput(inst);
+
+                        match (&form_def.kind, &inst_def.inputs[..]) {
+                            (DataInstKind::QPtr(QPtrOp::FuncLocalVar(_)), inputs) => {
+                                for &v in inputs {
+                                    self.disqualify_if_ptr(v);
+                                }
+                                let info = self.var_info(
+                                    func_def_body,
+                                    inst_def.attrs,
+                                    inputs.first().copied(),
+                                );
+                                self.vars.insert(inst, info);
+                                self.ptrs.insert(output, (inst, 0));
+                            }
+                            (&DataInstKind::QPtr(QPtrOp::Offset(offset)), &[base]) => {
+                                if let Some(&(var, base_offset)) = self.ptrs.get(&base) {
+                                    let offset = i64::from(base_offset) + i64::from(offset);
+                                    match u32::try_from(offset) {
+                                        Ok(offset) => {
+                                            self.ptrs.insert(output, (var, offset));
+                                        }
+                                        Err(_) => self.vars[&var] = None,
+                                    }
+                                }
+                            }
+                            (DataInstKind::QPtr(QPtrOp::Load), &[ptr]) => {
+                                if let Some(output_type) = form_def.output_type {
+                                    self.require_leaf_access(ptr, output_type);
+                                }
+                            }
+                            (DataInstKind::QPtr(QPtrOp::Store), &[ptr, value]) => {
+                                self.disqualify_if_ptr(value);
+                                self.require_leaf_access(ptr, func_def_body.at(value).type_of(cx));
+                            }
+                            (_, inputs) => {
+                                for &v in inputs {
+                                    self.disqualify_if_ptr(v);
+                                }
+                            }
+                        }
+                    }
+                }
+                ControlNodeKind::Select { kind: _, scrutinee, cases } => {
+                    self.disqualify_if_ptr(*scrutinee);
+                    for &case in cases {
+                        self.collect_in_region(func_def_body, case);
+                    }
+                }
+                ControlNodeKind::Loop { initial_inputs, body, repeat_condition } => {
+                    for &v in initial_inputs {
+                        self.disqualify_if_ptr(v);
+                    }
+                    self.collect_in_region(func_def_body, *body);
+                    self.disqualify_if_ptr(*repeat_condition);
+                }
+            }
+        }
+
+        for &v in &func_def_body.at(region).def().outputs {
+            self.disqualify_if_ptr(v);
+        }
+    }
+
+    /// Collect all the leaves (of promoted variables) written to in `region`.
+    fn collect_stored_leaves(
+        &self,
+        func_def_body: &FuncDefBody,
+        region: ControlRegion,
+        stored_leaves: &mut FxIndexSet<LeafKey>,
+    ) {
+        for func_at_node in func_def_body.at(region).at_children() {
+            match &func_at_node.def().kind {
+                &ControlNodeKind::Block { insts } => {
+                    for func_at_inst in func_def_body.at(insts) {
+                        let inst_def = func_at_inst.def();
+                        if let (DataInstKind::QPtr(QPtrOp::Store), &[ptr, _]) =
+                            (&self.cx[inst_def.form].kind, &inst_def.inputs[..])
+                        {
+                            stored_leaves.extend(self.promoted_leaf(ptr));
+                        }
+                    }
+                }
+                ControlNodeKind::Select { cases, .. } => {
+                    for &case in cases {
+                        self.collect_stored_leaves(func_def_body, case, stored_leaves);
+                    }
+                }
+                &ControlNodeKind::Loop { body, .. } => {
+                    self.collect_stored_leaves(func_def_body, body, stored_leaves);
+                }
+            }
+        }
+    }
+
+    /// Replace all accesses to promoted variables in `region`, starting with
+    /// the values of (all the leaves of) those variables in `state`, and
+    /// updating `state` to the values they have at the end of `region`.
+    fn promote_in_region(
+        &mut self,
+        func_def_body: &mut FuncDefBody,
+        region: ControlRegion,
+        state: &mut FxIndexMap<LeafKey, Value>,
+    ) {
+        let cx = self.cx;
+
+        let mut next_node = func_def_body.at(region).def().children.iter().first;
+        while let Some(node) = next_node {
+            next_node = func_def_body.control_nodes[node].next_in_list();
+
+            match func_def_body.control_nodes[node].kind {
+                ControlNodeKind::Block { mut insts } => {
+                    let mut next_inst = insts.iter().first;
+                    while let Some(inst) = next_inst {
+                        next_inst = func_def_body.data_insts[inst].next_in_list();
+
+                        let inst_def = &func_def_body.data_insts[inst];
+                        let promoted = match (&cx[inst_def.form].kind, &inst_def.inputs[..]) {
+                            (DataInstKind::QPtr(QPtrOp::FuncLocalVar(_)), _) => {
+                                match &self.vars[&inst] {
+                                    Some(info) => {
+                                        for (&offset, &ty) in &info.leaves {
+                                            let initial_value = match info.initializer {
+                                                Some(v) if offset == 0 => self.resolve(v),
+                                                _ => Value::Const(cx.intern(ConstDef {
+                                                    attrs: AttrSet::default(),
+                                                    ty,
+                                                    kind: ConstKind::Undef,
+                                                })),
+                                            };
+                                            state.insert((inst, offset), initial_value);
+                                        }
+                                        true
+                                    }
+                                    None => false,
+                                }
+                            }
+                            (DataInstKind::QPtr(QPtrOp::Offset(_)), &[base]) => {
+                                self.promoted_leaf(base).is_some()
+                            }
+                            (DataInstKind::QPtr(QPtrOp::Load), &[ptr]) => {
+                                match self.promoted_leaf(ptr) {
+                                    Some(leaf) => {
+                                        self.replacements.insert(inst, state[&leaf]);
+                                        true
+                                    }
+                                    None => false,
+                                }
+                            }
+                            (DataInstKind::QPtr(QPtrOp::Store), &[ptr, value]) => {
+                                match self.promoted_leaf(ptr) {
+                                    Some(leaf) => {
+                                        state.insert(leaf, self.resolve(value));
+                                        true
+                                    }
+                                    None => false,
+                                }
+                            }
+                            _ => false,
+                        };
+                        if promoted {
+                            insts.remove(inst, &mut func_def_body.data_insts);
+                        }
+                    }
+                    func_def_body.control_nodes[node].kind = ControlNodeKind::Block { insts };
+
+                    if insts.is_empty() {
+                        func_def_body.control_regions[region]
+                            .children
+                            .remove(node, &mut func_def_body.control_nodes);
+                    }
+                }
+
+                ControlNodeKind::Select { ref cases, .. } => {
+                    let cases = cases.clone();
+
+                    // NOTE(eddyb) a single case can be treated as part of the
+                    // parent region (which also keeps any variables defined in
+                    // it, as they remain usable after the `Select`).
+                    if let [case] = cases[..] {
+                        self.promote_in_region(func_def_body, case, state);
+                        continue;
+                    }
+
+                    let case_states: SmallVec<[_; 2]> = cases
+                        .iter()
+                        .map(|&case| {
+                            let mut case_state = state.clone();
+                            self.promote_in_region(func_def_body, case, &mut case_state);
+                            case_state
+                        })
+                        .collect();
+
+                    // Merge the values from all cases, using `Select` outputs
+                    // (only when the cases disagree).
+                    for (&leaf, value) in state.iter_mut() {
+                        let first_case_value = case_states[0][&leaf];
+                        if case_states
+                            .iter()
+                            .all(|case_state| case_state[&leaf] == first_case_value)
+                        {
+                            *value = first_case_value;
+                            continue;
+                        }
+
+                        let outputs = &mut func_def_body.control_nodes[node].outputs;
+                        let output_idx = u32::try_from(outputs.len()).unwrap();
+                        outputs.push(ControlNodeOutputDecl {
+                            attrs: AttrSet::default(),
+                            ty: self.leaf_type(leaf),
+                        });
+                        for (&case, case_state) in cases.iter().zip(&case_states) {
+                            func_def_body.control_regions[case].outputs.push(case_state[&leaf]);
+                        }
+                        *value = Value::ControlNodeOutput { control_node: node, output_idx };
+                    }
+                }
+
+                ControlNodeKind::Loop { body, .. } => {
+                    let mut stored_leaves = FxIndexSet::default();
+                    self.collect_stored_leaves(func_def_body, body, &mut stored_leaves);
+
+                    // Leaves written to in the loop body become loop state, i.e.
+                    // body inputs (and outputs, for the next iteration).
+                    let loop_state_leaves: SmallVec<[_; 4]> =
+                        state.keys().copied().filter(|leaf| stored_leaves.contains(leaf)).collect();
+                    for &leaf in &loop_state_leaves {
+                        let inputs = &mut func_def_body.control_regions[body].inputs;
+                        let input_idx = u32::try_from(inputs.len()).unwrap();
+                        inputs.push(ControlRegionInputDecl {
+                            attrs: AttrSet::default(),
+                            ty: self.leaf_type(leaf),
+                        });
+                        match &mut func_def_body.control_nodes[node].kind {
+                            ControlNodeKind::Loop { initial_inputs, .. } => {
+                                initial_inputs.push(state[&leaf]);
+                            }
+                            _ => unreachable!(),
+                        }
+                        state.insert(leaf, Value::ControlRegionInput { region: body, input_idx });
+                    }
+
+                    // NOTE(eddyb) values defined in the body remain usable after
+                    // the loop, so `state` can be used as-is after the loop
+                    // (as the loop can only be exited at the end of its body).
+                    self.promote_in_region(func_def_body, body, state);
+
+                    for leaf in loop_state_leaves {
+                        func_def_body.control_regions[body].outputs.push(state[&leaf]);
+                    }
+                }
+            }
+        }
+    }
+}
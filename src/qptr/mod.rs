@@ -4,7 +4,7 @@
 // FIXME(eddyb) PR description of https://github.com/EmbarkStudios/spirt/pull/24
 // has more useful docs that could be copied here.
 
-use crate::{AddrSpace, OrdAssertEq, Type};
+use crate::{AddrSpace, DataInstEffects, OrdAssertEq, Type};
 use std::collections::BTreeMap;
 use std::num::NonZeroU32;
 use std::ops::Range;
@@ -16,6 +16,7 @@ pub mod analyze;
 mod layout;
 pub mod lift;
 pub mod lower;
+pub mod mem2reg;
 pub mod shapes;
 
 pub use layout::LayoutConfig;
@@ -166,7 +167,8 @@ pub enum QPtrOp {
     // FIXME(eddyb) should this handle _only_ "length in bytes", with additional
     // integer subtraction+division operations on lowering to `QPtr`, and then
     // multiplication+addition on lifting back to SPIR-V, followed by simplifying
-    // the redundant `(x * a + b - b) / a` to just `x`?
+    // the redundant `(x * a + b - b) / a` to just `x`? (`passes::fold` can do
+    // the latter, as long as the multiplication is marked `NoUnsignedWrap`)
     //
     // FIXME(eddyb) actually lower `OpArrayLength` to this!
     BufferDynLen {
@@ -206,3 +208,19 @@ pub enum QPtrOp {
     // FIXME(eddyb) implement more ops! at the very least copying!
     // (and lowering could ignore pointercasts, I guess?)
 }
+
+impl QPtrOp {
+    pub(crate) fn effects(&self) -> DataInstEffects {
+        match self {
+            QPtrOp::HandleArrayIndex
+            | QPtrOp::BufferData
+            | QPtrOp::BufferDynLen { .. }
+            | QPtrOp::Offset(_)
+            | QPtrOp::DynOffset { .. } => DataInstEffects::Pure,
+
+            QPtrOp::FuncLocalVar(_) | QPtrOp::Load => DataInstEffects::ReadOnly,
+
+            QPtrOp::Store => DataInstEffects::SideEffects,
+        }
+    }
+}
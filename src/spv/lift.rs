@@ -5,12 +5,13 @@ use crate::spv::{self, spec};
 use crate::visit::{InnerVisit, Visitor};
 use crate::{
     cfg, AddrSpace, Attr, AttrSet, Const, ConstDef, ConstKind, Context, ControlNode,
-    ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionInputDecl, DataInst,
-    DataInstDef, DataInstForm, DataInstFormDef, DataInstKind, DeclDef, EntityList, ExportKey,
-    Exportee, Func, FuncDecl, FuncParam, FxIndexMap, FxIndexSet, GlobalVar, GlobalVarDefBody,
-    Import, Module, ModuleDebugInfo, ModuleDialect, SelectionKind, Type, TypeDef, TypeKind,
-    TypeOrConst, Value,
+    ControlNodeDef, ControlNodeKind, ControlNodeOutputDecl, ControlRegion, ControlRegionInputDecl,
+    DataInst, DataInstDef, DataInstForm, DataInstFormDef, DataInstKind, DeclDef, EntityList,
+    ExportKey, Exportee, Func, FuncDecl, FuncParam, FxIndexMap, FxIndexSet, GlobalVar,
+    GlobalVarDefBody, Import, Module, ModuleDebugInfo, ModuleDialect, PureOp, ScalarKind,
+    SelectionKind, Type, TypeDef, TypeKind, TypeOrConst, Value,
 };
+use lazy_static::lazy_static;
 use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
 use std::borrow::Cow;
@@ -92,6 +93,96 @@ impl FuncDecl {
     }
 }
 
+/// Encode a [`TypeKind::Scalar`] as a SPIR-V `OpTypeBool`/`OpTypeInt`/`OpTypeFloat`.
+pub(crate) fn scalar_type_to_spv_inst(kind: ScalarKind, width: u32) -> spv::Inst {
+    let wk = &spec::Spec::get().well_known;
+
+    let literal = |x| spv::Imm::Short(wk.LiteralInteger, x);
+    match kind {
+        ScalarKind::Bool => wk.OpTypeBool.into(),
+        ScalarKind::SInt | ScalarKind::UInt => spv::Inst {
+            opcode: wk.OpTypeInt,
+            imms: [literal(width), literal(u32::from(kind == ScalarKind::SInt))]
+                .into_iter()
+                .collect(),
+        },
+        ScalarKind::Float => {
+            spv::Inst { opcode: wk.OpTypeFloat, imms: [literal(width)].into_iter().collect() }
+        }
+    }
+}
+
+/// Encode a [`ConstKind::Scalar`] (of a [`TypeKind::Scalar`] type with the
+/// given `kind` and `width`) as a SPIR-V `OpConstantFalse`/`OpConstantTrue`/`OpConstant`.
+pub(crate) fn scalar_const_to_spv_inst(kind: ScalarKind, width: u32, bits: u64) -> spv::Inst {
+    let wk = &spec::Spec::get().well_known;
+
+    if kind == ScalarKind::Bool {
+        return if bits == 0 { wk.OpConstantFalse.into() } else { wk.OpConstantTrue.into() };
+    }
+
+    // NOTE(eddyb) SPIR-V requires sign-extension of signed integers, to fill
+    // the whole (32-bit or 64-bit) literal, and zero-extension for everything else.
+    let words_width = if width <= 32 { 32 } else { 64 };
+    let mut literal_bits = bits;
+    if kind == ScalarKind::SInt && width < words_width {
+        let shift = 64 - width;
+        literal_bits = (((literal_bits << shift) as i64) >> shift) as u64;
+        literal_bits &= u64::MAX >> (64 - words_width);
+    }
+
+    let kind = wk.LiteralContextDependentNumber;
+    spv::Inst {
+        opcode: wk.OpConstant,
+        imms: if words_width == 32 {
+            [spv::Imm::Short(kind, literal_bits as u32)].into_iter().collect()
+        } else {
+            [
+                spv::Imm::LongStart(kind, literal_bits as u32),
+                spv::Imm::LongCont(kind, (literal_bits >> 32) as u32),
+            ]
+            .into_iter()
+            .collect()
+        },
+    }
+}
+
+/// Encode a [`TypeKind::Vector`] as a SPIR-V `OpTypeVector` (which will also
+/// need the element type as its only ID operand).
+pub(crate) fn vector_type_to_spv_inst(elem_count: u32) -> spv::Inst {
+    let wk = &spec::Spec::get().well_known;
+
+    spv::Inst {
+        opcode: wk.OpTypeVector,
+        imms: [spv::Imm::Short(wk.LiteralInteger, elem_count)].into_iter().collect(),
+    }
+}
+
+/// Encode a [`PureOp`] as the SPIR-V instruction of the same name.
+pub(crate) fn pure_op_to_spv_inst(op: PureOp) -> spv::Inst {
+    lazy_static! {
+        /// SPIR-V opcodes of all [`PureOp`]s, indexed by [`PureOp::index`].
+        static ref PURE_OP_OPCODES: Vec<spec::Opcode> = {
+            let spv_spec = spec::Spec::get();
+            PureOp::ALL_NAMES
+                .iter()
+                .map(|name| spv_spec.instructions.lookup(&format!("Op{name}")).unwrap())
+                .collect()
+        };
+    }
+
+    let wk = &spec::Spec::get().well_known;
+
+    let opcode = PURE_OP_OPCODES[op.index()];
+    let imms = match op {
+        PureOp::CompositeExtract { idx } | PureOp::CompositeInsert { idx } => {
+            [spv::Imm::Short(wk.LiteralInteger, idx)].into_iter().collect()
+        }
+        _ => [].into_iter().collect(),
+    };
+    spv::Inst { opcode, imms }
+}
+
 struct NeedsIdsCollector<'a> {
     cx: &'a Context,
     module: &'a Module,
@@ -103,6 +194,17 @@ struct NeedsIdsCollector<'a> {
     data_inst_forms_seen: FxIndexSet<DataInstForm>,
     global_vars_seen: FxIndexSet<GlobalVar>,
     funcs: FxIndexSet<Func>,
+
+    // NOTE(eddyb) the fields below are only used to report (all of the)
+    // constructs which can't be lifted, before any lifting is attempted.
+    errors: Vec<spv::Error>,
+    current_func: Option<Func>,
+    current_data_inst: Option<DataInst>,
+    current_global_var: Option<GlobalVar>,
+
+    /// Set only while visiting the inputs of a [`DataInstKind::SpvExtInst`],
+    /// the only place where [`ConstKind::SpvStringLiteralForExtInst`] is allowed.
+    spv_string_literal_allowed: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -111,7 +213,17 @@ enum Global {
     Const(Const),
 }
 
-impl Visitor<'_> for NeedsIdsCollector<'_> {
+impl NeedsIdsCollector<'_> {
+    fn error(&mut self, err: spv::Error) {
+        self.errors.push(
+            err.with_func(self.current_func)
+                .with_data_inst(self.current_data_inst)
+                .with_global_var(self.current_global_var),
+        );
+    }
+}
+
+impl<'a> Visitor<'a> for NeedsIdsCollector<'a> {
     fn visit_attr_set_use(&mut self, attrs: AttrSet) {
         self.visit_attr_set_def(&self.cx[attrs]);
     }
@@ -122,31 +234,48 @@ impl Visitor<'_> for NeedsIdsCollector<'_> {
         }
         let ty_def = &self.cx[ty];
         match ty_def.kind {
-            // FIXME(eddyb) this should be a proper `Result`-based error instead,
-            // and/or `spv::lift` should mutate the module for legalization.
+            // FIXME(eddyb) consider having `spv::lift` mutate the module for
+            // legalization, instead of only reporting an error.
+            // NOTE(eddyb) errors are reported only once per type (at its first
+            // use), by still adding it to `self.globals` (which is never used
+            // for lifting, if any errors were reported).
             TypeKind::QPtr => {
-                unreachable!("`TypeKind::QPtr` should be legalized away before lifting");
+                self.globals.insert(global);
+                return self.error(spv::Error::unsupported(
+                    "`qptr` type should be legalized away before lifting",
+                ));
             }
 
-            TypeKind::SpvInst { .. } => {}
+            TypeKind::Scalar { .. } | TypeKind::Vector { .. } | TypeKind::SpvInst { .. } => {}
             TypeKind::SpvStringLiteralForExtInst => {
-                unreachable!(
-                    "`TypeKind::SpvStringLiteralForExtInst` should not be used \
-                     as a type outside of `ConstKind::SpvStringLiteralForExtInst`"
-                );
+                self.globals.insert(global);
+                return self.error(spv::Error::malformed(
+                    "`OpString` literal type used outside of `OpExtInst` string literal constants",
+                ));
             }
         }
         self.visit_type_def(ty_def);
         self.globals.insert(global);
     }
     fn visit_const_use(&mut self, ct: Const) {
+        let spv_string_literal_allowed = mem::take(&mut self.spv_string_literal_allowed);
+
         let global = Global::Const(ct);
         if self.globals.contains(&global) {
             return;
         }
         let ct_def = &self.cx[ct];
         match ct_def.kind {
-            ConstKind::PtrToGlobalVar(_) | ConstKind::SpvInst { .. } => {
+            ConstKind::PtrToGlobalVar(_)
+            | ConstKind::Undef
+            | ConstKind::Scalar(_)
+            | ConstKind::SpvInst { .. } => {
+                if let ConstKind::Scalar(_) = ct_def.kind {
+                    if !matches!(self.cx[ct_def.ty].kind, TypeKind::Scalar { .. }) {
+                        self.error(spv::Error::malformed("scalar constant of non-scalar type"));
+                    }
+                }
+
                 self.visit_const_def(ct_def);
                 self.globals.insert(global);
             }
@@ -157,20 +286,42 @@ impl Visitor<'_> for NeedsIdsCollector<'_> {
             ConstKind::SpvStringLiteralForExtInst(s) => {
                 let ConstDef { attrs, ty, kind: _ } = ct_def;
 
-                assert!(*attrs == AttrSet::default());
-                assert!(
-                    self.cx[*ty]
-                        == TypeDef {
-                            attrs: AttrSet::default(),
-                            kind: TypeKind::SpvStringLiteralForExtInst,
-                        }
-                );
+                if !spv_string_literal_allowed {
+                    self.error(spv::Error::malformed(
+                        "`OpString` literal used outside of `OpExtInst` inputs",
+                    ));
+                }
+                if *attrs != AttrSet::default() {
+                    self.error(spv::Error::malformed(
+                        "`OpString` literal constant cannot have attributes",
+                    ));
+                }
+                if self.cx[*ty]
+                    != (TypeDef {
+                        attrs: AttrSet::default(),
+                        kind: TypeKind::SpvStringLiteralForExtInst,
+                    })
+                {
+                    self.error(spv::Error::malformed(
+                        "`OpString` literal constant must have the `OpString` literal type",
+                    ));
+                }
 
                 self.debug_strings.insert(&self.cx[s]);
             }
         }
     }
     fn visit_data_inst_form_use(&mut self, data_inst_form: DataInstForm) {
+        // NOTE(eddyb) this is checked before deduplication, to report every
+        // `DataInst` using a `qptr` operation (not just the first one).
+        if let DataInstKind::QPtr(_) = self.cx[data_inst_form].kind {
+            // FIXME(eddyb) consider having `spv::lift` mutate the module for
+            // legalization, instead of only reporting an error.
+            self.error(spv::Error::unsupported(
+                "`qptr` operation should be legalized away before lifting",
+            ));
+        }
+
         if self.data_inst_forms_seen.insert(data_inst_form) {
             self.visit_data_inst_form_def(&self.cx[data_inst_form]);
         }
@@ -178,7 +329,21 @@ impl Visitor<'_> for NeedsIdsCollector<'_> {
 
     fn visit_global_var_use(&mut self, gv: GlobalVar) {
         if self.global_vars_seen.insert(gv) {
-            self.visit_global_var_decl(&self.module.global_vars[gv]);
+            let outer_location = (
+                self.current_func.take(),
+                self.current_data_inst.take(),
+                self.current_global_var.replace(gv),
+            );
+
+            let gv_decl = &self.module.global_vars[gv];
+            if let AddrSpace::Handles = gv_decl.addr_space {
+                self.error(spv::Error::unsupported(
+                    "`AddrSpace::Handles` should be legalized away before lifting",
+                ));
+            }
+            self.visit_global_var_decl(gv_decl);
+
+            (self.current_func, self.current_data_inst, self.current_global_var) = outer_location;
         }
     }
     fn visit_func_use(&mut self, func: Func) {
@@ -190,10 +355,48 @@ impl Visitor<'_> for NeedsIdsCollector<'_> {
         // to avoid infinite recursion for recursive functions.
         self.funcs.insert(func);
 
+        let outer_location = (
+            self.current_func.replace(func),
+            self.current_data_inst.take(),
+            self.current_global_var.take(),
+        );
+
         let func_decl = &self.module.funcs[func];
         // FIXME(eddyb) should this be cached in `self.funcs`?
         self.visit_type_use(func_decl.spv_func_type(self.cx));
         self.visit_func_decl(func_decl);
+
+        // Unstructured control-flow can only be lifted if every region has
+        // an explicit terminator (except for the function body, which is
+        // allowed to exit by returning its outputs).
+        if let DeclDef::Present(func_def_body) = &func_decl.def {
+            if let Some(cfg) = &func_def_body.unstructured_cfg {
+                for region in cfg.rev_post_order(func_def_body) {
+                    match cfg.control_inst_on_exit_from.get(region) {
+                        Some(control_inst) => {
+                            let has_annotations = self.cx[control_inst.attrs]
+                                .attrs
+                                .iter()
+                                .any(|attr| matches!(attr, Attr::SpvAnnotation(_)));
+                            if has_annotations {
+                                self.error(spv::Error::malformed(
+                                    "`OpDecorate`/`OpName`/etc. cannot target \
+                                     a control-flow instruction (which has no output)",
+                                ));
+                            }
+                        }
+                        None if region != func_def_body.body => {
+                            self.error(spv::Error::unsupported(
+                                "unstructured control-flow region without a terminator",
+                            ));
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        (self.current_func, self.current_data_inst, self.current_global_var) = outer_location;
     }
 
     fn visit_spv_module_debug_info(&mut self, debug_info: &spv::ModuleDebugInfo) {
@@ -202,7 +405,7 @@ impl Visitor<'_> for NeedsIdsCollector<'_> {
             self.debug_strings.extend(sources.file_contents.keys().copied().map(|s| &self.cx[s]));
         }
     }
-    fn visit_attr(&mut self, attr: &Attr) {
+    fn visit_attr(&mut self, attr: &'a Attr) {
         match *attr {
             Attr::Diagnostics(_)
             | Attr::QPtr(_)
@@ -215,18 +418,57 @@ impl Visitor<'_> for NeedsIdsCollector<'_> {
         attr.inner_visit_with(self);
     }
 
-    fn visit_data_inst_form_def(&mut self, data_inst_form_def: &DataInstFormDef) {
+    fn visit_control_node_def(&mut self, func_at_control_node: FuncAt<'a, ControlNode>) {
+        // HACK(eddyb) this duplicates part of `FuncAt<ControlNode>::inner_visit_with`,
+        // only to keep track of the current `DataInst` (for error reporting).
+        let ControlNodeDef { kind, outputs } = func_at_control_node.def();
+        if let ControlNodeKind::Block { insts } = *kind {
+            for func_at_inst in func_at_control_node.at(insts) {
+                self.current_data_inst = Some(func_at_inst.position);
+                self.visit_data_inst_def(func_at_inst.def());
+            }
+            self.current_data_inst = None;
+            for output in outputs {
+                output.inner_visit_with(self);
+            }
+        } else {
+            func_at_control_node.inner_visit_with(self);
+        }
+    }
+
+    fn visit_data_inst_def(&mut self, data_inst_def: &'a DataInstDef) {
+        let DataInstDef { attrs, form, inputs } = data_inst_def;
+        let form_def = &self.cx[*form];
+
+        if form_def.output_type.is_none() {
+            let has_annotations =
+                self.cx[*attrs].attrs.iter().any(|attr| matches!(attr, Attr::SpvAnnotation(_)));
+            if has_annotations {
+                self.error(spv::Error::malformed(
+                    "`OpDecorate`/`OpName`/etc. cannot target an instruction without an output",
+                ));
+            }
+        }
+
+        self.visit_attr_set_use(*attrs);
+        self.visit_data_inst_form_use(*form);
+
+        let is_spv_ext_inst = matches!(form_def.kind, DataInstKind::SpvExtInst { .. });
+        for v in inputs {
+            self.spv_string_literal_allowed = is_spv_ext_inst;
+            self.visit_value_use(v);
+        }
+        self.spv_string_literal_allowed = false;
+    }
+    fn visit_data_inst_form_def(&mut self, data_inst_form_def: &'a DataInstFormDef) {
         #[allow(clippy::match_same_arms)]
         match data_inst_form_def.kind {
-            // FIXME(eddyb) this should be a proper `Result`-based error instead,
-            // and/or `spv::lift` should mutate the module for legalization.
-            DataInstKind::QPtr(_) => {
-                unreachable!("`DataInstKind::QPtr` should be legalized away before lifting");
-            }
+            // Reported by `visit_data_inst_form_use` (for every use).
+            DataInstKind::QPtr(_) => {}
 
             DataInstKind::FuncCall(_) => {}
 
-            DataInstKind::SpvInst(_) => {}
+            DataInstKind::Pure(_) | DataInstKind::SpvInst(_) => {}
             DataInstKind::SpvExtInst { ext_set, .. } => {
                 self.ext_inst_imports.insert(&self.cx[ext_set]);
             }
@@ -363,6 +605,11 @@ impl<'a> NeedsIdsCollector<'a> {
             data_inst_forms_seen: _,
             global_vars_seen: _,
             funcs,
+            errors: _,
+            current_func: _,
+            current_data_inst: _,
+            current_global_var: _,
+            spv_string_literal_allowed: _,
         } = self;
 
         Ok(AllocatedIds {
@@ -522,8 +769,6 @@ impl<'a> FuncLifting<'a> {
         func_decl: &'a FuncDecl,
         mut alloc_id: impl FnMut() -> Result<spv::Id, E>,
     ) -> Result<Self, E> {
-        let wk = &spec::Spec::get().well_known;
-
         let func_id = alloc_id()?;
         let param_ids = func_decl.params.iter().map(|_| alloc_id()).collect::<Result<_, _>>()?;
 
@@ -666,7 +911,8 @@ impl<'a> FuncLifting<'a> {
                             merge: None,
                         }
                     } else {
-                        // Structured return out of the function body.
+                        // Structured return out of the function body
+                        // (any other region was rejected while visiting).
                         assert!(region == func_def_body.body);
                         Terminator {
                             attrs: AttrSet::default(),
@@ -758,14 +1004,7 @@ impl<'a> FuncLifting<'a> {
                                 .collect();
 
                             let is_infinite_loop = match repeat_condition {
-                                Value::Const(cond) => match &cx[cond].kind {
-                                    ConstKind::SpvInst { spv_inst_and_const_inputs } => {
-                                        let (spv_inst, _const_inputs) =
-                                            &**spv_inst_and_const_inputs;
-                                        spv_inst.opcode == wk.OpConstantTrue
-                                    }
-                                    _ => false,
-                                },
+                                Value::Const(cond) => matches!(cx[cond].kind, ConstKind::Scalar(1)),
 
                                 _ => false,
                             };
@@ -1036,7 +1275,9 @@ impl LazyInst<'_, '_> {
                                 };
                                 (gv_decl.attrs, import)
                             }
-                            ConstKind::SpvInst { .. } => (ct_def.attrs, None),
+                            ConstKind::Undef | ConstKind::Scalar(_) | ConstKind::SpvInst { .. } => {
+                                (ct_def.attrs, None)
+                            }
 
                             // Not inserted into `globals` while visiting.
                             ConstKind::SpvStringLiteralForExtInst(_) => unreachable!(),
@@ -1103,6 +1344,18 @@ impl LazyInst<'_, '_> {
         let inst = match self {
             Self::Global(global) => match global {
                 Global::Type(ty) => match &cx[ty].kind {
+                    &TypeKind::Scalar { kind, width } => spv::InstWithIds {
+                        without_ids: scalar_type_to_spv_inst(kind, width),
+                        result_type_id: None,
+                        result_id,
+                        ids: [].into_iter().collect(),
+                    },
+                    &TypeKind::Vector { elem, elem_count } => spv::InstWithIds {
+                        without_ids: vector_type_to_spv_inst(elem_count),
+                        result_type_id: None,
+                        result_id,
+                        ids: [ids.globals[&Global::Type(elem)]].into_iter().collect(),
+                    },
                     TypeKind::SpvInst { spv_inst, type_and_const_inputs } => spv::InstWithIds {
                         without_ids: spv_inst.clone(),
                         result_type_id: None,
@@ -1132,11 +1385,8 @@ impl LazyInst<'_, '_> {
                             assert!(ct_def.ty == gv_decl.type_of_ptr_to);
 
                             let storage_class = match gv_decl.addr_space {
-                                AddrSpace::Handles => {
-                                    unreachable!(
-                                        "`AddrSpace::Handles` should be legalized away before lifting"
-                                    );
-                                }
+                                // Rejected while visiting.
+                                AddrSpace::Handles => unreachable!(),
                                 AddrSpace::SpvStorageClass(sc) => {
                                     spv::Imm::Short(wk.StorageClass, sc)
                                 }
@@ -1157,6 +1407,27 @@ impl LazyInst<'_, '_> {
                             }
                         }
 
+                        ConstKind::Undef => spv::InstWithIds {
+                            without_ids: wk.OpUndef.into(),
+                            result_type_id: Some(ids.globals[&Global::Type(ct_def.ty)]),
+                            result_id,
+                            ids: [].into_iter().collect(),
+                        },
+
+                        &ConstKind::Scalar(bits) => {
+                            let (kind, width) = match cx[ct_def.ty].kind {
+                                TypeKind::Scalar { kind, width } => (kind, width),
+                                // Rejected while visiting.
+                                _ => unreachable!("`ConstKind::Scalar` of non-scalar type"),
+                            };
+                            spv::InstWithIds {
+                                without_ids: scalar_const_to_spv_inst(kind, width, bits),
+                                result_type_id: Some(ids.globals[&Global::Type(ct_def.ty)]),
+                                result_id,
+                                ids: [].into_iter().collect(),
+                            }
+                        }
+
                         ConstKind::SpvInst { spv_inst_and_const_inputs } => {
                             let (spv_inst, const_inputs) = &**spv_inst_and_const_inputs;
                             spv::InstWithIds {
@@ -1235,6 +1506,7 @@ impl LazyInst<'_, '_> {
                     &DataInstKind::FuncCall(callee) => {
                         (wk.OpFunctionCall.into(), Some(ids.funcs[&callee].func_id))
                     }
+                    &DataInstKind::Pure(op) => (pure_op_to_spv_inst(op), None),
                     DataInstKind::SpvInst(inst) => (inst.clone(), None),
                     &DataInstKind::SpvExtInst { ext_set, inst } => (
                         spv::Inst {
@@ -1324,12 +1596,28 @@ impl LazyInst<'_, '_> {
     }
 }
 
+// NOTE(eddyb) `ModuleEmitter::push_inst` can only fail if lifting produced an
+// invalid instruction, which is a bug in lifting (not caused by its input).
+fn push_inst(
+    emitter: &mut spv::write::ModuleEmitter,
+    inst: &spv::InstWithIds,
+) -> Result<(), spv::Errors> {
+    emitter.push_inst(inst).map_err(|err| spv::Error { kind: spv::ErrorKind::Bug, ..err }.into())
+}
+
 impl Module {
+    /// Lift to SPIR-V and write it to `path` (see [`Module::lift_to_spv_module_emitter`]),
+    /// with all lifting errors (if any) combined into one [`io::Error`].
     pub fn lift_to_spv_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
         self.lift_to_spv_module_emitter()?.write_to_spv_file(path)
     }
 
-    pub fn lift_to_spv_module_emitter(&self) -> io::Result<spv::write::ModuleEmitter> {
+    /// Lift to SPIR-V, after checking that all the (reachable) SPIR-T constructs
+    /// can be lifted, returning an error for each one that can't (e.g. any uses
+    /// of [`TypeKind::QPtr`], which have to first be legalized by
+    /// [`passes::qptr::lift_to_spv_ptrs`](crate::passes::qptr::lift_to_spv_ptrs)),
+    /// pointing at the offending [`Func`]/[`DataInst`]/[`GlobalVar`].
+    pub fn lift_to_spv_module_emitter(&self) -> Result<spv::write::ModuleEmitter, spv::Errors> {
         let spv_spec = spec::Spec::get();
         let wk = &spv_spec.well_known;
 
@@ -1343,7 +1631,7 @@ impl Module {
             // `spv::Dialect`, or by taking it as additional input.
             #[allow(unreachable_patterns)]
             _ => {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "not a SPIR-V module"));
+                return Err(spv::Error::unsupported("not a SPIR-V module").into());
             }
         };
 
@@ -1357,8 +1645,16 @@ impl Module {
             data_inst_forms_seen: FxIndexSet::default(),
             global_vars_seen: FxIndexSet::default(),
             funcs: FxIndexSet::default(),
+            errors: vec![],
+            current_func: None,
+            current_data_inst: None,
+            current_global_var: None,
+            spv_string_literal_allowed: false,
         };
         needs_ids_collector.visit_module(self);
+        if !needs_ids_collector.errors.is_empty() {
+            return Err(spv::Errors(needs_ids_collector.errors));
+        }
 
         // Because `GlobalVar`s are given IDs by the `Const`s that point to them
         // (i.e. `ConstKind::PtrToGlobalVar`), any `GlobalVar`s in other positions
@@ -1387,10 +1683,9 @@ impl Module {
                     id_bound = new_bound;
                     Ok(id)
                 }
-                None => Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
+                None => Err(spv::Errors::from(spv::Error::unsupported(
                     "ID bound of SPIR-V module doesn't fit in 32 bits",
-                )),
+                ))),
             }
         })?;
 
@@ -1466,36 +1761,42 @@ impl Module {
         let mut emitter = spv::write::ModuleEmitter::with_header(header);
 
         for cap_inst in dialect.capability_insts() {
-            emitter.push_inst(&cap_inst)?;
+            push_inst(&mut emitter, &cap_inst)?;
         }
         for ext_inst in dialect.extension_insts() {
-            emitter.push_inst(&ext_inst)?;
+            push_inst(&mut emitter, &ext_inst)?;
         }
         for (&name, &id) in &ids.ext_inst_imports {
-            emitter.push_inst(&spv::InstWithIds {
+            push_inst(
+                &mut emitter,
+                &spv::InstWithIds {
+                    without_ids: spv::Inst {
+                        opcode: wk.OpExtInstImport,
+                        imms: spv::encode_literal_string(name).collect(),
+                    },
+                    result_type_id: None,
+                    result_id: Some(id),
+                    ids: [].into_iter().collect(),
+                },
+            )?;
+        }
+        push_inst(
+            &mut emitter,
+            &spv::InstWithIds {
                 without_ids: spv::Inst {
-                    opcode: wk.OpExtInstImport,
-                    imms: spv::encode_literal_string(name).collect(),
+                    opcode: wk.OpMemoryModel,
+                    imms: [
+                        spv::Imm::Short(wk.AddressingModel, dialect.addressing_model),
+                        spv::Imm::Short(wk.MemoryModel, dialect.memory_model),
+                    ]
+                    .into_iter()
+                    .collect(),
                 },
                 result_type_id: None,
-                result_id: Some(id),
+                result_id: None,
                 ids: [].into_iter().collect(),
-            })?;
-        }
-        emitter.push_inst(&spv::InstWithIds {
-            without_ids: spv::Inst {
-                opcode: wk.OpMemoryModel,
-                imms: [
-                    spv::Imm::Short(wk.AddressingModel, dialect.addressing_model),
-                    spv::Imm::Short(wk.MemoryModel, dialect.memory_model),
-                ]
-                .into_iter()
-                .collect(),
             },
-            result_type_id: None,
-            result_id: None,
-            ids: [].into_iter().collect(),
-        })?;
+        )?;
 
         // Collect the various sources of attributes.
         let mut entry_point_insts = vec![];
@@ -1513,10 +1814,9 @@ impl Module {
                     | Attr::SpvDebugLine { .. }
                     | Attr::SpvBitflagsOperand(_) => {}
                     Attr::SpvAnnotation(inst @ spv::Inst { opcode, .. }) => {
-                        let target_id = result_id.expect(
-                            "FIXME: it shouldn't be possible to attach \
-                                 attributes to instructions without an output",
-                        );
+                        // NOTE(eddyb) annotations on instructions without
+                        // an output were rejected while visiting.
+                        let target_id = result_id.unwrap();
 
                         let inst = spv::InstWithIds {
                             without_ids: inst.clone(),
@@ -1602,22 +1902,25 @@ impl Module {
 
         // FIXME(eddyb) maybe make a helper for `push_inst` with an iterator?
         for entry_point_inst in entry_point_insts {
-            emitter.push_inst(&entry_point_inst)?;
+            push_inst(&mut emitter, &entry_point_inst)?;
         }
         for execution_mode_inst in execution_mode_insts {
-            emitter.push_inst(&execution_mode_inst)?;
+            push_inst(&mut emitter, &execution_mode_inst)?;
         }
 
         for (&s, &id) in &ids.debug_strings {
-            emitter.push_inst(&spv::InstWithIds {
-                without_ids: spv::Inst {
-                    opcode: wk.OpString,
-                    imms: spv::encode_literal_string(s).collect(),
+            push_inst(
+                &mut emitter,
+                &spv::InstWithIds {
+                    without_ids: spv::Inst {
+                        opcode: wk.OpString,
+                        imms: spv::encode_literal_string(s).collect(),
+                    },
+                    result_type_id: None,
+                    result_id: Some(id),
+                    ids: [].into_iter().collect(),
                 },
-                result_type_id: None,
-                result_id: Some(id),
-                ids: [].into_iter().collect(),
-            })?;
+            )?;
         }
         for (lang, sources) in &debug_info.source_languages {
             let lang_imms = || {
@@ -1628,12 +1931,15 @@ impl Module {
                 .into_iter()
             };
             if sources.file_contents.is_empty() {
-                emitter.push_inst(&spv::InstWithIds {
-                    without_ids: spv::Inst { opcode: wk.OpSource, imms: lang_imms().collect() },
-                    result_type_id: None,
-                    result_id: None,
-                    ids: [].into_iter().collect(),
-                })?;
+                push_inst(
+                    &mut emitter,
+                    &spv::InstWithIds {
+                        without_ids: spv::Inst { opcode: wk.OpSource, imms: lang_imms().collect() },
+                        result_type_id: None,
+                        result_id: None,
+                        ids: [].into_iter().collect(),
+                    },
+                )?;
             } else {
                 for (&file, contents) in &sources.file_contents {
                     // The maximum word count is `2**16 - 1`, the first word is
@@ -1649,17 +1955,20 @@ impl Module {
                     let (contents_initial, mut contents_rest) =
                         contents.split_at(contents.len().min(MAX_OP_SOURCE_CONTENTS_LEN));
 
-                    emitter.push_inst(&spv::InstWithIds {
-                        without_ids: spv::Inst {
-                            opcode: wk.OpSource,
-                            imms: lang_imms()
-                                .chain(spv::encode_literal_string(contents_initial))
-                                .collect(),
+                    push_inst(
+                        &mut emitter,
+                        &spv::InstWithIds {
+                            without_ids: spv::Inst {
+                                opcode: wk.OpSource,
+                                imms: lang_imms()
+                                    .chain(spv::encode_literal_string(contents_initial))
+                                    .collect(),
+                            },
+                            result_type_id: None,
+                            result_id: None,
+                            ids: iter::once(ids.debug_strings[&cx[file]]).collect(),
                         },
-                        result_type_id: None,
-                        result_id: None,
-                        ids: iter::once(ids.debug_strings[&cx[file]]).collect(),
-                    })?;
+                    )?;
 
                     while !contents_rest.is_empty() {
                         // FIXME(eddyb) test with UTF-8! this `split_at` should
@@ -1669,31 +1978,34 @@ impl Module {
                             .split_at(contents_rest.len().min(MAX_OP_SOURCE_CONT_CONTENTS_LEN));
                         contents_rest = rest;
 
-                        emitter.push_inst(&spv::InstWithIds {
-                            without_ids: spv::Inst {
-                                opcode: wk.OpSourceContinued,
-                                imms: spv::encode_literal_string(cont_chunk).collect(),
+                        push_inst(
+                            &mut emitter,
+                            &spv::InstWithIds {
+                                without_ids: spv::Inst {
+                                    opcode: wk.OpSourceContinued,
+                                    imms: spv::encode_literal_string(cont_chunk).collect(),
+                                },
+                                result_type_id: None,
+                                result_id: None,
+                                ids: [].into_iter().collect(),
                             },
-                            result_type_id: None,
-                            result_id: None,
-                            ids: [].into_iter().collect(),
-                        })?;
+                        )?;
                     }
                 }
             }
         }
         for ext_inst in debug_info.source_extension_insts() {
-            emitter.push_inst(&ext_inst)?;
+            push_inst(&mut emitter, &ext_inst)?;
         }
         for debug_name_inst in debug_name_insts {
-            emitter.push_inst(&debug_name_inst)?;
+            push_inst(&mut emitter, &debug_name_inst)?;
         }
         for mod_proc_inst in debug_info.module_processed_insts() {
-            emitter.push_inst(&mod_proc_inst)?;
+            push_inst(&mut emitter, &mod_proc_inst)?;
         }
 
         for decoration_inst in decoration_insts {
-            emitter.push_inst(&decoration_inst)?;
+            push_inst(&mut emitter, &decoration_inst)?;
         }
 
         let mut current_debug_line = None;
@@ -1738,16 +2050,19 @@ impl Module {
                     ),
                     None => (wk.OpNoLine, [].into_iter().collect(), [].into_iter().collect()),
                 };
-                emitter.push_inst(&spv::InstWithIds {
-                    without_ids: spv::Inst { opcode, imms },
-                    result_type_id: None,
-                    result_id: None,
-                    ids,
-                })?;
+                push_inst(
+                    &mut emitter,
+                    &spv::InstWithIds {
+                        without_ids: spv::Inst { opcode, imms },
+                        result_type_id: None,
+                        result_id: None,
+                        ids,
+                    },
+                )?;
             }
             current_debug_line = new_debug_line;
 
-            emitter.push_inst(&inst)?;
+            push_inst(&mut emitter, &inst)?;
         }
 
         Ok(emitter)
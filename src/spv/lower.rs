@@ -7,7 +7,8 @@ use crate::{
     ControlNodeKind, ControlRegion, ControlRegionDef, ControlRegionInputDecl, DataInstDef,
     DataInstFormDef, DataInstKind, DeclDef, Diag, EntityDefs, EntityList, ExportKey, Exportee,
     Func, FuncDecl, FuncDefBody, FuncParam, FxIndexMap, GlobalVarDecl, GlobalVarDefBody, Import,
-    InternedStr, Module, SelectionKind, Type, TypeDef, TypeKind, TypeOrConst, Value,
+    InternedStr, Module, PureOp, ScalarKind, SelectionKind, Type, TypeDef, TypeKind, TypeOrConst,
+    Value,
 };
 use rustc_hash::FxHashMap;
 use smallvec::SmallVec;
@@ -78,11 +79,119 @@ struct IntraFuncInst {
 
     // FIXME(eddyb) change the inline size of this to fit most instructions.
     ids: SmallVec<[spv::Id; 4]>,
+
+    // Location in the original SPIR-V module (only used for errors).
+    inst_index: usize,
+    word_offset: usize,
+}
+
+fn invalid(reason: &str) -> spv::Error {
+    spv::Error::malformed(reason.to_string())
+}
+
+fn unsupported(reason: &str) -> spv::Error {
+    spv::Error::unsupported(reason.to_string())
 }
 
-// FIXME(eddyb) stop abusing `io::Error` for error reporting.
-fn invalid(reason: &str) -> io::Error {
-    io::Error::new(io::ErrorKind::InvalidData, format!("malformed SPIR-V ({reason})"))
+/// Convert a SPIR-V `OpTypeBool`/`OpTypeInt`/`OpTypeFloat` instruction into
+/// a [`TypeKind::Scalar`], unless that would lose information.
+pub(crate) fn scalar_type_from_spv_inst(spv_inst: &spv::Inst) -> Option<TypeKind> {
+    let wk = &spec::Spec::get().well_known;
+
+    let &spv::Inst { opcode, ref imms } = spv_inst;
+    let (kind, width) = if opcode == wk.OpTypeBool {
+        (ScalarKind::Bool, 1)
+    } else if opcode == wk.OpTypeInt {
+        match imms[..] {
+            [spv::Imm::Short(_, width), spv::Imm::Short(_, 0)] => (ScalarKind::UInt, width),
+            [spv::Imm::Short(_, width), spv::Imm::Short(_, 1)] => (ScalarKind::SInt, width),
+            _ => return None,
+        }
+    } else if opcode == wk.OpTypeFloat {
+        // NOTE(eddyb) this intentionally rejects any `FPEncoding` operand.
+        match imms[..] {
+            [spv::Imm::Short(_, width)] => (ScalarKind::Float, width),
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+
+    let roundtrips = width > 0 && spv::lift::scalar_type_to_spv_inst(kind, width) == *spv_inst;
+    roundtrips.then_some(TypeKind::Scalar { kind, width })
+}
+
+/// Convert a SPIR-V `OpConstantFalse`/`OpConstantTrue`/`OpConstant` instruction
+/// (of type `ty`) into a [`ConstKind::Scalar`], unless that would lose information
+/// (including non-canonical encodings, e.g. missing sign-extension).
+pub(crate) fn scalar_const_from_spv_inst(
+    cx: &Context,
+    ty: Type,
+    spv_inst: &spv::Inst,
+) -> Option<ConstKind> {
+    let wk = &spec::Spec::get().well_known;
+
+    let (kind, width) = match cx[ty].kind {
+        TypeKind::Scalar { kind, width } if width <= 64 => (kind, width),
+        _ => return None,
+    };
+
+    let &spv::Inst { opcode, ref imms } = spv_inst;
+    let bits = if opcode == wk.OpConstantFalse {
+        0
+    } else if opcode == wk.OpConstantTrue {
+        1
+    } else if opcode == wk.OpConstant {
+        let raw_bits = match imms[..] {
+            [spv::Imm::Short(_, x)] => u64::from(x),
+            [spv::Imm::LongStart(_, lo), spv::Imm::LongCont(_, hi)] => {
+                u64::from(lo) | (u64::from(hi) << 32)
+            }
+            _ => return None,
+        };
+        raw_bits & (u64::MAX >> (64 - width))
+    } else {
+        return None;
+    };
+
+    let roundtrips = spv::lift::scalar_const_to_spv_inst(kind, width, bits) == *spv_inst;
+    roundtrips.then_some(ConstKind::Scalar(bits))
+}
+
+/// Convert a SPIR-V `OpTypeVector` instruction (with `elem` as its only ID
+/// operand) into a [`TypeKind::Vector`], unless that would lose information.
+pub(crate) fn vector_type_from_spv_inst(
+    cx: &Context,
+    spv_inst: &spv::Inst,
+    elem: Type,
+) -> Option<TypeKind> {
+    let wk = &spec::Spec::get().well_known;
+
+    if spv_inst.opcode != wk.OpTypeVector || !matches!(cx[elem].kind, TypeKind::Scalar { .. }) {
+        return None;
+    }
+    let elem_count = match spv_inst.imms[..] {
+        [spv::Imm::Short(_, elem_count)] => elem_count,
+        _ => return None,
+    };
+
+    let roundtrips = spv::lift::vector_type_to_spv_inst(elem_count) == *spv_inst;
+    roundtrips.then_some(TypeKind::Vector { elem, elem_count })
+}
+
+/// Convert a SPIR-V instruction into the [`PureOp`] of the same name, if any
+/// (and unless that would lose information).
+pub(crate) fn pure_op_from_spv_inst(spv_inst: &spv::Inst) -> Option<PureOp> {
+    let name = spv_inst.opcode.name().strip_prefix("Op")?;
+    let op = match (name, &spv_inst.imms[..]) {
+        ("CompositeExtract", &[spv::Imm::Short(_, idx)]) => PureOp::CompositeExtract { idx },
+        ("CompositeInsert", &[spv::Imm::Short(_, idx)]) => PureOp::CompositeInsert { idx },
+        (_, []) => PureOp::from_name_without_imms(name)?,
+        _ => return None,
+    };
+
+    let roundtrips = spv::lift::pure_op_to_spv_inst(op) == *spv_inst;
+    roundtrips.then_some(op)
 }
 
 // FIXME(eddyb) provide more information about any normalization that happened:
@@ -92,11 +201,20 @@ fn invalid(reason: &str) -> io::Error {
 // (and more directproducers) can keep around errors in the SPIR-T IR, and still
 // have the opportunity of silencing them e.g. by removing dead code.
 impl Module {
+    /// Read and lower a SPIR-V module from a file, which is assumed to contain
+    /// the SPIR-V binary form, unless it has the `.spvasm` extension (in which
+    /// case it's assembled from the textual form, see [`spv::read::ModuleParser`]).
     pub fn lower_from_spv_file(cx: Rc<Context>, path: impl AsRef<Path>) -> io::Result<Self> {
-        Self::lower_from_spv_module_parser(cx, spv::read::ModuleParser::read_from_spv_file(path)?)
+        let path = path.as_ref();
+        let parser = if path.extension().is_some_and(|ext| ext == "spvasm") {
+            spv::read::ModuleParser::read_from_spvasm_file(path)?
+        } else {
+            spv::read::ModuleParser::read_from_spv_file(path)?
+        };
+        Ok(Self::lower_from_spv_module_parser(cx, parser)?)
     }
 
-    pub fn lower_from_spv_bytes(cx: Rc<Context>, spv_bytes: Vec<u8>) -> io::Result<Self> {
+    pub fn lower_from_spv_bytes(cx: Rc<Context>, spv_bytes: Vec<u8>) -> Result<Self, spv::Error> {
         Self::lower_from_spv_module_parser(
             cx,
             spv::read::ModuleParser::read_from_spv_bytes(spv_bytes)?,
@@ -106,7 +224,7 @@ impl Module {
     pub fn lower_from_spv_module_parser(
         cx: Rc<Context>,
         parser: spv::read::ModuleParser,
-    ) -> io::Result<Self> {
+    ) -> Result<Self, spv::Error> {
         let spv_spec = spec::Spec::get();
         let wk = &spv_spec.well_known;
 
@@ -191,11 +309,20 @@ impl Module {
         let mut pending_func_bodies = vec![];
         let mut current_func_body = None;
 
+        let (mut next_inst_index, mut next_word_offset) = (0, spec::HEADER_LEN);
         let mut spv_insts = parser.peekable();
         while let Some(mut inst) = spv_insts.next().transpose()? {
             let opcode = inst.opcode;
 
-            let invalid = |msg: &str| invalid(&format!("in {}: {}", opcode.name(), msg));
+            let (inst_index, word_offset) = (next_inst_index, next_word_offset);
+            next_inst_index += 1;
+            next_word_offset += inst.word_count();
+
+            let at_inst = |err: spv::Error| {
+                err.with_opcode(opcode).with_inst_location(inst_index, word_offset)
+            };
+            let invalid = |msg: &str| at_inst(invalid(msg));
+            let unsupported = |msg: &str| at_inst(unsupported(msg));
 
             // Handle line debuginfo early, as it doesn't have its own section,
             // but rather can go almost anywhere among globals and functions.
@@ -214,7 +341,8 @@ impl Module {
                                 _ => {
                                     return Err(invalid(&format!(
                                         "%{file_path_id} is not an OpString"
-                                    )));
+                                    ))
+                                    .with_id(file_path_id));
                                 }
                             };
                             Some((file_path, line, col))
@@ -273,18 +401,20 @@ impl Module {
 
             // FIXME(eddyb) move this kind of lookup into methods on some sort
             // of "lowering context" type.
-            let result_type = inst
-                .result_type_id
-                .map(|type_id| match id_defs.get(&type_id) {
-                    Some(&IdDef::Type(ty)) => Ok(ty),
-                    Some(id_def) => Err(invalid(&format!(
-                        "result type %{} should be a type, not a {}",
-                        type_id,
-                        id_def.descr(&cx)
-                    ))),
-                    None => Err(invalid(&format!("result type %{type_id} not defined"))),
-                })
-                .transpose()?;
+            let result_type =
+                inst.result_type_id
+                    .map(|type_id| match id_defs.get(&type_id) {
+                        Some(&IdDef::Type(ty)) => Ok(ty),
+                        Some(id_def) => Err(invalid(&format!(
+                            "result type %{} should be a type, not a {}",
+                            type_id,
+                            id_def.descr(&cx)
+                        ))
+                        .with_id(type_id)),
+                        None => Err(invalid(&format!("result type %{type_id} not defined"))
+                            .with_id(type_id)),
+                    })
+                    .transpose()?;
 
             let inst_category = spv_spec.instructions[opcode].category;
 
@@ -388,7 +518,8 @@ impl Module {
                             _ => {
                                 return Err(invalid(&format!(
                                     "%{file_path_id} is not an OpString"
-                                )));
+                                ))
+                                .with_id(file_path_id));
                             }
                         };
                         let mut contents = if contents.is_empty() {
@@ -404,6 +535,8 @@ impl Module {
                                 break;
                             }
                             let cont_inst = spv_insts.next().unwrap().unwrap();
+                            next_inst_index += 1;
+                            next_word_offset += cont_inst.word_count();
 
                             assert!(
                                 cont_inst.result_type_id.is_none()
@@ -489,7 +622,7 @@ impl Module {
 
                 let target_id = inst.ids[0];
                 if inst.ids.len() > 1 {
-                    return Err(invalid("unsupported decoration with ID"));
+                    return Err(unsupported("decoration with ID"));
                 }
 
                 match inst.imms[..] {
@@ -533,7 +666,7 @@ impl Module {
             } else if [wk.OpDecorationGroup, wk.OpGroupDecorate, wk.OpGroupMemberDecorate]
                 .contains(&opcode)
             {
-                return Err(invalid("unsupported decoration groups (officially deprecated)"));
+                return Err(unsupported("decoration groups (officially deprecated)"));
             } else if opcode == wk.OpTypeForwardPointer {
                 assert!(inst.result_type_id.is_none() && inst.result_id.is_none());
                 let (id, sc) = match (&inst.imms[..], &inst.ids[..]) {
@@ -567,20 +700,38 @@ impl Module {
                         None => Err(format!("a forward reference to %{id}")),
                     })
                     .map(|result| {
-                        result.map_err(|descr| {
-                            invalid(&format!("unsupported use of {descr} in a type"))
-                        })
+                        result.map_err(|descr| unsupported(&format!("use of {descr} in a type")))
                     })
-                    .collect::<Result<_, _>>()?;
+                    .collect::<Result<SmallVec<_>, _>>()?;
 
-                let ty = cx.intern(TypeDef {
-                    attrs: mem::take(&mut attrs),
-                    kind: TypeKind::SpvInst { spv_inst: inst.without_ids, type_and_const_inputs },
+                let native_kind = match type_and_const_inputs[..] {
+                    [] => scalar_type_from_spv_inst(&inst.without_ids),
+                    [TypeOrConst::Type(elem)] => {
+                        vector_type_from_spv_inst(&cx, &inst.without_ids, elem)
+                    }
+                    _ => None,
+                };
+                let kind = native_kind.unwrap_or(TypeKind::SpvInst {
+                    spv_inst: inst.without_ids,
+                    type_and_const_inputs,
                 });
+                let ty = cx.intern(TypeDef { attrs: mem::take(&mut attrs), kind });
                 id_defs.insert(id, IdDef::Type(ty));
 
                 Seq::TypeConstOrGlobalVar
-            } else if inst_category == spec::InstructionCategory::Const || opcode == wk.OpUndef {
+            } else if opcode == wk.OpUndef {
+                let id = inst.result_id.unwrap();
+                let ct = cx.intern(ConstDef {
+                    attrs: mem::take(&mut attrs),
+                    ty: result_type.unwrap(),
+                    kind: ConstKind::Undef,
+                });
+                id_defs.insert(id, IdDef::Const(ct));
+
+                // `OpUndef` can appear either among constants, or in a
+                // function, so at most advance `seq` to globals.
+                seq.max(Some(Seq::TypeConstOrGlobalVar)).unwrap()
+            } else if inst_category == spec::InstructionCategory::Const {
                 let id = inst.result_id.unwrap();
                 let const_inputs = inst
                     .ids
@@ -591,28 +742,22 @@ impl Module {
                         None => Err(format!("a forward reference to %{id}")),
                     })
                     .map(|result| {
-                        result.map_err(|descr| {
-                            invalid(&format!("unsupported use of {descr} in a constant"))
-                        })
+                        result
+                            .map_err(|descr| unsupported(&format!("use of {descr} in a constant")))
                     })
                     .collect::<Result<_, _>>()?;
 
-                let ct = cx.intern(ConstDef {
-                    attrs: mem::take(&mut attrs),
-                    ty: result_type.unwrap(),
-                    kind: ConstKind::SpvInst {
-                        spv_inst_and_const_inputs: Rc::new((inst.without_ids, const_inputs)),
-                    },
-                });
+                let ty = result_type.unwrap();
+                let kind =
+                    scalar_const_from_spv_inst(&cx, ty, &inst.without_ids).unwrap_or_else(|| {
+                        ConstKind::SpvInst {
+                            spv_inst_and_const_inputs: Rc::new((inst.without_ids, const_inputs)),
+                        }
+                    });
+                let ct = cx.intern(ConstDef { attrs: mem::take(&mut attrs), ty, kind });
                 id_defs.insert(id, IdDef::Const(ct));
 
-                if opcode == wk.OpUndef {
-                    // `OpUndef` can appear either among constants, or in a
-                    // function, so at most advance `seq` to globals.
-                    seq.max(Some(Seq::TypeConstOrGlobalVar)).unwrap()
-                } else {
-                    Seq::TypeConstOrGlobalVar
-                }
+                Seq::TypeConstOrGlobalVar
             } else if opcode == wk.OpVariable && current_func_body.is_none() {
                 let global_var_id = inst.result_id.unwrap();
                 let type_of_ptr_to_global_var = result_type.unwrap();
@@ -642,8 +787,8 @@ impl Module {
                     })
                     .transpose()
                     .map_err(|descr| {
-                        invalid(&format!(
-                            "unsupported use of {descr} as the initializer of a global variable"
+                        unsupported(&format!(
+                            "use of {descr} as the initializer of a global variable"
                         ))
                     })?;
 
@@ -714,6 +859,7 @@ impl Module {
                     }
                     .ok_or_else(|| {
                         invalid(&format!("function type %{func_type_id} not an `OpTypeFunction`"))
+                            .with_id(func_type_id)
                     })?;
 
                 if func_ret_type != func_type_ret_type {
@@ -734,7 +880,8 @@ impl Module {
                         )
                         .pretty_print()
                         .to_string(),
-                    ));
+                    )
+                    .with_id(func_id));
                 }
 
                 let def = match pending_imports.remove(&func_id) {
@@ -792,6 +939,9 @@ impl Module {
                     without_ids: spv::Inst { opcode, imms: inst.without_ids.imms },
                     result_id: inst.result_id,
                     ids: inst.ids,
+
+                    inst_index,
+                    word_offset,
                 });
 
                 Seq::Function
@@ -972,7 +1122,8 @@ impl Module {
                         return Err(invalid(&format!(
                             "non-empty function %{} decorated as `Import` of {:?}",
                             func_id, &cx[*name]
-                        )));
+                        ))
+                        .with_id(func_id));
                     }
                     DeclDef::Present(def) => Some(def),
                 }
@@ -985,7 +1136,8 @@ impl Module {
                         return Err(invalid(&format!(
                             "function %{func_id} lacks any blocks, \
                              but isn't an import either"
-                        )));
+                        ))
+                        .with_id(func_id));
                     }
                 }
 
@@ -1003,22 +1155,28 @@ impl Module {
                     without_ids: spv::Inst { opcode, ref imms },
                     result_id,
                     ref ids,
+                    inst_index,
+                    word_offset,
                 } = *raw_inst;
 
-                let invalid = |msg: &str| invalid(&format!("in {}: {}", opcode.name(), msg));
+                let at_inst = |err: spv::Error| {
+                    err.with_opcode(opcode).with_inst_location(inst_index, word_offset)
+                };
+                let invalid = |msg: &str| at_inst(invalid(msg));
+                let unsupported = |msg: &str| at_inst(unsupported(msg));
 
                 // FIXME(eddyb) find a more compact name and/or make this a method.
                 // FIXME(eddyb) this returns `LocalIdDef` even for global values.
                 let lookup_global_or_local_id_for_data_or_control_inst_input =
                     |id| match id_defs.get(&id) {
                         Some(&IdDef::Const(ct)) => Ok(LocalIdDef::Value(Value::Const(ct))),
-                        Some(id_def @ IdDef::Type(_)) => Err(invalid(&format!(
-                            "unsupported use of {} as an operand for \
+                        Some(id_def @ IdDef::Type(_)) => Err(unsupported(&format!(
+                            "use of {} as an operand for \
                              an instruction in a function",
                             id_def.descr(&cx),
                         ))),
-                        Some(id_def @ IdDef::Func(_)) => Err(invalid(&format!(
-                            "unsupported use of {} outside `OpFunctionCall`",
+                        Some(id_def @ IdDef::Func(_)) => Err(unsupported(&format!(
+                            "use of {} outside `OpFunctionCall`",
                             id_def.descr(&cx),
                         ))),
                         Some(id_def @ IdDef::SpvDebugString(s)) => {
@@ -1033,21 +1191,21 @@ impl Module {
                                 });
                                 Ok(LocalIdDef::Value(Value::Const(ct)))
                             } else {
-                                Err(invalid(&format!(
-                                    "unsupported use of {} outside `OpSource`, \
+                                Err(unsupported(&format!(
+                                    "use of {} outside `OpSource`, \
                                      `OpLine`, or `OpExtInst`",
                                     id_def.descr(&cx),
                                 )))
                             }
                         }
-                        Some(id_def @ IdDef::SpvExtInstImport(_)) => Err(invalid(&format!(
-                            "unsupported use of {} outside `OpExtInst`",
+                        Some(id_def @ IdDef::SpvExtInstImport(_)) => Err(unsupported(&format!(
+                            "use of {} outside `OpExtInst`",
                             id_def.descr(&cx),
                         ))),
                         None => local_id_defs
                             .get(&id)
                             .copied()
-                            .ok_or_else(|| invalid(&format!("undefined ID %{id}",))),
+                            .ok_or_else(|| invalid(&format!("undefined ID %{id}")).with_id(id)),
                     };
 
                 if opcode == wk.OpFunctionParameter {
@@ -1123,13 +1281,13 @@ impl Module {
                     let phi_value_id_to_value = |phi_key: &PhiKey, id| {
                         match lookup_global_or_local_id_for_data_or_control_inst_input(id)? {
                             LocalIdDef::Value(v) => Ok(v),
-                            LocalIdDef::BlockLabel { .. } => Err(invalid(&format!(
-                                "unsupported use of block label as the value for {}",
+                            LocalIdDef::BlockLabel { .. } => Err(unsupported(&format!(
+                                "use of block label as the value for {}",
                                 descr_phi_case(phi_key)
                             ))),
                         }
                     };
-                    let mut record_cfg_edge = |target_block| -> io::Result<()> {
+                    let mut record_cfg_edge = |target_block| -> Result<(), spv::Error> {
                         use indexmap::map::Entry;
 
                         let target_block_details = &block_details[&target_block];
@@ -1213,7 +1371,7 @@ impl Module {
                             raw_inst.without_ids.clone(),
                         ))
                     } else {
-                        return Err(invalid("unsupported control-flow instruction"));
+                        return Err(unsupported("control-flow instruction"));
                     };
 
                     func_def_body
@@ -1285,8 +1443,8 @@ impl Module {
                             })
                             .transpose()
                             .map_err(|descr| {
-                                invalid(&format!(
-                                    "unsupported use of {descr} as the `OpFunctionCall` callee"
+                                unsupported(&format!(
+                                    "use of {descr} as the `OpFunctionCall` callee"
                                 ))
                             })?;
 
@@ -1318,15 +1476,30 @@ impl Module {
                             None => Err(format!("unknown ID %{ext_set_id}")),
                         }
                         .map_err(|descr| {
-                            invalid(&format!(
-                                "unsupported use of {descr} as the `OpExtInst` \
+                            unsupported(&format!(
+                                "use of {descr} as the `OpExtInst` \
                                  extended instruction set ID"
                             ))
+                            .with_id(ext_set_id)
                         })?;
 
                         DataInstKind::SpvExtInst { ext_set, inst }
                     } else {
-                        DataInstKind::SpvInst(raw_inst.without_ids.clone())
+                        // NOTE(eddyb) `PureOp`s are limited to scalar/vector
+                        // results (e.g. integer-to-pointer `OpBitcast`s remain
+                        // `SpvInst`s), but pointer-to-integer `OpBitcast`s
+                        // are `PureOp::Bitcast`s with a pointer input (which
+                        // `qptr::lower` handles like any other pointer input).
+                        let has_scalar_or_vector_output = result_type.is_some_and(|ty| {
+                            matches!(cx[ty].kind, TypeKind::Scalar { .. } | TypeKind::Vector { .. })
+                        });
+                        has_scalar_or_vector_output
+                            .then(|| pure_op_from_spv_inst(&raw_inst.without_ids))
+                            .flatten()
+                            .map_or_else(
+                                || DataInstKind::SpvInst(raw_inst.without_ids.clone()),
+                                DataInstKind::Pure,
+                            )
                     };
 
                     let data_inst_def = DataInstDef {
@@ -1350,13 +1523,13 @@ impl Module {
                                 match lookup_global_or_local_id_for_data_or_control_inst_input(id)?
                                 {
                                     LocalIdDef::Value(v) => Ok(v),
-                                    LocalIdDef::BlockLabel { .. } => Err(invalid(
-                                        "unsupported use of block label as a value, \
+                                    LocalIdDef::BlockLabel { .. } => Err(unsupported(
+                                        "use of block label as a value, \
                                          in non-terminator instruction",
                                     )),
                                 }
                             })
-                            .collect::<io::Result<_>>()?,
+                            .collect::<Result<_, _>>()?,
                     };
                     let inst = match result_id {
                         Some(id) => match local_id_defs[&id] {
@@ -1417,7 +1590,8 @@ impl Module {
                         func_id,
                         func_decl.params.len(),
                         params.len(),
-                    )));
+                    ))
+                    .with_id(func_id));
                 }
 
                 for (i, (func_decl_param, param)) in
@@ -1443,7 +1617,8 @@ impl Module {
                             )
                             .pretty_print()
                             .to_string(),
-                        ));
+                        )
+                        .with_id(func_id));
                     }
                 }
             }
@@ -1460,7 +1635,8 @@ impl Module {
                     "in %{}, `OpPhi`s refer to non-existent edges: {}",
                     func_id,
                     edges.join(", ")
-                )));
+                ))
+                .with_id(func_id));
             }
 
             // Sanity-check the entry block.
@@ -1470,7 +1646,8 @@ impl Module {
                     // `let invalid = |...| ...;` closure that wraps insts.
                     return Err(invalid(&format!(
                         "in %{func_id}, the entry block contains `OpPhi`s"
-                    )));
+                    ))
+                    .with_id(func_id));
                 }
             }
         }
@@ -1490,28 +1667,22 @@ impl Module {
                         None => Err(format!("unknown ID %{target_id}")),
                     }
                     .map_err(|descr| {
-                        invalid(&format!(
-                            "unsupported use of {descr} as the `LinkageAttributes` target"
-                        ))
+                        unsupported(&format!("use of {descr} as the `LinkageAttributes` target"))
+                            .with_id(target_id)
                     })?;
 
                     Ok((ExportKey::LinkName(name), exportee))
                 }
 
-                Export::EntryPoint {
-                    func_id,
-                    imms,
-                    interface_ids,
-                } => {
+                Export::EntryPoint { func_id, imms, interface_ids } => {
                     let func = match id_defs.get(&func_id) {
                         Some(&IdDef::Func(func)) => Ok(func),
                         Some(id_def) => Err(id_def.descr(&cx)),
                         None => Err(format!("unknown ID %{func_id}")),
                     }
                     .map_err(|descr| {
-                        invalid(&format!(
-                            "unsupported use of {descr} as the `OpEntryPoint` target"
-                        ))
+                        unsupported(&format!("use of {descr} as the `OpEntryPoint` target"))
+                            .with_id(func_id)
                     })?;
                     let interface_global_vars = interface_ids
                         .into_iter()
@@ -1525,22 +1696,19 @@ impl Module {
                         })
                         .map(|result| {
                             result.map_err(|descr| {
-                                invalid(&format!(
-                                    "unsupported use of {descr} as an `OpEntryPoint` interface variable"
+                                unsupported(&format!(
+                                    "use of {descr} as an `OpEntryPoint` interface variable"
                                 ))
                             })
                         })
                         .collect::<Result<_, _>>()?;
                     Ok((
-                        ExportKey::SpvEntryPoint {
-                            imms,
-                            interface_global_vars,
-                        },
+                        ExportKey::SpvEntryPoint { imms, interface_global_vars },
                         Exportee::Func(func),
                     ))
                 }
             })
-            .collect::<io::Result<_>>()?;
+            .collect::<Result<_, _>>()?;
 
         Ok(module)
     }
@@ -9,12 +9,13 @@ pub mod read;
 pub mod spec;
 pub mod write;
 
-use crate::{FxIndexMap, InternedStr};
+use crate::{DataInst, DataInstEffects, Func, FxIndexMap, GlobalVar, InternedStr};
 use smallvec::SmallVec;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
-use std::iter;
 use std::num::NonZeroU32;
 use std::string::FromUtf8Error;
+use std::{fmt, io, iter};
 
 /// Semantic properties of a SPIR-V module (not tied to any IDs).
 #[derive(Clone)]
@@ -69,6 +70,148 @@ impl From<spec::Opcode> for Inst {
     }
 }
 
+impl Inst {
+    /// Classify the effects of this instruction (see [`DataInstEffects`]),
+    /// when used as a [`DataInstKind::SpvInst`](crate::DataInstKind::SpvInst).
+    //
+    // FIXME(eddyb) this is (conservatively) incomplete, and would be better
+    // served by some kind of side-table in `spec`, or by having more SPIR-V
+    // instructions be converted to e.g. `DataInstKind::Pure` during lowering.
+    pub(crate) fn effects(&self) -> DataInstEffects {
+        let wk = &spec::Spec::get().well_known;
+
+        if self.opcode == wk.OpLoad {
+            // NOTE(eddyb) `Volatile` loads can't be removed, even if unused.
+            let is_volatile = self.imms.iter().any(|&imm| match imm {
+                Imm::Short(kind, word) => {
+                    let (kind_name, kind_def) = kind.name_and_def();
+                    match kind_def {
+                        spec::OperandKindDef::BitEnum { bits, .. }
+                            if kind_name == "MemoryAccess" =>
+                        {
+                            bits.lookup("Volatile")
+                                .is_some_and(|spec::BitIdx(i)| (word >> i) & 1 != 0)
+                        }
+                        _ => false,
+                    }
+                }
+                Imm::LongStart(..) | Imm::LongCont(..) => false,
+            });
+            return if is_volatile {
+                DataInstEffects::SideEffects
+            } else {
+                DataInstEffects::ReadOnly
+            };
+        }
+
+        let name = self.opcode.name();
+        match name {
+            "OpCopyObject"
+            | "OpCopyLogical"
+            | "OpCompositeConstruct"
+            | "OpCompositeExtract"
+            | "OpCompositeInsert"
+            | "OpVectorShuffle"
+            | "OpTranspose"
+            | "OpAccessChain"
+            | "OpInBoundsAccessChain"
+            | "OpPtrAccessChain"
+            | "OpInBoundsPtrAccessChain"
+            | "OpArrayLength"
+            | "OpConvertPtrToU"
+            | "OpConvertUToPtr"
+            | "OpVectorTimesScalar"
+            | "OpMatrixTimesScalar"
+            | "OpVectorTimesMatrix"
+            | "OpMatrixTimesVector"
+            | "OpMatrixTimesMatrix"
+            | "OpOuterProduct"
+            | "OpDot"
+            | "OpIAddCarry"
+            | "OpISubBorrow"
+            | "OpUMulExtended"
+            | "OpSMulExtended"
+            | "OpBitFieldInsert"
+            | "OpBitFieldSExtract"
+            | "OpBitFieldUExtract"
+            | "OpBitReverse"
+            | "OpBitCount"
+            | "OpAny"
+            | "OpAll"
+            | "OpIsNan"
+            | "OpIsInf"
+            | "OpIsFinite"
+            | "OpIsNormal"
+            | "OpSignBitSet"
+            | "OpOrdered"
+            | "OpUnordered"
+            | "OpQuantizeToF16"
+            | "OpSatConvertSToU"
+            | "OpSatConvertUToS"
+            | "OpImageSparseTexelsResident" => DataInstEffects::Pure,
+
+            // NOTE(eddyb) `OpSampledImage` and `OpImage` have no effects, but
+            // (in e.g. Vulkan SPIR-V) their results can only be used in the
+            // same block, so they can't be moved (or deduplicated) like pure
+            // instructions can (though they can still be removed, if unused).
+            "OpSampledImage"
+            | "OpImage"
+            | "OpVariable"
+            | "OpImageQueryFormat"
+            | "OpImageQueryOrder"
+            | "OpImageQuerySizeLod"
+            | "OpImageQuerySize"
+            | "OpImageQueryLod"
+            | "OpImageQueryLevels"
+            | "OpImageQuerySamples"
+            | "OpImageFetch"
+            | "OpImageGather"
+            | "OpImageDrefGather"
+            | "OpImageRead"
+            | "OpDPdx"
+            | "OpDPdy"
+            | "OpFwidth"
+            | "OpDPdxFine"
+            | "OpDPdyFine"
+            | "OpFwidthFine"
+            | "OpDPdxCoarse"
+            | "OpDPdyCoarse"
+            | "OpFwidthCoarse" => DataInstEffects::ReadOnly,
+
+            _ if name.starts_with("OpImageSample") || name.starts_with("OpImageSparse") => {
+                DataInstEffects::ReadOnly
+            }
+
+            _ => DataInstEffects::SideEffects,
+        }
+    }
+}
+
+/// Classify the effects of the `inst`th instruction of the `ext_set` extended
+/// instruction set (see [`DataInstEffects`]), when used as a
+/// [`DataInstKind::SpvExtInst`](crate::DataInstKind::SpvExtInst).
+pub(crate) fn ext_inst_effects(ext_set: &str, inst: u32) -> DataInstEffects {
+    let name = spec::Spec::get()
+        .get_ext_inst_set_by_lowercase_name(&ext_set.to_ascii_lowercase())
+        .and_then(|ext_inst_set| ext_inst_set.instructions.get(&inst))
+        .map(|inst_desc| &inst_desc.name[..]);
+
+    // FIXME(eddyb) support more extended instruction sets (e.g. `OpenCL.std`),
+    // but non-semantic ones should stay `SideEffects`, to avoid losing debuginfo.
+    match (&ext_set.to_ascii_lowercase()[..], name) {
+        // NOTE(eddyb) these write their second output through a pointer
+        // (unlike the `...Struct` variants, which return both outputs).
+        ("glsl.std.450", Some("Modf" | "Frexp")) => DataInstEffects::SideEffects,
+
+        ("glsl.std.450", Some(name)) if name.starts_with("InterpolateAt") => {
+            DataInstEffects::ReadOnly
+        }
+        ("glsl.std.450", Some(_)) => DataInstEffects::Pure,
+
+        _ => DataInstEffects::SideEffects,
+    }
+}
+
 /// A full SPIR-V instruction (like [`Inst`], but including input/output ID operands).
 pub struct InstWithIds {
     pub without_ids: Inst,
@@ -94,6 +237,17 @@ impl std::ops::DerefMut for InstWithIds {
     }
 }
 
+impl InstWithIds {
+    /// Total number of words in the SPIR-V binary form of this instruction
+    /// (including the first word, which encodes the opcode and word count).
+    pub fn word_count(&self) -> usize {
+        1 + (self.result_type_id.is_some() as usize)
+            + (self.result_id.is_some() as usize)
+            + self.imms.len()
+            + self.ids.len()
+    }
+}
+
 /// SPIR-V immediate (one word, longer immediates are a sequence of multiple [`Imm`]s).
 //
 // FIXME(eddyb) consider replacing with a `struct` e.g.:
@@ -170,3 +324,219 @@ pub fn encode_literal_string(s: &str) -> impl Iterator<Item = Imm> + '_ {
         },
     )
 }
+
+/// Error produced while reading (see [`read`]), lowering (see [`lower`]),
+/// or lifting (see [`lift`]) SPIR-V, with as much location information as
+/// was available (e.g. lifting doesn't have instruction indices/offsets,
+/// but can instead point to the offending SPIR-T [`Func`]/[`DataInst`]/etc.).
+//
+// FIXME(eddyb) consider converting these to `Diag`s, when lowering/lifting
+// can emit diagnostics (and continue) instead of failing.
+#[derive(Clone, Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+
+    /// Index of the offending instruction (`0` being the first one after the
+    /// header), if the error can be attributed to a specific instruction.
+    pub inst_index: Option<usize>,
+
+    /// Position of the offending instruction, in words from the start of the
+    /// module (i.e. counting the header as well).
+    pub word_offset: Option<usize>,
+
+    /// Line of the offending instruction (or token), when reading SPIR-V
+    /// assembly (see [`read::ModuleParser::read_from_spvasm_str`]).
+    pub line: Option<usize>,
+
+    pub opcode: Option<spec::Opcode>,
+
+    /// The offending ID, if the error can be attributed to a specific one.
+    pub id: Option<Id>,
+
+    // FIXME(eddyb) these are only used by lifting, and can't be shown by
+    // `Display` (which has no access to the `Module`), but could be
+    // replaced by attaching `Diag`s to the module instead.
+    pub func: Option<Func>,
+    pub data_inst: Option<DataInst>,
+    pub global_var: Option<GlobalVar>,
+
+    pub message: Cow<'static, str>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Invalid SPIR-V (e.g. truncated instructions, or undefined IDs),
+    /// or invalid SPIR-T (when lifting, e.g. misused string literals).
+    Malformed,
+
+    /// Potentially valid SPIR-V, that isn't supported by SPIR-T (yet),
+    /// or SPIR-T that can't be lifted to SPIR-V without legalization
+    /// (e.g. [`TypeKind::QPtr`](crate::TypeKind::QPtr)).
+    Unsupported,
+
+    /// Internal inconsistency (i.e. a bug in SPIR-T, not caused by its input).
+    Bug,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            kind,
+            inst_index: None,
+            word_offset: None,
+            line: None,
+            opcode: None,
+            id: None,
+            func: None,
+            data_inst: None,
+            global_var: None,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn malformed(message: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(ErrorKind::Malformed, message)
+    }
+
+    pub(crate) fn unsupported(message: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(ErrorKind::Unsupported, message)
+    }
+
+    // NOTE(eddyb) the `with_*` methods below don't overwrite existing values,
+    // to allow attaching context at multiple levels (innermost wins).
+
+    pub(crate) fn with_inst_location(mut self, inst_index: usize, word_offset: usize) -> Self {
+        self.inst_index.get_or_insert(inst_index);
+        self.word_offset.get_or_insert(word_offset);
+        self
+    }
+
+    pub(crate) fn with_line(mut self, line: usize) -> Self {
+        self.line.get_or_insert(line);
+        self
+    }
+
+    pub(crate) fn with_opcode(mut self, opcode: spec::Opcode) -> Self {
+        self.opcode.get_or_insert(opcode);
+        self
+    }
+
+    pub(crate) fn with_id(mut self, id: Id) -> Self {
+        self.id.get_or_insert(id);
+        self
+    }
+
+    pub(crate) fn with_func(mut self, func: Option<Func>) -> Self {
+        self.func = self.func.or(func);
+        self
+    }
+
+    pub(crate) fn with_data_inst(mut self, data_inst: Option<DataInst>) -> Self {
+        self.data_inst = self.data_inst.or(data_inst);
+        self
+    }
+
+    pub(crate) fn with_global_var(mut self, global_var: Option<GlobalVar>) -> Self {
+        self.global_var = self.global_var.or(global_var);
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            kind,
+            inst_index,
+            word_offset,
+            line,
+            opcode,
+            id: _,
+            func: _,
+            data_inst: _,
+            global_var: _,
+            message,
+        } = self;
+
+        f.write_str(match kind {
+            ErrorKind::Malformed => "malformed SPIR-V",
+            ErrorKind::Unsupported => "unsupported SPIR-V",
+            ErrorKind::Bug => "SPIR-V internal error (SPIR-T bug)",
+        })?;
+
+        f.write_str(" (")?;
+        let location = [
+            line.map(|line| format!("line {line}")),
+            opcode.map(|opcode| format!("in {}", opcode.name())),
+            inst_index.map(|i| format!("instruction #{i}")),
+            word_offset.map(|offset| format!("word offset {offset}")),
+        ];
+        let mut location = location.into_iter().flatten().peekable();
+        if location.peek().is_some() {
+            for (i, part) in location.enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                f.write_str(&part)?;
+            }
+            f.write_str(": ")?;
+        }
+        write!(f, "{message})")
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ErrorKind> for io::ErrorKind {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::Malformed => io::ErrorKind::InvalidData,
+            ErrorKind::Unsupported => io::ErrorKind::Unsupported,
+            ErrorKind::Bug => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        io::Error::new(err.kind.into(), err)
+    }
+}
+
+/// All the [`Error`]s produced by an operation which can report more than one
+/// (e.g. lifting, see [`Module::lift_to_spv_module_emitter`](crate::Module::lift_to_spv_module_emitter)).
+#[derive(Clone, Debug)]
+pub struct Errors(pub Vec<Error>);
+
+impl From<Error> for Errors {
+    fn from(err: Error) -> Self {
+        Self(vec![err])
+    }
+}
+
+impl fmt::Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, err) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Errors {}
+
+impl From<Errors> for io::Error {
+    fn from(errors: Errors) -> Self {
+        // NOTE(eddyb) only errors of the same kind can share an `io::ErrorKind`,
+        // a mixed list (or an empty one, which shouldn't happen) has to fall
+        // back to `io::ErrorKind::Other`.
+        let mut kinds = errors.0.iter().map(|err| err.kind);
+        let kind = match kinds.next() {
+            Some(first) if kinds.all(|kind| kind == first) => first.into(),
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, errors)
+    }
+}
@@ -1,10 +1,11 @@
-//! Pretty-printing SPIR-V operands.
+//! Pretty-printing SPIR-V operands (and disassembling whole SPIR-V modules).
 
 use crate::spv::{self, spec};
+use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::SmallVec;
 use std::borrow::Cow;
 use std::fmt::Write;
-use std::{iter, mem, str};
+use std::{iter, mem, slice, str};
 
 /// The smallest unit produced by printing a ("logical") SPIR-V operand.
 ///
@@ -288,3 +289,450 @@ pub fn inst_operands<ID>(
     }
     .inst_operands(opcode)
 }
+
+/// Disassemble a whole SPIR-V module (read from its binary form by `parser`),
+/// into the textual form produced by `spirv-dis` (and accepted by `spirv-as`,
+/// as well as [`spv::read::ModuleParser::read_from_spvasm_str`]).
+///
+/// Like `spirv-dis`, "friendly names" are used for IDs, taken from `OpName`
+/// when available, or generated for common types and constants (e.g. `%int`,
+/// `%v4float`, `%_ptr_Function_int`, `%int_1`), falling back to the numeric ID.
+pub fn disassemble_module(parser: spv::read::ModuleParser) -> Result<String, spv::Error> {
+    let header = parser.header;
+    let insts = parser.collect::<Result<Vec<_>, _>>()?;
+
+    let mut disassembler = Disassembler::default();
+    disassembler.collect_names_and_types(&insts);
+
+    let [_magic, version, generator, id_bound, schema] = header;
+    let [_, version_major, version_minor, _] = version.to_be_bytes();
+    let mut out = String::new();
+    writeln!(out, "; SPIR-V").unwrap();
+    writeln!(out, "; Version: {version_major}.{version_minor}").unwrap();
+    // FIXME(eddyb) use the names of known generators (from the SPIR-V registry).
+    writeln!(out, "; Generator: {}; {}", generator >> 16, generator & 0xffff).unwrap();
+    writeln!(out, "; Bound: {id_bound}").unwrap();
+    writeln!(out, "; Schema: {schema}").unwrap();
+
+    for inst in &insts {
+        disassembler.inst(&mut out, inst);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+/// Numeric type, as used for interpreting `LiteralContextDependentNumber`s.
+#[derive(Copy, Clone)]
+enum DisasmNumType {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+}
+
+#[derive(Default)]
+struct Disassembler {
+    /// "Friendly names" (i.e. the `%name` syntax, without the `%` prefix).
+    names: FxHashMap<spv::Id, String>,
+    used_names: FxHashSet<String>,
+
+    /// Result types of all instructions (that have them).
+    result_types: FxHashMap<spv::Id, spv::Id>,
+
+    /// All `OpTypeInt`/`OpTypeFloat` types.
+    num_types: FxHashMap<spv::Id, DisasmNumType>,
+
+    /// All `OpExtInstImport`s (of extended instruction sets known to `spec`).
+    ext_inst_sets: FxHashMap<spv::Id, &'static spec::ExtInstSetDesc>,
+}
+
+impl Disassembler {
+    /// Assign `name` (after sanitizing and deduplicating it) to `id`, unless
+    /// `id` already has a name (i.e. earlier names always take precedence).
+    fn save_name(&mut self, id: spv::Id, name: &str) {
+        if self.names.contains_key(&id) {
+            return;
+        }
+
+        // NOTE(eddyb) this matches `spirv-dis`, which only keeps `[A-Za-z0-9_]`,
+        // but additionally avoids conflicts with the numeric fallback names.
+        let mut name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+            .collect();
+        if name.is_empty() || name.bytes().all(|b| b.is_ascii_digit()) {
+            name.insert(0, '_');
+        }
+        if self.used_names.contains(&name) {
+            name = (0..)
+                .map(|i| format!("{name}_{i}"))
+                .find(|candidate| !self.used_names.contains(candidate))
+                .unwrap();
+        }
+        self.used_names.insert(name.clone());
+        self.names.insert(id, name);
+    }
+
+    fn name(&self, id: spv::Id) -> Cow<'_, str> {
+        match self.names.get(&id) {
+            Some(name) => name.into(),
+            None => id.to_string().into(),
+        }
+    }
+
+    fn collect_names_and_types(&mut self, insts: &[spv::InstWithIds]) {
+        let spv_spec = spec::Spec::get();
+        let wk = &spv_spec.well_known;
+
+        // `OpName`s take precedence over all generated names.
+        for inst in insts {
+            if inst.opcode == wk.OpName {
+                if let Ok(name) = spv::extract_literal_string(&inst.imms) {
+                    self.save_name(inst.ids[0], &name);
+                }
+            }
+        }
+
+        for inst in insts {
+            let Some(result_id) = inst.result_id else {
+                continue;
+            };
+            if let Some(type_id) = inst.result_type_id {
+                self.result_types.insert(result_id, type_id);
+            }
+
+            let imm_words = || {
+                inst.imms.iter().map(|&imm| match imm {
+                    spv::Imm::Short(_, word)
+                    | spv::Imm::LongStart(_, word)
+                    | spv::Imm::LongCont(_, word) => word,
+                })
+            };
+            let opcode_name = inst.opcode.name();
+            let name = if inst.opcode == wk.OpExtInstImport {
+                if let Ok(name) = spv::extract_literal_string(&inst.imms) {
+                    if let Some(desc) =
+                        spv_spec.get_ext_inst_set_by_lowercase_name(&name.to_ascii_lowercase())
+                    {
+                        self.ext_inst_sets.insert(result_id, desc);
+                    }
+                }
+                None
+            } else if inst.opcode == wk.OpTypeInt {
+                let (width, signed) = match imm_words().collect::<SmallVec<[_; 2]>>()[..] {
+                    [width, signedness] => (width, signedness != 0),
+                    _ => continue,
+                };
+                self.num_types.insert(result_id, DisasmNumType::Int { width, signed });
+                Some(match (width, signed) {
+                    (8, true) => "char".into(),
+                    (8, false) => "uchar".into(),
+                    (16, true) => "short".into(),
+                    (16, false) => "ushort".into(),
+                    (32, true) => "int".into(),
+                    (32, false) => "uint".into(),
+                    (64, true) => "long".into(),
+                    (64, false) => "ulong".into(),
+                    (_, true) => format!("i{width}"),
+                    (_, false) => format!("u{width}"),
+                })
+            } else if inst.opcode == wk.OpTypeFloat {
+                let Some(width) = imm_words().next() else {
+                    continue;
+                };
+                self.num_types.insert(result_id, DisasmNumType::Float { width });
+                Some(match width {
+                    16 => "half".into(),
+                    32 => "float".into(),
+                    64 => "double".into(),
+                    _ => format!("fp{width}"),
+                })
+            } else {
+                // NOTE(eddyb) these are rarer and/or not in `spec::WellKnown`.
+                match (opcode_name, &inst.ids[..]) {
+                    ("OpTypeVoid", []) => Some("void".into()),
+                    ("OpTypeBool", []) => Some("bool".into()),
+                    ("OpTypeSampler", []) => Some("type_sampler".into()),
+                    ("OpTypeVector", &[elem]) => {
+                        imm_words().next().map(|count| format!("v{count}{}", self.name(elem)))
+                    }
+                    ("OpTypeMatrix", &[column]) => {
+                        imm_words().next().map(|count| format!("mat{count}{}", self.name(column)))
+                    }
+                    ("OpTypeArray", &[elem, len]) => {
+                        Some(format!("_arr_{}_{}", self.name(elem), self.name(len)))
+                    }
+                    ("OpTypeRuntimeArray", &[elem]) => {
+                        Some(format!("_runtimearr_{}", self.name(elem)))
+                    }
+                    ("OpTypePointer", &[pointee]) => inst.imms.first().map(|&storage_class| {
+                        let storage_class = operand_from_imms::<()>([storage_class]);
+                        let storage_class =
+                            storage_class.tokens.iter().find_map(|token| match *token {
+                                Token::EnumerandName(name) => Some(name),
+                                _ => None,
+                            });
+                        format!("_ptr_{}_{}", storage_class.unwrap_or(""), self.name(pointee))
+                    }),
+                    ("OpConstantTrue", []) => Some("true".into()),
+                    ("OpConstantFalse", []) => Some("false".into()),
+                    ("OpConstant", []) => inst.result_type_id.and_then(|type_id| {
+                        let value = self.contextual_number(type_id, &inst.imms)?;
+                        let value: String = value
+                            .chars()
+                            .map(|c| match c {
+                                '-' => 'n',
+                                '.' => '_',
+                                _ => c,
+                            })
+                            .collect();
+                        Some(format!("{}_{value}", self.name(type_id)))
+                    }),
+                    _ => None,
+                }
+            };
+            if let Some(name) = name {
+                self.save_name(result_id, &name);
+            }
+        }
+    }
+
+    /// Format a `LiteralContextDependentNumber` (if `type_id` is numeric).
+    fn contextual_number(&self, type_id: spv::Id, imms: &[spv::Imm]) -> Option<String> {
+        let mut words = imms.iter().map(|&imm| match imm {
+            spv::Imm::Short(_, word)
+            | spv::Imm::LongStart(_, word)
+            | spv::Imm::LongCont(_, word) => word,
+        });
+        let lo = words.next()?;
+        let hi = words.next();
+        if words.next().is_some() {
+            return None;
+        }
+        let bits = u64::from(lo) | (u64::from(hi.unwrap_or(0)) << 32);
+
+        Some(match *self.num_types.get(&type_id)? {
+            DisasmNumType::Int { width, signed } if (1..=64).contains(&width) => {
+                let shift = 64 - width;
+                if signed {
+                    (((bits << shift) as i64) >> shift).to_string()
+                } else {
+                    ((bits << shift) >> shift).to_string()
+                }
+            }
+            DisasmNumType::Float { width: 32 } if hi.is_none() => format_float(bits, 32),
+            DisasmNumType::Float { width: 64 } => format_float(bits, 64),
+            _ => return None,
+        })
+    }
+
+    fn inst(&self, out: &mut String, inst: &spv::InstWithIds) {
+        let spv_spec = spec::Spec::get();
+        let wk = &spv_spec.well_known;
+
+        // NOTE(eddyb) `spirv-dis` right-aligns `%name =` so that opcodes line up.
+        let result = inst.result_id.map(|id| format!("%{} = ", self.name(id)));
+        write!(out, "{:>15}{}", result.as_deref().unwrap_or(""), inst.opcode.name()).unwrap();
+        if let Some(type_id) = inst.result_type_id {
+            write!(out, " %{}", self.name(type_id)).unwrap();
+        }
+
+        // NOTE(eddyb) the type is either the result type (e.g. `OpConstant`),
+        // or the type of the first operand (i.e. the `OpSwitch` selector).
+        let contextual_type = if inst.opcode == wk.OpSwitch {
+            inst.ids.first().and_then(|selector| self.result_types.get(selector).copied())
+        } else {
+            inst.result_type_id
+        };
+        let ext_inst_set = if inst.opcode == wk.OpExtInst {
+            inst.ids.first().and_then(|set| self.ext_inst_sets.get(set).copied())
+        } else {
+            None
+        };
+
+        let mut printer = DisasmOperandPrinter {
+            disassembler: self,
+            contextual_type,
+            ext_inst_set,
+            imms: inst.imms.iter().copied().peekable(),
+            ids: inst.ids.iter().copied().peekable(),
+            out,
+        };
+        for (mode, kind) in inst.opcode.def().all_operands() {
+            if mode == spec::OperandMode::Optional && printer.is_exhausted() {
+                break;
+            }
+            printer.operand(kind);
+        }
+    }
+}
+
+struct DisasmOperandPrinter<'a> {
+    disassembler: &'a Disassembler,
+
+    /// Type for `LiteralContextDependentNumber` operands.
+    contextual_type: Option<spv::Id>,
+
+    /// Extended instruction set for `LiteralExtInstInteger` operands.
+    ext_inst_set: Option<&'static spec::ExtInstSetDesc>,
+
+    imms: iter::Peekable<iter::Copied<slice::Iter<'a, spv::Imm>>>,
+    ids: iter::Peekable<iter::Copied<slice::Iter<'a, spv::Id>>>,
+
+    out: &'a mut String,
+}
+
+impl DisasmOperandPrinter<'_> {
+    fn is_exhausted(&mut self) -> bool {
+        self.imms.peek().is_none() && self.ids.peek().is_none()
+    }
+
+    fn enumerant_params(&mut self, enumerant: &spec::Enumerant) {
+        for (mode, kind) in enumerant.all_params() {
+            if mode == spec::OperandMode::Optional && self.is_exhausted() {
+                break;
+            }
+            self.operand(kind);
+        }
+    }
+
+    fn operand(&mut self, kind: spec::OperandKind) {
+        let spv_spec = spec::Spec::get();
+        let wk = &spv_spec.well_known;
+
+        let (name, def) = kind.name_and_def();
+
+        // FIXME(eddyb) should this be a hard error?
+        if let spec::OperandKindDef::Id = def {
+            match self.ids.next() {
+                Some(id) => write!(self.out, " %{}", self.disassembler.name(id)).unwrap(),
+                None => write!(self.out, " /* missing {name} */").unwrap(),
+            }
+            return;
+        }
+        let Some(first_imm) = self.imms.next() else {
+            write!(self.out, " /* missing {name} */").unwrap();
+            return;
+        };
+        let first_word = match first_imm {
+            spv::Imm::Short(found_kind, word) | spv::Imm::LongStart(found_kind, word) => {
+                assert_eq!(kind, found_kind);
+                word
+            }
+            spv::Imm::LongCont(..) => unreachable!(),
+        };
+
+        match def {
+            spec::OperandKindDef::BitEnum { empty_name, bits } => {
+                self.out.push(' ');
+                if first_word == 0 {
+                    self.out.push_str(empty_name);
+                }
+                for (i, bit_idx) in spec::BitIdx::of_all_set_bits(first_word).enumerate() {
+                    if i > 0 {
+                        self.out.push('|');
+                    }
+                    self.out.push_str(bits.get_named(bit_idx).unwrap().0);
+                }
+                for bit_idx in spec::BitIdx::of_all_set_bits(first_word) {
+                    self.enumerant_params(&bits[bit_idx]);
+                }
+            }
+            spec::OperandKindDef::ValueEnum { variants } => {
+                let (variant_name, variant_def) =
+                    variants.get_named(first_word.try_into().unwrap()).unwrap();
+                write!(self.out, " {variant_name}").unwrap();
+                self.enumerant_params(variant_def);
+            }
+            spec::OperandKindDef::Id => unreachable!(),
+            spec::OperandKindDef::Literal { .. } => {
+                let mut imms = SmallVec::<[_; 4]>::new();
+                imms.push(first_imm);
+                while let Some(&imm @ spv::Imm::LongCont(..)) = self.imms.peek() {
+                    self.imms.next();
+                    imms.push(imm);
+                }
+
+                let ext_inst_name =
+                    || self.ext_inst_set?.instructions.get(&first_word).map(|inst| &inst.name[..]);
+                if kind == wk.LiteralString {
+                    match spv::extract_literal_string(&imms) {
+                        Ok(s) => {
+                            // NOTE(eddyb) `spirv-as` only needs `"` and `\` escaped.
+                            self.out.push_str(" \"");
+                            for c in s.chars() {
+                                if matches!(c, '"' | '\\') {
+                                    self.out.push('\\');
+                                }
+                                self.out.push(c);
+                            }
+                            self.out.push('"');
+                        }
+                        Err(e) => write!(self.out, " /* {e} */").unwrap(),
+                    }
+                } else if kind == wk.LiteralContextDependentNumber {
+                    let value = self
+                        .contextual_type
+                        .and_then(|type_id| self.disassembler.contextual_number(type_id, &imms));
+                    match value {
+                        Some(value) => write!(self.out, " {value}").unwrap(),
+                        None => {
+                            // FIXME(eddyb) this can't be reassembled as-is.
+                            self.out.push_str(" 0x");
+                            for (i, imm) in imms.iter().rev().enumerate() {
+                                let (spv::Imm::Short(_, word)
+                                | spv::Imm::LongStart(_, word)
+                                | spv::Imm::LongCont(_, word)) = *imm;
+                                if i == 0 {
+                                    write!(self.out, "{word:x}").unwrap();
+                                } else {
+                                    write!(self.out, "{word:08x}").unwrap();
+                                }
+                            }
+                        }
+                    }
+                } else if let Some(ext_inst_name) =
+                    ext_inst_name().filter(|_| kind == wk.LiteralExtInstInteger)
+                {
+                    write!(self.out, " {ext_inst_name}").unwrap();
+                } else if let Some(opcode_name) = spec::Opcode::try_from_u16_with_name_and_def(
+                    first_word.try_into().unwrap_or(u16::MAX),
+                )
+                .and_then(|(_, name, _)| name.strip_prefix("Op"))
+                .filter(|_| name == "LiteralSpecConstantOpInteger")
+                {
+                    write!(self.out, " {opcode_name}").unwrap();
+                } else {
+                    write!(self.out, " {first_word}").unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// Format a floating-point `LiteralContextDependentNumber`, using the shortest
+/// decimal representation for finite values, and hex floats (as `spirv-dis`
+/// does) for infinities and NaNs (e.g. `0x1p+128` and `0x1.8p+128` for `f32`).
+fn format_float(bits: u64, width: u32) -> String {
+    let (sign, mantissa, mantissa_hex_digits, max_exp) = if width == 32 {
+        let x = f32::from_bits(bits as u32);
+        if x.is_finite() {
+            return x.to_string();
+        }
+        // NOTE(eddyb) the 23-bit mantissa is shifted to fill 6 hex digits.
+        (bits >> 31, (bits & 0x7f_ffff) << 1, 6, 128)
+    } else {
+        let x = f64::from_bits(bits);
+        if x.is_finite() {
+            return x.to_string();
+        }
+        (bits >> 63, bits & 0xf_ffff_ffff_ffff, 13, 1024)
+    };
+    let sign = if sign & 1 != 0 { "-" } else { "" };
+    let mantissa = format!("{mantissa:0mantissa_hex_digits$x}");
+    let mantissa = mantissa.trim_end_matches('0');
+    if mantissa.is_empty() {
+        format!("{sign}0x1p+{max_exp}")
+    } else {
+        format!("{sign}0x1.{mantissa}p+{max_exp}")
+    }
+}
@@ -1,4 +1,4 @@
-//! Low-level parsing of SPIR-V binary form.
+//! Low-level parsing of SPIR-V binary form (and assembly, i.e. `.spvasm` text).
 
 use crate::spv::{self, spec};
 use rustc_hash::FxHashMap;
@@ -63,6 +63,14 @@ enum InstParseError {
 }
 
 impl InstParseError {
+    fn kind(&self) -> spv::ErrorKind {
+        match self {
+            Self::UnsupportedEnumerand(..)
+            | Self::UnsupportedContextSensitiveLiteralType { .. } => spv::ErrorKind::Unsupported,
+            _ => spv::ErrorKind::Malformed,
+        }
+    }
+
     // FIXME(eddyb) improve messages and add more contextual information.
     fn message(&self) -> Cow<'static, str> {
         match *self {
@@ -248,33 +256,49 @@ pub struct ModuleParser {
     /// Next (instructions') word position in the module.
     next_word: usize,
 
+    /// Index of the next instruction in the module (used only for errors).
+    next_inst_index: usize,
+
     /// IDs defined so far in the module.
     known_ids: FxHashMap<spv::Id, KnownIdDef>,
 }
 
-// FIXME(eddyb) stop abusing `io::Error` for error reporting.
-fn invalid(reason: &str) -> io::Error {
-    io::Error::new(io::ErrorKind::InvalidData, format!("malformed SPIR-V ({reason})"))
-}
-
 impl ModuleParser {
     pub fn read_from_spv_file(path: impl AsRef<Path>) -> io::Result<Self> {
-        Self::read_from_spv_bytes(fs::read(path)?)
+        Ok(Self::read_from_spv_bytes(fs::read(path)?)?)
+    }
+
+    pub fn read_from_spvasm_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::read_from_spvasm_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Assemble SPIR-V from its textual form (i.e. the `spirv-as` syntax), and
+    /// start parsing the resulting binary form (i.e. as `read_from_spv_bytes`).
+    ///
+    /// IDs (i.e. `%name`) are numbered in the order of their first appearance,
+    /// while the module version is taken from a `; Version: 1.x` comment (as
+    /// emitted by `spirv-dis`), if present, or otherwise defaults to `1.0`.
+    //
+    // FIXME(eddyb) support more of the `spirv-as` syntax (e.g. `OpSpecConstantOp`
+    // integer overflow checks, or named IDs that look like numbers).
+    pub fn read_from_spvasm_str(spvasm: &str) -> Result<Self, spv::Error> {
+        let spv_words = Assembler::new(spvasm)?.assemble()?;
+        Self::read_from_spv_bytes(bytemuck::cast_slice(&spv_words).to_vec())
     }
 
     // FIXME(eddyb) also add `from_spv_words`.
-    pub fn read_from_spv_bytes(spv_bytes: Vec<u8>) -> io::Result<Self> {
+    pub fn read_from_spv_bytes(spv_bytes: Vec<u8>) -> Result<Self, spv::Error> {
         let spv_spec = spec::Spec::get();
 
         if spv_bytes.len() % 4 != 0 {
-            return Err(invalid("not a multiple of 4 bytes"));
+            return Err(spv::Error::malformed("not a multiple of 4 bytes"));
         }
         // May need to mutate the bytes (to normalize endianness) later below.
         let mut spv_bytes = spv_bytes;
         let spv_words = bytemuck::cast_slice_mut::<u8, u32>(&mut spv_bytes);
 
         if spv_words.len() < spec::HEADER_LEN {
-            return Err(invalid("truncated header"));
+            return Err(spv::Error::malformed("truncated header"));
         }
 
         // Check the magic, and swap endianness of all words if we have to.
@@ -287,7 +311,7 @@ impl ModuleParser {
                     *word = word.swap_bytes();
                 }
             } else {
-                return Err(invalid("incorrect magic number"));
+                return Err(spv::Error::malformed("incorrect magic number"));
             }
         }
 
@@ -295,6 +319,7 @@ impl ModuleParser {
             header: spv_words[..spec::HEADER_LEN].try_into().unwrap(),
             word_bytes: spv_bytes,
             next_word: spec::HEADER_LEN,
+            next_inst_index: 0,
 
             known_ids: FxHashMap::default(),
         })
@@ -302,7 +327,7 @@ impl ModuleParser {
 }
 
 impl Iterator for ModuleParser {
-    type Item = io::Result<spv::InstWithIds>;
+    type Item = Result<spv::InstWithIds, spv::Error>;
     fn next(&mut self) -> Option<Self::Item> {
         let spv_spec = spec::Spec::get();
         let wk = &spv_spec.well_known;
@@ -312,12 +337,20 @@ impl Iterator for ModuleParser {
 
         let (inst_len, opcode) = ((opcode >> 16) as usize, opcode as u16);
 
-        let (opcode, inst_name, def) = match spec::Opcode::try_from_u16_with_name_and_def(opcode) {
+        let (inst_index, word_offset) = (self.next_inst_index, self.next_word);
+        let at_inst = |err: spv::Error| err.with_inst_location(inst_index, word_offset);
+
+        let (opcode, _, def) = match spec::Opcode::try_from_u16_with_name_and_def(opcode) {
             Some(opcode_name_and_def) => opcode_name_and_def,
-            None => return Some(Err(invalid(&format!("unsupported opcode {opcode}")))),
+            None => {
+                return Some(Err(at_inst(spv::Error::unsupported(format!(
+                    "unsupported opcode {opcode}"
+                )))));
+            }
         };
 
-        let invalid = |msg: &str| invalid(&format!("in {inst_name}: {msg}"));
+        let invalid =
+            |msg: &str| at_inst(spv::Error::malformed(msg.to_string()).with_opcode(opcode));
 
         if words.len() < inst_len {
             return Some(Err(invalid("truncated instruction")));
@@ -336,7 +369,13 @@ impl Iterator for ModuleParser {
 
         let inst = match parser.inst(def) {
             Ok(inst) => inst,
-            Err(e) => return Some(Err(invalid(&e.message()))),
+            Err(e) => {
+                let mut err = spv::Error::new(e.kind(), e.message()).with_opcode(opcode);
+                if let InstParseError::UnknownResultTypeId(id) = e {
+                    err = err.with_id(id);
+                }
+                return Some(Err(at_inst(err)));
+            }
         };
 
         // HACK(eddyb) `Option::map` allows using `?` for `Result` in the closure.
@@ -363,7 +402,9 @@ impl Iterator for ModuleParser {
 
             let old = self.known_ids.insert(id, known_id_def);
             if old.is_some() {
-                return Err(invalid(&format!("ID %{id} is a result of multiple instructions")));
+                return Err(
+                    invalid(&format!("ID %{id} is a result of multiple instructions")).with_id(id)
+                );
             }
 
             Ok(())
@@ -373,7 +414,523 @@ impl Iterator for ModuleParser {
         }
 
         self.next_word += inst_len;
+        self.next_inst_index += 1;
 
         Some(Ok(inst))
     }
 }
+
+fn invalid_spvasm(line: usize, reason: &str) -> spv::Error {
+    spv::Error::malformed(reason.to_string()).with_line(line)
+}
+
+enum AsmToken<'a> {
+    /// `%name`, without the `%` prefix.
+    Id(&'a str),
+
+    /// `=`, used only to separate a result ID from its instruction.
+    Eq,
+
+    /// String literal, with all escapes already processed.
+    Str(String),
+
+    /// Any other whitespace-separated word, i.e. instruction or enumerand
+    /// names, and numeric literals (interpreted based on their operand kind).
+    Word(&'a str),
+}
+
+/// Numeric type, as used for interpreting `LiteralContextDependentNumber`s.
+#[derive(Copy, Clone)]
+enum AsmNumType {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+}
+
+struct Assembler<'a> {
+    /// All the tokens (and the line each of them appears on).
+    tokens: Vec<(AsmToken<'a>, usize)>,
+
+    /// Next token position.
+    next_token: usize,
+
+    /// Numeric IDs assigned to `%name`s (in the order of their first appearance).
+    ids: FxHashMap<&'a str, spv::Id>,
+
+    version: (u8, u8),
+
+    /// Result types of all instructions defined so far (that have them).
+    result_types: FxHashMap<spv::Id, spv::Id>,
+
+    /// All `OpTypeInt`/`OpTypeFloat` types defined so far.
+    num_types: FxHashMap<spv::Id, AsmNumType>,
+
+    /// `OpExtInstImport` names (lowercased) for all extended instruction sets.
+    ext_inst_sets: FxHashMap<spv::Id, String>,
+
+    /// The most recent `LiteralString` operand (used for `OpExtInstImport`).
+    last_string: Option<String>,
+
+    /// Output words (only the instructions, the header is added at the end).
+    words: Vec<u32>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(spvasm: &'a str) -> Result<Self, spv::Error> {
+        let mut tokens = vec![];
+        let mut version = None;
+        for (line_idx, mut line) in spvasm.lines().enumerate() {
+            let line_number = line_idx + 1;
+            loop {
+                line = line.trim_start();
+                let Some(c) = line.chars().next() else {
+                    break;
+                };
+
+                let is_word_end = |c: char| c.is_whitespace() || matches!(c, ';' | '"' | '=');
+                let word_end = line.find(is_word_end).unwrap_or(line.len());
+                let token = match c {
+                    ';' => {
+                        // HACK(eddyb) `spirv-dis` emits the version in a comment.
+                        if let Some(v) = line[1..].trim().strip_prefix("Version:") {
+                            version = v.trim().split_once('.').and_then(|(major, minor)| {
+                                Some((major.parse().ok()?, minor.parse().ok()?))
+                            });
+                        }
+                        break;
+                    }
+                    '=' => {
+                        line = &line[1..];
+                        AsmToken::Eq
+                    }
+                    '"' => {
+                        // NOTE(eddyb) `spirv-as` allows escaping any character
+                        // with `\` (but strings can't span multiple lines).
+                        let mut s = String::new();
+                        let mut chars = line[1..].char_indices();
+                        let end = loop {
+                            match chars.next() {
+                                Some((i, '"')) => break 1 + i + 1,
+                                Some((_, '\\')) => match chars.next() {
+                                    Some((_, c)) => s.push(c),
+                                    None => break 0,
+                                },
+                                Some((_, c)) => s.push(c),
+                                None => break 0,
+                            }
+                        };
+                        if end == 0 {
+                            return Err(invalid_spvasm(line_number, "unterminated string"));
+                        }
+                        line = &line[end..];
+                        AsmToken::Str(s)
+                    }
+                    '%' => {
+                        let name = &line[1..word_end];
+                        line = &line[word_end..];
+                        if name.is_empty() {
+                            return Err(invalid_spvasm(line_number, "empty ID name"));
+                        }
+                        AsmToken::Id(name)
+                    }
+                    _ => {
+                        let word = &line[..word_end];
+                        line = &line[word_end..];
+                        AsmToken::Word(word)
+                    }
+                };
+                tokens.push((token, line_number));
+            }
+        }
+
+        Ok(Self {
+            tokens,
+            next_token: 0,
+            ids: FxHashMap::default(),
+            version: version.unwrap_or((1, 0)),
+            result_types: FxHashMap::default(),
+            num_types: FxHashMap::default(),
+            ext_inst_sets: FxHashMap::default(),
+            last_string: None,
+            words: vec![],
+        })
+    }
+
+    fn peek_token(&self, offset: usize) -> Option<&AsmToken<'a>> {
+        self.tokens.get(self.next_token + offset).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.next_token).or_else(|| self.tokens.last()).map_or(0, |&(_, line)| line)
+    }
+
+    fn invalid(&self, reason: &str) -> spv::Error {
+        invalid_spvasm(self.line(), reason)
+    }
+
+    /// Whether the current instruction has ended, i.e. there are no more
+    /// tokens, or the next instruction is starting (`%name =` or `OpName`).
+    fn at_inst_end(&self) -> bool {
+        match self.peek_token(0) {
+            None => true,
+            Some(AsmToken::Id(_)) => matches!(self.peek_token(1), Some(AsmToken::Eq)),
+            Some(AsmToken::Word(word)) => {
+                word.starts_with("Op") && spec::Spec::get().instructions.lookup(word).is_some()
+            }
+            Some(AsmToken::Eq | AsmToken::Str(_)) => false,
+        }
+    }
+
+    fn id(&mut self, name: &'a str) -> Result<spv::Id, spv::Error> {
+        let next_id = u32::try_from(self.ids.len() + 1).ok().and_then(spv::Id::new);
+        match self.ids.get(name) {
+            Some(&id) => Ok(id),
+            None => {
+                let id = next_id.ok_or_else(|| self.invalid("too many IDs"))?;
+                self.ids.insert(name, id);
+                Ok(id)
+            }
+        }
+    }
+
+    fn expect_id(&mut self) -> Result<spv::Id, spv::Error> {
+        match self.peek_token(0) {
+            Some(&AsmToken::Id(name)) => {
+                let id = self.id(name)?;
+                self.next_token += 1;
+                Ok(id)
+            }
+            _ => Err(self.invalid("expected `%name` ID")),
+        }
+    }
+
+    fn expect_word(&mut self, expected: &str) -> Result<&'a str, spv::Error> {
+        match self.peek_token(0) {
+            Some(&AsmToken::Word(word)) => {
+                self.next_token += 1;
+                Ok(word)
+            }
+            _ => Err(self.invalid(&format!("expected {expected}"))),
+        }
+    }
+
+    fn assemble(mut self) -> Result<Vec<u32>, spv::Error> {
+        let spv_spec = spec::Spec::get();
+
+        while self.next_token < self.tokens.len() {
+            self.inst()?;
+        }
+
+        let (version_major, version_minor) = self.version;
+        let id_bound = u32::try_from(self.ids.len() + 1).unwrap();
+        let header = [
+            spv_spec.magic,
+            u32::from_be_bytes([0, version_major, version_minor, 0]),
+            // NOTE(eddyb) unknown generator (`0`), as this isn't `spirv-as`.
+            0,
+            id_bound,
+            0,
+        ];
+        Ok(header.into_iter().chain(self.words).collect())
+    }
+
+    fn inst(&mut self) -> Result<(), spv::Error> {
+        let spv_spec = spec::Spec::get();
+        let wk = &spv_spec.well_known;
+
+        let result_id = match (self.peek_token(0), self.peek_token(1)) {
+            (Some(&AsmToken::Id(name)), Some(AsmToken::Eq)) => {
+                let id = self.id(name)?;
+                self.next_token += 2;
+                Some(id)
+            }
+            _ => None,
+        };
+
+        let opcode_name = self.expect_word("instruction name")?;
+        let opcode = spv_spec
+            .instructions
+            .lookup(opcode_name)
+            .ok_or_else(|| self.invalid(&format!("unknown instruction {opcode_name}")))?;
+        let def = opcode.def();
+
+        // NOTE(eddyb) the length is only filled in once all operands are known.
+        let inst_start = self.words.len();
+        self.words.push(u32::from(opcode.as_u16()));
+
+        let result_type_id = if def.has_result_type_id {
+            let id = self.expect_id()?;
+            self.words.push(id.get());
+            Some(id)
+        } else {
+            None
+        };
+        match (def.has_result_id, result_id) {
+            (true, Some(id)) => self.words.push(id.get()),
+            (false, None) => {}
+            (true, None) => return Err(self.invalid(&format!("{opcode_name} requires `%name =`"))),
+            (false, Some(_)) => {
+                return Err(self.invalid(&format!("{opcode_name} does not have a result")));
+            }
+        }
+
+        self.last_string = None;
+        for (mode, kind) in def.all_operands() {
+            if mode == spec::OperandMode::Optional && self.at_inst_end() {
+                break;
+            }
+            self.operand(inst_start, kind)?;
+        }
+        if !self.at_inst_end() {
+            return Err(self.invalid(&format!("too many operands for {opcode_name}")));
+        }
+
+        let inst_len = u32::try_from(self.words.len() - inst_start)
+            .ok()
+            .filter(|&len| len <= 0xffff)
+            .ok_or_else(|| self.invalid(&format!("{opcode_name} is too long")))?;
+        self.words[inst_start] |= inst_len << 16;
+
+        if let Some(id) = result_id {
+            if let Some(type_id) = result_type_id {
+                self.result_types.insert(id, type_id);
+            }
+
+            let operand_words = &self.words[inst_start + 2..];
+            if opcode == wk.OpTypeInt {
+                self.num_types.insert(
+                    id,
+                    AsmNumType::Int { width: operand_words[0], signed: operand_words[1] != 0 },
+                );
+            } else if opcode == wk.OpTypeFloat {
+                self.num_types.insert(id, AsmNumType::Float { width: operand_words[0] });
+            } else if opcode == wk.OpExtInstImport {
+                let name = self.last_string.take().unwrap_or_default();
+                self.ext_inst_sets.insert(id, name.to_ascii_lowercase());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn operand(&mut self, inst_start: usize, kind: spec::OperandKind) -> Result<(), spv::Error> {
+        let spv_spec = spec::Spec::get();
+        let wk = &spv_spec.well_known;
+
+        // NOTE(eddyb) `spirv-as` allows `!<integer>` for raw words.
+        if let Some(AsmToken::Word(word)) = self.peek_token(0) {
+            if let Some(raw_word) = word.strip_prefix('!') {
+                let raw_word = parse_spvasm_int(raw_word)
+                    .and_then(|x| u32::try_from(x).ok())
+                    .ok_or_else(|| self.invalid(&format!("invalid raw word {word}")))?;
+                self.next_token += 1;
+                self.words.push(raw_word);
+                return Ok(());
+            }
+        }
+
+        let (kind_name, kind_def) = kind.name_and_def();
+        match kind_def {
+            spec::OperandKindDef::Id => {
+                let id = self.expect_id()?;
+                self.words.push(id.get());
+            }
+
+            spec::OperandKindDef::BitEnum { empty_name, bits } => {
+                let names = self.expect_word(kind_name)?;
+                let mut word = 0;
+                for name in names.split('|').filter(|&name| name != *empty_name) {
+                    let bit_idx = bits
+                        .lookup(name)
+                        .ok_or_else(|| self.invalid(&format!("unknown {kind_name} {name}")))?;
+                    word |= 1 << bit_idx.0;
+                }
+                self.words.push(word);
+
+                for bit_idx in spec::BitIdx::of_all_set_bits(word) {
+                    self.enumerant_params(inst_start, &bits[bit_idx])?;
+                }
+            }
+            spec::OperandKindDef::ValueEnum { variants } => {
+                let name = self.expect_word(kind_name)?;
+                let v = variants
+                    .lookup(name)
+                    .ok_or_else(|| self.invalid(&format!("unknown {kind_name} {name}")))?;
+                self.words.push(v.into());
+
+                self.enumerant_params(inst_start, &variants[v])?;
+            }
+
+            spec::OperandKindDef::Literal { size: spec::LiteralSize::Word } => {
+                let word = self.expect_word(kind_name)?;
+                let x = if kind == wk.LiteralExtInstInteger {
+                    // NOTE(eddyb) the `Set` operand immediately precedes this one.
+                    let ext_inst_set = spv::Id::new(self.words[self.words.len() - 1])
+                        .and_then(|id| self.ext_inst_sets.get(&id));
+                    parse_spvasm_int(word).or_else(|| {
+                        let desc = spv_spec.get_ext_inst_set_by_lowercase_name(ext_inst_set?)?;
+                        let (&inst, _) = desc
+                            .instructions
+                            .iter()
+                            .find(|(_, inst_desc)| inst_desc.name == word)?;
+                        Some(inst.into())
+                    })
+                } else if kind_name == "LiteralSpecConstantOpInteger" {
+                    parse_spvasm_int(word).or_else(|| {
+                        Some(spv_spec.instructions.lookup(&format!("Op{word}"))?.as_u16().into())
+                    })
+                } else {
+                    // NOTE(eddyb) negative values are encoded as two's complement.
+                    parse_spvasm_int(word).filter(|&x| x >= i128::from(i32::MIN))
+                };
+                let word = x
+                    .and_then(|x| {
+                        u32::try_from(x).or_else(|_| i32::try_from(x).map(|x| x as u32)).ok()
+                    })
+                    .ok_or_else(|| self.invalid(&format!("invalid {kind_name} {word}")))?;
+                self.words.push(word);
+            }
+            spec::OperandKindDef::Literal { size: spec::LiteralSize::NulTerminated } => {
+                let s = match self.peek_token(0) {
+                    Some(AsmToken::Str(s)) if !s.contains('\0') => s.clone(),
+                    _ => return Err(self.invalid(&format!("expected {kind_name}"))),
+                };
+                self.next_token += 1;
+                self.words.extend(spv::encode_literal_string(&s).map(|imm| match imm {
+                    spv::Imm::Short(_, word)
+                    | spv::Imm::LongStart(_, word)
+                    | spv::Imm::LongCont(_, word) => word,
+                }));
+                self.last_string = Some(s);
+            }
+            spec::OperandKindDef::Literal { size: spec::LiteralSize::FromContextualType } => {
+                // NOTE(eddyb) the type is either the result type (e.g. `OpConstant`),
+                // or the type of the first operand (i.e. the `OpSwitch` selector).
+                let type_id = if self.words[inst_start] == u32::from(wk.OpSwitch.as_u16()) {
+                    spv::Id::new(self.words[inst_start + 1])
+                        .and_then(|selector| self.result_types.get(&selector).copied())
+                } else {
+                    spv::Id::new(self.words[inst_start + 1])
+                };
+                let num_type = type_id
+                    .and_then(|type_id| self.num_types.get(&type_id).copied())
+                    .ok_or_else(|| self.invalid(&format!("{kind_name} requires a numeric type")))?;
+
+                let literal = self.expect_word(kind_name)?;
+                let invalid_literal = || self.invalid(&format!("invalid {kind_name} {literal}"));
+                match num_type {
+                    AsmNumType::Int { width, signed } => {
+                        let x = parse_spvasm_int(literal).ok_or_else(invalid_literal)?;
+                        if !(1..=64).contains(&width) {
+                            return Err(
+                                self.invalid(&format!("unsupported {width}-bit integer type"))
+                            );
+                        }
+                        // NOTE(eddyb) unsigned bit patterns are also accepted for
+                        // signed types (e.g. `0xffffffff` for `-1` in `i32`).
+                        let (min, max) = if signed {
+                            (-(1 << (width - 1)), (1 << width) - 1)
+                        } else {
+                            (0, (1 << width) - 1)
+                        };
+                        if !(min..=max).contains(&x) {
+                            return Err(invalid_literal());
+                        }
+                        let x = if signed && x >= 1 << (width - 1) { x - (1 << width) } else { x };
+
+                        // NOTE(eddyb) signed values are sign-extended to full words.
+                        for i in 0..width.div_ceil(32) {
+                            self.words.push((x >> (i * 32)) as u32);
+                        }
+                    }
+                    AsmNumType::Float { width: width @ (32 | 64) } => {
+                        let bits =
+                            parse_spvasm_float(literal, width).ok_or_else(invalid_literal)?;
+                        self.words.push(bits as u32);
+                        if width == 64 {
+                            self.words.push((bits >> 32) as u32);
+                        }
+                    }
+                    AsmNumType::Float { width } => {
+                        return Err(self.invalid(&format!("unsupported {width}-bit float type")));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn enumerant_params(
+        &mut self,
+        inst_start: usize,
+        enumerant: &spec::Enumerant,
+    ) -> Result<(), spv::Error> {
+        for (mode, kind) in enumerant.all_params() {
+            if mode == spec::OperandMode::Optional && self.at_inst_end() {
+                break;
+            }
+            self.operand(inst_start, kind)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a (decimal or `0x`-prefixed hexadecimal, optionally negative) integer.
+fn parse_spvasm_int(s: &str) -> Option<i128> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let x = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None if s.bytes().all(|b| b.is_ascii_digit()) => s.parse().ok()?,
+        None => return None,
+    };
+    Some(if negative { -x } else { x })
+}
+
+/// Parse a (decimal or hexadecimal, e.g. `0x1.8p+1`) floating-point literal,
+/// returning the bits of its `width`-bit (i.e. `f32` or `f64`) representation.
+fn parse_spvasm_float(s: &str, width: u32) -> Option<u64> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, s),
+    };
+    let Some(hex) = unsigned.strip_prefix("0x").or_else(|| unsigned.strip_prefix("0X")) else {
+        return Some(if width == 32 {
+            u64::from(s.parse::<f32>().ok()?.to_bits())
+        } else {
+            s.parse::<f64>().ok()?.to_bits()
+        });
+    };
+
+    let (mantissa, exp) = hex.split_once(['p', 'P'])?;
+    let exp: i32 = exp.parse().ok()?;
+    let (int_digits, frac_digits) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let frac_bit_count = u32::try_from(frac_digits.len()).ok()? * 4;
+
+    // NOTE(eddyb) infinities and NaNs use the (otherwise out of range) maximum
+    // exponent, e.g. `0x1p+128` and `0x1.8p+128` for `f32` (as `spirv-dis` does).
+    let (mantissa_bit_count, max_exp) = if width == 32 { (23, 128) } else { (52, 1024) };
+    if exp == max_exp && int_digits == "1" {
+        let frac =
+            if frac_digits.is_empty() { 0 } else { u64::from_str_radix(frac_digits, 16).ok()? };
+        let frac = if frac_bit_count > mantissa_bit_count {
+            let extra_bits = frac_bit_count - mantissa_bit_count;
+            if extra_bits >= 4 || frac & ((1 << extra_bits) - 1) != 0 {
+                return None;
+            }
+            frac >> extra_bits
+        } else {
+            frac << (mantissa_bit_count - frac_bit_count)
+        };
+        let sign = u64::from(negative) << (width - 1);
+        let all_ones_exp = ((1 << (width - 1 - mantissa_bit_count)) - 1) << mantissa_bit_count;
+        return Some(sign | all_ones_exp | frac);
+    }
+
+    let mantissa = u64::from_str_radix(&format!("{int_digits}{frac_digits}"), 16).ok()?;
+    let x = (mantissa as f64) * 2f64.powi(exp - i32::try_from(frac_bit_count).ok()?);
+    let x = if negative { -x } else { x };
+    Some(if width == 32 { u64::from((x as f32).to_bits()) } else { x.to_bits() })
+}
@@ -136,6 +136,7 @@ def_well_known! {
         OpConstantFalse,
         OpConstantTrue,
         OpConstant,
+        OpConstantComposite,
         OpUndef,
 
         OpVariable,
@@ -166,6 +167,7 @@ def_well_known! {
         OpPtrAccessChain,
         OpInBoundsPtrAccessChain,
         OpBitcast,
+        OpCompositeConstruct,
     ],
     operand_kind: OperandKind = [
         Capability,
@@ -209,6 +211,9 @@ def_well_known! {
         Block,
         RowMajor,
         Offset,
+
+        NoSignedWrap,
+        NoUnsignedWrap,
     ],
     linkage_type: u32 = [
         Import,
@@ -259,6 +264,12 @@ impl Opcode {
     }
 }
 
+impl fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub struct InstructionDef {
     pub category: InstructionCategory,
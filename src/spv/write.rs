@@ -175,23 +175,26 @@ pub struct ModuleEmitter {
     /// Output SPIR-V words.
     // FIXME(eddyb) try to write bytes to an `impl io::Write` directly.
     pub words: Vec<u32>,
-}
 
-// FIXME(eddyb) stop abusing `io::Error` for error reporting.
-fn invalid(reason: &str) -> io::Error {
-    io::Error::new(io::ErrorKind::InvalidData, format!("malformed SPIR-V ({reason})"))
+    /// Number of instructions emitted so far (used only for errors).
+    inst_count: usize,
 }
 
 impl ModuleEmitter {
     pub fn with_header(header: [u32; spec::HEADER_LEN]) -> Self {
         // FIXME(eddyb) sanity-check the provided header words.
-        Self { words: header.into() }
+        Self { words: header.into(), inst_count: 0 }
     }
 
     // FIXME(eddyb) sanity-check the operands against the definition of `inst.opcode`.
-    pub fn push_inst(&mut self, inst: &spv::InstWithIds) -> io::Result<()> {
-        let (inst_name, def) = inst.opcode.name_and_def();
-        let invalid = |msg: &str| invalid(&format!("in {inst_name}: {msg}"));
+    pub fn push_inst(&mut self, inst: &spv::InstWithIds) -> Result<(), spv::Error> {
+        let def = inst.opcode.def();
+        let (inst_index, word_offset) = (self.inst_count, self.words.len());
+        let invalid = |msg: &str| {
+            spv::Error::malformed(msg.to_string())
+                .with_opcode(inst.opcode)
+                .with_inst_location(inst_index, word_offset)
+        };
 
         // FIXME(eddyb) make these errors clearer (or turn them into asserts?).
         if inst.result_type_id.is_some() != def.has_result_type_id {
@@ -201,11 +204,7 @@ impl ModuleEmitter {
             return Err(invalid("result ID (`IdResult`) mismatch"));
         }
 
-        let total_word_count = 1
-            + (inst.result_type_id.is_some() as usize)
-            + (inst.result_id.is_some() as usize)
-            + inst.imms.len()
-            + inst.ids.len();
+        let total_word_count = inst.word_count();
 
         self.words.reserve(total_word_count);
         let expected_final_pos = self.words.len() + total_word_count;
@@ -226,16 +225,34 @@ impl ModuleEmitter {
             out: &mut self.words,
         }
         .inst_operands(def)
-        .map_err(|e| invalid(&e.message()))?;
+        .map_err(|e| {
+            let err = invalid(&e.message());
+            match e {
+                OperandEmitError::UnsupportedEnumerand(..) => {
+                    spv::Error { kind: spv::ErrorKind::Unsupported, ..err }
+                }
+                _ => err,
+            }
+        })?;
 
         // If no error was produced so far, `OperandEmitter` should've pushed
         // the exact number of words.
         assert_eq!(self.words.len(), expected_final_pos);
 
+        self.inst_count += 1;
+
         Ok(())
     }
 
     pub fn write_to_spv_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
         fs::write(path, bytemuck::cast_slice::<u32, u8>(&self.words))
     }
+
+    /// Disassemble the module emitted so far into the textual form produced by
+    /// `spirv-dis` (see [`spv::print::disassemble_module`] for more details).
+    pub fn disassemble(&self) -> Result<String, spv::Error> {
+        spv::print::disassemble_module(spv::read::ModuleParser::read_from_spv_bytes(
+            bytemuck::cast_slice::<u32, u8>(&self.words).to_vec(),
+        )?)
+    }
 }
@@ -216,6 +216,16 @@ pub trait Transformer: Sized {
     }
 }
 
+/// [`Transformer`] replacing each [`Value`] use with the result of calling the
+/// wrapped function on it (leaving the use unchanged if that returns `None`).
+pub(crate) struct ReplaceValueWith<F>(pub(crate) F);
+
+impl<F: Fn(Value) -> Option<Value>> Transformer for ReplaceValueWith<F> {
+    fn transform_value_use(&mut self, v: &Value) -> Transformed<Value> {
+        self.0(*v).map_or(Transformed::Unchanged, Transformed::Changed)
+    }
+}
+
 /// Trait implemented on "transformable" types, to further "elaborate" a type by
 /// transforming its "interior" (i.e. variants and/or fields).
 ///
@@ -424,7 +434,13 @@ impl InnerTransform for TypeDef {
         transform!({
             attrs -> transformer.transform_attr_set_use(*attrs),
             kind -> match kind {
-                TypeKind::QPtr | TypeKind::SpvStringLiteralForExtInst => Transformed::Unchanged,
+                TypeKind::QPtr
+                | TypeKind::Scalar { .. }
+                | TypeKind::SpvStringLiteralForExtInst => Transformed::Unchanged,
+
+                &TypeKind::Vector { elem, elem_count } => transform!({
+                    elem -> transformer.transform_type_use(elem),
+                } => TypeKind::Vector { elem, elem_count }),
 
                 TypeKind::SpvInst { spv_inst, type_and_const_inputs } => Transformed::map_iter(
                     type_and_const_inputs.iter(),
@@ -470,7 +486,9 @@ impl InnerTransform for ConstDef {
                         spv_inst_and_const_inputs: Rc::new((spv_inst.clone(), new_iter.collect())),
                     })
                 }
-                ConstKind::SpvStringLiteralForExtInst(_) => Transformed::Unchanged
+                ConstKind::Undef
+                | ConstKind::Scalar(_)
+                | ConstKind::SpvStringLiteralForExtInst(_) => Transformed::Unchanged
             },
         } => Self {
             attrs,
@@ -716,7 +734,9 @@ impl InnerTransform for DataInstFormDef {
                     | QPtrOp::Load
                     | QPtrOp::Store => Transformed::Unchanged,
                 },
-                DataInstKind::SpvInst(_) | DataInstKind::SpvExtInst { .. } => Transformed::Unchanged,
+                DataInstKind::Pure(_)
+                | DataInstKind::SpvInst(_)
+                | DataInstKind::SpvExtInst { .. } => Transformed::Unchanged,
             },
             // FIXME(eddyb) this should be replaced with an impl of `InnerTransform`
             // for `Option<T>` or some other helper, to avoid "manual transpose".
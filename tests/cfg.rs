@@ -0,0 +1,100 @@
+mod common;
+
+use common::{lower_test_data, only_exported_func_def_body};
+use spirt::{cfg, ControlNodeKind, ControlRegion, DataInst, FuncDefBody, Value};
+
+/// Get all the [`DataInst`]s directly in `region` (i.e. in its `Block`s).
+fn insts_in_region(func_def_body: &FuncDefBody, region: ControlRegion) -> Vec<DataInst> {
+    func_def_body
+        .at(region)
+        .at_children()
+        .into_iter()
+        .flat_map(|func_at_node| match func_at_node.def().kind {
+            ControlNodeKind::Block { insts } => func_def_body
+                .at(insts)
+                .into_iter()
+                .map(|func_at_inst| func_at_inst.position)
+                .collect(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+#[test]
+fn dom_trees_of_diamond() {
+    let module = lower_test_data("dce.spvasm");
+    let func_def_body = only_exported_func_def_body(&module);
+    let cfg = func_def_body.unstructured_cfg.as_ref().unwrap();
+
+    let [entry, then_case, else_case, merge] =
+        <[_; 4]>::try_from(cfg.rev_post_order(func_def_body).collect::<Vec<_>>()).unwrap();
+    assert_eq!(entry, func_def_body.body);
+
+    let dom_tree = cfg.dom_tree(func_def_body);
+    assert!(dom_tree.idom(entry).is_none());
+    for region in [then_case, else_case, merge] {
+        assert_eq!(dom_tree.idom(region), Some(entry));
+        assert!(dom_tree.dominates(entry, region));
+    }
+    assert!(!dom_tree.dominates(then_case, merge));
+    assert!(!dom_tree.dominates(merge, then_case));
+
+    let post_dom_tree = cfg.post_dom_tree(func_def_body);
+    assert!(post_dom_tree.idom(merge).is_none());
+    for region in [entry, then_case, else_case] {
+        assert_eq!(post_dom_tree.idom(region), Some(merge));
+        assert!(post_dom_tree.dominates(merge, region));
+    }
+    assert!(!post_dom_tree.dominates(then_case, entry));
+}
+
+#[test]
+fn structured_dominance_across_diamond_cfg() {
+    let module = lower_test_data("verify-undominated-use.spvasm");
+    let func_def_body = only_exported_func_def_body(&module);
+    let cfg = func_def_body.unstructured_cfg.as_ref().unwrap();
+
+    let [entry, then_case, _, merge] =
+        <[_; 4]>::try_from(cfg.rev_post_order(func_def_body).collect::<Vec<_>>()).unwrap();
+    let [load] = <[_; 1]>::try_from(insts_in_region(func_def_body, entry)).unwrap();
+    let [add] = <[_; 1]>::try_from(insts_in_region(func_def_body, then_case)).unwrap();
+    let [store] = <[_; 1]>::try_from(insts_in_region(func_def_body, merge)).unwrap();
+
+    // `%x` (loaded in the entry) dominates all of its uses, but `%y` (defined
+    // in the `then` case) doesn't dominate its use after the merge.
+    let dominance = cfg::StructuredDominance::new(func_def_body);
+    let (use_in_then_case, use_after_merge) =
+        (cfg::UseSite::DataInst(add), cfg::UseSite::DataInst(store));
+    assert!(dominance.value_dominates(Value::DataInstOutput(load), use_in_then_case));
+    assert!(dominance.value_dominates(Value::DataInstOutput(load), use_after_merge));
+    assert!(!dominance.value_dominates(Value::DataInstOutput(add), use_after_merge));
+}
+
+#[test]
+fn loop_info_before_and_after_structurizing() {
+    let mut module = lower_test_data("for-loop.wgsl.spvasm");
+
+    let func_def_body = only_exported_func_def_body(&module);
+    let loop_info = cfg::LoopInfo::new(func_def_body);
+    assert_eq!(loop_info.structured_loops().len(), 0);
+    let [cfg_loop] = <[_; 1]>::try_from(loop_info.cfg_loops().collect::<Vec<_>>()).ok().unwrap();
+    assert_eq!((cfg_loop.parent, cfg_loop.depth), (None, 1));
+    assert_eq!(func_def_body.at(cfg_loop.header).def().inputs.len(), 2);
+    assert!(cfg_loop.body.contains(&cfg_loop.header));
+    assert_eq!(cfg_loop.latches.len(), 1);
+    assert_eq!(cfg_loop.exits.len(), 1);
+    assert!(!cfg_loop.body.contains(&cfg_loop.exits[0]));
+    for &region in &cfg_loop.body {
+        assert_eq!(loop_info.innermost_cfg_loop_of(region).unwrap().header, cfg_loop.header);
+    }
+
+    spirt::passes::legalize::structurize_func_cfgs(&mut module);
+    let func_def_body = only_exported_func_def_body(&module);
+    let loop_info = cfg::LoopInfo::new(func_def_body);
+    assert_eq!(loop_info.cfg_loops().len(), 0);
+    let [structured_loop] =
+        <[_; 1]>::try_from(loop_info.structured_loops().collect::<Vec<_>>()).ok().unwrap();
+    assert_eq!((structured_loop.parent, structured_loop.depth), (None, 1));
+    assert_eq!(structured_loop.body_inputs.len(), 2);
+    assert!(structured_loop.body_inputs.iter().all(|input| !input.is_loop_invariant()));
+}
@@ -0,0 +1,112 @@
+//! Helpers shared by all the integration tests (included as `mod common;`).
+
+// NOTE(eddyb) not every test uses every helper.
+#![allow(dead_code)]
+
+use spirt::spv::spec;
+use spirt::{print, Context, DeclDef, Exportee, Func, FuncDefBody, Module};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Assemble a SPIR-V module (with `id_bound` as the ID bound in its header),
+/// from `insts`, each an opcode name followed by all of its operands as raw
+/// words (i.e. IDs as plain numbers, and string literals from [`str_words`]).
+pub fn assemble_spv(id_bound: u32, insts: &[(&str, Vec<u32>)]) -> Vec<u8> {
+    let spv_spec = spec::Spec::get();
+
+    // Version 1.0, no generator, and the schema left as `0`.
+    let mut words = vec![spv_spec.magic, 0x0001_0000, 0, id_bound, 0];
+    for (opcode_name, operands) in insts {
+        let opcode = spv_spec.instructions.lookup(opcode_name).unwrap();
+        let word_count = u32::try_from(1 + operands.len()).unwrap();
+        words.push((word_count << 16) | u32::from(opcode.as_u16()));
+        words.extend_from_slice(operands);
+    }
+    bytemuck::cast_slice(&words).to_vec()
+}
+
+pub fn test_data_path(file_name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(file_name)
+}
+
+/// Get the names of all the files in `tests/data` with the `extension` suffix
+/// (e.g. `".spvasm"`), in sorted order.
+pub fn test_data_file_names(extension: &str) -> Vec<String> {
+    let mut file_names: Vec<_> = std::fs::read_dir(test_data_path(""))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|file_name| file_name.ends_with(extension))
+        .collect();
+    file_names.sort();
+    file_names
+}
+
+/// Lower `file_name` from `tests/data` (either SPIR-V binary or `.spvasm`).
+pub fn lower_test_data(file_name: &str) -> Module {
+    lower_test_data_with_cx(Rc::new(Context::new()), file_name)
+}
+
+pub fn lower_test_data_with_cx(cx: Rc<Context>, file_name: &str) -> Module {
+    Module::lower_from_spv_file(cx, test_data_path(file_name)).unwrap()
+}
+
+/// Lower the SPIR-V module assembled from `insts` (see [`assemble_spv`]).
+pub fn lower_insts(id_bound: u32, insts: &[(&str, Vec<u32>)]) -> Module {
+    Module::lower_from_spv_bytes(Rc::new(Context::new()), assemble_spv(id_bound, insts)).unwrap()
+}
+
+/// Capabilities, memory model, and the `main` export, for modules assembled
+/// with [`assemble_spv`] (with `main_id` as the ID of the `main` function).
+pub fn module_prologue(main_id: u32) -> Vec<(&'static str, Vec<u32>)> {
+    vec![
+        // OpCapability Shader
+        ("OpCapability", vec![1]),
+        // OpCapability Linkage
+        ("OpCapability", vec![5]),
+        // OpMemoryModel Logical GLSL450
+        ("OpMemoryModel", vec![0, 1]),
+        // OpDecorate %main LinkageAttributes "main" Export
+        ("OpDecorate", [vec![main_id, 41], str_words("main"), vec![0]].concat()),
+    ]
+}
+
+/// Encode `s` as the words of a SPIR-V string literal operand (i.e. its UTF-8
+/// bytes, followed by a `0` byte, and padded with more `0`s to whole words).
+pub fn str_words(s: &str) -> Vec<u32> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+    bytes.chunks(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap())).collect()
+}
+
+/// Get the only export of `module`, which must be a function (e.g. `"main"`).
+pub fn only_exported_func(module: &Module) -> Func {
+    let [Exportee::Func(func)] =
+        <[_; 1]>::try_from(module.exports.values().copied().collect::<Vec<_>>()).ok().unwrap()
+    else {
+        unreachable!()
+    };
+    func
+}
+
+/// Get the body of [`only_exported_func`], which must be a definition.
+pub fn only_exported_func_def_body(module: &Module) -> &FuncDefBody {
+    match &module.funcs[only_exported_func(module)].def {
+        DeclDef::Present(func_def_body) => func_def_body,
+        DeclDef::Imported(_) => unreachable!(),
+    }
+}
+
+pub fn print_module(module: &Module) -> String {
+    print::Plan::for_module(module).pretty_print().to_string()
+}
+
+/// Lift `module` to SPIR-V, and lower the result back (in the same `Context`).
+pub fn lift_and_relower(module: &Module) -> Module {
+    let spv_words = module.lift_to_spv_module_emitter().unwrap().words;
+    Module::lower_from_spv_bytes(module.cx(), bytemuck::cast_slice(&spv_words).to_vec()).unwrap()
+}
+
+/// Lift `module` to SPIR-V, and disassemble the result (see [`spirt::spv::print`]).
+pub fn lift_and_disassemble(module: &Module) -> String {
+    module.lift_to_spv_module_emitter().unwrap().disassemble().unwrap()
+}
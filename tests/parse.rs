@@ -0,0 +1,112 @@
+mod common;
+
+use common::{lower_insts, module_prologue};
+use spirt::{passes, Context, Module};
+use std::path::Path;
+use std::rc::Rc;
+
+fn read_test_data(file_name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(file_name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+/// Check that printing `module`, parsing it back, and printing it again,
+/// produces the same text (i.e. nothing that is printed is lost by parsing).
+fn assert_print_parse_roundtrip(module: &Module) {
+    let printed = common::print_module(module);
+    let parsed = Module::parse_from_spirt_text(module.cx(), &printed)
+        .unwrap_or_else(|err| panic!("{err}\n\n{printed}"));
+    assert_eq!(common::print_module(&parsed), printed);
+}
+
+#[test]
+fn parse_print_roundtrip() {
+    let text = read_test_data("parse-for-loop.spirt");
+    let module = Module::parse_from_spirt_text(Rc::new(Context::new()), &text).unwrap();
+    assert_eq!(common::print_module(&module), text);
+}
+
+#[test]
+fn roundtrip_lowered_from_spv() {
+    let mut module = lower_insts(
+        15,
+        &[
+            module_prologue(10),
+            vec![
+                // %bool = OpTypeBool
+                ("OpTypeBool", vec![1]),
+                // %uint = OpTypeInt 32 0
+                ("OpTypeInt", vec![2, 32, 0]),
+                // %uint_1 = OpConstant %uint 1
+                ("OpConstant", vec![2, 3, 1]),
+                // %_ptr_Function_uint = OpTypePointer Function %uint
+                ("OpTypePointer", vec![8, 7, 2]),
+                // %typeof_main = OpTypeFunction %uint %bool %_ptr_Function_uint
+                ("OpTypeFunction", vec![4, 2, 1, 8]),
+                // %main = OpFunction %uint None %typeof_main
+                ("OpFunction", vec![2, 10, 0, 4]),
+                // %cond = OpFunctionParameter %bool
+                ("OpFunctionParameter", vec![1, 11]),
+                // %ptr = OpFunctionParameter %_ptr_Function_uint
+                ("OpFunctionParameter", vec![8, 12]),
+                // %entry = OpLabel
+                ("OpLabel", vec![5]),
+                // %x = OpLoad %uint %ptr
+                ("OpLoad", vec![2, 14, 12]),
+                // %y = OpIAdd %uint %x %uint_1
+                ("OpIAdd", vec![2, 13, 14, 3]),
+                // OpSelectionMerge %merge None
+                ("OpSelectionMerge", vec![7, 0]),
+                // OpBranchConditional %cond %then %merge
+                ("OpBranchConditional", vec![11, 6, 7]),
+                // %then = OpLabel
+                ("OpLabel", vec![6]),
+                // OpReturnValue %y
+                ("OpReturnValue", vec![13]),
+                // %merge = OpLabel
+                ("OpLabel", vec![7]),
+                // OpReturnValue %x
+                ("OpReturnValue", vec![14]),
+                ("OpFunctionEnd", vec![]),
+            ],
+        ]
+        .concat(),
+    );
+    assert_print_parse_roundtrip(&module);
+
+    passes::legalize::structurize_func_cfgs(&mut module);
+    assert_print_parse_roundtrip(&module);
+
+    passes::qptr::lower_from_spv_ptrs(
+        &mut module,
+        &spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT,
+    );
+    assert_print_parse_roundtrip(&module);
+}
+
+#[test]
+fn parse_errors_have_line_and_col() {
+    let text = read_test_data("parse-for-loop.spirt");
+
+    // Replace the first `=` with an unexpected token, on some later line.
+    let (line_idx, line) = text.lines().enumerate().find(|(_, line)| line.contains(" = ")).unwrap();
+    let col = line.find(" = ").unwrap() + 2;
+    let broken = text.replacen(" = ", " ? ", 1);
+
+    let Err(err) = Module::parse_from_spirt_text(Rc::new(Context::new()), &broken) else {
+        panic!("parsing should fail on `?`");
+    };
+    assert_eq!((err.line, err.col), (line_idx as u32 + 1, col as u32), "{err}");
+}
+
+#[test]
+fn parse_then_verify_invalid_structured_control_flow() {
+    let path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/verify-structured-invalid.spirt");
+    let mut module = Module::parse_from_spirt_file(Rc::new(Context::new()), path).unwrap();
+
+    // `Loop` initial input of the wrong type, `Select` case with too few outputs,
+    // use of a value defined in a `Select` case outside of it, and a non-`bool`
+    // `Loop` repeat condition.
+    assert_eq!(passes::verify::verify_funcs(&mut module), 4);
+}
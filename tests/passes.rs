@@ -0,0 +1,549 @@
+mod common;
+
+use common::{
+    lift_and_disassemble, lower_insts, lower_test_data, lower_test_data_with_cx, module_prologue,
+    only_exported_func_def_body,
+};
+use spirt::{passes, Context, ControlNodeKind, DataInstKind, DeclDef, ExportKey, Module, Value};
+use std::rc::Rc;
+
+fn run_passes(module: &mut Module, pipeline: &str) {
+    let pass_manager =
+        passes::PassManager::with_default_passes(spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT);
+    pass_manager.run(module, pipeline).unwrap();
+}
+
+/// Lower an `if`-`else` "diamond", with a value defined in its `then` arm, and
+/// an `OpStore` after the merge, of the value with the ID `stored_value_id`
+/// (i.e. `13` for the load in the entry block, or `16` for the `then` value).
+fn lower_diamond_storing(stored_value_id: u32) -> Module {
+    lower_insts(
+        19,
+        &[
+            module_prologue(10),
+            vec![
+                // %void = OpTypeVoid
+                ("OpTypeVoid", vec![1]),
+                // %bool = OpTypeBool
+                ("OpTypeBool", vec![2]),
+                // %int = OpTypeInt 32 1
+                ("OpTypeInt", vec![3, 32, 1]),
+                // %int_1 = OpConstant %int 1
+                ("OpConstant", vec![3, 4, 1]),
+                // %_ptr_Function_int = OpTypePointer Function %int
+                ("OpTypePointer", vec![5, 7, 3]),
+                // %typeof_main = OpTypeFunction %void %bool %_ptr_Function_int
+                ("OpTypeFunction", vec![6, 1, 2, 5]),
+                // %main = OpFunction %void None %typeof_main
+                ("OpFunction", vec![1, 10, 0, 6]),
+                // %cond = OpFunctionParameter %bool
+                ("OpFunctionParameter", vec![2, 11]),
+                // %ptr = OpFunctionParameter %_ptr_Function_int
+                ("OpFunctionParameter", vec![5, 12]),
+                // %entry = OpLabel
+                ("OpLabel", vec![14]),
+                // %x = OpLoad %int %ptr
+                ("OpLoad", vec![3, 13, 12]),
+                // OpSelectionMerge %merge None
+                ("OpSelectionMerge", vec![18, 0]),
+                // OpBranchConditional %cond %then %else
+                ("OpBranchConditional", vec![11, 15, 17]),
+                // %then = OpLabel
+                ("OpLabel", vec![15]),
+                // %y = OpIAdd %int %x %int_1
+                ("OpIAdd", vec![3, 16, 13, 4]),
+                // OpBranch %merge
+                ("OpBranch", vec![18]),
+                // %else = OpLabel
+                ("OpLabel", vec![17]),
+                // OpBranch %merge
+                ("OpBranch", vec![18]),
+                // %merge = OpLabel
+                ("OpLabel", vec![18]),
+                // OpStore %ptr %x (or %y)
+                ("OpStore", vec![12, stored_value_id]),
+                ("OpReturn", vec![]),
+                ("OpFunctionEnd", vec![]),
+            ],
+        ]
+        .concat(),
+    )
+}
+
+#[test]
+fn verify_accepts_valid_funcs() {
+    let mut module = lower_diamond_storing(13);
+    passes::legalize::structurize_func_cfgs(&mut module);
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+}
+
+#[test]
+fn verify_rejects_undominated_use() {
+    let mut module = lower_diamond_storing(16);
+    passes::legalize::structurize_func_cfgs(&mut module);
+    assert_eq!(passes::verify::verify_funcs(&mut module), 1);
+
+    // The violation should be reported on the `OpStore` using `%y`.
+    let printed = common::print_module(&module);
+    assert!(printed.contains("violates SSA dominance"), "{printed}");
+}
+
+#[test]
+fn verify_rejects_undominated_use_in_cfg() {
+    let mut module = lower_test_data("verify-undominated-use.spvasm");
+    assert_eq!(passes::verify::verify_funcs(&mut module), 1);
+}
+
+#[test]
+fn verify_accepts_valid_cfg() {
+    let mut module = lower_test_data("for-loop.wgsl.spvasm");
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+}
+
+#[test]
+fn verify_accepts_valid_types() {
+    let mut module = lower_test_data("verify-types-valid.spvasm");
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+}
+
+#[test]
+fn verify_rejects_invalid_types() {
+    let mut module = lower_test_data("verify-types-invalid.spvasm");
+
+    // `OpLoad` from `float*` into `int`, `OpIAdd` with a `bool` input, `OpFAdd`
+    // of `int`s (both inputs), `OpSelect` with an `int` condition, and `OpStore`
+    // of an `int` to a `float*`.
+    assert_eq!(passes::verify::verify_funcs(&mut module), 1 + 1 + 2 + 1 + 1);
+}
+
+#[test]
+fn inline_all_calls_to_structured_callees() {
+    // Calls to functions with unstructured control-flow are never inlined.
+    let mut module = lower_test_data("inline-calls.spvasm");
+    passes::inline::inline_calls(&mut module, |_, _| true);
+    assert_eq!(lift_and_disassemble(&module).matches("OpFunctionCall").count(), 5);
+
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::inline::inline_calls(&mut module, |_, _| true);
+    let spvasm = lift_and_disassemble(&module);
+
+    // Everything (including `add_one` called by `add_two`) got inlined.
+    assert_eq!(spvasm.matches("OpFunctionCall").count(), 0, "{spvasm}");
+    assert_eq!(spvasm.matches("OpFunction ").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpIAdd").count(), 3, "{spvasm}");
+}
+
+#[test]
+fn inline_calls_except_dont_inline() {
+    let mut module = lower_test_data("inline-calls.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::inline::inline_calls(&mut module, |cx, callee_decl| {
+        passes::inline::spv_inline_hint(cx, callee_decl)
+            != Some(passes::inline::SpvInlineHint::DontInline)
+    });
+    let spvasm = lift_and_disassemble(&module);
+
+    // Only the `DontInline` function is left (as the only callee of `main`).
+    assert_eq!(spvasm.matches("OpFunctionCall").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpFunction ").count(), 2, "{spvasm}");
+    assert_eq!(spvasm.matches("OpIAdd").count(), 3, "{spvasm}");
+}
+
+#[test]
+fn inline_calls_pass_except_dont_inline() {
+    let mut module = lower_test_data("inline-calls.spvasm");
+    run_passes(&mut module, "legalize::structurize_func_cfgs, inline::inline_calls");
+    let spvasm = lift_and_disassemble(&module);
+
+    // Only the `DontInline` function is left (as the only callee of `main`).
+    assert_eq!(spvasm.matches("OpFunctionCall").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpFunction ").count(), 2, "{spvasm}");
+    assert_eq!(spvasm.matches("OpIAdd").count(), 3, "{spvasm}");
+}
+
+#[test]
+fn inline_multi_output_callee() {
+    let mut module = lower_test_data("inline-calls.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+
+    // Split the struct returned by `swap` into its components (as separate
+    // outputs of its body), which inlining has to combine back into a struct.
+    let cx = module.cx();
+    let main_body = only_exported_func_def_body(&module);
+    let swap = main_body
+        .at(main_body.body)
+        .at_children()
+        .into_iter()
+        .flat_map(|func_at_node| match func_at_node.def().kind {
+            ControlNodeKind::Block { insts } => main_body.at(insts).into_iter().collect(),
+            _ => vec![],
+        })
+        .find_map(|func_at_inst| match cx[func_at_inst.def().form].kind {
+            DataInstKind::FuncCall(callee) if func_at_inst.def().inputs.len() == 2 => Some(callee),
+            _ => None,
+        })
+        .unwrap();
+    let DeclDef::Present(swap_body) = &mut module.funcs[swap].def else { unreachable!() };
+    let [Value::DataInstOutput(composite)] = swap_body.at_body().def().outputs[..] else {
+        unreachable!()
+    };
+    let components = swap_body.at(composite).def().inputs.clone();
+    swap_body.at_mut_body().def().outputs = components;
+
+    // NOTE(eddyb) the original `OpCompositeConstruct` (now unused) is still
+    // copied by inlining, so it has to be removed separately.
+    passes::inline::inline_calls(&mut module, |_, _| true);
+    passes::dce::eliminate_dead_code(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // The only `OpCompositeConstruct` left is the one replacing the call.
+    assert_eq!(spvasm.matches("OpFunctionCall").count(), 0, "{spvasm}");
+    assert_eq!(spvasm.matches("OpFunction ").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpCompositeConstruct").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpCompositeExtract").count(), 1, "{spvasm}");
+}
+
+#[test]
+fn dce_removes_unused_insts_and_selects() {
+    let mut module = lower_test_data("dce.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::dce::eliminate_dead_code(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // Only the `OpStore` is left (in a single block).
+    assert!(!spvasm.contains("OpIMul"), "{spvasm}");
+    assert!(!spvasm.contains("OpIAdd"), "{spvasm}");
+    assert!(!spvasm.contains("OpPhi"), "{spvasm}");
+    assert_eq!(spvasm.matches("OpLabel").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpStore").count(), 1, "{spvasm}");
+}
+
+#[test]
+fn gvn_deduplicates_and_hoists_pure_insts() {
+    let mut module = lower_test_data("gvn.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::gvn::deduplicate_pure_data_insts(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // The `OpIMul` is hoisted out of the `if`-`else` (before its `OpStore`s).
+    assert_eq!(spvasm.matches("OpIAdd").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpIMul").count(), 1, "{spvasm}");
+    assert!(spvasm.find("OpIMul").unwrap() < spvasm.find("OpSelectionMerge").unwrap(), "{spvasm}");
+    assert_eq!(spvasm.matches("OpStore").count(), 5, "{spvasm}");
+}
+
+#[test]
+fn gvn_keeps_sampled_images_in_place() {
+    let mut module = lower_test_data("gvn-sampled-image.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::gvn::deduplicate_pure_data_insts(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // The `OpSampledImage`s aren't hoisted out of the `if`-`else`.
+    assert_eq!(spvasm.matches("OpSampledImage").count(), 2, "{spvasm}");
+    assert!(
+        spvasm.find("OpSampledImage").unwrap() > spvasm.find("OpSelectionMerge").unwrap(),
+        "{spvasm}"
+    );
+}
+
+#[test]
+fn fold_consts_and_simplify() {
+    let mut module = lower_test_data("fold.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::fold::fold_consts_and_simplify(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // `2 * 3` and `2 < 3` are evaluated, `(x + 6) - 6` is replaced with `x`
+    // (leaving the `OpIAdd` unused), and only the taken `if` case is kept.
+    for removed in ["OpIMul", "OpISub", "OpSLessThan", "OpSelectionMerge"] {
+        assert!(!spvasm.contains(removed), "{spvasm}");
+    }
+    assert!(spvasm.contains("OpConstant %int 6"), "{spvasm}");
+    let loaded = spvasm.lines().find_map(|line| line.trim().split_once(" = OpLoad")).unwrap().0;
+    assert!(
+        spvasm
+            .lines()
+            .any(|line| line.trim().ends_with(&format!(" {loaded}")) && line.contains("OpStore")),
+        "{spvasm}"
+    );
+    assert_eq!(spvasm.matches("OpLabel").count(), 1, "{spvasm}");
+}
+
+#[test]
+fn fold_keeps_mixed_signedness_identities() {
+    let mut module = lower_test_data("fold-mixed-signedness.spvasm");
+    passes::fold::fold_consts_and_simplify(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // `x_int + 0u` must stay, as `x_int` isn't a `uint`, while `1u * _` (of
+    // that `uint` sum) and `x_int + 0` (of the same type) can be removed.
+    assert_eq!(spvasm.matches("OpIAdd %uint").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpIMul").count(), 0, "{spvasm}");
+    assert_eq!(spvasm.matches("OpIAdd %int").count(), 0, "{spvasm}");
+}
+
+#[test]
+fn fold_select_with_const_cond() {
+    let mut module = lower_test_data("fold-select.spvasm");
+    passes::fold::fold_consts_and_simplify(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // The loaded value (i.e. the `true` case) should be stored directly.
+    assert!(!spvasm.contains("OpSelect"), "{spvasm}");
+    let loaded = spvasm.lines().find_map(|line| line.trim().split_once(" = OpLoad")).unwrap().0;
+    assert!(
+        spvasm
+            .lines()
+            .any(|line| line.trim().ends_with(&format!(" {loaded}")) && line.contains("OpStore")),
+        "{spvasm}"
+    );
+}
+
+#[test]
+fn fold_composite_construct_of_non_spec_consts() {
+    let mut module = lower_test_data("fold-composite.spvasm");
+    passes::fold::fold_consts_and_simplify(&mut module);
+    let spvasm = lift_and_disassemble(&module);
+
+    // Only the `OpCompositeConstruct` using `OpSpecConstant` is left.
+    assert_eq!(spvasm.matches("OpCompositeConstruct").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpConstantComposite").count(), 2, "{spvasm}");
+    assert!(!spvasm.contains("OpSpecConstantComposite"), "{spvasm}");
+}
+
+#[test]
+fn fold_buffer_dyn_len_times_stride_div_stride() {
+    let layout_config = &spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT;
+
+    let mut module = lower_test_data("fold-buffer-dyn-len.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::qptr::lower_from_spv_ptrs(&mut module, layout_config);
+    passes::fold::fold_consts_and_simplify(&mut module);
+    passes::dce::eliminate_dead_code(&mut module);
+    passes::qptr::analyze_uses(&mut module, layout_config);
+    passes::qptr::lift_to_spv_ptrs(&mut module, layout_config);
+    let spvasm = lift_and_disassemble(&module);
+
+    // The `OpArrayLength` result is stored directly.
+    assert!(!spvasm.contains("OpUDiv"), "{spvasm}");
+    assert!(!spvasm.contains("OpIMul"), "{spvasm}");
+    let len = spvasm.lines().find_map(|line| line.trim().split_once(" = OpArrayLength")).unwrap().0;
+    assert!(
+        spvasm
+            .lines()
+            .any(|line| line.trim().ends_with(&format!(" {len}")) && line.contains("OpStore")),
+        "{spvasm}"
+    );
+}
+
+#[test]
+fn mem2reg_promotes_func_local_var() {
+    let layout_config = &spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT;
+
+    let mut module = lower_test_data("mem2reg.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    passes::qptr::lower_from_spv_ptrs(&mut module, layout_config);
+    passes::qptr::analyze_uses(&mut module, layout_config);
+    passes::qptr::promote_func_local_vars(&mut module);
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+    passes::qptr::lift_to_spv_ptrs(&mut module, layout_config);
+    let spvasm = lift_and_disassemble(&module);
+
+    // The variable is replaced by an `OpPhi` of the values stored in each case.
+    assert!(
+        !spvasm.lines().any(|line| line.contains("OpVariable") && line.ends_with(" Function")),
+        "{spvasm}"
+    );
+    assert_eq!(spvasm.matches("OpLoad").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpStore").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpPhi").count(), 1, "{spvasm}");
+}
+
+#[test]
+fn qptr_lower_lift_ptr_to_int_bitcast() {
+    let mut module = lower_test_data("qptr-ptr-to-int-bitcast.spvasm");
+    let layout_config = spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT;
+    passes::qptr::lower_from_spv_ptrs(&mut module, &layout_config);
+    passes::qptr::analyze_uses(&mut module, &layout_config);
+    passes::qptr::lift_to_spv_ptrs(&mut module, &layout_config);
+    let spvasm = lift_and_disassemble(&module);
+
+    assert!(spvasm.contains("OpBitcast %ulong"), "{spvasm}");
+}
+
+#[test]
+fn pass_manager_reports() {
+    let mut module = lower_test_data("dce.spvasm");
+    let mut pass_manager =
+        passes::PassManager::with_default_passes(spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT);
+
+    // Unknown passes are reported before running any of the passes.
+    let err = pass_manager.run(&mut module, "legalize::structurize_func_cfgs, dce").err().unwrap();
+    assert_eq!(err.to_string(), "unknown pass `dce`");
+    assert!(only_exported_func_def_body(&module).unstructured_cfg.is_some());
+
+    pass_manager.time_passes = true;
+    pass_manager.record_versions = true;
+    let report = pass_manager
+        .run(&mut module, "legalize::structurize_func_cfgs, dce::eliminate_dead_code,")
+        .unwrap();
+    assert!(only_exported_func_def_body(&module).unstructured_cfg.is_none());
+
+    let timed_passes: Vec<_> = report.timings.iter().map(|&(pass, _)| pass).collect();
+    assert_eq!(timed_passes, ["legalize::structurize_func_cfgs", "dce::eliminate_dead_code"]);
+    let versions: Vec<_> = report.versions.iter().map(|(name, _)| &name[..]).collect();
+    assert_eq!(
+        versions,
+        ["initial", "after legalize::structurize_func_cfgs", "after dce::eliminate_dead_code"]
+    );
+    assert!(only_exported_func_def_body(&report.versions[0].1).unstructured_cfg.is_some());
+}
+
+#[test]
+fn link_modules_merges_exports() {
+    let cx = Rc::new(Context::new());
+    let modules = ["link-imports-main.spvasm", "link-imports-lib.spvasm"]
+        .map(|file_name| lower_test_data_with_cx(cx.clone(), file_name));
+    let module = passes::link::link_modules(modules).unwrap();
+
+    let mut export_names: Vec<_> = module
+        .exports
+        .keys()
+        .map(|key| match key {
+            ExportKey::LinkName(name) => &cx[*name],
+            ExportKey::SpvEntryPoint { .. } => unreachable!(),
+        })
+        .collect();
+    export_names.sort();
+    assert_eq!(export_names, ["main", "mismatched_func", "mismatched_var", "ok_func"]);
+
+    // `ok_func` is now defined in the linked module (while the other imports
+    // are left unresolved, see `link_reports_missing_and_mismatched_imports`).
+    let printed = common::print_module(&module);
+    assert!(!printed.contains("import \"ok_func\""), "{printed}");
+    assert!(printed.contains("= import \"missing_func\""), "{printed}");
+    assert!(printed.contains("\"ok_func\": F"), "{printed}");
+}
+
+#[test]
+fn link_rejects_duplicate_entry_points() {
+    let cx = Rc::new(Context::new());
+    let modules = ["link-entry-point-a.spvasm", "link-entry-point-b.spvasm"]
+        .map(|file_name| lower_test_data_with_cx(cx.clone(), file_name));
+
+    match passes::link::link_modules(modules) {
+        Err(err @ passes::link::LinkError::DuplicateEntryPoint(_)) => {
+            assert_eq!(
+                err.to_string(),
+                "GLCompute `main` entry point exported by more than one module"
+            );
+        }
+        Err(err) => panic!("unexpected link error: {err}"),
+        Ok(_) => panic!("linking two `GLCompute` `main` entry points should fail"),
+    }
+}
+
+#[test]
+fn link_reports_missing_and_mismatched_imports() {
+    let cx = Rc::new(Context::new());
+    let [mut module, lib] = ["link-imports-main.spvasm", "link-imports-lib.spvasm"]
+        .map(|file_name| lower_test_data_with_cx(cx.clone(), file_name));
+    passes::link::merge_module_into(&mut module, lib).unwrap();
+
+    match passes::link::resolve_imports_strict(&mut module) {
+        Err(err @ passes::link::LinkError::UnresolvedImports(3)) => {
+            assert_eq!(err.to_string(), "3 import(s) couldn't be resolved");
+        }
+        Err(err) => panic!("unexpected link error: {err}"),
+        Ok(()) => panic!("`missing_func`, `mismatched_func` and `mismatched_var` should fail"),
+    }
+
+    // Resolving again (which still fails) shouldn't attach the same diagnostics.
+    assert!(passes::link::resolve_imports_strict(&mut module).is_err());
+
+    // The diagnostics are attached to the unresolved imports (while `ok_func`
+    // got resolved, i.e. all its uses now refer to its definition instead).
+    let printed = common::print_module(&module);
+    assert_eq!(printed.matches("ERR ").count(), 3, "{printed}");
+    for expected in [
+        "ERR no export found for import `missing_func`",
+        "ERR `mismatched_func` imported with return type `s32`, but exported with return type `f32`",
+        "ERR `mismatched_var` imported with type `",
+    ] {
+        assert!(printed.contains(expected), "{printed}");
+    }
+    assert!(!printed.contains("= import \"ok_func\""), "{printed}");
+}
+
+#[test]
+fn structurize_early_return_from_loop() {
+    let mut module = lower_test_data("early-return.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    assert!(only_exported_func_def_body(&module).unstructured_cfg.is_none());
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+
+    // Both returns are merged into one, at the end of the function body.
+    let spvasm = lift_and_disassemble(&module);
+    assert_eq!(spvasm.matches("OpReturnValue").count(), 1, "{spvasm}");
+    assert_eq!(spvasm.matches("OpLoopMerge").count(), 1, "{spvasm}");
+}
+
+#[test]
+fn structurize_irreducible_cfg() {
+    // Irreducible CFGs can't be structurized without being made reducible first.
+    let mut module = lower_test_data("irreducible.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    assert!(only_exported_func_def_body(&module).unstructured_cfg.is_some());
+
+    let mut module = lower_test_data("irreducible.spvasm");
+    passes::legalize::structurize_func_cfgs_including_irreducible(&mut module);
+    assert!(only_exported_func_def_body(&module).unstructured_cfg.is_none());
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+
+    let printed = common::print_module(&module);
+    assert!(
+        printed.contains(
+            "WARN irreducible control-flow made reducible by dispatching 2 cycle entries"
+        ),
+        "{printed}"
+    );
+}
+
+#[test]
+fn destructurize_and_restructurize() {
+    let cx = Rc::new(Context::new());
+    let mut module = lower_test_data_with_cx(cx.clone(), "irreducible.spvasm");
+    passes::legalize::structurize_func_cfgs_including_irreducible(&mut module);
+    passes::legalize::destructurize_funcs(&mut module);
+    assert!(only_exported_func_def_body(&module).unstructured_cfg.is_some());
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+
+    // The destructurized CFG (once lifted to SPIR-V, and lowered back) should
+    // now be reducible, and so structurizable without any other changes.
+    let spvasm = lift_and_disassemble(&module);
+    let parser = spirt::spv::read::ModuleParser::read_from_spvasm_str(&spvasm).unwrap();
+    let mut module = Module::lower_from_spv_module_parser(cx, parser).unwrap();
+    passes::legalize::structurize_func_cfgs(&mut module);
+    assert!(only_exported_func_def_body(&module).unstructured_cfg.is_none());
+    assert_eq!(passes::verify::verify_funcs(&mut module), 0);
+}
+
+#[test]
+fn destructurize_and_restructurize_all_test_data() {
+    for file_name in common::test_data_file_names(".spvasm") {
+        let mut module = lower_test_data(&file_name);
+        passes::legalize::structurize_func_cfgs_including_irreducible(&mut module);
+        let structurized = common::print_module(&module);
+
+        // Restructurizing should reproduce the original structurization.
+        passes::legalize::destructurize_funcs(&mut module);
+        passes::legalize::structurize_func_cfgs(&mut module);
+        let restructurized = common::print_module(&module);
+        assert!(
+            structurized == restructurized,
+            "{file_name}: restructurizing changed the module, from:\n\
+             {structurized}\nto:\n{restructurized}"
+        );
+    }
+}
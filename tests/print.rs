@@ -0,0 +1,93 @@
+mod common;
+
+use common::{lower_test_data, only_exported_func};
+use spirt::passes;
+use spirt::print::{self, dot};
+
+#[test]
+fn func_to_dot_unstructured() {
+    let module = lower_test_data("for-loop.wgsl.spvasm");
+    let plan = print::Plan::for_module(&module);
+    let dot = dot::func_to_dot(&plan, only_exported_func(&module), dot::DotOptions::default());
+
+    assert!(dot.starts_with("digraph \"F0\" {\n"), "{dot}");
+    assert!(dot.ends_with("}\n"), "{dot}");
+
+    // One graph node per CFG region (and the function body, i.e. its entry),
+    // and one graph edge per CFG edge, labelled with the inputs passed along
+    // (or the condition value, for conditional branches).
+    assert_eq!(dot.matches(" [label=\"label L").count(), 7, "{dot}");
+    assert_eq!(dot.matches(" -> ").count(), 8, "{dot}");
+    for edge_label in ["[label=\"true\"]", "[label=\"false\"]", "[label=\"(v3, v4)\"]"] {
+        assert!(dot.contains(edge_label), "{dot}");
+    }
+}
+
+#[test]
+fn func_to_dot_structured_clusters() {
+    let mut module = lower_test_data("for-loop.wgsl.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    let plan = print::Plan::for_module(&module);
+    let func = only_exported_func(&module);
+
+    // Structured control-flow has no CFG edges, but can be expanded into clusters.
+    let summarized = dot::func_to_dot(&plan, func, dot::DotOptions::default());
+    assert!(!summarized.contains("subgraph cluster"), "{summarized}");
+    let clusters = dot::func_to_dot(
+        &plan,
+        func,
+        dot::DotOptions { structured_clusters: true, ..Default::default() },
+    );
+    assert!(clusters.contains("subgraph cluster"), "{clusters}");
+}
+
+#[test]
+fn options_layout() {
+    let module = lower_test_data("for-loop.wgsl.spvasm");
+    let plan = print::Plan::for_module(&module);
+    let default = plan.pretty_print().to_string();
+
+    let narrow = plan
+        .pretty_print_with_options(&print::Options { max_line_width: 40, ..Default::default() })
+        .to_string();
+    assert!(narrow.lines().count() > default.lines().count(), "{narrow}");
+
+    let wide_indent = plan
+        .pretty_print_with_options(&print::Options { indent_width: 4, ..Default::default() })
+        .to_string();
+    assert_eq!(wide_indent.lines().count(), default.lines().count(), "{wide_indent}");
+    assert!(wide_indent.contains("\n    branch L0\n"), "{wide_indent}");
+    assert!(default.contains("\n  branch L0\n"), "{default}");
+}
+
+#[test]
+fn options_hide_diags() {
+    let mut module = lower_test_data("verify-undominated-use.spvasm");
+    passes::legalize::structurize_func_cfgs(&mut module);
+    assert_eq!(passes::verify::verify_funcs(&mut module), 1);
+    let plan = print::Plan::for_module(&module);
+
+    assert!(plan.pretty_print().to_string().contains("/* ERR "));
+    let without_diags = plan
+        .pretty_print_with_options(&print::Options { show_diags: false, ..Default::default() })
+        .to_string();
+    assert!(!without_diags.contains("/* ERR "), "{without_diags}");
+}
+
+#[test]
+fn render_to_ansi_styles_plain_text() {
+    let module = lower_test_data("for-loop.wgsl.spvasm");
+    let pretty = print::Plan::for_module(&module).pretty_print();
+    let ansi = pretty.render_to_ansi();
+    assert!(ansi.contains("\x1b["), "{ansi}");
+
+    // Removing all the (SGR) escape sequences should result in the plain text.
+    let mut stripped = String::new();
+    let mut rest = &ansi[..];
+    while let Some((text, escape)) = rest.split_once("\x1b[") {
+        stripped += text;
+        rest = &escape[escape.find('m').unwrap() + 1..];
+    }
+    stripped += rest;
+    assert_eq!(stripped, pretty.to_string());
+}
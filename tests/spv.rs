@@ -0,0 +1,278 @@
+mod common;
+
+use common::{lower_insts, module_prologue};
+use spirt::{passes, spv, Context, Module};
+use std::rc::Rc;
+
+#[test]
+fn lower_lift_native_scalars() {
+    let module = lower_insts(
+        16,
+        &[
+            module_prologue(10),
+            vec![
+                // %uint = OpTypeInt 32 0
+                ("OpTypeInt", vec![1, 32, 0]),
+                // %float = OpTypeFloat 32
+                ("OpTypeFloat", vec![2, 32]),
+                // %uint_7 = OpConstant %uint 7
+                ("OpConstant", vec![1, 3, 7]),
+                // %float_2 = OpConstant %float 2
+                ("OpConstant", vec![2, 4, 2.0f32.to_bits()]),
+                // %typeof_main = OpTypeFunction %float %uint
+                ("OpTypeFunction", vec![5, 2, 1]),
+                // %main = OpFunction %float None %typeof_main
+                ("OpFunction", vec![2, 10, 0, 5]),
+                // %n = OpFunctionParameter %uint
+                ("OpFunctionParameter", vec![1, 11]),
+                // %entry = OpLabel
+                ("OpLabel", vec![12]),
+                // %m = OpIMul %uint %n %uint_7
+                ("OpIMul", vec![1, 13, 11, 3]),
+                // %mf = OpConvertUToF %float %m
+                ("OpConvertUToF", vec![2, 14, 13]),
+                // %r = OpFMul %float %mf %float_2
+                ("OpFMul", vec![2, 15, 14, 4]),
+                // OpReturnValue %r
+                ("OpReturnValue", vec![15]),
+                ("OpFunctionEnd", vec![]),
+            ],
+        ]
+        .concat(),
+    );
+    let printed = common::print_module(&module);
+    for expected in ["func F0(v0: u32) -> f32", "(v0, 7u32): u32", "2.0f32): f32"] {
+        assert!(printed.contains(expected), "{printed}");
+    }
+
+    // Lifting (and lowering back) should preserve all the types and constants.
+    assert_eq!(common::print_module(&common::lift_and_relower(&module)), printed);
+}
+
+#[test]
+fn lower_lift_native_vectors() {
+    let module = lower_insts(
+        17,
+        &[
+            module_prologue(10),
+            vec![
+                // %float = OpTypeFloat 32
+                ("OpTypeFloat", vec![1, 32]),
+                // %v4float = OpTypeVector %float 4
+                ("OpTypeVector", vec![2, 1, 4]),
+                // %float_2 = OpConstant %float 2
+                ("OpConstant", vec![1, 3, 2.0f32.to_bits()]),
+                // %typeof_main = OpTypeFunction %v4float %v4float %float
+                ("OpTypeFunction", vec![4, 2, 2, 1]),
+                // %main = OpFunction %v4float None %typeof_main
+                ("OpFunction", vec![2, 10, 0, 4]),
+                // %v = OpFunctionParameter %v4float
+                ("OpFunctionParameter", vec![2, 11]),
+                // %x = OpFunctionParameter %float
+                ("OpFunctionParameter", vec![1, 12]),
+                // %entry = OpLabel
+                ("OpLabel", vec![13]),
+                // %x2 = OpFMul %float %x %float_2
+                ("OpFMul", vec![1, 14, 12, 3]),
+                // %w = OpCompositeInsert %v4float %x2 %v 3
+                ("OpCompositeInsert", vec![2, 15, 14, 11, 3]),
+                // %r = OpFAdd %v4float %w %v
+                ("OpFAdd", vec![2, 16, 15, 11]),
+                // OpReturnValue %r
+                ("OpReturnValue", vec![16]),
+                ("OpFunctionEnd", vec![]),
+            ],
+        ]
+        .concat(),
+    );
+    let printed = common::print_module(&module);
+    for expected in [
+        "func F0(v0: f32×4, v1: f32) -> f32×4",
+        "FMul(v1, 2.0f32): f32",
+        "CompositeInsert(v2, v0, 3): f32×4",
+        "FAdd(v3, v0): f32×4",
+    ] {
+        assert!(printed.contains(expected), "{printed}");
+    }
+    assert!(!printed.contains("spv.Op"), "{printed}");
+
+    assert_eq!(common::print_module(&common::lift_and_relower(&module)), printed);
+}
+
+#[test]
+fn lower_lift_native_undef() {
+    let module = lower_insts(
+        16,
+        &[
+            module_prologue(10),
+            vec![
+                // %float = OpTypeFloat 32
+                ("OpTypeFloat", vec![1, 32]),
+                // %v4float = OpTypeVector %float 4
+                ("OpTypeVector", vec![2, 1, 4]),
+                // %undef = OpUndef %float
+                ("OpUndef", vec![1, 3]),
+                // %typeof_main = OpTypeFunction %v4float %v4float
+                ("OpTypeFunction", vec![4, 2, 2]),
+                // %main = OpFunction %v4float None %typeof_main
+                ("OpFunction", vec![2, 10, 0, 4]),
+                // %v = OpFunctionParameter %v4float
+                ("OpFunctionParameter", vec![2, 11]),
+                // %entry = OpLabel
+                ("OpLabel", vec![12]),
+                // %w = OpCompositeInsert %v4float %undef %v 3
+                ("OpCompositeInsert", vec![2, 13, 3, 11, 3]),
+                // OpReturnValue %w
+                ("OpReturnValue", vec![13]),
+                ("OpFunctionEnd", vec![]),
+            ],
+        ]
+        .concat(),
+    );
+    let printed = common::print_module(&module);
+    assert!(printed.contains("CompositeInsert(undef: f32, v0, 3): f32×4"), "{printed}");
+    assert!(!printed.contains("OpUndef"), "{printed}");
+
+    assert_eq!(common::print_module(&common::lift_and_relower(&module)), printed);
+}
+
+#[test]
+fn lower_spvasm() {
+    let spvasm = r#"
+        ; Same module as in `lower_lift_native_undef`.
+        OpCapability Shader
+        OpCapability Linkage
+        OpMemoryModel Logical GLSL450
+        OpDecorate %main LinkageAttributes "main" Export
+
+        %float = OpTypeFloat 32
+        %v4float = OpTypeVector %float 4
+        %undef = OpUndef %float
+        %typeof_main = OpTypeFunction %v4float %v4float
+
+        %main = OpFunction %v4float None %typeof_main
+          %v = OpFunctionParameter %v4float
+          %entry = OpLabel
+            %w = OpCompositeInsert %v4float %undef %v 3
+            OpReturnValue %w
+        OpFunctionEnd
+    "#;
+    let module = Module::lower_from_spv_module_parser(
+        Rc::new(Context::new()),
+        spv::read::ModuleParser::read_from_spvasm_str(spvasm).unwrap(),
+    )
+    .unwrap();
+    let printed = common::print_module(&module);
+    for expected in
+        ["func F0(v0: f32×4) -> f32×4", "CompositeInsert(undef: f32, v0, 3): f32×4", "\"main\": F0"]
+    {
+        assert!(printed.contains(expected), "{printed}");
+    }
+
+    assert_eq!(common::print_module(&common::lift_and_relower(&module)), printed);
+}
+
+#[test]
+fn lower_spvasm_file() {
+    let module = common::lower_test_data("for-loop.wgsl.spvasm");
+    let printed = common::print_module(&module);
+    for expected in ["spv.OpEntryPoint(spv.ExecutionModel.Vertex, Name: \"main\")", "SLessThan"] {
+        assert!(printed.contains(expected), "{printed}");
+    }
+}
+
+#[test]
+fn spvasm_disassemble_roundtrip() {
+    for file_name in common::test_data_file_names(".spvasm") {
+        let parser =
+            spv::read::ModuleParser::read_from_spvasm_file(common::test_data_path(&file_name));
+        let disassembled = spv::print::disassemble_module(parser.unwrap()).unwrap();
+
+        // Reassembling the disassembly should produce the same module (which
+        // would then get disassembled back into the exact same text).
+        let reparsed = spv::read::ModuleParser::read_from_spvasm_str(&disassembled)
+            .unwrap_or_else(|err| panic!("{file_name}: {err}\n\n{disassembled}"));
+        assert_eq!(spv::print::disassemble_module(reparsed).unwrap(), disassembled, "{file_name}");
+    }
+}
+
+#[test]
+fn spvasm_lower_lift_roundtrip() {
+    let cx = Rc::new(Context::new());
+    let module = common::lower_test_data_with_cx(cx.clone(), "for-loop.wgsl.spvasm");
+    let disassembled = common::lift_and_disassemble(&module);
+
+    // Lowering the disassembly of the lifted module, and lifting it again,
+    // should also produce the same disassembly.
+    let relowered = Module::lower_from_spv_module_parser(
+        cx,
+        spv::read::ModuleParser::read_from_spvasm_str(&disassembled).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(common::lift_and_disassemble(&relowered), disassembled);
+}
+
+#[test]
+fn lower_errors_have_location() {
+    // `%int` is used by the third instruction (at word offset `5 + 2 + 3`, i.e.
+    // after the header, `OpCapability` and `OpMemoryModel`), but never defined.
+    let spvasm =
+        "OpCapability Shader\nOpMemoryModel Logical GLSL450\n%ptr = OpTypePointer Function %int\n";
+    let parser = spv::read::ModuleParser::read_from_spvasm_str(spvasm).unwrap();
+    let Err(err) = Module::lower_from_spv_module_parser(Rc::new(Context::new()), parser) else {
+        panic!("expected error (for forward reference in a type)");
+    };
+    assert_eq!(err.kind, spv::ErrorKind::Unsupported);
+    assert_eq!((err.inst_index, err.word_offset), (Some(2), Some(10)));
+    assert_eq!(
+        err.to_string(),
+        "unsupported SPIR-V (in OpTypePointer, instruction #2, word offset 10: \
+         use of a forward reference to %2 in a type)"
+    );
+}
+
+#[test]
+fn spvasm_errors_have_line() {
+    let spvasm = "OpCapability Shader\nOpMemoryModel Logical GLSL450\n%x = OpTypeInt \"32\" 0\n";
+    let Err(err) = spv::read::ModuleParser::read_from_spvasm_str(spvasm) else {
+        panic!("expected error (for string literal instead of width integer)");
+    };
+    assert_eq!(err.kind, spv::ErrorKind::Malformed);
+    assert_eq!(err.line, Some(3));
+    assert!(err.to_string().starts_with("malformed SPIR-V (line 3: "), "{err}");
+}
+
+#[test]
+fn lift_reports_unlegalized_qptr() {
+    let mut module = common::lower_test_data("for-loop.wgsl.spvasm");
+    passes::qptr::lower_from_spv_ptrs(
+        &mut module,
+        &spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT,
+    );
+
+    // Without `qptr::lift_to_spv_ptrs`, lifting should fail (instead of panicking).
+    let Err(errors) = module.lift_to_spv_module_emitter() else {
+        panic!("lifting `qptr`s without legalizing them should fail");
+    };
+    assert_eq!(std::io::Error::from(errors.clone()).kind(), std::io::ErrorKind::Unsupported);
+    let spv::Errors(errors) = errors;
+    assert!(errors.iter().any(|err| err.to_string().contains("`qptr` type")), "{errors:?}");
+    assert!(errors.iter().all(|err| err.kind == spv::ErrorKind::Unsupported), "{errors:?}");
+}
+
+#[test]
+fn lift_reports_unlegalized_qptr_type_once() {
+    let mut module = common::lower_test_data("qptr-ptr-to-int-bitcast.spvasm");
+    passes::qptr::lower_from_spv_ptrs(
+        &mut module,
+        &spirt::qptr::LayoutConfig::VULKAN_SCALAR_LAYOUT,
+    );
+
+    // Without `qptr::lift_to_spv_ptrs`, the (single) `qptr` type is used by both
+    // global variables and their uses, but should only be reported once.
+    let Err(spv::Errors(errors)) = module.lift_to_spv_module_emitter() else {
+        panic!("lifting `qptr`s without legalizing them should fail");
+    };
+    let qptr_errors = errors.iter().filter(|err| err.to_string().contains("`qptr` type")).count();
+    assert_eq!(qptr_errors, 1, "{errors:?}");
+}